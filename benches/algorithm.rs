@@ -0,0 +1,93 @@
+// Renders a full synth with every voice sounding, to measure how fast
+// `Algorithm::process` runs the operator graph, and how rendering scales
+// with worker threads.
+mod common;
+
+use common::RealTime;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rustfmsynth::synth::note::{NoteEvent, NoteSource};
+use rustfmsynth::synth::Synth;
+use std::hint::black_box;
+
+const OPERATORS: usize = 12; // `SynthConfig::default().operators_per_voice`
+const VOICES: u8 = 128; // `SynthConfig::default().max_voices`
+const BUFFER_SIZE: usize = 256;
+const SAMPLE_RATE: f32 = 44100.0;
+
+/// Combined matrix as taken by `Synth::set_algorithm`: `[source][target]`
+/// connections, with the last column marking carriers.
+fn matrix(connections: &[(usize, usize)], carriers: &[usize]) -> Vec<Vec<u32>> {
+    let mut matrix = vec![vec![0; OPERATORS + 1]; OPERATORS];
+    for &(source, target) in connections {
+        matrix[source][target] = 1;
+    }
+    for &carrier in carriers {
+        matrix[carrier][OPERATORS] = 1;
+    }
+    matrix
+}
+
+fn algorithms() -> Vec<(&'static str, Vec<Vec<u32>>)> {
+    let chain: Vec<_> = (1..OPERATORS).map(|op| (op, op - 1)).collect();
+    let pairs: Vec<_> = (0..OPERATORS).step_by(2).map(|op| (op + 1, op)).collect();
+    let pair_carriers: Vec<_> = (0..OPERATORS).step_by(2).collect();
+    let fanout: Vec<_> = (1..OPERATORS).map(|op| (op, 0)).collect();
+    let mut pairs_feedback = pairs.clone();
+    pairs_feedback.extend((0..OPERATORS).step_by(2).map(|op| (op + 1, op + 1)));
+    vec![
+        ("stack", matrix(&chain, &[0])),
+        ("pairs", matrix(&pairs, &pair_carriers)),
+        ("fanout", matrix(&fanout, &[0])),
+        ("pairs_feedback", matrix(&pairs_feedback, &pair_carriers)),
+    ]
+}
+
+/// A synth with every voice sounding.
+fn full_synth(matrix: &[Vec<u32>], render_threads: usize) -> Synth {
+    let mut synth = Synth::new();
+    synth.set_buffer_size(BUFFER_SIZE);
+    synth.set_render_threads(render_threads);
+    synth.set_algorithm(matrix).unwrap();
+    for note in 0..VOICES {
+        synth.note_on(&NoteEvent::new(note, 100, true, NoteSource::Midi).unwrap());
+    }
+    synth
+}
+
+fn voices(c: &mut Criterion<RealTime>) {
+    let mut group = c.benchmark_group(format!("{}_voices_{}_operators", VOICES, OPERATORS));
+    group.throughput(Throughput::Elements(BUFFER_SIZE as u64));
+    for (name, matrix) in algorithms() {
+        let mut synth = full_synth(&matrix, 0);
+        let mut output = vec![0.0; BUFFER_SIZE];
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                synth.process(black_box(&mut output), SAMPLE_RATE);
+            })
+        });
+    }
+    group.finish();
+}
+
+fn render_threads(c: &mut Criterion<RealTime>) {
+    let mut group = c.benchmark_group("render_threads");
+    group.throughput(Throughput::Elements(BUFFER_SIZE as u64));
+    let (_, matrix) = &algorithms()[1]; // Pairs
+    for render_threads in [0, 1, 3, 7] {
+        let mut synth = full_synth(matrix, render_threads);
+        let mut output = vec![0.0; BUFFER_SIZE];
+        group.bench_function(BenchmarkId::from_parameter(render_threads), |b| {
+            b.iter(|| {
+                synth.process(black_box(&mut output), SAMPLE_RATE);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_measurement(RealTime::new(BUFFER_SIZE, SAMPLE_RATE));
+    targets = voices, render_threads
+}
+criterion_main!(benches);
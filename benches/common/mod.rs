@@ -0,0 +1,77 @@
+// Criterion measurement shared by the benchmarks: wall time reported per
+// rendered sample. Setting any throughput on a group adds a line showing how
+// many times faster than real time that is.
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::Throughput;
+use std::time::{Duration, Instant};
+
+pub struct RealTime {
+    samples_per_iteration: usize,
+    sample_rate: f32,
+}
+
+impl RealTime {
+    /// Each benchmark iteration renders `samples_per_iteration` samples at
+    /// `sample_rate`.
+    pub fn new(samples_per_iteration: usize, sample_rate: f32) -> Self {
+        Self {
+            samples_per_iteration,
+            sample_rate,
+        }
+    }
+}
+
+impl Measurement for RealTime {
+    type Intermediate = Instant;
+    type Value = Duration;
+
+    fn start(&self) -> Instant {
+        Instant::now()
+    }
+    fn end(&self, start: Instant) -> Duration {
+        start.elapsed()
+    }
+    fn add(&self, v1: &Duration, v2: &Duration) -> Duration {
+        *v1 + *v2
+    }
+    fn zero(&self) -> Duration {
+        Duration::ZERO
+    }
+    /// Nanoseconds per sample.
+    fn to_f64(&self, value: &Duration) -> f64 {
+        value.as_nanos() as f64 / self.samples_per_iteration as f64
+    }
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+
+impl ValueFormatter for RealTime {
+    fn scale_values(&self, ns_per_sample: f64, values: &mut [f64]) -> &'static str {
+        if ns_per_sample < 1000.0 {
+            "ns/sample"
+        } else {
+            for value in values {
+                *value /= 1000.0;
+            }
+            "µs/sample"
+        }
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical: f64,
+        _throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        // Audio seconds rendered per second of wall time
+        for value in values {
+            *value = 1e9 / (*value * self.sample_rate as f64);
+        }
+        "x real time"
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "ns/sample"
+    }
+}
@@ -0,0 +1,164 @@
+// Measures the DSP building blocks on their own: one operator per waveform,
+// the built-in algorithms at a range of voice counts, each filter type, and
+// the reverb at a range of channel counts.
+mod common;
+
+use common::RealTime;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rustfmsynth::synth::algorithm::{Algorithm, AlgorithmScratch};
+use rustfmsynth::synth::context::ProcessContext;
+use rustfmsynth::synth::error::SynthError;
+use rustfmsynth::synth::filter::Filter;
+use rustfmsynth::synth::operator::{Operator, OperatorState};
+use rustfmsynth::synth::reverb::Reverb;
+use rustfmsynth::synth::waveform::Waveform;
+use std::hint::black_box;
+
+const OPERATORS: usize = 12; // `SynthConfig::default().operators_per_voice`
+const BUFFER_SIZE: usize = 256;
+const SAMPLE_RATE: f32 = 44100.0;
+
+/// A held note past its attack, so every operator renders its sustain.
+fn held_note(operators: &[Operator]) -> ProcessContext<'_> {
+    ProcessContext {
+        sample_rate: SAMPLE_RATE,
+        base_frequency: 220.0,
+        velocity_scale: 1.0,
+        modulation_index_scale: 1.0,
+        cutoff_scale: 1.0,
+        samples_elapsed_since_trigger: SAMPLE_RATE as u64,
+        note_off_sample_index: None,
+        operators,
+    }
+}
+
+/// Deterministic white noise in [-1, 1).
+fn noise(len: usize) -> Vec<f32> {
+    let mut seed = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+        })
+        .collect()
+}
+
+fn operator(c: &mut Criterion<RealTime>) {
+    let mut group = c.benchmark_group("operator");
+    group.throughput(Throughput::Elements(BUFFER_SIZE as u64));
+    for waveform in [
+        Waveform::Sine,
+        Waveform::Triangle,
+        Waveform::Square,
+        Waveform::Sawtooth,
+        Waveform::SawtoothSmooth,
+        Waveform::Noise,
+        Waveform::Input,
+    ] {
+        let mut operator = Operator::new();
+        operator.set_waveform(waveform);
+        let operators = [operator];
+        let context = held_note(&operators);
+        let modulation = vec![0.0; BUFFER_SIZE];
+        let mut state = OperatorState::default();
+        let mut output = vec![0.0; BUFFER_SIZE];
+        let name = format!("{:?}", waveform).to_lowercase();
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                operators[0].process(&context, &modulation, &mut state, black_box(&mut output));
+            })
+        });
+    }
+    group.finish();
+}
+
+fn algorithm(c: &mut Criterion<RealTime>) {
+    type Constructor = fn(usize) -> Result<Algorithm, SynthError>;
+    let constructors: [(&str, Constructor); 7] = [
+        ("simple", Algorithm::default_simple),
+        ("feedback_1", Algorithm::default_feedback_1),
+        ("stack_2", Algorithm::default_stack_2),
+        ("stack_3_feedback", Algorithm::stack_3_feedback),
+        ("fanout", Algorithm::default_fanout),
+        ("fanout_feedback", Algorithm::default_fanout_feedback),
+        ("dual_stack", Algorithm::default_dual_stack),
+    ];
+    let operators = vec![Operator::new(); OPERATORS];
+    let context = held_note(&operators);
+    let mut group = c.benchmark_group("algorithm");
+    group.throughput(Throughput::Elements(BUFFER_SIZE as u64));
+    for (name, constructor) in constructors {
+        let algorithm = constructor(OPERATORS).unwrap();
+        for voices in [1, 16, 64] {
+            let nodes = algorithm.length();
+            let mut states = vec![vec![OperatorState::default(); nodes]; voices];
+            let mut scratch = AlgorithmScratch::new(nodes, BUFFER_SIZE);
+            let mut output = vec![0.0; BUFFER_SIZE];
+            group.bench_function(BenchmarkId::new(name, voices), |b| {
+                b.iter(|| {
+                    for states in states.iter_mut() {
+                        algorithm.process(&context, states, &mut scratch, black_box(&mut output));
+                    }
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn filter(c: &mut Criterion<RealTime>) {
+    let mut pitched_comb = Filter::new_pitched_comb(0.995);
+    if let Filter::PitchedComb(state) = &mut pitched_comb {
+        state.update_k_frequency(SAMPLE_RATE, 220.0);
+    }
+    let filters = [
+        (
+            "lowpass_biquad",
+            Filter::new_lowpass_biquad(2000.0, SAMPLE_RATE),
+        ),
+        ("comb", Filter::new_comb(0.7, 100)),
+        ("pitched_comb", pitched_comb),
+    ];
+    let input = noise(BUFFER_SIZE);
+    let mut group = c.benchmark_group("filter");
+    group.throughput(Throughput::Elements(BUFFER_SIZE as u64));
+    for (name, mut filter) in filters {
+        let mut output = vec![0.0; BUFFER_SIZE];
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                for (sample, &x) in output.iter_mut().zip(&input) {
+                    *sample = filter.process(black_box(x));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn reverb(c: &mut Criterion<RealTime>) {
+    let input = noise(BUFFER_SIZE);
+    let mut group = c.benchmark_group("reverb");
+    group.throughput(Throughput::Elements(BUFFER_SIZE as u64));
+    for channels in [4, 8, 16, 32, 64] {
+        let mut reverb = Reverb::new_fdn_with_channels(10.0, 2000.0, 0.3, SAMPLE_RATE, channels);
+        let mut buffer = input.clone();
+        group.bench_function(BenchmarkId::from_parameter(channels), |b| {
+            b.iter(|| {
+                buffer.copy_from_slice(&input);
+                for sample in buffer.iter_mut() {
+                    reverb.process(black_box(sample));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_measurement(RealTime::new(BUFFER_SIZE, SAMPLE_RATE));
+    targets = operator, algorithm, filter, reverb
+}
+criterion_main!(benches);
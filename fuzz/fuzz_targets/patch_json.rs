@@ -0,0 +1,23 @@
+//! Arbitrary text imported as a bank of patches. Anything that isn't a valid
+//! bank must be an error, and every patch that imports must load and play
+//! without panicking. Seed the corpus with `web/public/default-patches.json`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustfmsynth::synth::note::{NoteEvent, NoteSource};
+use rustfmsynth::synth::Synth;
+
+const BANK: u16 = 0;
+
+fuzz_target!(|json: &str| {
+    let mut synth = Synth::new();
+    let Ok(patches) = synth.presets.import_json(BANK, json) else {
+        return;
+    };
+    let mut output = [0.0; 64];
+    for program in 0..patches.min(128) {
+        synth.program_change(BANK, program as u8).unwrap();
+        synth.note_on(&NoteEvent::new(60, 100, true, NoteSource::Midi).unwrap());
+        synth.process(&mut output, 44100.0);
+    }
+});
@@ -0,0 +1,47 @@
+//! Random UI matrices of any shape, in each feedback mode. `set_matrix` must
+//! answer with `Ok` or `Err`, and whatever the algorithm ends up as must render
+//! finite audio.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustfmsynth::synth::algorithm::{Algorithm, AlgorithmScratch, FeedbackMode};
+use rustfmsynth::synth::context::ProcessContext;
+use rustfmsynth::synth::operator::{Operator, OperatorState};
+
+const MAX_OPERATORS: u8 = 16;
+const BUFFER_SIZE: usize = 64;
+
+fuzz_target!(|input: (u8, u8, Vec<Vec<u8>>)| {
+    let (operators, mode, rows) = input;
+    let operators = (operators % MAX_OPERATORS) as usize + 1;
+    let mode = match mode % 3 {
+        0 => FeedbackMode::Unrolled,
+        1 => FeedbackMode::OneSample,
+        _ => FeedbackMode::Averaged,
+    };
+    let matrix: Vec<Vec<u32>> = rows
+        .into_iter()
+        .map(|row| row.into_iter().map(u32::from).collect())
+        .collect();
+
+    let mut algorithm = Algorithm::default_simple(operators).unwrap();
+    algorithm.set_feedback_mode(mode).unwrap();
+    let _ = algorithm.set_matrix(&matrix);
+
+    let ops = vec![Operator::new(); operators];
+    let context = ProcessContext {
+        sample_rate: 44100.0,
+        base_frequency: 440.0,
+        velocity_scale: 1.0,
+        modulation_index_scale: 1.0,
+        cutoff_scale: 1.0,
+        samples_elapsed_since_trigger: 0,
+        note_off_sample_index: None,
+        operators: &ops,
+    };
+    let mut states = vec![OperatorState::default(); algorithm.length()];
+    let mut scratch = AlgorithmScratch::new(algorithm.length(), BUFFER_SIZE);
+    let mut output = [0.0; BUFFER_SIZE];
+    algorithm.process(&context, &mut states, &mut scratch, &mut output);
+    assert!(output.iter().all(|sample| sample.is_finite()));
+});
@@ -1,19 +1,21 @@
 use crate::audio::AudioBackend;
 use crate::runtime::NativeSynth;
+use crate::utils::logging;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{SampleFormat, Stream};
-use std::sync::{Arc, Mutex};
+use log::{error, info};
 
 pub struct CpalBackend {
     stream: Option<Stream>,
-    synth: Arc<Mutex<NativeSynth>>,
+    // Moved into the audio callback when the stream is built
+    synth: Option<NativeSynth>,
 }
 
 impl CpalBackend {
-    pub fn new(synth: Arc<Mutex<NativeSynth>>) -> Self {
+    pub fn new(synth: NativeSynth) -> Self {
         Self {
             stream: None,
-            synth,
+            synth: Some(synth),
         }
     }
 
@@ -87,7 +89,7 @@ impl CpalBackend {
                     let buffer_size = data.len() / channels;
                     buffer_size_sender.send(buffer_size).unwrap();
                 },
-                |err| eprintln!("Stream error: {}", err),
+                |err| error!(target: logging::RUNTIME, "Stream error: {}", err),
                 None,
             )?,
             _ => return Err("Unsupported sample format".into()),
@@ -103,7 +105,7 @@ impl CpalBackend {
     fn build_stream(&mut self) -> Result<Stream, Box<dyn std::error::Error>> {
         let host = cpal::default_host();
         let device = self.select_output_device(&host)?;
-        println!("Selected device: {}", device.name().unwrap_or_default());
+        info!(target: logging::RUNTIME, "Selected device: {}", device.name().unwrap_or_default());
 
         let supported_config = device.default_output_config()?;
         let mut stream_config: cpal::StreamConfig = supported_config.clone().into();
@@ -112,30 +114,29 @@ impl CpalBackend {
         let buffer_size =
             self.determine_buffer_size(&device, &stream_config, supported_config.sample_format())?;
 
-        {
-            let mut synth = self.synth.lock().unwrap();
-            synth.set_buffer_size(buffer_size);
-        }
+        let mut synth = self.synth.take().ok_or("Audio stream already built")?;
+        synth.set_buffer_size(buffer_size);
 
         let sample_rate = stream_config.sample_rate.0;
         let channels = stream_config.channels as usize;
-        let synth = self.synth.clone();
+        // Preallocated mono mix buffer; the callback renders in chunks of this size
+        // so it never allocates, even if the host hands us a larger buffer.
+        let mut buffer = vec![0.0; buffer_size.max(1)];
 
         let stream = match supported_config.sample_format() {
             SampleFormat::F32 => device.build_output_stream(
                 &stream_config,
                 move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                    let mut synth = synth.lock().unwrap();
-                    let mut buffer = vec![0.0; data.len() / channels];
-                    synth.process(&mut buffer, sample_rate as f32);
+                    for frames in data.chunks_mut(buffer.len() * channels) {
+                        let mono = &mut buffer[..frames.len() / channels];
+                        synth.process(mono, sample_rate as f32);
 
-                    for (i, frame) in data.chunks_mut(channels).enumerate() {
-                        for sample in frame.iter_mut() {
-                            *sample = buffer[i];
+                        for (frame, sample) in frames.chunks_mut(channels).zip(mono.iter()) {
+                            frame.fill(*sample);
                         }
                     }
                 },
-                |err| eprintln!("Stream error: {}", err),
+                |err| error!(target: logging::RUNTIME, "Stream error: {}", err),
                 None,
             )?,
             _ => return Err("Unsupported sample format".into()),
@@ -1,6 +1,10 @@
+use crate::runtime::EngineCommand;
+use crate::synth::clock::{ClockEvent, ClockMessage};
 use crate::synth::note::{NoteEvent, NoteSource};
 use crate::synth::operator::{CycleDirection, OperatorEvent};
+use crate::utils::logging;
 use device_query::{DeviceQuery, DeviceState, Keycode};
+use log::{debug, error};
 use std::collections::HashMap;
 use std::sync::mpsc::Sender;
 
@@ -9,12 +13,12 @@ pub struct KeyboardHandler {
     key_states: HashMap<Keycode, bool>,
     key_to_note: HashMap<Keycode, u8>,
     control_keys: HashMap<Keycode, bool>, // Track control keys separately
-    note_sender: Sender<NoteEvent>,
-    operator_sender: Sender<OperatorEvent>,
+    command_sender: Sender<EngineCommand>,
+    sequencer_running: bool,
 }
 
 impl KeyboardHandler {
-    pub fn new(note_sender: Sender<NoteEvent>, operator_sender: Sender<OperatorEvent>) -> Self {
+    pub fn new(command_sender: Sender<EngineCommand>) -> Self {
         let device_state = DeviceState::new();
         let mut key_states: HashMap<Keycode, bool> = HashMap::new();
         let mut control_keys: HashMap<Keycode, bool> = HashMap::new();
@@ -53,14 +57,15 @@ impl KeyboardHandler {
         // Initialize control keys
         control_keys.insert(Keycode::Comma, false);
         control_keys.insert(Keycode::Dot, false);
+        control_keys.insert(Keycode::Space, false);
 
         Self {
             device_state,
             key_states,
             key_to_note,
             control_keys,
-            note_sender,
-            operator_sender,
+            command_sender,
+            sequencer_running: false,
         }
     }
 
@@ -74,23 +79,25 @@ impl KeyboardHandler {
 
             if is_pressed != was_pressed {
                 if is_pressed {
-                    println!(
+                    debug!(
+                        target: logging::INPUT,
                         "Key '{:?}' pressed - sending note on for note {}",
                         key, note
                     );
                     if let Ok(event) = NoteEvent::new(*note, 100, true, NoteSource::Keyboard) {
-                        if let Err(e) = self.note_sender.send(event) {
-                            eprintln!("Error sending note on event: {}", e);
+                        if let Err(e) = self.command_sender.send(EngineCommand::now(event)) {
+                            error!(target: logging::INPUT, "Error sending note on event: {}", e);
                         }
                     }
                 } else {
-                    println!(
+                    debug!(
+                        target: logging::INPUT,
                         "Key '{:?}' released - sending note off for note {}",
                         key, note
                     );
                     if let Ok(event) = NoteEvent::new(*note, 0, false, NoteSource::Keyboard) {
-                        if let Err(e) = self.note_sender.send(event) {
-                            eprintln!("Error sending note off event: {}", e);
+                        if let Err(e) = self.command_sender.send(EngineCommand::now(event)) {
+                            error!(target: logging::INPUT, "Error sending note off event: {}", e);
                         }
                     }
                 }
@@ -99,7 +106,7 @@ impl KeyboardHandler {
         }
 
         // Check control keys for waveform cycling
-        for key in [Keycode::Comma, Keycode::Dot].iter() {
+        for key in [Keycode::Comma, Keycode::Dot, Keycode::Space].iter() {
             let is_pressed = keys.contains(key);
             let was_pressed = self.control_keys.get(key).cloned().unwrap_or(false);
 
@@ -107,19 +114,42 @@ impl KeyboardHandler {
                 // Key just pressed
                 match key {
                     Keycode::Comma => {
-                        println!("Cycling waveform backward");
-                        if let Err(e) = self.operator_sender.send(OperatorEvent::CycleWaveform {
-                            direction: CycleDirection::Backward,
-                        }) {
-                            eprintln!("Error sending operator event: {}", e);
+                        debug!(target: logging::INPUT, "Cycling waveform backward");
+                        if let Err(e) =
+                            self.command_sender
+                                .send(EngineCommand::now(OperatorEvent::CycleWaveform {
+                                    direction: CycleDirection::Backward,
+                                    frame_offset: 0,
+                                }))
+                        {
+                            error!(target: logging::INPUT, "Error sending operator event: {}", e);
                         }
                     }
                     Keycode::Dot => {
-                        println!("Cycling waveform forward");
-                        if let Err(e) = self.operator_sender.send(OperatorEvent::CycleWaveform {
-                            direction: CycleDirection::Forward,
-                        }) {
-                            eprintln!("Error sending operator event: {}", e);
+                        debug!(target: logging::INPUT, "Cycling waveform forward");
+                        if let Err(e) =
+                            self.command_sender
+                                .send(EngineCommand::now(OperatorEvent::CycleWaveform {
+                                    direction: CycleDirection::Forward,
+                                    frame_offset: 0,
+                                }))
+                        {
+                            error!(target: logging::INPUT, "Error sending operator event: {}", e);
+                        }
+                    }
+                    Keycode::Space => {
+                        self.sequencer_running = !self.sequencer_running;
+                        let message = if self.sequencer_running {
+                            ClockMessage::Start
+                        } else {
+                            ClockMessage::Stop
+                        };
+                        debug!(target: logging::INPUT, "Transport {:?}", message);
+                        if let Err(e) = self
+                            .command_sender
+                            .send(EngineCommand::now(ClockEvent::new(message)))
+                        {
+                            error!(target: logging::INPUT, "Error sending transport event: {}", e);
                         }
                     }
                     _ => {}
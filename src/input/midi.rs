@@ -1,107 +1,291 @@
-use crate::synth::note::{NoteEvent, NoteSource};
-use midir::{MidiInput, MidiInputConnection, MidiInputPort};
+use crate::runtime::EngineCommand;
+use crate::synth::error::SynthError;
+use crate::synth::midi::MidiDecoder;
+use crate::synth::midi_learn::{ControlCurve, MidiLearn};
+use crate::synth::mpe::MpeConfig;
+use crate::synth::note::NoteSource;
+use crate::synth::params::{ParamEvent, ParamId};
+use crate::utils::logging;
+use log::{error, info, warn};
+use midir::{Ignore, MidiIO, MidiInput, MidiInputConnection};
+use std::collections::VecDeque;
 use std::error::Error;
 use std::io::{stdin, stdout, Write};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
 use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+/// Which MIDI port to open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortSelection {
+    /// List the ports and ask on stdin.
+    Prompt,
+    /// The first port whose name contains this, ignoring case.
+    Name(String),
+    /// Create a virtual port with this name for other applications, such as
+    /// a DAW, to connect to. Not available on Windows.
+    Virtual(String),
+}
+
+impl PortSelection {
+    /// Parse a command line value: `virtual` or `virtual:<name>` creates a
+    /// virtual port, anything else is a name pattern.
+    pub fn from_arg(arg: &str, default_virtual_name: &str) -> Self {
+        match arg.split_once(':') {
+            Some(("virtual", name)) if !name.is_empty() => PortSelection::Virtual(name.to_string()),
+            _ if arg == "virtual" => PortSelection::Virtual(default_virtual_name.to_string()),
+            _ => PortSelection::Name(arg.to_string()),
+        }
+    }
+}
+
+/// Find an input or output port by name pattern, or by asking on stdin.
+pub(crate) fn select_port<T: MidiIO>(
+    io: &T,
+    selection: &PortSelection,
+    direction: &str,
+) -> Result<T::Port, Box<dyn Error>> {
+    let ports = io.ports();
+    if ports.is_empty() {
+        return Err(format!("No MIDI {} ports found", direction).into());
+    }
+    let pattern = match selection {
+        PortSelection::Name(pattern) => pattern.to_lowercase(),
+        PortSelection::Virtual(_) => return Err("Virtual ports aren't selected from a list".into()),
+        PortSelection::Prompt => {
+            println!("Available MIDI {} ports:", direction);
+            for (i, port) in ports.iter().enumerate() {
+                println!("{}: {}", i, io.port_name(port)?);
+            }
+
+            print!("Select MIDI {} port: ", direction);
+            stdout().flush()?;
+            let mut input = String::new();
+            stdin().read_line(&mut input)?;
+            let selection = input.trim().parse::<usize>().unwrap_or(0);
+
+            return Ok(ports
+                .get(selection)
+                .ok_or(format!("Invalid MIDI {} port selection", direction))?
+                .clone());
+        }
+    };
+    ports
+        .into_iter()
+        .find(|port| {
+            io.port_name(port)
+                .is_ok_and(|name| name.to_lowercase().contains(&pattern))
+        })
+        .ok_or_else(|| format!("No MIDI {} port matching '{}'", direction, pattern).into())
+}
 
 pub struct MidiHandler {
     /// Holds the connection to keep it alive
     #[allow(dead_code)]
     connection: Option<MidiInputConnection<()>>,
-    receiver: Option<Receiver<(u8, u8, u8)>>, // (status, data1, data2)
-    note_sender: Sender<NoteEvent>,
+    receiver: Option<Receiver<(Instant, [u8; 3])>>, // (time, [status, data1, data2])
+    sysex_receiver: Option<Receiver<Vec<u8>>>,
+    command_sender: Sender<EngineCommand>,
+    decoder: MidiDecoder,
+    learn: MidiLearn,
+    learn_queue: VecDeque<ParamId>,
+    profile_path: Option<PathBuf>, // Controller profile that learned mappings are saved to
 }
 
 impl MidiHandler {
-    pub fn new(note_sender: Sender<NoteEvent>) -> Self {
-        match Self::try_new(note_sender.clone()) {
+    /// Open an input port chosen on stdin.
+    pub fn new(command_sender: Sender<EngineCommand>) -> Self {
+        Self::with_port(command_sender, &PortSelection::Prompt)
+    }
+
+    pub fn with_port(command_sender: Sender<EngineCommand>, selection: &PortSelection) -> Self {
+        match Self::try_new(command_sender.clone(), selection) {
             Ok(handler) => handler,
             Err(e) => {
-                println!(
+                warn!(
+                    target: logging::INPUT,
                     "Failed to initialize MIDI: {}. MIDI functionality will be disabled.",
                     e
                 );
                 Self {
                     connection: None,
                     receiver: None,
-                    note_sender,
+                    sysex_receiver: None,
+                    command_sender,
+                    decoder: MidiDecoder::new(NoteSource::Midi),
+                    learn: MidiLearn::new(),
+                    learn_queue: VecDeque::new(),
+                    profile_path: None,
                 }
             }
         }
     }
 
-    fn try_new(note_sender: Sender<NoteEvent>) -> Result<Self, Box<dyn Error>> {
-        let midi_in = MidiInput::new("RustFMSynth Input")?;
-        let port = Self::select_input_port(&midi_in)?;
-        let port_name = midi_in.port_name(&port)?;
+    fn try_new(
+        command_sender: Sender<EngineCommand>,
+        selection: &PortSelection,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut midi_in = MidiInput::new("RustFMSynth Input")?;
+        midi_in.ignore(Ignore::ActiveSense); // Keep SysEx for MTS retuning and clock for the arpeggiator
 
         let (sender, receiver) = mpsc::channel();
+        let (sysex_sender, sysex_receiver) = mpsc::channel();
+        // midir timestamps are microseconds from an arbitrary origin; anchor the first
+        // one to the wall clock so later messages keep their driver-measured spacing.
+        let mut anchor: Option<(u64, Instant)> = None;
+        let callback = move |stamp: u64, message: &[u8], _: &mut ()| {
+            if message.first() == Some(&0xF0) {
+                let _ = sysex_sender.send(message.to_vec());
+            } else if !message.is_empty() {
+                let now = Instant::now();
+                let (anchor_stamp, anchor_time) = *anchor.get_or_insert((stamp, now));
+                let time = anchor_time + Duration::from_micros(stamp.saturating_sub(anchor_stamp));
+                // Shorter messages such as channel pressure or clock are padded with zero
+                let bytes = [
+                    message[0],
+                    message.get(1).copied().unwrap_or(0),
+                    message.get(2).copied().unwrap_or(0),
+                ];
+                let _ = sender.send((time.min(now), bytes));
+            }
+        };
 
-        let connection = midi_in.connect(
-            &port,
-            "midir-read-input",
-            move |_, message, _| {
-                if message.len() >= 3 {
-                    let _ = sender.send((message[0], message[1], message[2]));
-                }
-            },
-            (),
-        )?;
+        let (connection, port_name) = match selection {
+            PortSelection::Virtual(name) => {
+                (Self::create_virtual(midi_in, name, callback)?, name.clone())
+            }
+            _ => {
+                let port = select_port(&midi_in, selection, "input")?;
+                let port_name = midi_in.port_name(&port)?;
+                let connection = midi_in.connect(&port, "midir-read-input", callback, ())?;
+                (connection, port_name)
+            }
+        };
 
-        println!("Opened MIDI port: {}", port_name);
+        info!(target: logging::INPUT, "Opened MIDI port: {}", port_name);
 
         Ok(Self {
             connection: Some(connection),
             receiver: Some(receiver),
-            note_sender,
+            sysex_receiver: Some(sysex_receiver),
+            command_sender,
+            decoder: MidiDecoder::new(NoteSource::Midi),
+            learn: MidiLearn::new(),
+            learn_queue: VecDeque::new(),
+            profile_path: None,
         })
     }
 
-    fn select_input_port(midi_in: &MidiInput) -> Result<MidiInputPort, Box<dyn Error>> {
-        let in_ports = midi_in.ports();
-        if in_ports.is_empty() {
-            return Err("No MIDI input ports found".into());
-        }
+    #[cfg(unix)]
+    fn create_virtual<F>(
+        midi_in: MidiInput,
+        name: &str,
+        callback: F,
+    ) -> Result<MidiInputConnection<()>, Box<dyn Error>>
+    where
+        F: FnMut(u64, &[u8], &mut ()) + Send + 'static,
+    {
+        use midir::os::unix::VirtualInput;
+        Ok(midi_in.create_virtual(name, callback, ())?)
+    }
+
+    #[cfg(not(unix))]
+    fn create_virtual<F>(
+        _midi_in: MidiInput,
+        _name: &str,
+        _callback: F,
+    ) -> Result<MidiInputConnection<()>, Box<dyn Error>>
+    where
+        F: FnMut(u64, &[u8], &mut ()) + Send + 'static,
+    {
+        Err("Virtual MIDI ports aren't supported on this platform".into())
+    }
 
-        println!("Available MIDI input ports:");
-        for (i, port) in in_ports.iter().enumerate() {
-            println!("{}: {}", i, midi_in.port_name(port)?);
+    /// Next complete SysEx message received, including the F0/F7 framing.
+    pub fn poll_sysex(&self) -> Option<Vec<u8>> {
+        self.sysex_receiver.as_ref()?.try_recv().ok()
+    }
+
+    /// Configure MPE zones. Controllers can also set them with an MPE
+    /// Configuration Message.
+    pub fn set_mpe_config(&mut self, config: MpeConfig) {
+        self.decoder.set_mpe_config(config);
+    }
+
+    /// Use a controller profile of MIDI mappings (see `MidiLearn::to_json`),
+    /// loading it if it exists. Mappings learned later are saved back to it.
+    pub fn load_controller_profile(&mut self, path: &Path) -> Result<(), SynthError> {
+        self.profile_path = Some(path.to_path_buf());
+        if !path.exists() {
+            return Ok(());
         }
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| SynthError::Io(format!("Failed to read controller profile: {}", e)))?;
+        self.learn.load_json(&json)?;
+        info!(
+            target: logging::INPUT,
+            "Loaded {} MIDI mappings from {}",
+            self.learn.mappings().len(),
+            path.display()
+        );
+        Ok(())
+    }
 
-        print!("Select MIDI input port: ");
-        stdout().flush()?;
-        let mut input = String::new();
-        stdin().read_line(&mut input)?;
-        let selection = input.trim().parse::<usize>().unwrap_or(0);
+    /// Learn controllers for these parameters in turn: each is bound to the
+    /// next controller that moves.
+    pub fn learn(&mut self, ids: &[ParamId]) {
+        self.learn_queue.extend(ids);
+        if self.learn.learning().is_none() {
+            self.learn_next();
+        }
+    }
 
-        let port = in_ports
-            .get(selection)
-            .ok_or("Invalid MIDI port selection")?
-            .clone();
+    fn learn_next(&mut self) {
+        if let Some(id) = self.learn_queue.pop_front() {
+            self.learn
+                .start_learning(id, 0.0, 1.0, ControlCurve::Linear);
+            info!(target: logging::INPUT, "MIDI learn: move a controller for {}", id.name());
+        }
+    }
 
-        Ok(port)
+    fn finish_learning(&mut self) {
+        if let Some(mapping) = self.learn.mappings().last() {
+            info!(
+                target: logging::INPUT,
+                "MIDI learn: {:?} mapped to {}",
+                mapping.source,
+                mapping.id.name()
+            );
+        }
+        if let Some(path) = &self.profile_path {
+            if let Err(e) = std::fs::write(path, self.learn.to_json()) {
+                error!(target: logging::INPUT, "Failed to save controller profile: {}", e);
+            }
+        }
+        self.learn_next();
     }
 
     pub fn update(&mut self) {
-        if let Some(receiver) = &self.receiver {
-            while let Ok((status, data1, data2)) = receiver.try_recv() {
-                let note_on = status & 0xF0 == 0x90 && data2 > 0;
-                let note_off = (status & 0xF0 == 0x80) || (status & 0xF0 == 0x90 && data2 == 0);
-
-                let result = if note_on {
-                    NoteEvent::new(data1, data2, true, NoteSource::Midi)
-                } else if note_off {
-                    NoteEvent::new(data1, 0, false, NoteSource::Midi)
-                } else {
-                    continue;
-                };
-
-                if let Ok(event) = result {
-                    if let Err(e) = self.note_sender.send(event) {
-                        eprintln!("Failed to send MIDI NoteEvent: {}", e);
-                    }
-                }
+        while let Some((time, message)) = self.receiver.as_ref().and_then(|r| r.try_recv().ok()) {
+            let was_learning = self.learn.learning().is_some();
+            let learned = self.learn.handle_message(&message);
+            if was_learning && self.learn.learning().is_none() {
+                self.finish_learning();
+            }
+            // A mapped or just-learned controller doesn't also reach the decoder
+            let event = match learned {
+                Some((id, normalized)) => ParamEvent::new(id, id.denormalize(normalized)).into(),
+                None => match self.decoder.decode(&message) {
+                    Some(event) => event,
+                    None => continue,
+                },
+            };
+            if let Err(e) = self
+                .command_sender
+                .send(EngineCommand::new(event, Some(time)))
+            {
+                error!(target: logging::INPUT, "Failed to send MIDI event: {}", e);
             }
         }
     }
@@ -0,0 +1,140 @@
+use crate::runtime::EngineCommand;
+use crate::synth::midi::MidiDecoder;
+use crate::synth::note::NoteSource;
+use crate::synth::smf::{MidiFile, PlaybackFilter, SmfError, TimelineEvent};
+use crate::utils::logging;
+use log::{error, info};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+/// Plays a Standard MIDI File into the engine. Each event is stamped with the
+/// instant it is due, so the engine places it on the matching frame whenever
+/// `update` happens to run.
+pub struct MidiFilePlayer {
+    events: Vec<TimelineEvent>,
+    duration: f64,                  // Seconds
+    loop_range: Option<(f64, f64)>, // Loop start and end in seconds
+    command_sender: Sender<EngineCommand>,
+    decoder: MidiDecoder,
+    origin: Option<Instant>, // When song time 0 was (or would have been) played; None when stopped
+    next_event: usize,
+    sounding: [u16; 128], // Channels holding each note, released at the loop end
+}
+
+impl MidiFilePlayer {
+    pub fn new(
+        file: &MidiFile,
+        filter: &PlaybackFilter,
+        command_sender: Sender<EngineCommand>,
+    ) -> Self {
+        Self {
+            events: file.timeline(filter),
+            duration: file.duration_seconds(),
+            loop_range: None,
+            command_sender,
+            decoder: MidiDecoder::new(NoteSource::Midi),
+            origin: None,
+            next_event: 0,
+            sounding: [0; 128],
+        }
+    }
+
+    /// Loop between two positions in quarter notes, following the file's tempo map.
+    pub fn set_loop(
+        &mut self,
+        file: &MidiFile,
+        start_beats: f64,
+        end_beats: f64,
+    ) -> Result<(), SmfError> {
+        let start = file.beats_to_seconds(start_beats);
+        let end = file.beats_to_seconds(end_beats);
+        if end <= start {
+            return Err(SmfError::InvalidData(format!(
+                "loop end {} must be after loop start {}",
+                end_beats, start_beats
+            )));
+        }
+        self.loop_range = Some((start, end));
+        Ok(())
+    }
+
+    pub fn play(&mut self) {
+        self.origin = Some(Instant::now());
+        self.next_event = 0;
+    }
+
+    pub fn stop(&mut self) {
+        if self.origin.take().is_some() {
+            self.release_sounding(Instant::now());
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.origin.is_some()
+    }
+
+    /// Send every event that has come due.
+    pub fn update(&mut self) {
+        let Some(mut origin) = self.origin else {
+            return;
+        };
+        let now = Instant::now();
+        let end = self.loop_range.map_or(self.duration, |(_, end)| end);
+        loop {
+            if let Some(event) = self.events.get(self.next_event).filter(|e| e.seconds < end) {
+                let time = origin + Duration::from_secs_f64(event.seconds);
+                if time > now {
+                    break;
+                }
+                let message = event.message;
+                self.send(&message, time);
+                self.next_event += 1;
+                continue;
+            }
+            let end_time = origin + Duration::from_secs_f64(end);
+            if end_time > now {
+                break;
+            }
+            self.release_sounding(end_time);
+            let Some((loop_start, _)) = self.loop_range else {
+                info!(target: logging::INPUT, "MIDI file finished");
+                self.origin = None;
+                return;
+            };
+            // Song time `loop_start` continues from the end of the loop
+            origin = end_time - Duration::from_secs_f64(loop_start);
+            self.next_event = self.events.partition_point(|e| e.seconds < loop_start);
+        }
+        self.origin = Some(origin);
+    }
+
+    fn send(&mut self, message: &[u8; 3], time: Instant) {
+        let channel_bit = 1 << (message[0] & 0x0F);
+        let note = (message[1] & 0x7F) as usize;
+        match message[0] & 0xF0 {
+            0x90 if message[2] > 0 => self.sounding[note] |= channel_bit,
+            0x80 | 0x90 => self.sounding[note] &= !channel_bit,
+            _ => {}
+        }
+        let Some(event) = self.decoder.decode(message) else {
+            return;
+        };
+        if let Err(e) = self
+            .command_sender
+            .send(EngineCommand::new(event, Some(time)))
+        {
+            error!(target: logging::INPUT, "Failed to send MIDI file event: {}", e);
+        }
+    }
+
+    /// Note off for every note still held, so none hang across a loop or stop.
+    fn release_sounding(&mut self, time: Instant) {
+        for note in 0..self.sounding.len() {
+            for channel in 0..16u8 {
+                if self.sounding[note] & (1 << channel) != 0 {
+                    self.send(&[0x80 | channel, note as u8, 0], time);
+                }
+            }
+        }
+    }
+}
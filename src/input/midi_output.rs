@@ -0,0 +1,78 @@
+use super::midi::{select_port, PortSelection};
+use crate::utils::logging;
+use log::{error, info, warn};
+use midir::{MidiOutput, MidiOutputConnection};
+use std::error::Error;
+
+/// Sends what the synth plays to a MIDI output port: notes from every source,
+/// including the sequencer and arpeggiator, and parameter changes as CCs.
+pub struct MidiOutputHandler {
+    connection: Option<MidiOutputConnection>,
+}
+
+impl MidiOutputHandler {
+    pub fn new(selection: &PortSelection) -> Self {
+        match Self::try_new(selection) {
+            Ok(handler) => handler,
+            Err(e) => {
+                warn!(
+                    target: logging::INPUT,
+                    "Failed to open MIDI output: {}. MIDI output will be disabled.",
+                    e
+                );
+                Self { connection: None }
+            }
+        }
+    }
+
+    fn try_new(selection: &PortSelection) -> Result<Self, Box<dyn Error>> {
+        let midi_out = MidiOutput::new("RustFMSynth Output")?;
+        let (connection, port_name) = match selection {
+            PortSelection::Virtual(name) => (Self::create_virtual(midi_out, name)?, name.clone()),
+            _ => {
+                let port = select_port(&midi_out, selection, "output")?;
+                let port_name = midi_out.port_name(&port)?;
+                (midi_out.connect(&port, "midir-write-output")?, port_name)
+            }
+        };
+        info!(target: logging::INPUT, "Opened MIDI output port: {}", port_name);
+        Ok(Self {
+            connection: Some(connection),
+        })
+    }
+
+    #[cfg(unix)]
+    fn create_virtual(
+        midi_out: MidiOutput,
+        name: &str,
+    ) -> Result<MidiOutputConnection, Box<dyn Error>> {
+        use midir::os::unix::VirtualOutput;
+        Ok(midi_out.create_virtual(name)?)
+    }
+
+    #[cfg(not(unix))]
+    fn create_virtual(
+        _midi_out: MidiOutput,
+        _name: &str,
+    ) -> Result<MidiOutputConnection, Box<dyn Error>> {
+        Err("Virtual MIDI ports aren't supported on this platform".into())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Send a channel message, trimmed to its length.
+    pub fn send(&mut self, message: &[u8; 3]) {
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        let len = match message[0] & 0xF0 {
+            0xC0 | 0xD0 => 2,
+            _ => 3,
+        };
+        if let Err(e) = connection.send(&message[..len]) {
+            error!(target: logging::INPUT, "Failed to send MIDI message: {}", e);
+        }
+    }
+}
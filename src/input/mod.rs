@@ -3,6 +3,18 @@ mod keyboard;
 #[cfg(not(target_arch = "wasm32"))]
 mod midi;
 #[cfg(not(target_arch = "wasm32"))]
+mod midi_file;
+#[cfg(not(target_arch = "wasm32"))]
+mod midi_output;
+#[cfg(not(target_arch = "wasm32"))]
+mod osc;
+#[cfg(not(target_arch = "wasm32"))]
 pub use self::keyboard::KeyboardHandler;
 #[cfg(not(target_arch = "wasm32"))]
-pub use self::midi::MidiHandler;
+pub use self::midi::{MidiHandler, PortSelection};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::midi_file::MidiFilePlayer;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::midi_output::MidiOutputHandler;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::osc::OscServer;
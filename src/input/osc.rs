@@ -0,0 +1,153 @@
+use crate::runtime::EngineCommand;
+use crate::synth::event::TimedEvent;
+use crate::synth::osc::{self, OscPacket, IMMEDIATELY};
+use crate::utils::logging;
+use log::{error, info, warn};
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::Sender;
+use std::time::{Instant, SystemTime};
+
+/// Large enough for any packet that fits in a UDP datagram.
+const MAX_PACKET_SIZE: usize = 65536;
+
+/// Receives OSC over UDP and forwards it to the engine (see `synth::osc` for
+/// the address space). Bundles timetagged in the future are held until they
+/// come due, then stamped with that instant so they land on the right frame.
+///
+/// Clients get parameter changes reported back once they send `/feedback 1`,
+/// or when added with `add_feedback_target`; `/feedback 0` unsubscribes.
+pub struct OscServer {
+    socket: UdpSocket,
+    command_sender: Sender<EngineCommand>,
+    scheduled: Vec<(Instant, TimedEvent)>, // Sorted by due time
+    feedback_targets: Vec<SocketAddr>,
+    buffer: Vec<u8>,
+}
+
+impl OscServer {
+    pub fn bind(
+        address: impl ToSocketAddrs,
+        command_sender: Sender<EngineCommand>,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        info!(target: logging::INPUT, "Listening for OSC on {}", socket.local_addr()?);
+        Ok(Self {
+            socket,
+            command_sender,
+            scheduled: Vec::new(),
+            feedback_targets: Vec::new(),
+            buffer: vec![0; MAX_PACKET_SIZE],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn add_feedback_target(&mut self, target: SocketAddr) {
+        if !self.feedback_targets.contains(&target) {
+            self.feedback_targets.push(target);
+        }
+    }
+
+    /// Handle every packet that has arrived, then send the scheduled events
+    /// that have come due.
+    pub fn update(&mut self) {
+        loop {
+            let (len, sender) = match self.socket.recv_from(&mut self.buffer) {
+                Ok(received) => received,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!(target: logging::INPUT, "OSC receive error: {}", e);
+                    break;
+                }
+            };
+            let received_at = Instant::now();
+            match OscPacket::decode(&self.buffer[..len]) {
+                Ok(packet) => self.handle_packet(packet, IMMEDIATELY, received_at, sender),
+                Err(e) => {
+                    warn!(target: logging::INPUT, "OSC packet from {} dropped: {}", sender, e)
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let due = self.scheduled.partition_point(|(time, _)| *time <= now);
+        for (time, event) in self.scheduled.drain(..due) {
+            let _ = self
+                .command_sender
+                .send(EngineCommand::new(event, Some(time)));
+        }
+    }
+
+    /// Report a parameter change to the feedback targets. The native runtime
+    /// passes every command on its way to the engine, so changes from MIDI
+    /// controllers are reported too.
+    pub fn report(&self, command: &EngineCommand) {
+        let TimedEvent::Param(event) = command.event else {
+            return;
+        };
+        if self.feedback_targets.is_empty() {
+            return;
+        }
+        let bytes = osc::param_message(&event).encode();
+        for target in &self.feedback_targets {
+            if let Err(e) = self.socket.send_to(&bytes, target) {
+                error!(target: logging::INPUT, "OSC feedback to {} failed: {}", target, e);
+            }
+        }
+    }
+
+    fn handle_packet(
+        &mut self,
+        packet: OscPacket,
+        timetag: u64,
+        received_at: Instant,
+        sender: SocketAddr,
+    ) {
+        match packet {
+            OscPacket::Bundle {
+                timetag: inner,
+                packets,
+            } => {
+                // Nested bundles without a time of their own follow the outer one
+                let timetag = if inner == IMMEDIATELY { timetag } else { inner };
+                for packet in packets {
+                    self.handle_packet(packet, timetag, received_at, sender);
+                }
+            }
+            OscPacket::Message(message) if message.address == "/feedback" => {
+                match message.args.first().and_then(|arg| arg.as_i32()) {
+                    Some(0) => self.feedback_targets.retain(|target| *target != sender),
+                    _ => self.add_feedback_target(sender),
+                }
+            }
+            OscPacket::Message(message) => match osc::message_to_event(&message) {
+                Ok(event) => self.schedule(event, timetag, received_at),
+                Err(e) => {
+                    warn!(target: logging::INPUT, "OSC message from {} ignored: {}", sender, e)
+                }
+            },
+        }
+    }
+
+    fn schedule(&mut self, event: TimedEvent, timetag: u64, received_at: Instant) {
+        let delay = osc::timetag_to_system_time(timetag)
+            .and_then(|due| due.duration_since(SystemTime::now()).ok());
+        match delay {
+            Some(delay) if !delay.is_zero() => {
+                let due = Instant::now() + delay;
+                let index = self.scheduled.partition_point(|(time, _)| *time <= due);
+                self.scheduled.insert(index, (due, event));
+            }
+            // Immediate, or already late
+            _ => {
+                let _ = self
+                    .command_sender
+                    .send(EngineCommand::new(event, Some(received_at)));
+            }
+        }
+    }
+}
@@ -6,4 +6,4 @@ pub use wasm::WasmSynth;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod native;
 #[cfg(not(target_arch = "wasm32"))]
-pub use native::NativeSynth;
+pub use native::{EngineCommand, NativeSynth};
@@ -1,49 +1,238 @@
 use crate::audio::{AudioBackend, CpalBackend};
-use crate::input::{KeyboardHandler, MidiHandler};
+use crate::input::{
+    KeyboardHandler, MidiFilePlayer, MidiHandler, MidiOutputHandler, OscServer, PortSelection,
+};
+use crate::synth::algorithm::FeedbackMode;
+use crate::synth::arpeggiator::ArpMode;
+use crate::synth::error::SynthError;
+use crate::synth::event::TimedEvent;
+use crate::synth::handoff::{BuildRequest, Handoff, Retired, HANDOFF_CAPACITY};
+use crate::synth::midi_out::MIDI_OUTPUT_CAPACITY;
+use crate::synth::mpe::MpeConfig;
 use crate::synth::note::NoteEvent;
 use crate::synth::operator::OperatorEvent;
+use crate::synth::params::ParamId;
+use crate::synth::preset::BankManager;
+use crate::synth::sequencer::Pattern;
+use crate::synth::smf::{MidiFile, PlaybackFilter};
+use crate::synth::transport::ClockSource;
+use crate::synth::tuning::{Tuning, TuningError};
+use crate::synth::voice_config::VoiceConfig;
 use crate::synth::Synth;
-use std::sync::mpsc::channel;
-use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
+use crate::utils::logging;
+use crate::utils::spsc::{self, Consumer, Producer};
+use crate::utils::triple_buffer::{triple_buffer, SnapshotReader, SnapshotWriter};
+use log::{error, info, warn};
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use std::sync::mpsc::{channel, Sender};
+use std::time::Instant;
+
+/// Maximum number of commands that can be queued between two audio callbacks.
+const COMMAND_QUEUE_CAPACITY: usize = 1024;
+
+/// Discrete events sent from the control thread to the audio thread.
+#[derive(Debug, Clone, Copy)]
+pub struct EngineCommand {
+    pub event: TimedEvent,
+    /// When the event happened. Timestamped events are placed at the matching
+    /// frame of the next buffer; untimed ones use the event's own frame offset.
+    pub timestamp: Option<Instant>,
+}
+
+impl EngineCommand {
+    pub fn new(event: impl Into<TimedEvent>, timestamp: Option<Instant>) -> Self {
+        Self {
+            event: event.into(),
+            timestamp,
+        }
+    }
+    /// A command for an event that is happening right now.
+    pub fn now(event: impl Into<TimedEvent>) -> Self {
+        Self::new(event, Some(Instant::now()))
+    }
+}
+
+/// Continuous engine parameters, published as a whole snapshot. The engine
+/// only applies the fields set since the last snapshot, so that it keeps
+/// changes made on the audio thread (program changes, MIDI learn, OSC) to
+/// the others.
+#[derive(Debug, Clone)]
+pub struct EngineParams {
+    pub master_volume: f32,
+    pub voice_config: VoiceConfig,
+    pub tuning: Tuning,
+    versions: ParamVersions,
+}
+
+/// How many times each `EngineParams` field has been set.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ParamVersions {
+    master_volume: u32,
+    voice_config: u32,
+    tuning: u32,
+}
+
+impl Default for EngineParams {
+    fn default() -> Self {
+        Self {
+            master_volume: 0.8,
+            voice_config: VoiceConfig::default(),
+            tuning: Tuning::default(),
+            versions: ParamVersions::default(),
+        }
+    }
+}
+
 /// Native runtime synth implementation for desktop/CPAL backends.
+/// Owned by the audio thread; it only talks to the control thread through
+/// lock-free queues.
 pub struct NativeSynth {
     synth: Synth,
-    note_receiver: Receiver<NoteEvent>,
-    operator_receiver: Receiver<OperatorEvent>,
+    commands: Consumer<EngineCommand>,
+    params: SnapshotReader<EngineParams>,
+    applied_versions: ParamVersions,
+    pending_events: Vec<TimedEvent>, // Preallocated to the command queue capacity
+    last_callback: Option<Instant>,
+    midi_output: Producer<[u8; 3]>,
+    build_requests: Producer<BuildRequest>,
+    handoffs: Consumer<Handoff>,
+    retired: Producer<Retired>,
+}
+
+/// Control-thread handle for a `NativeSynth` running on the audio thread.
+pub struct EngineController {
+    commands: Producer<EngineCommand>,
+    params: SnapshotWriter<EngineParams>,
+    current_params: EngineParams,
+    midi_output: Consumer<[u8; 3]>,
+    build_requests: Consumer<BuildRequest>,
+    handoffs: Producer<Handoff>,
+    retired: Consumer<Retired>,
+    presets: BankManager, // Copy of the synth's, to build program changes from
+}
+
+/// Create a connected controller/engine pair.
+pub fn engine(command_capacity: usize) -> (EngineController, NativeSynth) {
+    let (command_tx, command_rx) = spsc::channel(command_capacity);
+    let (params_tx, params_rx) = triple_buffer(EngineParams::default());
+    let (midi_output_tx, midi_output_rx) = spsc::channel(MIDI_OUTPUT_CAPACITY);
+    let (build_request_tx, build_request_rx) = spsc::channel(HANDOFF_CAPACITY);
+    let (handoff_tx, handoff_rx) = spsc::channel(HANDOFF_CAPACITY);
+    let (retired_tx, retired_rx) = spsc::channel(HANDOFF_CAPACITY);
+    let mut synth = Synth::new();
+    synth.set_deferred_builds(true);
+    (
+        EngineController {
+            commands: command_tx,
+            params: params_tx,
+            current_params: EngineParams::default(),
+            midi_output: midi_output_rx,
+            build_requests: build_request_rx,
+            handoffs: handoff_tx,
+            retired: retired_rx,
+            presets: BankManager::new(),
+        },
+        NativeSynth {
+            synth,
+            commands: command_rx,
+            params: params_rx,
+            applied_versions: ParamVersions::default(),
+            pending_events: Vec::with_capacity(command_capacity.max(1)),
+            last_callback: None,
+            midi_output: midi_output_tx,
+            build_requests: build_request_tx,
+            handoffs: handoff_rx,
+            retired: retired_tx,
+        },
+    )
 }
 
 impl NativeSynth {
-    pub fn new(
-        note_receiver: Receiver<NoteEvent>,
-        operator_receiver: Receiver<OperatorEvent>,
-    ) -> Self {
-        Self {
-            synth: Synth::new(),
-            note_receiver,
-            operator_receiver,
+    pub fn process(&mut self, output: &mut [f32], sample_rate: f32) {
+        let callback_time = Instant::now();
+        let previous_callback = self.last_callback.replace(callback_time);
+        self.apply_params();
+        while let Some(handoff) = self.handoffs.pop() {
+            self.synth.install(handoff);
+        }
+        self.collect_commands(previous_callback, output.len(), sample_rate);
+        self.synth
+            .process_with_events(output, sample_rate, &self.pending_events);
+        for message in self.synth.midi_output.drain() {
+            // Dropped if the control thread falls behind
+            let _ = self.midi_output.push(message);
+        }
+        for request in self.synth.drain_build_requests() {
+            // Likewise; the synth holds no more than one per part and effect slot
+            let _ = self.build_requests.push(request);
+        }
+        // Dropping these would free memory here, so wait for room instead
+        while self.retired.len() < self.retired.capacity() {
+            match self.synth.pop_retired() {
+                Some(retired) => {
+                    let _ = self.retired.push(retired);
+                }
+                None => break,
+            }
         }
     }
 
-    pub fn process(&mut self, output: &mut [f32], sample_rate: f32) {
-        self.process_note_events();
-        self.process_operator_events();
-        self.synth.process(output, sample_rate);
+    /// The engine's synth, for setup (e.g. loading sequencer patterns) before
+    /// it moves to the audio thread.
+    pub fn synth_mut(&mut self) -> &mut Synth {
+        &mut self.synth
     }
 
-    fn process_note_events(&mut self) {
-        while let Ok(event) = self.note_receiver.try_recv() {
-            if event.is_on {
-                self.synth.note_on(&event);
-            } else {
-                self.synth.note_off(&event);
-            }
+    fn apply_params(&mut self) {
+        let Some(params) = self.params.update() else {
+            return;
+        };
+        let applied = &mut self.applied_versions;
+        if params.versions.master_volume != applied.master_volume {
+            self.synth.set_master_volume(params.master_volume);
+        }
+        if params.versions.voice_config != applied.voice_config {
+            self.synth.set_voice_config(params.voice_config.clone());
         }
+        if params.versions.tuning != applied.tuning {
+            self.synth.set_tuning(&params.tuning);
+        }
+        *applied = params.versions;
     }
 
-    fn process_operator_events(&mut self) {
-        while let Ok(event) = self.operator_receiver.try_recv() {
-            self.synth.process_operator_events(&event);
+    /// Drain the command queue into `pending_events`, sorted by frame offset.
+    /// An event stamped during the previous callback period lands at the same relative
+    /// position in this buffer, trading a constant one-buffer latency for no jitter.
+    fn collect_commands(
+        &mut self,
+        previous_callback: Option<Instant>,
+        buffer_len: usize,
+        sample_rate: f32,
+    ) {
+        self.pending_events.clear();
+        let last_frame = buffer_len.saturating_sub(1);
+        while let Some(command) = self.commands.pop() {
+            let mut event = command.event;
+            if let (Some(timestamp), Some(previous)) = (command.timestamp, previous_callback) {
+                let elapsed = timestamp.saturating_duration_since(previous).as_secs_f32();
+                let offset = (elapsed * sample_rate) as usize;
+                event = event.with_frame_offset(offset.min(last_frame));
+            }
+            if self.pending_events.len() == self.pending_events.capacity() {
+                // Can't happen while the queue and this buffer share a capacity,
+                // but never grow the vector on the audio thread.
+                self.synth.handle_event(&event);
+                continue;
+            }
+            // Insertion sort: commands mostly arrive in order, and unlike
+            // `sort_by_key` this neither allocates nor reorders equal offsets.
+            let insert_at = self
+                .pending_events
+                .iter()
+                .rposition(|e| e.frame_offset() <= event.frame_offset())
+                .map_or(0, |i| i + 1);
+            self.pending_events.insert(insert_at, event);
         }
     }
     pub fn set_buffer_size(&mut self, buffer_size: usize) {
@@ -51,22 +240,459 @@ impl NativeSynth {
     }
 }
 
+impl EngineController {
+    /// Queue a command for the audio thread. Returns false if the queue is full.
+    pub fn send(&mut self, command: EngineCommand) -> bool {
+        if self.commands.push(command).is_err() {
+            warn!(target: logging::RUNTIME, "Engine command queue full, dropping {:?}", command);
+            return false;
+        }
+        true
+    }
+    pub fn send_note(&mut self, event: NoteEvent) -> bool {
+        self.send(EngineCommand::now(event))
+    }
+    pub fn send_operator_event(&mut self, event: OperatorEvent) -> bool {
+        self.send(EngineCommand::now(event))
+    }
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.current_params.master_volume = volume;
+        let versions = &mut self.current_params.versions;
+        versions.master_volume = versions.master_volume.wrapping_add(1);
+        self.params.publish(self.current_params.clone());
+    }
+    pub fn set_voice_config(&mut self, config: VoiceConfig) {
+        self.current_params.voice_config = config;
+        let versions = &mut self.current_params.versions;
+        versions.voice_config = versions.voice_config.wrapping_add(1);
+        self.params.publish(self.current_params.clone());
+    }
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.current_params.tuning = tuning;
+        self.publish_tuning();
+    }
+    pub fn set_reference_frequency(&mut self, frequency: f32) -> Result<(), TuningError> {
+        self.current_params
+            .tuning
+            .set_reference_frequency(frequency)?;
+        self.publish_tuning();
+        Ok(())
+    }
+    /// Retune from an MTS SysEx message. Returns the number of notes changed.
+    pub fn apply_mts_sysex(&mut self, message: &[u8]) -> Result<usize, TuningError> {
+        let changed = self.current_params.tuning.apply_mts_sysex(message)?;
+        if changed > 0 {
+            self.publish_tuning();
+        }
+        Ok(changed)
+    }
+    fn publish_tuning(&mut self) {
+        let versions = &mut self.current_params.versions;
+        versions.tuning = versions.tuning.wrapping_add(1);
+        self.params.publish(self.current_params.clone());
+    }
+    /// The parameters last set through this controller. The audio thread
+    /// may have changed some of them since.
+    pub fn params(&self) -> &EngineParams {
+        &self.current_params
+    }
+    /// The programs the synth has, for building its program changes. Call
+    /// with a copy of `Synth::presets` whenever they change.
+    pub fn set_presets(&mut self, presets: BankManager) {
+        self.presets = presets;
+    }
+    /// Build what the audio thread asked for and drop what it replaced. Call
+    /// regularly from the control loop: program changes and new reverbs only
+    /// take effect once built.
+    pub fn update(&mut self) {
+        while let Some(retired) = self.retired.pop() {
+            drop(retired);
+        }
+        while let Some(request) = self.build_requests.pop() {
+            let Some(handoff) = request.build(&self.presets) else {
+                continue;
+            };
+            if self.handoffs.push(handoff).is_err() {
+                warn!(target: logging::RUNTIME, "Handoff queue full, dropping {:?}", request);
+            }
+        }
+    }
+    /// Next message the synth played, for a MIDI output. Only produced once
+    /// the synth's `midi_output` is enabled.
+    pub fn poll_midi_output(&mut self) -> Option<[u8; 3]> {
+        self.midi_output.pop()
+    }
+}
+
+/// The value following the command line flag `name`, e.g. `44100` in `--rate 44100`.
+fn flag_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// Build the startup tuning from `--scl <file>`, `--kbm <file>` and
+/// `--reference-pitch <Hz>` command line flags.
+fn tuning_from_args(args: &[String]) -> Result<Option<Tuning>, TuningError> {
+    let mut tuning = match flag_value(args, "--scl") {
+        Some(scl) => {
+            Tuning::from_scala_files(Path::new(scl), flag_value(args, "--kbm").map(Path::new))?
+        }
+        None => Tuning::default(),
+    };
+    if let Some(pitch) = flag_value(args, "--reference-pitch") {
+        let frequency = pitch
+            .parse::<f32>()
+            .map_err(|_| TuningError::InvalidReferencePitch(pitch.to_string()))?;
+        tuning.set_reference_frequency(frequency)?;
+    }
+    Ok((tuning != Tuning::default()).then_some(tuning))
+}
+
+/// Load sequencer pattern 0 from `--pattern <file.json>`.
+fn load_pattern_from_args(args: &[String], synth: &mut Synth) -> Result<(), SynthError> {
+    if let Some(path) = flag_value(args, "--pattern") {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| SynthError::Io(format!("Failed to read pattern '{}': {}", path, e)))?;
+        synth.sequencer.set_pattern(0, Pattern::from_json(&json)?)?;
+        info!(target: logging::RUNTIME, "Loaded pattern '{}'; press Space to start/stop it", path);
+    }
+    Ok(())
+}
+
+/// Set the transport tempo from `--tempo <bpm>`, or follow MIDI clock with `--clock midi`.
+fn transport_from_args(args: &[String], synth: &mut Synth) -> Result<(), SynthError> {
+    if let Some(tempo) = flag_value(args, "--tempo") {
+        let bpm = tempo
+            .parse::<f32>()
+            .map_err(|_| SynthError::InvalidArgument(format!("tempo '{}'", tempo)))?;
+        synth.transport.set_tempo(bpm);
+    }
+    match flag_value(args, "--clock") {
+        Some("midi") => synth.set_clock_source(ClockSource::Midi),
+        Some("internal") | None => {}
+        Some(other) => return Err(SynthError::unknown("clock source", other)),
+    }
+    Ok(())
+}
+
+/// Load bank 0 from the patch files in `--presets <dir>` and start on program
+/// 0, or `--program <n>`. MIDI Bank Select and Program Change switch between them.
+fn presets_from_args(args: &[String], synth: &mut Synth) -> Result<(), SynthError> {
+    let Some(dir) = flag_value(args, "--presets") else {
+        return Ok(());
+    };
+    let count = synth.presets.load_directory(0, Path::new(dir))?;
+    info!(target: logging::RUNTIME, "Loaded {} patches from '{}'", count, dir);
+    let program = match flag_value(args, "--program") {
+        Some(program) => program
+            .parse::<u8>()
+            .map_err(|_| SynthError::InvalidArgument(format!("program '{}'", program)))?,
+        None => 0,
+    };
+    synth.program_change(0, program)?;
+    if let Some(patch) = synth.presets.patch(0, program) {
+        info!(target: logging::RUNTIME, "Program {}: {}", program, patch.name);
+    }
+    Ok(())
+}
+
+/// Split the keyboard at `--split <note>`: notes from there up play on a
+/// second part, with program `--split-program <n>` of bank 0 if given.
+fn split_from_args(args: &[String], synth: &mut Synth) -> Result<(), SynthError> {
+    let Some(note) = flag_value(args, "--split") else {
+        return Ok(());
+    };
+    let note = note
+        .parse::<u8>()
+        .ok()
+        .filter(|note| (1..=127).contains(note))
+        .ok_or_else(|| SynthError::InvalidArgument(format!("split note '{}'", note)))?;
+    let upper = synth.add_part(synth.config.max_voices);
+    synth.part_mut(0).unwrap().set_key_range(0, note - 1);
+    synth.part_mut(upper).unwrap().set_key_range(note, 127);
+    if let Some(program) = flag_value(args, "--split-program") {
+        let program = program
+            .parse::<u8>()
+            .map_err(|_| SynthError::InvalidArgument(format!("program '{}'", program)))?;
+        let result = synth
+            .select_part(upper)
+            .and_then(|()| synth.program_change(0, program));
+        synth.select_part(0)?;
+        result?;
+    }
+    info!(target: logging::RUNTIME, "Keyboard split at note {}", note);
+    Ok(())
+}
+
+/// Render cycles in the algorithm with `--feedback <one-sample|averaged|unrolled>`.
+fn feedback_mode_from_args(args: &[String], synth: &mut Synth) -> Result<(), SynthError> {
+    if let Some(mode) = flag_value(args, "--feedback") {
+        synth.set_feedback_mode(mode.parse::<FeedbackMode>()?)?;
+    }
+    Ok(())
+}
+
+/// Share voice rendering with `--threads <n>` worker threads.
+fn render_threads_from_args(args: &[String], synth: &mut Synth) -> Result<(), SynthError> {
+    if let Some(threads) = flag_value(args, "--threads") {
+        let workers = threads
+            .parse::<usize>()
+            .map_err(|_| SynthError::InvalidArgument(format!("thread count '{}'", threads)))?;
+        synth.set_render_threads(workers);
+        info!(target: logging::RUNTIME, "Rendering voices on {} worker threads", workers);
+    }
+    Ok(())
+}
+
+/// Configure the arpeggiator from `--arp <mode>`, `--arp-octaves <n>`,
+/// `--arp-rate <division>`, `--arp-gate <fraction>` and `--arp-latch`.
+fn arpeggiator_from_args(args: &[String], synth: &mut Synth) -> Result<(), SynthError> {
+    let Some(mode) = flag_value(args, "--arp") else {
+        return Ok(());
+    };
+    let arpeggiator = &mut synth.arpeggiator;
+    arpeggiator.set_mode(mode.parse::<ArpMode>()?);
+    if let Some(octaves) = flag_value(args, "--arp-octaves") {
+        let octaves = octaves
+            .parse::<u8>()
+            .map_err(|_| SynthError::InvalidArgument(format!("octave range '{}'", octaves)))?;
+        arpeggiator.set_octaves(octaves);
+    }
+    if let Some(rate) = flag_value(args, "--arp-rate") {
+        arpeggiator.set_rate(rate)?;
+    }
+    if let Some(gate) = flag_value(args, "--arp-gate") {
+        let gate = gate
+            .parse::<f32>()
+            .map_err(|_| SynthError::InvalidArgument(format!("gate '{}'", gate)))?;
+        arpeggiator.set_gate(gate);
+    }
+    synth.set_arpeggiator_latch(args.iter().any(|arg| arg == "--arp-latch"));
+    synth.set_arpeggiator_enabled(true);
+    info!(target: logging::RUNTIME, "Arpeggiator on: {}", mode);
+    Ok(())
+}
+
+/// Parse a comma-separated list of numbers counted from 1, as `--midi-tracks 1,3`.
+fn one_based_list(list: &str, max: usize) -> Result<Vec<usize>, SynthError> {
+    list.split(',')
+        .map(|item| match item.trim().parse::<usize>() {
+            Ok(n) if (1..=max).contains(&n) => Ok(n - 1),
+            _ => Err(SynthError::InvalidArgument(format!(
+                "entry '{}' in '{}' (1-{})",
+                item, list, max
+            ))),
+        })
+        .collect()
+}
+
+/// Play a MIDI file from `--midi-file <file.mid>`, optionally limited to
+/// `--midi-tracks <1,2,..>` and `--midi-channels <1-16,..>`, and looping with
+/// `--midi-loop <start>:<end>` in quarter notes.
+fn midi_file_player_from_args(
+    args: &[String],
+    command_sender: Sender<EngineCommand>,
+) -> Result<Option<MidiFilePlayer>, SynthError> {
+    let Some(path) = flag_value(args, "--midi-file") else {
+        return Ok(None);
+    };
+    let file = MidiFile::from_file(Path::new(path))?;
+    let mut filter = PlaybackFilter::all();
+    if let Some(tracks) = flag_value(args, "--midi-tracks") {
+        filter = filter.with_tracks(one_based_list(tracks, file.tracks.len())?);
+    }
+    if let Some(channels) = flag_value(args, "--midi-channels") {
+        let channels: Vec<u8> = one_based_list(channels, 16)?
+            .into_iter()
+            .map(|channel| channel as u8)
+            .collect();
+        filter = filter.with_channels(&channels);
+    }
+    let mut player = MidiFilePlayer::new(&file, &filter, command_sender);
+    if let Some(range) = flag_value(args, "--midi-loop") {
+        let (start, end) = range
+            .split_once(':')
+            .and_then(|(start, end)| Some((start.parse::<f64>().ok()?, end.parse::<f64>().ok()?)))
+            .ok_or_else(|| {
+                SynthError::InvalidArgument(format!("loop '{}', expected <start>:<end>", range))
+            })?;
+        player.set_loop(&file, start, end)?;
+    }
+    info!(
+        target: logging::RUNTIME,
+        "Playing '{}': {} tracks, {:.1} s",
+        path,
+        file.tracks.len(),
+        file.duration_seconds()
+    );
+    Ok(Some(player))
+}
+
+/// Listen for OSC on `--osc <port>` (or `<address:port>`), reporting
+/// parameter changes to `--osc-feedback <address:port>` as well as to clients
+/// that ask for them.
+fn osc_server_from_args(
+    args: &[String],
+    command_sender: Sender<EngineCommand>,
+) -> Result<Option<OscServer>, SynthError> {
+    let Some(address) = flag_value(args, "--osc") else {
+        return Ok(None);
+    };
+    let address = match address.parse::<u16>() {
+        Ok(port) => format!("0.0.0.0:{}", port),
+        Err(_) => address.to_string(),
+    };
+    let mut server = OscServer::bind(address.as_str(), command_sender)
+        .map_err(|e| SynthError::Io(format!("Failed to listen for OSC on {}: {}", address, e)))?;
+    if let Some(target) = flag_value(args, "--osc-feedback") {
+        let target = target
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| {
+                SynthError::InvalidArgument(format!("OSC feedback address '{}'", target))
+            })?;
+        server.add_feedback_target(target);
+    }
+    Ok(Some(server))
+}
+
+/// Log with `--log <filter>`, or the `RUSTFMSYNTH_LOG` environment variable,
+/// e.g. `--log debug,audio=warn`.
+fn init_logging(args: &[String]) {
+    let filter = flag_value(args, "--log")
+        .map(str::to_string)
+        .or_else(|| std::env::var("RUSTFMSYNTH_LOG").ok());
+    let result = logging::init(filter.as_deref().unwrap_or(logging::DEFAULT_FILTER));
+    if let Err(e) = result {
+        // An invalid filter changes nothing, so fall back to the default
+        let _ = logging::set_filter(logging::DEFAULT_FILTER);
+        error!(target: logging::RUNTIME, "Invalid log filter: {}", e);
+    }
+}
+
 pub fn start() {
-    let (note_tx, note_rx) = channel();
-    let (op_tx, op_rx) = channel();
+    let (command_tx, command_rx) = channel();
+
+    let args: Vec<String> = std::env::args().collect();
+    init_logging(&args);
+    let (mut controller, mut synth) = engine(COMMAND_QUEUE_CAPACITY);
+    if let Err(e) = load_pattern_from_args(&args, synth.synth_mut()) {
+        error!(target: logging::RUNTIME, "{}", e);
+    }
+    if let Err(e) = transport_from_args(&args, synth.synth_mut()) {
+        error!(target: logging::RUNTIME, "{}", e);
+    }
+    if let Err(e) = arpeggiator_from_args(&args, synth.synth_mut()) {
+        error!(target: logging::RUNTIME, "{}", e);
+    }
+    if let Err(e) = presets_from_args(&args, synth.synth_mut()) {
+        error!(target: logging::RUNTIME, "{}", e);
+    }
+    controller.set_presets(synth.synth_mut().presets.clone());
+    if let Err(e) = split_from_args(&args, synth.synth_mut()) {
+        error!(target: logging::RUNTIME, "{}", e);
+    }
+    if let Err(e) = feedback_mode_from_args(&args, synth.synth_mut()) {
+        error!(target: logging::RUNTIME, "{}", e);
+    }
+    if let Err(e) = render_threads_from_args(&args, synth.synth_mut()) {
+        error!(target: logging::RUNTIME, "{}", e);
+    }
+    match tuning_from_args(&args) {
+        Ok(Some(tuning)) => {
+            info!(target: logging::RUNTIME, "Using tuning: {}", tuning.scale().description);
+            controller.set_tuning(tuning);
+        }
+        Ok(None) => {}
+        Err(e) => error!(target: logging::RUNTIME, "{}. Using 12-TET.", e),
+    }
 
-    let synth = Arc::new(Mutex::new(NativeSynth::new(note_rx, op_rx)));
+    // Ports are picked by name with `--midi-in <pattern>` and `--midi-out <pattern>`;
+    // `virtual` or `virtual:<name>` creates a port for other applications instead.
+    let input_port = flag_value(&args, "--midi-in").map_or(PortSelection::Prompt, |arg| {
+        PortSelection::from_arg(arg, "RustFMSynth Input")
+    });
+    let mut midi_output = flag_value(&args, "--midi-out")
+        .map(|arg| MidiOutputHandler::new(&PortSelection::from_arg(arg, "RustFMSynth Output")))
+        .filter(MidiOutputHandler::is_connected);
+    if midi_output.is_some() {
+        synth.synth_mut().midi_output.set_enabled(true);
+    }
 
-    let mut audio_backend = CpalBackend::new(synth.clone());
+    let mut audio_backend = CpalBackend::new(synth);
     audio_backend.start();
 
-    let mut keyboard_handler = KeyboardHandler::new(note_tx.clone(), op_tx.clone());
-    let mut midi_handler = MidiHandler::new(note_tx);
+    let mut midi_file_player = match midi_file_player_from_args(&args, command_tx.clone()) {
+        Ok(player) => player,
+        Err(e) => {
+            error!(target: logging::RUNTIME, "{}", e);
+            None
+        }
+    };
+    let mut keyboard_handler = KeyboardHandler::new(command_tx.clone());
+    let mut midi_handler = MidiHandler::with_port(command_tx.clone(), &input_port);
+    if let Some(path) = flag_value(&args, "--midi-map") {
+        if let Err(e) = midi_handler.load_controller_profile(Path::new(path)) {
+            error!(target: logging::RUNTIME, "{}", e);
+        }
+    }
+    if let Some(ids) = flag_value(&args, "--midi-learn") {
+        match ids
+            .split(',')
+            .map(|id| id.parse::<ParamId>())
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(ids) => midi_handler.learn(&ids),
+            Err(e) => error!(target: logging::RUNTIME, "{}", e),
+        }
+    }
+    if args.iter().any(|arg| arg == "--mpe") {
+        midi_handler.set_mpe_config(MpeConfig::lower_zone());
+    }
 
+    let mut osc_server = match osc_server_from_args(&args, command_tx.clone()) {
+        Ok(server) => server,
+        Err(e) => {
+            error!(target: logging::RUNTIME, "{}", e);
+            None
+        }
+    };
+    if let Some(player) = &mut midi_file_player {
+        player.play();
+    }
+
+    // Input handlers may live on several threads, so they feed this control loop
+    // through an mpsc channel; only this loop touches the single-producer queue.
     loop {
-        {
-            keyboard_handler.update();
-            midi_handler.update();
+        keyboard_handler.update();
+        midi_handler.update();
+        if let Some(player) = &mut midi_file_player {
+            player.update();
+        }
+        if let Some(server) = &mut osc_server {
+            server.update();
+        }
+        while let Ok(command) = command_rx.try_recv() {
+            if let Some(server) = &osc_server {
+                server.report(&command);
+            }
+            controller.send(command);
+        }
+        controller.update();
+        if let Some(output) = &mut midi_output {
+            while let Some(message) = controller.poll_midi_output() {
+                output.send(&message);
+            }
+        }
+        while let Some(message) = midi_handler.poll_sysex() {
+            match controller.apply_mts_sysex(&message) {
+                Ok(changed) => info!(target: logging::RUNTIME, "MTS retuned {} notes", changed),
+                Err(TuningError::UnsupportedSysEx) => {}
+                Err(e) => error!(target: logging::RUNTIME, "{}", e),
+            }
         }
     }
 }
@@ -1,9 +1,22 @@
+use crate::synth::algorithm::FeedbackMode;
+use crate::synth::arpeggiator::ArpMode;
+use crate::synth::clock::{ClockEvent, ClockMessage};
 use crate::synth::core::EffectSlot;
+use crate::synth::error::SynthError;
+use crate::synth::event::TimedEvent;
 use crate::synth::filter::{Filter, FilterType};
+use crate::synth::midi::MidiDecoder;
+use crate::synth::midi_learn::{ControlCurve, MidiLearn};
+use crate::synth::mpe::MpeZone;
 use crate::synth::note::{NoteEvent, NoteSource};
+use crate::synth::params::{ParamId, ParamScale};
+use crate::synth::part::Part;
+use crate::synth::sequencer::Pattern;
+use crate::synth::transport::ClockSource;
+use crate::synth::tuning::Tuning;
 use crate::synth::waveform::Waveform;
 use crate::synth::Synth;
-use core::str;
+use crate::utils::logging;
 use js_sys::Float32Array;
 use serde::Deserialize;
 use serde_wasm_bindgen;
@@ -22,7 +35,6 @@ use wasm_bindgen::prelude::*;
 #[derive(Deserialize, Debug, Clone)]
 pub struct LowPassParams {
     cutoff: f32,
-    #[allow(dead_code)]
     q: f32,
 }
 #[derive(Deserialize, Debug, Clone)]
@@ -54,26 +66,55 @@ pub struct WasmSynth {
     synth: Synth,
     temp_buffer: Vec<f32>,
     sample_rate: f32,
+    scheduled_events: Vec<TimedEvent>, // Applied at their frame offset by the next render
+    tuning: Tuning,
+    midi_decoder: MidiDecoder,
+    midi_learn: MidiLearn,
 }
 
+/// Errors are thrown as JS `Error`s carrying the error's message.
 #[wasm_bindgen]
 impl WasmSynth {
     #[wasm_bindgen(constructor)]
     pub fn new(sample_rate: f32) -> WasmSynth {
+        // Logs to the browser console; the default filter always parses
+        let _ = logging::init(logging::DEFAULT_FILTER);
         WasmSynth {
             synth: Synth::new(),
             temp_buffer: Vec::new(),
             sample_rate,
+            scheduled_events: Vec::new(),
+            tuning: Tuning::default(),
+            midi_decoder: MidiDecoder::new(NoteSource::Midi),
+            midi_learn: MidiLearn::new(),
         }
     }
 
+    /// Set which messages reach the console, e.g. `"debug,audio=warn"`.
+    /// Categories are `audio`, `synth`, `input` and `runtime`.
+    #[wasm_bindgen]
+    pub fn set_log_filter(&mut self, filter: &str) -> Result<(), JsError> {
+        Ok(logging::set_filter(filter)?)
+    }
+
     /// Render audio buffer into a JS-friendly Float32Array
     #[wasm_bindgen]
     pub fn render(&mut self, length: usize, sample_rate: f32) -> Float32Array {
         if self.temp_buffer.len() != length {
             self.temp_buffer = vec![0.0; length];
         }
-        self.synth.process(&mut self.temp_buffer, sample_rate);
+        // Stable sort keeps same-frame events in the order they were scheduled
+        self.scheduled_events.sort_by_key(|e| e.frame_offset());
+        let due = self
+            .scheduled_events
+            .partition_point(|e| e.frame_offset() < length);
+        self.synth
+            .process_with_events(&mut self.temp_buffer, sample_rate, &self.scheduled_events[..due]);
+        // Events beyond this render stay queued, shifted into the next one
+        self.scheduled_events.drain(..due);
+        for event in self.scheduled_events.iter_mut() {
+            *event = event.with_frame_offset(event.frame_offset() - length);
+        }
 
         // Return a view without copy
         Float32Array::from(self.temp_buffer.as_slice())
@@ -92,42 +133,81 @@ impl WasmSynth {
             self.synth.note_off(&event);
         }
     }
+    /// Schedule a note on `frame_offset` frames into the next rendered buffer.
+    #[wasm_bindgen]
+    pub fn schedule_note_on(&mut self, note: u8, velocity: u8, frame_offset: usize) {
+        if let Ok(event) = NoteEvent::new(note, velocity, true, NoteSource::Midi) {
+            self.scheduled_events
+                .push(event.with_frame_offset(frame_offset).into());
+        }
+    }
+
+    /// Schedule a note off `frame_offset` frames into the next rendered buffer.
+    #[wasm_bindgen]
+    pub fn schedule_note_off(&mut self, note: u8, frame_offset: usize) {
+        if let Ok(event) = NoteEvent::new(note, 0, false, NoteSource::Midi) {
+            self.scheduled_events
+                .push(event.with_frame_offset(frame_offset).into());
+        }
+    }
     #[wasm_bindgen]
     pub fn set_master_volume(&mut self, volume: f32) {
         self.synth.set_master_volume(volume);
     }
 
     #[wasm_bindgen]
-    pub fn set_operator_ratio(&mut self, operator_index: usize, ratio: f32) {
-        self.synth.set_operator_ratio(operator_index, ratio);
+    pub fn set_operator_ratio(&mut self, operator_index: usize, ratio: f32) -> Result<(), JsError> {
+        self.synth.set_operator_ratio(operator_index, ratio)?;
+        Ok(())
     }
 
     #[wasm_bindgen]
-    pub fn set_operator_fixed_frequency(&mut self, operator_index: usize, frequency: f32) {
+    pub fn set_operator_fixed_frequency(
+        &mut self,
+        operator_index: usize,
+        frequency: f32,
+    ) -> Result<(), JsError> {
         self.synth
-            .set_operator_fixed_frequency(operator_index, frequency);
+            .set_operator_fixed_frequency(operator_index, frequency)?;
+        Ok(())
     }
     #[wasm_bindgen]
-    pub fn set_operator_detune(&mut self, operator_index: usize, detune: f32) {
-        self.synth.set_operator_detune(operator_index, detune);
+    pub fn set_operator_detune(
+        &mut self,
+        operator_index: usize,
+        detune: f32,
+    ) -> Result<(), JsError> {
+        self.synth.set_operator_detune(operator_index, detune)?;
+        Ok(())
     }
     #[wasm_bindgen]
-    pub fn set_operator_filter(&mut self, operator_index: usize, params_bytes: &[u8]) {
+    pub fn set_operator_filter(
+        &mut self,
+        operator_index: usize,
+        params_bytes: &[u8],
+    ) -> Result<(), JsError> {
         let filter_params: FilterParams = serde_json::from_slice(params_bytes)
-            .expect("PANIC: Tagged JSON deserialize FilterParams failed");
+            .map_err(|e| SynthError::InvalidJson(e.to_string()))?;
 
         let filter = match filter_params {
-            FilterParams::LowPass(p) => Filter::new_lowpass_biquad(p.cutoff, self.sample_rate),
+            FilterParams::LowPass(p) => {
+                Filter::new_lowpass_biquad_with_q(p.cutoff, p.q, self.sample_rate)
+            }
             FilterParams::Comb(p) => Filter::new_comb(p.alpha, p.k),
             FilterParams::PitchedComb(p) => Filter::new_pitched_comb(p.alpha),
         };
 
-        self.synth.set_operator_filter(operator_index, filter);
+        self.synth.set_operator_filter(operator_index, filter)?;
+        Ok(())
     }
     #[wasm_bindgen]
-    pub fn set_effect_reverb(&mut self, params_bytes: &[u8], effect_slot: usize) {
+    pub fn set_effect_reverb(
+        &mut self,
+        params_bytes: &[u8],
+        effect_slot: usize,
+    ) -> Result<(), JsError> {
         let reverb_params: ReverbParams = serde_json::from_slice(params_bytes)
-            .expect("PANIC: Tagged JSON deserialize ReverbParams failed");
+            .map_err(|e| SynthError::InvalidJson(e.to_string()))?;
         let ReverbParams {
             predelay_ms,
             decay_ms,
@@ -142,7 +222,8 @@ impl WasmSynth {
                 3 => EffectSlot::Three,
                 _ => EffectSlot::One,
             },
-        )
+        );
+        Ok(())
     }
     #[wasm_bindgen]
     pub fn remove_effect(&mut self, effect_slot: usize) {
@@ -153,50 +234,485 @@ impl WasmSynth {
         });
     }
     #[wasm_bindgen]
-    pub fn remove_operator_filter(&mut self, operator_index: usize, filter_type_bytes: &[u8]) {
-        let filter_type_str = str::from_utf8(filter_type_bytes);
-        let filter_type = match filter_type_str {
-            Ok("LowPass") => FilterType::LowPassBiquad,
-            Ok("Comb") => FilterType::Comb,
-            Ok("PitchedComb") => FilterType::PitchedComb,
-            _ => {
-                eprintln!("WasmSynth Error: Invalid filter type received");
-                return;
-            }
+    pub fn remove_operator_filter(
+        &mut self,
+        operator_index: usize,
+        filter_type_bytes: &[u8],
+    ) -> Result<(), JsError> {
+        let filter_type_str = String::from_utf8_lossy(filter_type_bytes);
+        let filter_type = match filter_type_str.as_ref() {
+            "LowPass" => FilterType::LowPassBiquad,
+            "Comb" => FilterType::Comb,
+            "PitchedComb" => FilterType::PitchedComb,
+            other => return Err(SynthError::unknown("filter type", other).into()),
         };
         self.synth
-            .remove_operator_filter(operator_index, filter_type);
+            .remove_operator_filter(operator_index, filter_type)?;
+        Ok(())
     }
     #[wasm_bindgen]
-    pub fn set_operator_envelope(&mut self, operator_index: usize, a: f32, d: f32, s: f32, r: f32) {
-        self.synth.set_operator_envelope(operator_index, a, d, s, r);
+    pub fn set_operator_envelope(
+        &mut self,
+        operator_index: usize,
+        a: f32,
+        d: f32,
+        s: f32,
+        r: f32,
+    ) -> Result<(), JsError> {
+        self.synth
+            .set_operator_envelope(operator_index, a, d, s, r)?;
+        Ok(())
+    }
+    /// Set the amplitude envelope that new notes apply on top of the operator
+    /// envelopes. A release of `Infinity` leaves the release to the operators.
+    #[wasm_bindgen]
+    pub fn set_voice_envelope(&mut self, a: f32, d: f32, s: f32, r: f32) -> Result<(), JsError> {
+        let mut config = self.synth.voice_config.clone();
+        config.set_envelope(a, d, s, r)?;
+        self.synth.set_voice_config(config);
+        Ok(())
     }
     /// Set the waveform for a specific operator using an integer code from JS.
-    /// Mapping: 0: Sine, 1: Triangle, 2: Square, 3: Sawtooth, 4: Noise
-    #[wasm_bindgen]
-    pub fn set_operator_waveform(&mut self, operator_index: usize, waveform_value: u8) {
-        let waveform = match waveform_value {
-            0 => Waveform::Sine,
-            1 => Waveform::Triangle,
-            2 => Waveform::Square,
-            3 => Waveform::Sawtooth,
-            4 => Waveform::Noise,
-            5 => Waveform::Input,
-            _ => {
-                eprintln!(
-                    "WasmSynth Error: Invalid waveform value received: {}",
-                    waveform_value
-                );
-                Waveform::Sine // Defaulting to Sine on invalid input
+    /// Mapping: 0: Sine, 1: Triangle, 2: Square, 3: Sawtooth, 4: Noise, 5: Input, 6: SawtoothSmooth
+    #[wasm_bindgen]
+    pub fn set_operator_waveform(
+        &mut self,
+        operator_index: usize,
+        waveform_value: u8,
+    ) -> Result<(), JsError> {
+        let waveform = Waveform::from_index(waveform_value)
+            .ok_or(SynthError::InvalidWaveform(waveform_value))?;
+        self.synth.set_operator_waveform(operator_index, waveform)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_operator_modulation_index(
+        &mut self,
+        operator_index: usize,
+        modulation_index: f32,
+    ) -> Result<(), JsError> {
+        self.synth
+            .set_operator_modulation_index(operator_index, modulation_index)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_operator_feedback(
+        &mut self,
+        operator_index: usize,
+        feedback: f32,
+    ) -> Result<(), JsError> {
+        self.synth.set_operator_feedback(operator_index, feedback)?;
+        Ok(())
+    }
+
+    /// How cycles in the algorithm are rendered: `one-sample`, `averaged` or `unrolled`.
+    /// Throws if a part's matrix is too dense to unroll; that part keeps its mode.
+    #[wasm_bindgen]
+    pub fn set_feedback_mode(&mut self, mode: &str) -> Result<(), JsError> {
+        self.synth
+            .set_feedback_mode(mode.parse::<FeedbackMode>()?)?;
+        Ok(())
+    }
+
+    /// Describe every parameter as a JSON array of
+    /// `{ id, name, unit, min, max, default, scale }` objects.
+    #[wasm_bindgen]
+    pub fn get_param_infos(&self) -> String {
+        let infos: Vec<serde_json::Value> = self
+            .synth
+            .params()
+            .iter()
+            .map(|info| {
+                let scale = match info.scale {
+                    ParamScale::Linear => serde_json::json!({ "type": "linear" }),
+                    ParamScale::Exponential => serde_json::json!({ "type": "exponential" }),
+                    ParamScale::Power(exponent) => {
+                        serde_json::json!({ "type": "power", "exponent": exponent })
+                    }
+                    ParamScale::Stepped => serde_json::json!({ "type": "stepped" }),
+                };
+                serde_json::json!({
+                    "id": info.id.to_string(),
+                    "name": info.name,
+                    "unit": info.unit,
+                    "min": info.min,
+                    "max": info.max,
+                    "default": info.default,
+                    "scale": scale,
+                })
+            })
+            .collect();
+        serde_json::Value::Array(infos).to_string()
+    }
+
+    /// Set a parameter by its registry ID, e.g. `op/0/ratio`.
+    #[wasm_bindgen]
+    pub fn set_param(&mut self, id: &str, value: f32) -> Result<(), JsError> {
+        self.synth.set_param(id.parse::<ParamId>()?, value)?;
+        Ok(())
+    }
+
+    /// Current value of a parameter, or NaN if the ID is unknown or unavailable.
+    #[wasm_bindgen]
+    pub fn get_param(&self, id: &str) -> f32 {
+        id.parse::<ParamId>()
+            .and_then(|id| self.synth.get_param(id))
+            .unwrap_or(f32::NAN)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_param_normalized(&mut self, id: &str, normalized: f32) -> Result<(), JsError> {
+        self.synth
+            .set_param_normalized(id.parse::<ParamId>()?, normalized)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_param_normalized(&self, id: &str) -> f32 {
+        id.parse::<ParamId>()
+            .and_then(|id| self.synth.get_param_normalized(id))
+            .unwrap_or(f32::NAN)
+    }
+
+    /// Switch to a Scala tuning. `kbm` may be empty for the default mapping,
+    /// which keeps the current reference pitch.
+    #[wasm_bindgen]
+    pub fn set_tuning(&mut self, scl: &str, kbm: &str) -> Result<(), JsError> {
+        let kbm = (!kbm.trim().is_empty()).then_some(kbm);
+        let reference_frequency = self.tuning.reference_frequency();
+        let mut tuning = Tuning::from_scala(scl, kbm)?;
+        if kbm.is_none() {
+            tuning.set_reference_frequency(reference_frequency)?;
+        }
+        self.tuning = tuning;
+        self.synth.set_tuning(&self.tuning);
+        Ok(())
+    }
+
+    /// Back to 12-TET, keeping the current reference pitch.
+    #[wasm_bindgen]
+    pub fn reset_tuning(&mut self) {
+        let reference_frequency = self.tuning.reference_frequency();
+        self.tuning = Tuning::default();
+        let _ = self.tuning.set_reference_frequency(reference_frequency);
+        self.synth.set_tuning(&self.tuning);
+    }
+
+    /// Set the frequency of the reference note (A4 unless a .kbm says otherwise).
+    #[wasm_bindgen]
+    pub fn set_reference_pitch(&mut self, frequency: f32) -> Result<(), JsError> {
+        self.tuning.set_reference_frequency(frequency)?;
+        self.synth.set_tuning(&self.tuning);
+        Ok(())
+    }
+
+    /// Handle a raw MIDI message from Web MIDI: learned controllers, notes,
+    /// MPE expression and configuration, and SysEx. Throws if a learned
+    /// controller's parameter can't be set, after handling the rest.
+    #[wasm_bindgen]
+    pub fn midi_message(&mut self, message: &[u8]) -> Result<(), JsError> {
+        self.handle_midi(message, None)
+    }
+
+    /// Like `midi_message`, but notes and expression take effect
+    /// `frame_offset` frames into the next rendered buffer. Learned
+    /// controllers and SysEx still apply straight away.
+    #[wasm_bindgen]
+    pub fn schedule_midi_message(
+        &mut self,
+        message: &[u8],
+        frame_offset: usize,
+    ) -> Result<(), JsError> {
+        self.handle_midi(message, Some(frame_offset))
+    }
+
+    fn handle_midi(&mut self, message: &[u8], frame_offset: Option<usize>) -> Result<(), JsError> {
+        if message.first() == Some(&0xF0) {
+            return self.midi_sysex(message);
+        }
+        // A mapped or just-learned controller doesn't also reach the decoder
+        if let Some((id, normalized)) = self.midi_learn.handle_message(message) {
+            self.synth.set_param_normalized(id, normalized)?;
+        } else if let Some(event) = self.midi_decoder.decode(message) {
+            match frame_offset {
+                Some(offset) => self.scheduled_events.push(event.with_frame_offset(offset)),
+                None => self.synth.handle_event(&event),
             }
-        };
-        self.synth.set_operator_waveform(operator_index, waveform);
+        }
+        Ok(())
+    }
+
+    /// Bind `id` to the next controller that moves, over `min..max` of its
+    /// normalized range. `curve` is "linear", "exponential" or "logarithmic".
+    #[wasm_bindgen]
+    pub fn start_midi_learn(
+        &mut self,
+        id: &str,
+        min: f32,
+        max: f32,
+        curve: &str,
+    ) -> Result<(), JsError> {
+        let id = id.parse::<ParamId>()?;
+        let curve = curve.parse::<ControlCurve>()?;
+        self.midi_learn.start_learning(id, min, max, curve);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn cancel_midi_learn(&mut self) {
+        self.midi_learn.cancel_learning();
+    }
+
+    /// The parameter waiting for a controller, or an empty string.
+    #[wasm_bindgen]
+    pub fn midi_learning(&self) -> String {
+        self.midi_learn
+            .learning()
+            .map_or_else(String::new, |id| id.to_string())
+    }
+
+    /// MIDI mappings as a JSON array, to save with a patch or controller profile.
+    #[wasm_bindgen]
+    pub fn get_midi_mappings(&self) -> String {
+        self.midi_learn.to_json()
+    }
+
+    /// Replace the MIDI mappings with a JSON array from `get_midi_mappings`.
+    #[wasm_bindgen]
+    pub fn set_midi_mappings(&mut self, json: &str) -> Result<(), JsError> {
+        self.midi_learn.load_json(json)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_midi_mapping(&mut self, id: &str) -> Result<(), JsError> {
+        self.midi_learn.unmap(id.parse::<ParamId>()?);
+        Ok(())
+    }
+
+    /// Replace `bank` with patches in the `default-patches.json` shape, for
+    /// MIDI Bank Select and Program Change to choose from.
+    #[wasm_bindgen]
+    pub fn load_preset_bank(&mut self, bank: u16, json: &str) -> Result<(), JsError> {
+        self.synth.presets.import_json(bank, json)?;
+        Ok(())
+    }
+
+    /// A bank as JSON in the `default-patches.json` shape.
+    #[wasm_bindgen]
+    pub fn export_preset_bank(&self, bank: u16) -> String {
+        self.synth.presets.export_json(bank)
+    }
+
+    #[wasm_bindgen]
+    pub fn program_change(&mut self, bank: u16, program: u8) -> Result<(), JsError> {
+        self.synth.program_change(bank, program)?;
+        Ok(())
+    }
+
+    /// The current program as JSON `{ bank, program, patch }`, or `null`.
+    #[wasm_bindgen]
+    pub fn current_program(&self) -> String {
+        self.synth.presets.current_json().to_string()
+    }
+
+    /// Add a part that plays on every channel, key and velocity; returns its
+    /// index. Parameter changes go to the selected part.
+    #[wasm_bindgen]
+    pub fn add_part(&mut self, max_voices: usize) -> usize {
+        self.synth.add_part(max_voices)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_part(&mut self, part: usize) -> Result<(), JsError> {
+        self.synth.remove_part(part)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn select_part(&mut self, part: usize) -> Result<(), JsError> {
+        self.synth.select_part(part)?;
+        Ok(())
+    }
+
+    /// Listen on one MIDI channel (0-15), or all of them with -1.
+    #[wasm_bindgen]
+    pub fn set_part_channel(&mut self, part: usize, channel: i32) -> Result<(), JsError> {
+        self.part_mut(part)?.set_channel(u8::try_from(channel).ok());
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_part_key_range(&mut self, part: usize, low: u8, high: u8) -> Result<(), JsError> {
+        self.part_mut(part)?.set_key_range(low, high);
+        Ok(())
     }
 
     #[wasm_bindgen]
-    pub fn set_operator_modulation_index(&mut self, operator_index: usize, modulation_index: f32) {
+    pub fn set_part_velocity_range(
+        &mut self,
+        part: usize,
+        low: u8,
+        high: u8,
+    ) -> Result<(), JsError> {
+        self.part_mut(part)?.set_velocity_range(low, high);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_part_effect_send(&mut self, part: usize, send: f32) -> Result<(), JsError> {
+        self.part_mut(part)?.set_effect_send(send);
+        Ok(())
+    }
+
+    fn part_mut(&mut self, index: usize) -> Result<&mut Part, SynthError> {
+        let parts = self.synth.parts().len();
         self.synth
-            .set_operator_modulation_index(operator_index, modulation_index);
+            .part_mut(index)
+            .ok_or(SynthError::PartOutOfRange { index, parts })
+    }
+
+    /// Configure an MPE zone; zero member channels disables it.
+    #[wasm_bindgen]
+    pub fn set_mpe_zone(&mut self, upper: bool, member_channels: u8) {
+        let mut config = self.midi_decoder.mpe_config().clone();
+        let zone = if upper { MpeZone::Upper } else { MpeZone::Lower };
+        config.set_zone(zone, member_channels);
+        self.midi_decoder.set_mpe_config(config);
+    }
+
+    /// Handle a SysEx message; MTS tuning messages retune the synth.
+    #[wasm_bindgen]
+    pub fn midi_sysex(&mut self, message: &[u8]) -> Result<(), JsError> {
+        if self.tuning.apply_mts_sysex(message)? > 0 {
+            self.synth.set_tuning(&self.tuning);
+        }
+        Ok(())
+    }
+
+    /// Store a sequencer pattern from JSON; see `Pattern::from_json` for the format.
+    #[wasm_bindgen]
+    pub fn set_sequencer_pattern(
+        &mut self,
+        index: usize,
+        pattern_json: &str,
+    ) -> Result<(), JsError> {
+        let pattern = Pattern::from_json(pattern_json)?;
+        self.synth.sequencer.set_pattern(index, pattern)?;
+        self.synth.reserve_sequencer_actions();
+        Ok(())
+    }
+
+    /// Set the order patterns play in; an empty chain loops pattern 0.
+    #[wasm_bindgen]
+    pub fn set_sequencer_chain(&mut self, chain: &[u32]) -> Result<(), JsError> {
+        let chain = chain.iter().map(|&i| i as usize).collect();
+        self.synth.sequencer.set_chain(chain)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_sequencer_swing(&mut self, swing: f32) {
+        self.synth.sequencer.set_swing(swing);
+    }
+
+    #[wasm_bindgen]
+    pub fn sequencer_step(&self) -> usize {
+        self.synth.sequencer.current_step()
+    }
+
+    #[wasm_bindgen]
+    pub fn set_arpeggiator_enabled(&mut self, enabled: bool) {
+        self.synth.set_arpeggiator_enabled(enabled);
+    }
+
+    /// One of `up`, `down`, `up-down`, `random`, `as-played` or `chord`.
+    #[wasm_bindgen]
+    pub fn set_arpeggiator_mode(&mut self, mode: &str) -> Result<(), JsError> {
+        self.synth.arpeggiator.set_mode(mode.parse::<ArpMode>()?);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_arpeggiator_octaves(&mut self, octaves: u8) {
+        self.synth.arpeggiator.set_octaves(octaves);
+    }
+
+    /// Step length as a note division, e.g. `1/16` or `1/8t`.
+    #[wasm_bindgen]
+    pub fn set_arpeggiator_rate(&mut self, division: &str) -> Result<(), JsError> {
+        self.synth.arpeggiator.set_rate(division)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_arpeggiator_gate(&mut self, gate: f32) {
+        self.synth.arpeggiator.set_gate(gate);
+    }
+
+    #[wasm_bindgen]
+    pub fn set_arpeggiator_latch(&mut self, latch: bool) {
+        self.synth.set_arpeggiator_latch(latch);
+    }
+
+    /// Internal tempo for the sequencer and arpeggiator.
+    #[wasm_bindgen]
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.synth.transport.set_tempo(bpm);
+    }
+
+    /// Follow MIDI clock from `midi_message` instead of the internal tempo.
+    #[wasm_bindgen]
+    pub fn set_clock_source(&mut self, follow_midi_clock: bool) {
+        self.synth.set_clock_source(if follow_midi_clock {
+            ClockSource::Midi
+        } else {
+            ClockSource::Internal
+        });
+    }
+
+    /// Start the transport from the top `frame_offset` frames into the next rendered buffer.
+    #[wasm_bindgen]
+    pub fn start_transport(&mut self, frame_offset: usize) {
+        self.schedule_clock(ClockMessage::Start, frame_offset);
+    }
+
+    #[wasm_bindgen]
+    pub fn stop_transport(&mut self, frame_offset: usize) {
+        self.schedule_clock(ClockMessage::Stop, frame_offset);
+    }
+
+    /// Carry on from the current song position.
+    #[wasm_bindgen]
+    pub fn continue_transport(&mut self, frame_offset: usize) {
+        self.schedule_clock(ClockMessage::Continue, frame_offset);
+    }
+
+    fn schedule_clock(&mut self, message: ClockMessage, frame_offset: usize) {
+        self.scheduled_events.push(
+            ClockEvent::new(message)
+                .with_frame_offset(frame_offset)
+                .into(),
+        );
+    }
+
+    /// Transport state as JSON: `playing`, `source`, `bpm`, `beats` and `songPosition`.
+    #[wasm_bindgen]
+    pub fn transport_state(&self) -> String {
+        let state = self.synth.transport_state();
+        serde_json::json!({
+            "playing": state.playing,
+            "source": match state.source {
+                ClockSource::Internal => "internal",
+                ClockSource::Midi => "midi",
+            },
+            "bpm": state.tempo_bpm,
+            "beats": state.beats,
+            "songPosition": state.song_position(),
+        })
+        .to_string()
     }
 
     #[wasm_bindgen]
@@ -209,18 +725,10 @@ impl WasmSynth {
     /// Expects a JsValue representing a number[][] (specifically Vec<Vec<u32>>).
     /// Dimensions: opCount x (opCount + 1)
     #[wasm_bindgen]
-    pub fn set_algorithm(&mut self, combined_matrix_js: JsValue) {
-        match serde_wasm_bindgen::from_value::<Vec<Vec<u32>>>(combined_matrix_js) {
-            Ok(combined_matrix) => {
-                // Pass a slice reference (&[Vec<u32>]) as expected by Synth::set_algorithm
-                self.synth.set_algorithm(&combined_matrix);
-            }
-            Err(e) => {
-                eprintln!(
-                    "WasmSynth Error: Failed to deserialize algorithm matrix: {}",
-                    e
-                );
-            }
-        }
+    pub fn set_algorithm(&mut self, combined_matrix_js: JsValue) -> Result<(), JsError> {
+        let combined_matrix = serde_wasm_bindgen::from_value::<Vec<Vec<u32>>>(combined_matrix_js)
+            .map_err(|e| SynthError::InvalidMatrix(e.to_string()))?;
+        self.synth.set_algorithm(&combined_matrix)?;
+        Ok(())
     }
 }
@@ -1,7 +1,24 @@
 use super::context::ProcessContext;
 use super::envelope::EnvelopeGenerator;
+use super::error::SynthError;
 use super::operator::OperatorState;
-use crate::synth::prelude::{HashMap, HashSet};
+use crate::synth::prelude::{Entry, HashMap, HashSet};
+use crate::utils::logging;
+use log::{debug, error, warn};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Most nodes `FeedbackMode::Unrolled` may copy a matrix out into. Copying
+/// cycles out grows exponentially with the operators in them, so a dense
+/// matrix would otherwise take forever to build and never render in time.
+const MAX_UNROLLED_NODES: usize = 512;
+
+fn too_many_nodes() -> SynthError {
+    SynthError::AlgorithmTooLarge {
+        max_nodes: MAX_UNROLLED_NODES,
+    }
+}
 
 // --- Internal Node ---
 
@@ -9,6 +26,25 @@ use crate::synth::prelude::{HashMap, HashSet};
 struct UnrolledNode {
     original_op_index: usize,
     input_node_indices: Vec<usize>,
+    feedback_node_indices: Vec<usize>, // Read one sample late; see `FeedbackMode`
+}
+
+/// One operator render in the execution plan. The plan lists steps so that
+/// every node comes after its forward inputs.
+#[derive(Debug, Clone)]
+struct PlanStep {
+    node: usize,
+    operator: usize,
+    inputs: Range<usize>,   // Into `Algorithm::plan_inputs`
+    feedback: Range<usize>, // Into `Algorithm::plan_inputs`
+}
+
+/// A modulation input of a plan step. Only inputs with a connection in the
+/// matrix make it into the plan.
+#[derive(Debug, Clone, Copy)]
+struct PlanInput {
+    node: usize,
+    source_operator: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -32,32 +68,161 @@ impl Default for ConnectionParams {
         }
     }
 }
+impl ConnectionParams {
+    /// Gain of the connection at sample `i` of the buffer being rendered.
+    fn gain_at(&self, context: &ProcessContext, i: usize) -> f32 {
+        let time_on =
+            (context.samples_elapsed_since_trigger + i as u64) as f32 / context.sample_rate;
+        let time_off = context
+            .note_off_sample_index
+            .map(|n| (n + i as u64) as f32 / context.sample_rate);
+        let env_value = self
+            .modulation_envelope
+            .as_ref()
+            .map(|e| e.evaluate(time_on, time_off))
+            .unwrap_or(1.0);
+        self.scale * env_value
+    }
+    /// The gain over the whole buffer, if it doesn't follow an envelope.
+    fn constant_gain(&self) -> Option<f32> {
+        self.modulation_envelope.is_none().then_some(self.scale)
+    }
+}
+
+/// How cycles in the matrix, including operators that modulate themselves,
+/// are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FeedbackMode {
+    /// Copy each cycle out into a chain of extra operators (see
+    /// `add_repeat_rule`). Only approximates feedback, at the cost of an extra
+    /// operator per copy.
+    #[default]
+    Unrolled,
+    /// The edge that closes a cycle carries the previous output sample,
+    /// scaled by the receiving operator's feedback amount. Opt in per part
+    /// with `Synth::set_feedback_mode`, or per patch with `feedbackMode`.
+    OneSample,
+    /// Like `OneSample`, but feeding back the average of the last two samples
+    /// as the DX7 does, which stops high feedback from hunting at Nyquist.
+    Averaged,
+}
+
+impl FeedbackMode {
+    /// Stable integer code for the parameter registry.
+    pub fn index(&self) -> u8 {
+        match self {
+            FeedbackMode::Unrolled => 0,
+            FeedbackMode::OneSample => 1,
+            FeedbackMode::Averaged => 2,
+        }
+    }
+    pub fn from_index(index: u8) -> Option<Self> {
+        match index {
+            0 => Some(FeedbackMode::Unrolled),
+            1 => Some(FeedbackMode::OneSample),
+            2 => Some(FeedbackMode::Averaged),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FeedbackMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeedbackMode::Unrolled => write!(f, "unrolled"),
+            FeedbackMode::OneSample => write!(f, "one-sample"),
+            FeedbackMode::Averaged => write!(f, "averaged"),
+        }
+    }
+}
+
+impl FromStr for FeedbackMode {
+    type Err = SynthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unrolled" => Ok(FeedbackMode::Unrolled),
+            "one-sample" => Ok(FeedbackMode::OneSample),
+            "averaged" => Ok(FeedbackMode::Averaged),
+            _ => Err(SynthError::unknown("feedback mode", s)),
+        }
+    }
+}
+
+/// Per-voice working memory for `Algorithm::process`, sized ahead of time so
+/// rendering doesn't allocate.
+#[derive(Debug, Default)]
+pub struct AlgorithmScratch {
+    node_outputs: Vec<f32>, // Output of each unrolled node, `buffer_size` samples apart
+    modulation: Vec<f32>,   // Summed modulation input for the node being evaluated
+    num_nodes: usize,
+    buffer_size: usize,
+}
+impl AlgorithmScratch {
+    pub fn new(num_nodes: usize, buffer_size: usize) -> Self {
+        let mut scratch = Self::default();
+        scratch.resize(num_nodes, buffer_size);
+        scratch
+    }
+    pub fn resize(&mut self, num_nodes: usize, buffer_size: usize) {
+        self.num_nodes = num_nodes;
+        self.buffer_size = buffer_size;
+        self.node_outputs.clear();
+        self.node_outputs.resize(num_nodes * buffer_size, 0.0);
+        self.modulation.resize(buffer_size, 0.0);
+    }
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+    fn fits(&self, num_nodes: usize, buffer_size: usize) -> bool {
+        self.num_nodes == num_nodes && buffer_size <= self.buffer_size
+    }
+    fn node_output(&self, node_idx: usize, len: usize) -> &[f32] {
+        &self.node_outputs[node_idx * self.buffer_size..][..len]
+    }
+}
+
+/// Search state of an operator while building the feedback graph, or of a
+/// node while compiling the plan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    OnPath,
+    Done(usize), // Node index, or step index when compiling the plan
+}
 
 // --- Algorithm ---
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Algorithm {
     matrix: Vec<Vec<Option<ConnectionParams>>>, // Adjacency matrix
     carriers: Vec<usize>,                       // Carrier operator indices
     repeat_rules: Vec<FeedbackLoop>,            // Repeat rules for feedback loops
     unrolled_nodes: Vec<UnrolledNode>,          // Unrolled graph structure
+    feedback_mode: FeedbackMode,
+    has_feedback_paths: bool, // Whether any node reads another one sample late
+    plan: Vec<PlanStep>,      // Execution order, compiled from `unrolled_nodes`
+    plan_inputs: Vec<PlanInput>,
+    carrier_nodes: Vec<usize>, // Node summed into the output for each carrier
 }
 
 impl Algorithm {
     pub fn new(
         matrix: Vec<Vec<Option<ConnectionParams>>>,
         carriers: Vec<usize>,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, SynthError> {
         let num_ops = matrix.len();
         if num_ops > 0 && !matrix.iter().all(|row| row.len() == num_ops) {
-            return Err("Adjacency matrix must be square.".to_string());
+            return Err(SynthError::InvalidMatrix(
+                "adjacency matrix must be square".to_string(),
+            ));
         }
         if let Some(&max_carrier) = carriers.iter().max() {
             if max_carrier >= num_ops {
-                return Err(format!(
-                    "Carrier index {} out of bounds for {} operators.",
-                    max_carrier, num_ops
-                ));
+                return Err(SynthError::OperatorOutOfRange {
+                    index: max_carrier,
+                    operators: num_ops,
+                });
             }
         }
 
@@ -66,36 +231,71 @@ impl Algorithm {
             carriers,
             repeat_rules: Vec::new(),
             unrolled_nodes: Vec::new(),
+            feedback_mode: FeedbackMode::default(),
+            has_feedback_paths: false,
+            plan: Vec::new(),
+            plan_inputs: Vec::new(),
+            carrier_nodes: Vec::new(),
         };
-        algo.rebuild_unrolled_graph();
+        algo.rebuild_unrolled_graph()?;
         Ok(algo)
     }
     pub fn get_carrier_indices(&self) -> &Vec<usize> {
         &self.carriers
     }
     pub fn get_modulator_indices(&self, operator_index: usize) -> Vec<usize> {
-        let mut modulator_indices = Vec::new();
-        for (i, row) in self.matrix.iter().enumerate() {
-            if i != operator_index && row[operator_index].is_some() {
-                modulator_indices.push(i);
+        self.modulators_of(operator_index).collect()
+    }
+    /// Non-allocating version of `get_modulator_indices`.
+    pub fn modulators_of(&self, operator_index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.matrix
+            .iter()
+            .enumerate()
+            .filter(move |(i, row)| *i != operator_index && row[operator_index].is_some())
+            .map(|(i, _)| i)
+    }
+    pub fn feedback_mode(&self) -> FeedbackMode {
+        self.feedback_mode
+    }
+    /// Switch how cycles are rendered. Voices must pick up the new structure
+    /// through `Voice::update_algorithm`. A matrix too dense to unroll is an
+    /// error, and keeps the current mode.
+    pub fn set_feedback_mode(&mut self, mode: FeedbackMode) -> Result<(), SynthError> {
+        if mode != self.feedback_mode {
+            let previous = std::mem::replace(&mut self.feedback_mode, mode);
+            if let Err(e) = self.rebuild_unrolled_graph() {
+                self.feedback_mode = previous;
+                return Err(e);
             }
         }
-        modulator_indices
+        Ok(())
     }
-    pub fn add_repeat_rule(&mut self, from_node: usize, to_node: usize, count: usize) {
+    /// Duplicate part of the unrolled graph to approximate a feedback loop.
+    /// Only used in `FeedbackMode::Unrolled`; the other modes follow cycles
+    /// in the matrix.
+    pub fn add_repeat_rule(
+        &mut self,
+        from_node: usize,
+        to_node: usize,
+        count: usize,
+    ) -> Result<(), SynthError> {
         self.repeat_rules.push(FeedbackLoop {
             from_node,
             to_node,
             count,
         });
-        self.rebuild_unrolled_graph();
+        if let Err(e) = self.rebuild_unrolled_graph() {
+            self.repeat_rules.pop();
+            return Err(e);
+        }
+        Ok(())
     }
     pub fn finished(&self, nodes: &[OperatorState]) -> bool {
-        let unrolled_carrier_indices = self.find_unrolled_carrier_indices();
-        unrolled_carrier_indices
+        self.carrier_nodes
             .iter()
-            .all(|&carrier| nodes[carrier].finished)
+            .all(|&node_idx| nodes.get(node_idx).is_none_or(|state| state.finished))
     }
+    #[cfg(test)]
     fn find_unrolled_carrier_indices(&self) -> Vec<usize> {
         // Return mapping indices of unrolled nodes that correspond to carriers
         let mut carrier_indices = Vec::new();
@@ -111,69 +311,74 @@ impl Algorithm {
     ///   if Source operator modulates Target operator (value >= 1 means connected).
     /// - `[source_index][ui_op_count]` indicates if Source operator is a carrier/output
     ///   (value >= 1 means it is).
-    pub fn set_matrix(&mut self, combined_matrix_from_ui: &[Vec<u32>]) -> Result<(), String> {
-        assert_eq!(
-            combined_matrix_from_ui.len(),
-            combined_matrix_from_ui[0].len() - 1,
-        );
+    ///
+    /// The UI may show fewer operators than the algorithm has; the rest are left
+    /// unconnected. An empty matrix leaves the algorithm unchanged. A matrix that
+    /// isn't `ui_op_count` rows of `ui_op_count + 1`, has more operators than the
+    /// algorithm, or is too dense to unroll is an error, and changes nothing.
+    pub fn set_matrix(&mut self, combined_matrix_from_ui: &[Vec<u32>]) -> Result<(), SynthError> {
         let ui_op_count = combined_matrix_from_ui.len();
-        let synth_op_count = self.matrix.len();
 
         if ui_op_count == 0 {
             return Ok(());
         }
+        if ui_op_count > self.matrix.len() {
+            return Err(SynthError::InvalidMatrix(format!(
+                "{} operators, the algorithm only has {}",
+                ui_op_count,
+                self.matrix.len()
+            )));
+        }
+        if let Some((row_index, row)) = combined_matrix_from_ui
+            .iter()
+            .enumerate()
+            .find(|(_, row)| row.len() != ui_op_count + 1)
+        {
+            return Err(SynthError::InvalidMatrix(format!(
+                "row {} has {} columns, expected {} (one per operator plus the output)",
+                row_index,
+                row.len(),
+                ui_op_count + 1
+            )));
+        }
 
         // --- Determine New Carriers from UI Matrix *before* modifying state ---
-        let mut new_carriers: Vec<usize> = Vec::new();
-        println!("{:?}", combined_matrix_from_ui);
-        println!("\nNext matrix\n");
-        for i in 0..ui_op_count {
-            // Bounds check UI matrix's last column
-            if i >= combined_matrix_from_ui.len() || ui_op_count >= combined_matrix_from_ui[i].len()
-            {
-                continue;
-            }
+        let new_carriers: Vec<usize> = (0..ui_op_count)
+            .filter(|&i| combined_matrix_from_ui[i][ui_op_count] >= 1)
+            .collect();
+        debug!(
+            target: logging::SYNTH,
+            "Setting matrix {:?} with carriers {:?}", combined_matrix_from_ui, new_carriers
+        );
 
-            if combined_matrix_from_ui[i][ui_op_count] >= 1 {
-                // Check the last column
-                println!("Adding new carrier: {}", i);
-                new_carriers.push(i);
-            }
-        }
+        let previous = (
+            self.matrix.clone(),
+            self.carriers.clone(),
+            self.repeat_rules.clone(),
+        );
 
         // --- Update Internal Connection Matrix ---
-        // ... (existing logic to update self.matrix based on UI matrix and zero out others) ...
-        // TODO: debug this logic if carrier is not operator 1
-        for i in 0..synth_op_count {
-            for j in 0..synth_op_count {
-                if i >= self.matrix.len() || j >= self.matrix[i].len() {
-                    continue;
-                }
-                if i < ui_op_count && j < ui_op_count {
-                    if combined_matrix_from_ui[i][j] >= 1 {
-                        if self.matrix[i][j].is_none() {
-                            self.matrix[i][j] = Some(ConnectionParams::default());
-                        }
-                    } else {
-                        self.matrix[i][j] = None;
-                    }
+        for (i, row) in self.matrix.iter_mut().enumerate() {
+            for (j, connection) in row.iter_mut().enumerate() {
+                if i < ui_op_count && j < ui_op_count && combined_matrix_from_ui[i][j] >= 1 {
+                    connection.get_or_insert_with(ConnectionParams::default);
                 } else {
-                    self.matrix[i][j] = None;
+                    *connection = None;
                 }
             }
         }
 
-        // --- Set Carriers (already calculated and validated non-empty) ---
-        new_carriers.sort_unstable(); // Sort is still good practice
-        new_carriers.dedup();
+        // --- Set Carriers (ascending, one per operator) ---
         self.carriers = new_carriers;
 
         // --- Clear Old Feedback Rules ---
         self.repeat_rules.clear();
 
         // --- Call the helper function to rebuild the graph ---
-        // This only runs if the carrier check passed
-        self.rebuild_unrolled_graph();
+        if let Err(e) = self.rebuild_unrolled_graph() {
+            (self.matrix, self.carriers, self.repeat_rules) = previous;
+            return Err(e);
+        }
 
         Ok(())
     }
@@ -185,35 +390,125 @@ impl Algorithm {
         from_operator: usize,
         to_operator: usize,
         params: ConnectionParams,
-    ) -> Result<(), String> {
-        if from_operator >= self.matrix.len() || to_operator >= self.matrix.len() {
-            return Err("Operator index out of bounds.".to_string());
+    ) -> Result<(), SynthError> {
+        let operators = self.matrix.len();
+        if let Some(index) = [from_operator, to_operator]
+            .into_iter()
+            .find(|&index| index >= operators)
+        {
+            return Err(SynthError::OperatorOutOfRange { index, operators });
+        }
+        let previous = self.matrix[from_operator][to_operator].replace(params);
+        if let Err(e) = self.rebuild_unrolled_graph() {
+            self.matrix[from_operator][to_operator] = previous;
+            return Err(e);
         }
-        self.matrix[from_operator][to_operator] = Some(params);
-        self.rebuild_unrolled_graph();
         Ok(())
     }
     pub fn length(&self) -> usize {
         self.unrolled_nodes.len()
     }
+    /// The operator each node renders, in node order.
+    pub fn node_operators(&self) -> impl Iterator<Item = usize> + '_ {
+        self.unrolled_nodes
+            .iter()
+            .map(|node| node.original_op_index)
+    }
 
-    fn rebuild_unrolled_graph(&mut self) {
-        let build_result =
-            Self::build_unrolled_graph(&self.matrix, &self.carriers, &self.repeat_rules);
-
-        match build_result {
-            Ok(nodes) => {
-                self.unrolled_nodes = nodes; // Assign the new nodes
+    /// Rebuild the graph and plan from the matrix. On error the previous
+    /// graph is kept, for the caller to restore the settings it was built from.
+    fn rebuild_unrolled_graph(&mut self) -> Result<(), SynthError> {
+        let nodes = match self.feedback_mode {
+            FeedbackMode::Unrolled => {
+                Self::build_unrolled_graph(&self.matrix, &self.carriers, &self.repeat_rules)?
             }
-            Err(e) => {
-                // Log error both ways for visibility
-                eprintln!("Error building unrolled graph: {}", e);
-                self.unrolled_nodes.clear(); // Clear nodes on error to prevent panic later
+            FeedbackMode::OneSample | FeedbackMode::Averaged => {
+                Self::build_feedback_graph(&self.matrix, &self.carriers)
+            }
+        };
+        self.unrolled_nodes = nodes;
+        self.has_feedback_paths = self
+            .unrolled_nodes
+            .iter()
+            .any(|node| !node.feedback_node_indices.is_empty());
+        self.compile_plan();
+        Ok(())
+    }
+
+    /// Flattens the unrolled graph into `plan`: a depth-first post-order over
+    /// the nodes, so rendering is a single pass with no recursion. Also picks
+    /// the node each carrier is heard through.
+    fn compile_plan(&mut self) {
+        self.plan.clear();
+        self.plan_inputs.clear();
+        let mut visits = vec![Visit::New; self.unrolled_nodes.len()];
+        let mut stack = Vec::new();
+        for root_idx in 0..self.unrolled_nodes.len() {
+            stack.push(root_idx);
+            while let Some(&node_idx) = stack.last() {
+                match visits[node_idx] {
+                    Visit::New => {
+                        visits[node_idx] = Visit::OnPath;
+                        // Reversed so the first input is rendered first
+                        let inputs = &self.unrolled_nodes[node_idx].input_node_indices;
+                        stack.extend(
+                            inputs
+                                .iter()
+                                .rev()
+                                .filter(|&&input_idx| visits[input_idx] == Visit::New),
+                        );
+                    }
+                    Visit::OnPath => {
+                        stack.pop();
+                        visits[node_idx] = Visit::Done(self.plan.len());
+                        self.push_plan_step(node_idx);
+                    }
+                    // Reached again through another path before it was rendered
+                    Visit::Done(_) => {
+                        stack.pop();
+                    }
+                }
             }
         }
+
+        self.carrier_nodes = self
+            .carriers
+            .iter()
+            .filter_map(|&op_idx| {
+                self.unrolled_nodes
+                    .iter()
+                    .position(|node| node.original_op_index == op_idx)
+            })
+            .collect();
     }
 
-    pub fn default_stack_2(num_operators: usize) -> Result<Self, String> {
+    fn push_plan_step(&mut self, node_idx: usize) {
+        let node = &self.unrolled_nodes[node_idx];
+        let operator = node.original_op_index;
+        let mut push_inputs = |input_indices: &[usize]| {
+            let start = self.plan_inputs.len();
+            for &input_idx in input_indices {
+                let source_operator = self.unrolled_nodes[input_idx].original_op_index;
+                if self.matrix[source_operator][operator].is_some() {
+                    self.plan_inputs.push(PlanInput {
+                        node: input_idx,
+                        source_operator,
+                    });
+                }
+            }
+            start..self.plan_inputs.len()
+        };
+        let inputs = push_inputs(&node.input_node_indices);
+        let feedback = push_inputs(&node.feedback_node_indices);
+        self.plan.push(PlanStep {
+            node: node_idx,
+            operator,
+            inputs,
+            feedback,
+        });
+    }
+
+    pub fn default_stack_2(num_operators: usize) -> Result<Self, SynthError> {
         if num_operators < 2 {
             return Self::default_simple(num_operators);
         }
@@ -221,7 +516,7 @@ impl Algorithm {
         matrix[1][0] = Some(ConnectionParams::default());
         Self::new(matrix, vec![0])
     }
-    pub fn default_fanout_feedback(num_operators: usize) -> Result<Self, String> {
+    pub fn default_fanout_feedback(num_operators: usize) -> Result<Self, SynthError> {
         if num_operators < 4 {
             return Self::default_simple(num_operators);
         }
@@ -230,10 +525,10 @@ impl Algorithm {
         matrix[3][1] = Some(ConnectionParams::default()); // Mod → B
         matrix[2][1] = Some(ConnectionParams::default()); // Extra mod → B
         let mut algo = Self::new(matrix, vec![0, 1])?;
-        algo.add_repeat_rule(3, 3, 1);
+        algo.add_repeat_rule(3, 3, 1)?;
         Ok(algo)
     }
-    pub fn default_dual_stack(num_operators: usize) -> Result<Self, String> {
+    pub fn default_dual_stack(num_operators: usize) -> Result<Self, SynthError> {
         if num_operators < 4 {
             return Self::default_simple(num_operators);
         }
@@ -242,7 +537,7 @@ impl Algorithm {
         matrix[3][1] = Some(ConnectionParams::default()); // Modulator B -> Carrier B
         Self::new(matrix, vec![0, 1])
     }
-    pub fn default_fanout(num_operators: usize) -> Result<Self, String> {
+    pub fn default_fanout(num_operators: usize) -> Result<Self, SynthError> {
         if num_operators < 3 {
             return Self::default_simple(num_operators);
         }
@@ -251,7 +546,7 @@ impl Algorithm {
         matrix[2][1] = Some(ConnectionParams::default());
         Self::new(matrix, vec![0, 1])
     }
-    pub fn stack_3_feedback(num_operators: usize) -> Result<Self, String> {
+    pub fn stack_3_feedback(num_operators: usize) -> Result<Self, SynthError> {
         if num_operators < 3 {
             return Self::default_simple(num_operators);
         }
@@ -264,25 +559,25 @@ impl Algorithm {
 
         // Add a repeat rule to structurally duplicate B → C chain once after C
         // Resulting chain: A → B → C → A → B → C
-        algo.add_repeat_rule(2, 1, 1); // from C (2), back to B (1), repeat 1 time
+        algo.add_repeat_rule(2, 1, 1)?; // from C (2), back to B (1), repeat 1 time
 
         Ok(algo)
     }
 
-    pub fn default_simple(num_operators: usize) -> Result<Self, String> {
+    pub fn default_simple(num_operators: usize) -> Result<Self, SynthError> {
         let matrix = vec![vec![None; num_operators]; num_operators];
         let carriers = if num_operators > 0 { vec![0] } else { vec![] };
         Self::new(matrix, carriers)
     }
 
-    pub fn default_feedback_1(num_operators: usize) -> Result<Self, String> {
+    pub fn default_feedback_1(num_operators: usize) -> Result<Self, SynthError> {
         if num_operators < 1 {
             return Self::default_simple(num_operators);
         }
         let matrix = vec![vec![None; num_operators]; num_operators];
         // TODO: check if this is correct. When I added Repeat Rules did I remove the matrix > 1 -> feedback?
         let mut alg = Self::new(matrix, vec![0])?;
-        alg.add_repeat_rule(0, 0, 1);
+        alg.add_repeat_rule(0, 0, 1)?;
         Ok(alg)
     }
 
@@ -290,6 +585,7 @@ impl Algorithm {
         &self,
         context: &ProcessContext,
         node_states: &mut [OperatorState],
+        scratch: &mut AlgorithmScratch,
         output: &mut [f32],
     ) {
         let buffer_size = output.len();
@@ -303,120 +599,187 @@ impl Algorithm {
         }
 
         // --- Scratch Buffers for Operator Outputs ---
-        let mut scratch_buffers: Vec<Vec<f32>> = self
-            .unrolled_nodes
-            .iter()
-            .map(|_| vec![0.0; buffer_size])
-            .collect();
-
-        // --- Visited flags to prevent reprocessing nodes in this buffer ---
-        // If the graph has cycles after unrolling (shouldn't happen?) or diamonds,
-        // we only want to process each node_idx once.
-        let mut visited = vec![false; self.unrolled_nodes.len()];
-
-        // --- Evaluate Graph - Ensure correct order by iterating ---
-        // Evaluate ALL nodes first to ensure dependencies are met before summing carriers.
-        for node_idx in 0..self.unrolled_nodes.len() {
-            self.evaluate_node_recursive(
-                context,
-                node_idx,
-                node_states,
-                &mut scratch_buffers,
-                &mut visited, // Pass visited flags
+        // Normally sized by the voice ahead of time; growing here is only a fallback.
+        if !scratch.fits(self.unrolled_nodes.len(), buffer_size) {
+            scratch.resize(
+                self.unrolled_nodes.len(),
+                buffer_size.max(scratch.buffer_size()),
             );
         }
 
+        if self.has_feedback_paths {
+            self.evaluate_per_sample(context, node_states, scratch, buffer_size);
+        } else {
+            self.evaluate_plan(context, node_states, scratch, buffer_size);
+        }
+
         // --- Sum Carrier Outputs ---
         output.fill(0.0);
-        for &carrier_op_original_idx in &self.carriers {
-            for (node_idx, node) in self.unrolled_nodes.iter().enumerate() {
-                if node.original_op_index == carrier_op_original_idx {
-                    let carrier_output = &scratch_buffers[node_idx];
-                    for i in 0..buffer_size {
-                        output[i] += carrier_output[i];
-                    }
-                    break;
-                }
+        for &node_idx in &self.carrier_nodes {
+            let carrier_output = scratch.node_output(node_idx, buffer_size);
+            for (sample, carrier_sample) in output.iter_mut().zip(carrier_output) {
+                *sample += carrier_sample;
             }
         }
     }
 
-    // Renamed to avoid confusion with the previous evaluate_node structure
-    fn evaluate_node_recursive(
+    /// Render the plan a whole buffer at a time, each node after its inputs.
+    fn evaluate_plan(
         &self,
         context: &ProcessContext,
-        node_idx: usize,
         node_states: &mut [OperatorState],
-        scratch_buffers: &mut [Vec<f32>],
-        visited: &mut [bool], // Track visited nodes for this buffer evaluation
+        scratch: &mut AlgorithmScratch,
+        buffer_size: usize,
     ) {
-        // If node already processed in this buffer call, skip.
-        if visited[node_idx] {
-            return;
-        }
-
-        let node = &self.unrolled_nodes[node_idx];
-        let buffer_size = scratch_buffers[node_idx].len();
+        let stride = scratch.buffer_size;
+        for step in &self.plan {
+            let modulation = &mut scratch.modulation[..buffer_size];
+            modulation.fill(0.0);
+            for input in &self.plan_inputs[step.inputs.clone()] {
+                let Some(conn) = &self.matrix[input.source_operator][step.operator] else {
+                    continue;
+                };
+                let input_output = &scratch.node_outputs[input.node * stride..][..buffer_size];
+                match conn.constant_gain() {
+                    Some(gain) => {
+                        for (sample, input_sample) in modulation.iter_mut().zip(input_output) {
+                            *sample += input_sample * gain;
+                        }
+                    }
+                    None => {
+                        for (i, (sample, input_sample)) in
+                            modulation.iter_mut().zip(input_output).enumerate()
+                        {
+                            *sample += input_sample * conn.gain_at(context, i);
+                        }
+                    }
+                }
+            }
 
-        // --- Ensure Inputs are Evaluated First ---
-        let mut modulation_input = vec![0.0; buffer_size];
-        for &input_idx in &node.input_node_indices {
-            // Recursively evaluate the input node
-            self.evaluate_node_recursive(
+            let output = &mut scratch.node_outputs[step.node * stride..][..buffer_size];
+            context.operators[step.operator].process(
                 context,
-                input_idx, // Evaluate the input index
-                node_states,
-                scratch_buffers,
-                visited,
+                modulation,
+                &mut node_states[step.node],
+                output,
             );
+        }
+    }
+
+    /// Render the plan one sample at a time, so that feedback inputs read
+    /// what their source operator produced on the previous sample.
+    fn evaluate_per_sample(
+        &self,
+        context: &ProcessContext,
+        node_states: &mut [OperatorState],
+        scratch: &mut AlgorithmScratch,
+        buffer_size: usize,
+    ) {
+        let averaged = self.feedback_mode == FeedbackMode::Averaged;
+        let stride = scratch.buffer_size;
+        // Targets are set once per buffer; the smoothers still glide per sample
+        for step in &self.plan {
+            context.operators[step.operator].update_targets(context, &mut node_states[step.node]);
+        }
+        let mut sample_context = context.clone();
+        for i in 0..buffer_size {
+            sample_context.samples_elapsed_since_trigger =
+                context.samples_elapsed_since_trigger + i as u64;
+            for step in &self.plan {
+                let operator = &context.operators[step.operator];
+                let connection =
+                    |input: &PlanInput| self.matrix[input.source_operator][step.operator].as_ref();
+
+                let mut modulation = 0.0;
+                for input in &self.plan_inputs[step.inputs.clone()] {
+                    if let Some(conn) = connection(input) {
+                        modulation += scratch.node_outputs[input.node * stride + i]
+                            * conn.gain_at(context, i);
+                    }
+                }
+                for input in &self.plan_inputs[step.feedback.clone()] {
+                    if let Some(conn) = connection(input) {
+                        modulation += node_states[input.node].delayed_output(averaged)
+                            * operator.get_feedback()
+                            * conn.gain_at(context, i);
+                    }
+                }
 
-            // --- Combine Input Contributions ---
-            // (Keep the modulation combining logic from previous attempts, using connection params)
-            let input_output = &scratch_buffers[input_idx]; // Output of the input node
-            let source_op_original_idx = self.unrolled_nodes[input_idx].original_op_index;
-            let target_op_original_idx = node.original_op_index;
+                let state = &mut node_states[step.node];
+                let output = operator.process_sample(&sample_context, modulation, state);
+                scratch.node_outputs[step.node * stride + i] = output;
+                state.push_output(output);
+            }
+        }
+    }
 
-            if let Some(Some(conn)) = self
-                .matrix
-                .get(source_op_original_idx)
-                .and_then(|row| row.get(target_op_original_idx))
+    /// Builds a graph with one node per operator reachable from the carriers,
+    /// in evaluation order. A connection that would close a cycle becomes a
+    /// feedback input instead, read one sample late.
+    fn build_feedback_graph(
+        matrix: &[Vec<Option<ConnectionParams>>],
+        carriers: &[usize],
+    ) -> Vec<UnrolledNode> {
+        let mut nodes = Vec::new();
+        let mut visits = vec![Visit::New; matrix.len()];
+        let mut feedback_connections = Vec::new(); // (source op, target op)
+        for &op_idx in carriers {
+            if op_idx < matrix.len() && visits[op_idx] == Visit::New {
+                Self::visit_operator(
+                    matrix,
+                    op_idx,
+                    &mut visits,
+                    &mut nodes,
+                    &mut feedback_connections,
+                );
+            }
+        }
+        // Every operator on the path was finished by the time the search returned
+        for (source_op_idx, target_op_idx) in feedback_connections {
+            if let (Visit::Done(source_node), Visit::Done(target_node)) =
+                (visits[source_op_idx], visits[target_op_idx])
             {
-                let scale = conn.scale;
-                for i in 0..buffer_size {
-                    let time_on = (context.samples_elapsed_since_trigger + i as u64) as f32
-                        / context.sample_rate;
-                    let time_off = context
-                        .note_off_sample_index
-                        .map(|n| (n + i as u64) as f32 / context.sample_rate);
-                    let env_value = conn
-                        .modulation_envelope
-                        .as_ref()
-                        .map(|e| e.evaluate(time_on, time_off))
-                        .unwrap_or(1.0);
-                    modulation_input[i] += input_output[i] * scale * env_value;
-                }
+                nodes[target_node].feedback_node_indices.push(source_node);
             }
-            // Decide how to handle cases where connection isn't in matrix (e.g., implied by feedback unrolling)
-            // If unrolling implies connection, modulation should likely still happen.
-            // Maybe default to scale=1.0 if matrix doesn't specify? Or ensure matrix reflects all connections?
-            // For now, assume matrix covers intended connections.
-        }
-
-        // --- Process Current Node ---
-        let output_for_this_node = &mut scratch_buffers[node_idx];
-        let operator = &context.operators[node.original_op_index];
-        // Use the unique temporary state for this specific node instance
-        let state_for_this_node = &mut node_states[node_idx];
-
-        operator.process(
-            context,
-            &modulation_input,
-            state_for_this_node,
-            output_for_this_node,
-        );
+        }
+        nodes
+    }
 
-        // Mark this node as processed for this buffer evaluation
-        visited[node_idx] = true;
+    /// Depth-first search from `op_idx` towards its modulators, adding each
+    /// operator's node after its inputs'. Returns the node index.
+    fn visit_operator(
+        matrix: &[Vec<Option<ConnectionParams>>],
+        op_idx: usize,
+        visits: &mut [Visit],
+        nodes: &mut Vec<UnrolledNode>,
+        feedback_connections: &mut Vec<(usize, usize)>,
+    ) -> usize {
+        visits[op_idx] = Visit::OnPath;
+        let mut input_indices = Vec::new();
+        for source_idx in 0..matrix.len() {
+            if matrix[source_idx][op_idx].is_none() {
+                continue;
+            }
+            match visits[source_idx] {
+                Visit::New => input_indices.push(Self::visit_operator(
+                    matrix,
+                    source_idx,
+                    visits,
+                    nodes,
+                    feedback_connections,
+                )),
+                Visit::Done(node_idx) => input_indices.push(node_idx),
+                Visit::OnPath => feedback_connections.push((source_idx, op_idx)),
+            }
+        }
+        nodes.push(UnrolledNode {
+            original_op_index: op_idx,
+            input_node_indices: input_indices,
+            feedback_node_indices: Vec::new(),
+        });
+        let node_idx = nodes.len() - 1;
+        visits[op_idx] = Visit::Done(node_idx);
+        node_idx
     }
 
     // --- NEW HELPER FUNCTION ---
@@ -429,7 +792,7 @@ impl Algorithm {
         target_op_idx: usize,
         nodes: &mut Vec<UnrolledNode>,
         visited_path: &mut Vec<usize>, // Tracks op indices in the current recursion path
-    ) -> Result<Option<usize>, String> {
+    ) -> Result<Option<usize>, SynthError> {
         const MAX_CYCLE_DEPTH: usize = 2; // Define the cycle limit
 
         // Check for cycles based on depth limit
@@ -445,7 +808,13 @@ impl Algorithm {
         // Bounds check
         if target_op_idx >= matrix.len() {
             // This is a real error
-            return Err(format!("Operator index {} out of bounds.", target_op_idx));
+            return Err(SynthError::OperatorOutOfRange {
+                index: target_op_idx,
+                operators: matrix.len(),
+            });
+        }
+        if nodes.len() >= MAX_UNROLLED_NODES {
+            return Err(too_many_nodes());
         }
 
         // Mark current node as visited for this path *before* recursive calls
@@ -456,6 +825,7 @@ impl Algorithm {
         nodes.push(UnrolledNode {
             original_op_index: target_op_idx,
             input_node_indices: Vec::new(), // Inputs added after recursive calls
+            feedback_node_indices: Vec::new(),
         });
 
         // Recursively create all input nodes
@@ -498,7 +868,7 @@ impl Algorithm {
         matrix: &[Vec<Option<ConnectionParams>>],
         carriers: &[usize],
         feedback_loops: &[FeedbackLoop],
-    ) -> Result<Vec<UnrolledNode>, String> {
+    ) -> Result<Vec<UnrolledNode>, SynthError> {
         let mut nodes = Vec::new();
         // Keep track of the top-level nodes created for each carrier index
         // to avoid adding the same carrier multiple times if listed multiple times.
@@ -507,28 +877,26 @@ impl Algorithm {
 
         // --- Step 1: Build base graph by traversing from carriers using new recursive function ---
         for &op_idx in carriers {
-            if !root_node_indices.contains_key(&op_idx) {
+            if let Entry::Vacant(root_entry) = root_node_indices.entry(op_idx) {
                 let mut visited_path = Vec::new(); // Fresh path for each carrier root
                                                    // Use the new recursive builder
                 match Self::build_node_recursive(matrix, op_idx, &mut nodes, &mut visited_path) {
                     Ok(Some(root_node_idx)) => {
-                        root_node_indices.insert(op_idx, root_node_idx);
+                        root_entry.insert(root_node_idx);
                     }
                     Ok(None) => {
                         // Hitting the depth limit immediately for a carrier.
                         // This could happen if MAX_CYCLE_DEPTH <= 1 and the carrier feeds itself.
                         // Log a warning but don't treat as error - might result in empty path for this carrier.
-                        eprintln!(
-                            "Warning: Build for carrier {} stopped immediately due to cycle depth limit. No nodes generated for this root.",
+                        warn!(
+                            target: logging::SYNTH,
+                            "Build for carrier {} stopped immediately due to cycle depth limit. No nodes generated for this root.",
                              op_idx
                         );
                     }
                     Err(e) => {
                         // If any carrier encounters a real error, the whole graph build fails.
-                        return Err(format!(
-                            "Failed building graph from carrier {}: {}",
-                            op_idx, e
-                        ));
+                        return Err(e);
                     }
                 }
             }
@@ -549,8 +917,9 @@ impl Algorithm {
             if loop_rule.from_node >= initial_nodes.len()
                 || loop_rule.to_node >= initial_nodes.len()
             {
-                eprintln!(
-                    "Warning: Feedback loop rule {:?} references node indices out of bounds (initial graph size {}). Skipping rule.",
+                warn!(
+                    target: logging::SYNTH,
+                    "Feedback loop rule {:?} references node indices out of bounds (initial graph size {}). Skipping rule.",
                     loop_rule, initial_nodes.len()
                 );
                 continue;
@@ -561,7 +930,7 @@ impl Algorithm {
                 let target_node_idx_for_input = match node_index_mapping.get(&loop_rule.from_node) {
                     Some(&idx) => idx,
                     None => {
-                        eprintln!("Warning: Could not map 'from_node' {} to current index for feedback loop {:?} iteration {}. Skipping iteration.", loop_rule.from_node, loop_rule, i);
+                        warn!(target: logging::SYNTH, "Could not map 'from_node' {} to current index for feedback loop {:?} iteration {}. Skipping iteration.", loop_rule.from_node, loop_rule, i);
                         continue; // Should not happen if initial validation passes, but for safety
                     }
                 };
@@ -585,6 +954,7 @@ impl Algorithm {
                     nodes.push(UnrolledNode {
                         original_op_index: node_to_copy.original_op_index,
                         input_node_indices: Vec::new(), // Links added in Phase 2
+                        feedback_node_indices: Vec::new(),
                     });
                     duplication_mapping.insert(original_idx, new_node_idx);
 
@@ -621,10 +991,10 @@ impl Algorithm {
                                     // Check if mapped target exists
                                     new_inputs.push(current_external_input_idx);
                                 } else {
-                                    eprintln!("Warning: Mapped external input index {} for original input {} is out of bounds (nodes len {}). Input skipped for duplicated node {}.", current_external_input_idx, input_original_idx, nodes.len(), new_idx);
+                                    warn!(target: logging::SYNTH, "Mapped external input index {} for original input {} is out of bounds (nodes len {}). Input skipped for duplicated node {}.", current_external_input_idx, input_original_idx, nodes.len(), new_idx);
                                 }
                             } else {
-                                eprintln!("Warning: Could not map external input {} (from original node {}) for duplicated node {}. Input skipped.", input_original_idx, original_idx, new_idx);
+                                warn!(target: logging::SYNTH, "Could not map external input {} (from original node {}) for duplicated node {}. Input skipped.", input_original_idx, original_idx, new_idx);
                             }
                         }
                     }
@@ -641,10 +1011,10 @@ impl Algorithm {
                             .input_node_indices
                             .push(duplicated_chain_root_idx);
                     } else {
-                        eprintln!("Error: target_node_idx_for_input {} is out of bounds (nodes len {}) during feedback connection. Connection skipped.", target_node_idx_for_input, nodes.len());
+                        error!(target: logging::SYNTH, "target_node_idx_for_input {} is out of bounds (nodes len {}) during feedback connection. Connection skipped.", target_node_idx_for_input, nodes.len());
                     }
                 } else {
-                    eprintln!("Warning: Root of duplicated chain (original index {}) not found for feedback rule {:?} iter {}. Connection skipped.", loop_rule.to_node, loop_rule, i);
+                    warn!(target: logging::SYNTH, "Root of duplicated chain (original index {}) not found for feedback rule {:?} iter {}. Connection skipped.", loop_rule.to_node, loop_rule, i);
                 }
 
                 // --- Update node_index_mapping for the next iteration/rule ---
@@ -652,6 +1022,9 @@ impl Algorithm {
                 for (original_idx, new_idx) in duplication_mapping {
                     node_index_mapping.insert(original_idx, new_idx);
                 }
+                if nodes.len() > MAX_UNROLLED_NODES {
+                    return Err(too_many_nodes());
+                }
             }
         }
 
@@ -668,6 +1041,12 @@ impl Algorithm {
                     print!("Node {} ", input);
                 }
             }
+            if !node.feedback_node_indices.is_empty() {
+                print!(" | Feedback: ");
+                for &input in &node.feedback_node_indices {
+                    print!("Node {} ", input);
+                }
+            }
             println!();
         }
 
@@ -839,4 +1218,71 @@ mod tests {
 
         println!("--- Test Finished: test_simple_self_feedback (in-module) ---");
     }
+
+    #[test]
+    fn test_cycles_become_one_sample_feedback_inputs() {
+        // 2 modulates 1, which modulates carrier 0; 0 feeds back to 2, and 1 to itself
+        let mut algorithm = Algorithm::default_simple(3).unwrap();
+        algorithm.set_feedback_mode(FeedbackMode::OneSample).unwrap();
+        algorithm
+            .set_matrix(&[vec![0, 0, 1, 1], vec![1, 1, 0, 0], vec![0, 1, 0, 0]])
+            .unwrap();
+        let structure: Vec<(usize, Vec<usize>, Vec<usize>)> = algorithm
+            .unrolled_nodes
+            .iter()
+            .map(|node| {
+                (
+                    node.original_op_index,
+                    node.input_node_indices.clone(),
+                    node.feedback_node_indices.clone(),
+                )
+            })
+            .collect();
+        // Each operator once, after its inputs
+        assert_eq!(
+            structure,
+            vec![
+                (2, vec![], vec![2]),
+                (1, vec![0], vec![1]),
+                (0, vec![1], vec![])
+            ]
+        );
+        assert!(algorithm.has_feedback_paths);
+
+        algorithm.set_feedback_mode(FeedbackMode::Unrolled).unwrap();
+        assert!(algorithm.length() > 3);
+        assert!(!algorithm.has_feedback_paths);
+    }
+
+    #[test]
+    fn test_plan_renders_inputs_first() {
+        // 3 modulates 1 and 2, which both modulate carrier 0
+        let mut algorithm = Algorithm::default_simple(4).unwrap();
+        algorithm
+            .set_matrix(&[
+                vec![0, 0, 0, 0, 1],
+                vec![1, 0, 0, 0, 0],
+                vec![1, 0, 0, 0, 0],
+                vec![0, 1, 1, 0, 0],
+            ])
+            .unwrap();
+        algorithm.print_structure();
+
+        let order: Vec<usize> = algorithm.plan.iter().map(|step| step.node).collect();
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..algorithm.length()).collect::<Vec<_>>());
+        for (position, step) in algorithm.plan.iter().enumerate() {
+            for input in &algorithm.plan_inputs[step.inputs.clone()] {
+                let input_position = order.iter().position(|&node| node == input.node).unwrap();
+                assert!(
+                    input_position < position,
+                    "node {} rendered before its input",
+                    step.node
+                );
+            }
+        }
+        assert_eq!(algorithm.carrier_nodes, vec![0]);
+        assert_eq!(order.last(), Some(&0));
+    }
 }
@@ -0,0 +1,443 @@
+//! Arpeggiator between the note inputs and the voices. Held notes from any
+//! source go in; notes tagged `NoteSource::Arpeggiator` come out, so note offs
+//! match the notes it played. Steps follow the synth's `Transport`: its tempo,
+//! or the MIDI clock pulses themselves when it follows MIDI clock.
+use super::clock::{division_pulses, PULSES_PER_QUARTER_NOTE};
+use super::error::SynthError;
+use super::note::{NoteEvent, NoteSource};
+use super::transport::{ClockSource, Transport};
+use std::fmt;
+use std::str::FromStr;
+
+const MAX_HELD_NOTES: usize = 128;
+const MAX_OCTAVES: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpMode {
+    Up,
+    Down,
+    UpDown,
+    Random,
+    AsPlayed,
+    /// Every held note at once, on each step.
+    Chord,
+}
+
+impl FromStr for ArpMode {
+    type Err = SynthError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "up" => Ok(ArpMode::Up),
+            "down" => Ok(ArpMode::Down),
+            "up-down" | "updown" => Ok(ArpMode::UpDown),
+            "random" => Ok(ArpMode::Random),
+            "as-played" | "asplayed" | "played" => Ok(ArpMode::AsPlayed),
+            "chord" => Ok(ArpMode::Chord),
+            _ => Err(SynthError::unknown("arpeggiator mode", s)),
+        }
+    }
+}
+
+impl fmt::Display for ArpMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ArpMode::Up => "up",
+            ArpMode::Down => "down",
+            ArpMode::UpDown => "up-down",
+            ArpMode::Random => "random",
+            ArpMode::AsPlayed => "as-played",
+            ArpMode::Chord => "chord",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HeldNote {
+    note: u8,
+    velocity: u8,
+    down: bool, // Still physically held, as opposed to latched
+}
+
+#[derive(Debug, Clone)]
+pub struct Arpeggiator {
+    enabled: bool,
+    mode: ArpMode,
+    octaves: u8,
+    pulses_per_step: u32,
+    gate: f32, // Fraction of a step
+    latch: bool,
+    held: Vec<HeldNote>,     // In the order they were played
+    sequence: Vec<(u8, u8)>, // (note, velocity) for one full cycle of the current mode
+    // --- Playback position ---
+    position: usize,
+    frames_into_step: f64,
+    pulses_into_step: u32,
+    step_due: bool,
+    sounding: Vec<(u8, f64)>, // (note, frames until its note off)
+    random_state: u32,
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: ArpMode::Up,
+            octaves: 1,
+            pulses_per_step: PULSES_PER_QUARTER_NOTE / 4, // Sixteenth notes
+            gate: 0.5,
+            latch: false,
+            held: Vec::with_capacity(MAX_HELD_NOTES),
+            sequence: Vec::with_capacity(MAX_HELD_NOTES * MAX_OCTAVES as usize * 2),
+            position: 0,
+            frames_into_step: 0.0,
+            pulses_into_step: 0,
+            step_due: false,
+            sounding: Vec::with_capacity(MAX_HELD_NOTES * MAX_OCTAVES as usize),
+            random_state: 0x2545_F491,
+        }
+    }
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    /// Turning the arpeggiator off forgets held notes; `pending` receives the
+    /// note offs for anything still sounding.
+    pub fn set_enabled(&mut self, enabled: bool, pending: &mut Vec<NoteEvent>) {
+        if !enabled {
+            self.held.clear();
+            self.release_sounding(pending);
+        }
+        self.enabled = enabled;
+    }
+    pub fn mode(&self) -> ArpMode {
+        self.mode
+    }
+    pub fn set_mode(&mut self, mode: ArpMode) {
+        self.mode = mode;
+        self.rebuild_sequence();
+    }
+    /// Octaves to span, from 1 to 4.
+    pub fn set_octaves(&mut self, octaves: u8) {
+        self.octaves = octaves.clamp(1, MAX_OCTAVES);
+        self.rebuild_sequence();
+    }
+    /// Step length as a note division, e.g. `1/16` or `1/8t`.
+    pub fn set_rate(&mut self, division: &str) -> Result<(), SynthError> {
+        self.pulses_per_step = division_pulses(division)
+            .ok_or_else(|| SynthError::unknown("note division", division))?;
+        Ok(())
+    }
+    /// Note length as a fraction of a step. 1.0 and above plays legato.
+    pub fn set_gate(&mut self, gate: f32) {
+        self.gate = gate.clamp(0.01, 1.0);
+    }
+    /// Keep playing notes after their keys are released, until a new chord is played.
+    /// `pending` receives note offs if turning latch off leaves nothing held.
+    pub fn set_latch(&mut self, latch: bool, pending: &mut Vec<NoteEvent>) {
+        self.latch = latch;
+        if !latch {
+            self.held.retain(|held| held.down);
+            self.notes_changed(pending);
+        }
+    }
+    /// True while there are notes to arpeggiate or release.
+    pub fn is_active(&self) -> bool {
+        self.enabled && (!self.held.is_empty() || !self.sounding.is_empty())
+    }
+
+    /// Take a note from an input. Note offs that release arpeggiated notes are
+    /// pushed onto `pending`.
+    pub fn handle_note(&mut self, event: &NoteEvent, pending: &mut Vec<NoteEvent>) {
+        if event.is_on {
+            if self.latch && !self.held.iter().any(|held| held.down) {
+                // A new chord replaces the latched one
+                self.held.clear();
+            }
+            let was_empty = self.held.is_empty();
+            self.held.retain(|held| held.note != event.note_number);
+            if self.held.len() < MAX_HELD_NOTES {
+                self.held.push(HeldNote {
+                    note: event.note_number,
+                    velocity: event.velocity,
+                    down: true,
+                });
+            }
+            if was_empty {
+                // Start on the next frame rather than waiting for a step
+                self.position = 0;
+                self.frames_into_step = 0.0;
+                self.pulses_into_step = 0;
+                self.step_due = true;
+            }
+        } else if self.latch {
+            if let Some(held) = self.held.iter_mut().find(|h| h.note == event.note_number) {
+                held.down = false;
+            }
+        } else {
+            self.held.retain(|held| held.note != event.note_number);
+        }
+        self.notes_changed(pending);
+    }
+
+    fn notes_changed(&mut self, pending: &mut Vec<NoteEvent>) {
+        self.rebuild_sequence();
+        if self.held.is_empty() {
+            self.release_sounding(pending);
+        }
+    }
+
+    fn rebuild_sequence(&mut self) {
+        self.sequence.clear();
+        for octave in 0..self.octaves {
+            for held in &self.held {
+                let note = held.note as u16 + 12 * octave as u16;
+                if note < 128 {
+                    self.sequence.push((note as u8, held.velocity));
+                }
+            }
+        }
+        match self.mode {
+            ArpMode::Up | ArpMode::UpDown | ArpMode::Random => {
+                self.sequence.sort_unstable_by_key(|&(note, _)| note)
+            }
+            ArpMode::Down => self
+                .sequence
+                .sort_unstable_by_key(|&(note, _)| std::cmp::Reverse(note)),
+            ArpMode::AsPlayed | ArpMode::Chord => {}
+        }
+    }
+
+    fn release_sounding(&mut self, pending: &mut Vec<NoteEvent>) {
+        for (note, _) in self.sounding.drain(..) {
+            if let Ok(event) = NoteEvent::new(note, 0, false, NoteSource::Arpeggiator) {
+                pending.push(event);
+            }
+        }
+    }
+
+    fn frames_per_step(&self, transport: &Transport) -> f64 {
+        transport.division_frames(self.pulses_per_step)
+    }
+
+    /// Count one MIDI clock pulse, while the transport follows MIDI clock.
+    pub fn clock_pulse(&mut self) {
+        if self.held.is_empty() {
+            return;
+        }
+        self.pulses_into_step += 1;
+        if self.pulses_into_step >= self.pulses_per_step {
+            self.pulses_into_step = 0;
+            self.step_due = true;
+        }
+    }
+
+    /// Frames until something is due, or `usize::MAX` if nothing is scheduled.
+    pub fn frames_until_next_event(&self, transport: &Transport) -> usize {
+        if !self.is_active() {
+            return usize::MAX;
+        }
+        if self.step_due {
+            return 0;
+        }
+        let mut next = f64::MAX;
+        if transport.source() == ClockSource::Internal && !self.held.is_empty() {
+            next = self.frames_per_step(transport) - self.frames_into_step;
+        }
+        for (_, remaining) in &self.sounding {
+            next = next.min(*remaining);
+        }
+        if next == f64::MAX {
+            usize::MAX
+        } else {
+            next.ceil().max(0.0) as usize
+        }
+    }
+
+    pub fn advance(&mut self, frames: usize) {
+        if !self.is_active() {
+            return;
+        }
+        self.frames_into_step += frames as f64;
+        for (_, remaining) in self.sounding.iter_mut() {
+            *remaining -= frames as f64;
+        }
+    }
+
+    /// Push the notes due at the current position onto `pending`.
+    pub fn collect_due(&mut self, transport: &Transport, pending: &mut Vec<NoteEvent>) {
+        if !self.enabled {
+            return;
+        }
+        let mut i = 0;
+        while i < self.sounding.len() {
+            if self.sounding[i].1 <= 0.0 {
+                let (note, _) = self.sounding.swap_remove(i);
+                if let Ok(event) = NoteEvent::new(note, 0, false, NoteSource::Arpeggiator) {
+                    pending.push(event);
+                }
+            } else {
+                i += 1;
+            }
+        }
+        let frames_per_step = self.frames_per_step(transport);
+        if transport.source() == ClockSource::Internal
+            && !self.held.is_empty()
+            && self.frames_into_step >= frames_per_step
+        {
+            self.frames_into_step -= frames_per_step;
+            self.step_due = true;
+        }
+        if self.step_due {
+            self.step_due = false;
+            self.play_step(frames_per_step, pending);
+        }
+    }
+
+    fn play_step(&mut self, frames_per_step: f64, pending: &mut Vec<NoteEvent>) {
+        // Legato gates hold until the next step
+        self.release_sounding(pending);
+        if self.sequence.is_empty() {
+            return;
+        }
+        let length = (self.gate as f64 * frames_per_step).max(1.0);
+        let len = self.sequence.len();
+        if self.mode == ArpMode::Chord {
+            // Each step plays the whole chord, moving up an octave per step
+            let octave = (self.position % self.octaves as usize) as u8;
+            let chord_len = len / self.octaves as usize;
+            let start = (octave as usize * chord_len).min(len);
+            for i in start..(start + chord_len.max(1)).min(len) {
+                let (note, velocity) = self.sequence[i];
+                self.start_note(note, velocity, length, pending);
+            }
+        } else {
+            let index = match self.mode {
+                ArpMode::UpDown if len > 1 => {
+                    let period = 2 * len - 2;
+                    let phase = self.position % period;
+                    if phase < len {
+                        phase
+                    } else {
+                        period - phase
+                    }
+                }
+                ArpMode::Random => (next_random(&mut self.random_state) as usize) % len,
+                _ => self.position % len,
+            };
+            let (note, velocity) = self.sequence[index];
+            self.start_note(note, velocity, length, pending);
+        }
+        self.position = self.position.wrapping_add(1);
+    }
+
+    fn start_note(&mut self, note: u8, velocity: u8, length: f64, pending: &mut Vec<NoteEvent>) {
+        if let Ok(event) = NoteEvent::new(note, velocity, true, NoteSource::Arpeggiator) {
+            pending.push(event);
+            if self.sounding.len() < self.sounding.capacity() {
+                self.sounding.push((note, length));
+            }
+        }
+    }
+}
+
+/// xorshift32, seeded per arpeggiator so offline renders repeat.
+fn next_random(state: &mut u32) -> u32 {
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn transport() -> Transport {
+        let mut transport = Transport::new();
+        transport.set_tempo(120.0);
+        transport.set_sample_rate(SAMPLE_RATE);
+        transport
+    }
+
+    fn note(number: u8, is_on: bool) -> NoteEvent {
+        NoteEvent::new(number, 100, is_on, NoteSource::Midi).unwrap()
+    }
+
+    /// Run for `frames`, returning the notes started, in order.
+    fn played_notes(arp: &mut Arpeggiator, frames: usize) -> Vec<u8> {
+        let transport = transport();
+        let mut pending = Vec::new();
+        let mut played = Vec::new();
+        let mut position = 0;
+        while position < frames {
+            arp.collect_due(&transport, &mut pending);
+            played.extend(pending.drain(..).filter(|e| e.is_on).map(|e| e.note_number));
+            let step = arp
+                .frames_until_next_event(&transport)
+                .clamp(1, frames - position);
+            arp.advance(step);
+            position += step;
+        }
+        played
+    }
+
+    #[test]
+    fn test_up_down_over_two_octaves() {
+        let mut arp = Arpeggiator::new();
+        let mut pending = Vec::new();
+        arp.set_enabled(true, &mut pending);
+        arp.set_mode(ArpMode::UpDown);
+        arp.set_octaves(2);
+        for number in [64, 60] {
+            arp.handle_note(&note(number, true), &mut pending);
+        }
+        // Sixteenths at 120 BPM are 6000 frames; play just under eight steps
+        let played = played_notes(&mut arp, 6000 * 7 + 100);
+        assert_eq!(played, vec![60, 64, 72, 76, 72, 64, 60, 64]);
+        assert!(pending.iter().all(|e| e.source == NoteSource::Arpeggiator));
+    }
+
+    #[test]
+    fn test_latch_holds_until_a_new_chord() {
+        let mut arp = Arpeggiator::new();
+        let mut pending = Vec::new();
+        arp.set_enabled(true, &mut pending);
+        arp.set_latch(true, &mut pending);
+        arp.handle_note(&note(60, true), &mut pending);
+        arp.handle_note(&note(60, false), &mut pending);
+        assert_eq!(played_notes(&mut arp, 6000 * 2 + 100), vec![60, 60, 60]);
+
+        // Playing again after every key was released starts a new chord
+        arp.handle_note(&note(67, true), &mut pending);
+        assert_eq!(played_notes(&mut arp, 100), vec![67]);
+
+        // Unlatching with no keys down stops it, releasing the sounding note
+        arp.handle_note(&note(67, false), &mut pending);
+        pending.clear();
+        arp.set_latch(false, &mut pending);
+        assert!(!arp.is_active());
+        assert_eq!(pending.len(), 1);
+        assert!(!pending[0].is_on && pending[0].source == NoteSource::Arpeggiator);
+    }
+
+    #[test]
+    fn test_note_divisions() {
+        assert_eq!(division_pulses("1/16"), Some(6));
+        assert_eq!(division_pulses("1/8t"), Some(8));
+        assert_eq!(division_pulses("1/4."), Some(36));
+        assert_eq!(division_pulses("1/7"), None);
+        assert_eq!(division_pulses("99999999/1"), None);
+        assert_eq!(division_pulses("1/4294967295t"), None);
+    }
+}
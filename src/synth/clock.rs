@@ -0,0 +1,58 @@
+//! MIDI clock and transport messages, and note divisions measured in clock pulses.
+
+/// MIDI clock resolution.
+pub const PULSES_PER_QUARTER_NOTE: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMessage {
+    /// One 0xF8 timing clock pulse.
+    Tick,
+    /// Play from the start of the song (0xFA).
+    Start,
+    /// Play from the current song position (0xFB).
+    Continue,
+    Stop,
+    /// Song position pointer in sixteenth notes (0xF2).
+    SongPosition(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockEvent {
+    pub message: ClockMessage,
+    pub frame_offset: usize, // Frame within the next rendered buffer at which the event applies
+}
+
+impl ClockEvent {
+    pub fn new(message: ClockMessage) -> Self {
+        Self {
+            message,
+            frame_offset: 0,
+        }
+    }
+    pub fn with_frame_offset(mut self, frame_offset: usize) -> Self {
+        self.frame_offset = frame_offset;
+        self
+    }
+}
+
+/// Clock pulses in a note division such as `1/16`, `1/8t` (triplet) or `1/4.` (dotted).
+pub fn division_pulses(division: &str) -> Option<u32> {
+    let division = division.trim();
+    let (division, numerator, denominator) = if let Some(d) = division.strip_suffix('t') {
+        (d, 2, 3)
+    } else if let Some(d) = division.strip_suffix('.') {
+        (d, 3, 2)
+    } else {
+        (division, 1, 1)
+    };
+    let (beats, note) = division.split_once('/')?;
+    let beats = beats.parse::<u32>().ok()?;
+    let note = note.parse::<u32>().ok()?;
+    // A whole note is four quarter notes; divisions overflowing a u32 are rejected
+    let pulses = (4 * PULSES_PER_QUARTER_NOTE * numerator).checked_mul(beats)?;
+    let divisor = note.checked_mul(denominator)?;
+    if divisor == 0 || !pulses.is_multiple_of(divisor) {
+        return None;
+    }
+    Some(pulses / divisor).filter(|&p| p > 0)
+}
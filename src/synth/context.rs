@@ -6,6 +6,9 @@ pub struct ProcessContext<'a> {
     pub sample_rate: f32,
     pub base_frequency: f32,
     pub velocity_scale: f32, // From voice
+    // Per-note expression, already mapped through the voice config
+    pub modulation_index_scale: f32,
+    pub cutoff_scale: f32,
     // Timing info needed by stateless envelopes
     pub samples_elapsed_since_trigger: u64,
     pub note_off_sample_index: Option<u64>,
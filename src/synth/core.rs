@@ -1,36 +1,93 @@
-use super::algorithm::Algorithm;
+use super::algorithm::FeedbackMode;
+use super::arpeggiator::Arpeggiator;
+use super::clock::{ClockEvent, ClockMessage};
 use super::config::SynthConfig;
 use super::effect::{Effect, EffectType};
+use super::error::SynthError;
+use super::event::TimedEvent;
+use super::expression::{Expression, ExpressionEvent, ExpressionTarget};
 use super::filter::{Filter, FilterType};
-use super::note::NoteEvent;
+use super::handoff::{BuildRequest, Handoff, Retired, HANDOFF_CAPACITY};
+use super::midi_out::MidiOutput;
+use super::note::{NoteEvent, NoteSource};
 use super::operator::Operator;
 use super::operator::OperatorEvent;
-use super::reverb::Reverb;
-use super::voice::Voice;
+use super::params::{self, ParamId, ParamInfo};
+use super::part::Part;
+use super::preset::{BankManager, EffectPatch, Patch, ProgramEvent};
+use super::sequencer::{Sequencer, SequencerAction};
+use super::transport::{ClockSource, Transport, TransportState};
+use super::tuning::Tuning;
 use super::voice_config::VoiceConfig;
 use super::waveform::Waveform;
+use crate::utils::logging;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::render_pool::RenderPool;
+use crate::utils::smoothing::{LinearSmoother, Smoother};
+use log::{debug, warn};
 
 /// The main synthesizer engine that manages voices and audio processing
 pub struct Synth {
-    voices: Vec<Voice>,
     pub config: SynthConfig,
     pub voice_config: VoiceConfig, // Configuration for the voices
-    algorithm: Algorithm,          // The algorithm defining operator connections
-    operators: Vec<Operator>,      // The set of operators shared by all voices
-    master_volume: f32,
+    parts: Vec<Part>,              // Each with its own algorithm, operators and voices
+    selected_part: usize,          // The part that operator, algorithm and parameter setters edit
+    master_volume: LinearSmoother,
     buffer_size: usize,
-    effect_1: Option<Effect>,
-    effect_2: Option<Effect>,
-    effect_3: Option<Effect>,
+    voice_buffer: Vec<f32>, // Per-voice render buffer, sized by set_buffer_size
+    part_buffer: Vec<f32>,  // Per-part mix
+    send_buffer: Vec<f32>,  // What the parts send through the effects
+    note_frequencies: [f32; 128], // From the current tuning; 0.0 for unmapped keys
+    channel_expression: [Expression; 16], // Latest expression per MIDI channel
+    global_expression: Expression,
+    pub transport: Transport,
+    pub sequencer: Sequencer,
+    sequencer_actions: Vec<SequencerAction>, // Preallocated for the audio thread
+    pub arpeggiator: Arpeggiator,
+    arpeggiator_events: Vec<NoteEvent>, // Preallocated for the audio thread
+    pub midi_output: MidiOutput,
+    pub presets: BankManager,
+    effect_1: Option<Box<Effect>>, // Boxed so that replacing one moves a pointer
+    effect_2: Option<Box<Effect>>,
+    effect_3: Option<Box<Effect>>,
+    effect_serials: [u32; 3], // Bumped whenever a slot changes, so stale reverb builds are dropped
+    deferred_builds: bool,
+    build_requests: Vec<BuildRequest>, // Preallocated for the audio thread when builds are deferred
+    retired: Vec<Retired>,             // Likewise
     sample_rate: f32,
+    #[cfg(not(target_arch = "wasm32"))]
+    render_pool: Option<RenderPool>, // Threads sharing the voices, if enabled
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EffectSlot {
     One,
     Two,
     Three,
 }
+impl EffectSlot {
+    pub const ALL: [EffectSlot; 3] = [EffectSlot::One, EffectSlot::Two, EffectSlot::Three];
+
+    /// The 1-based slot number used by the web UI.
+    pub fn number(&self) -> usize {
+        match self {
+            EffectSlot::One => 1,
+            EffectSlot::Two => 2,
+            EffectSlot::Three => 3,
+        }
+    }
+    pub fn from_number(number: usize) -> Option<Self> {
+        match number {
+            1 => Some(EffectSlot::One),
+            2 => Some(EffectSlot::Two),
+            3 => Some(EffectSlot::Three),
+            _ => None,
+        }
+    }
+}
 const MAX_MODULATION_INDEX: f32 = 10.0;
+/// Enough for every note the arpeggiator can have sounding to turn off at once, plus the next step.
+const ARPEGGIATOR_EVENT_CAPACITY: usize = 1024;
 pub const MODULATION_INDEX_GAIN_OFFSET: f32 = 1.0 / MAX_MODULATION_INDEX;
 
 impl Synth {
@@ -38,44 +95,77 @@ impl Synth {
         Self::default()
     }
     pub fn note_on(&mut self, event: &NoteEvent) {
-        let voice_config = self.voice_config.clone();
-        // Find a free voice or steal one
-        let voice = if let Some(v) = self.find_free_voice() {
-            v
-        } else {
-            self.steal_voice()
-        };
-
-        // Activate the voice with the note details
-        voice.activate(event, &voice_config);
+        if self.arpeggiates(event) {
+            self.arpeggiator
+                .handle_note(event, &mut self.arpeggiator_events);
+            self.play_arpeggiator_events();
+            return;
+        }
+        self.midi_output.note(event);
+        // The event carries a 12-TET frequency; play the key in the current tuning
+        let mut event = *event;
+        event.frequency = self
+            .note_frequencies
+            .get(event.note_number as usize)
+            .copied()
+            .unwrap_or(0.0);
+        if event.frequency <= 0.0 {
+            return; // Unmapped key
+        }
+        let expression = self.channel_expression[event.channel as usize & 0x0F];
+        for part in self.parts.iter_mut().filter(|part| part.accepts(&event)) {
+            part.note_on(&event, &self.voice_config, expression);
+        }
     }
     pub fn note_off(&mut self, event: &NoteEvent) {
-        for voice in self.voices.iter_mut() {
-            // Check if the voice is active OR still releasing (envelope not finished)
-            // and matches the note number and source.
-            if (!voice.releasing || voice.active) // Check if it's making sound or just triggered
-                        && voice.note_number == event.note_number
-                        && voice.note_source == Some(event.source)
-            {
-                voice.release(); // Initiate the release phase
-            }
+        if self.arpeggiates(event) {
+            self.arpeggiator
+                .handle_note(event, &mut self.arpeggiator_events);
+            self.play_arpeggiator_events();
+            // Fall through, for notes started before the arpeggiator was enabled
+        } else {
+            self.midi_output.note(event);
         }
-    }
-    fn update_voice_algorithm(&mut self) {
-        for voice in self.voices.iter_mut() {
-            voice.update_algorithm(&self.algorithm);
+        for part in self.parts.iter_mut() {
+            part.note_off(event);
         }
     }
-    pub fn set_algorithm(&mut self, combined_matrix: &[Vec<u32>]) {
-        if let Err(e) = self.algorithm.set_matrix(combined_matrix) {
-            eprintln!("Synth Error: Failed to set algorithm matrix: {}", e);
-            // Depending on the error, maybe log more or take other action
-        } else {
-            // Only update voices if setting the algorithm structure succeeded
-            self.update_voice_algorithm();
-            println!("Synth: Algorithm updated successfully.");
-            // self.algorithm.print_structure(); // Optional debug print
+    /// Whether a note goes to the arpeggiator instead of straight to a voice.
+    /// Sequencer notes and the arpeggiator's own notes bypass it.
+    fn arpeggiates(&self, event: &NoteEvent) -> bool {
+        self.arpeggiator.is_enabled()
+            && !matches!(
+                event.source,
+                NoteSource::Arpeggiator | NoteSource::Sequencer
+            )
+    }
+    pub fn set_arpeggiator_enabled(&mut self, enabled: bool) {
+        self.arpeggiator
+            .set_enabled(enabled, &mut self.arpeggiator_events);
+        self.play_arpeggiator_events();
+    }
+    pub fn set_arpeggiator_latch(&mut self, latch: bool) {
+        self.arpeggiator
+            .set_latch(latch, &mut self.arpeggiator_events);
+        self.play_arpeggiator_events();
+    }
+    fn play_arpeggiator_events(&mut self) {
+        for i in 0..self.arpeggiator_events.len() {
+            let event = self.arpeggiator_events[i];
+            if event.is_on {
+                self.note_on(&event);
+            } else {
+                self.note_off(&event);
+            }
         }
+        self.arpeggiator_events.clear();
+    }
+    /// Set the algorithm of the selected part. See `Algorithm::set_matrix`.
+    pub fn set_algorithm(&mut self, combined_matrix: &[Vec<u32>]) -> Result<(), SynthError> {
+        let part = self.selected_part;
+        self.parts[part].set_algorithm(combined_matrix)?;
+        debug!(target: logging::SYNTH, "Algorithm of part {} updated", part);
+        Ok(())
     }
     pub fn set_effect_reverb(
         &mut self,
@@ -84,117 +174,385 @@ impl Synth {
         wet_mix: f32,
         effect_slot: EffectSlot,
     ) {
-        let reverb = Reverb::new_fdn(predelay_ms, decay_ms, wet_mix, self.sample_rate);
-        let effect = Effect::new(EffectType::Reverb(reverb));
-        self.set_effect(effect_slot, Some(effect));
+        // Reuse a running reverb when only its continuous params changed, so they
+        // glide instead of restarting the tail
+        if let Some(Effect {
+            effect: EffectType::Reverb(reverb),
+        }) = self.effect_slot_mut(&effect_slot)
+        {
+            if reverb.predelay_ms() == predelay_ms {
+                reverb.set_params(decay_ms, wet_mix);
+                return;
+            }
+        }
+        let serial = self.bump_effect_serial(effect_slot);
+        self.build(BuildRequest::Reverb {
+            slot: effect_slot,
+            serial,
+            predelay_ms,
+            decay_ms,
+            wet_mix,
+            sample_rate: self.sample_rate,
+        });
     }
-    pub fn set_effect(&mut self, effect_slot: EffectSlot, effect: Option<Effect>) {
+    fn effect_slot(&self, effect_slot: &EffectSlot) -> Option<&Effect> {
         match effect_slot {
-            EffectSlot::One => self.effect_1 = effect,
-            EffectSlot::Two => self.effect_2 = effect,
-            EffectSlot::Three => self.effect_3 = effect,
+            EffectSlot::One => self.effect_1.as_deref(),
+            EffectSlot::Two => self.effect_2.as_deref(),
+            EffectSlot::Three => self.effect_3.as_deref(),
         }
     }
-    pub fn remove_effect(&mut self, effect_slot: EffectSlot) {
+    fn effect_slot_mut(&mut self, effect_slot: &EffectSlot) -> Option<&mut Effect> {
         match effect_slot {
-            EffectSlot::One => self.effect_1 = None,
-            EffectSlot::Two => self.effect_2 = None,
-            EffectSlot::Three => self.effect_3 = None,
+            EffectSlot::One => self.effect_1.as_deref_mut(),
+            EffectSlot::Two => self.effect_2.as_deref_mut(),
+            EffectSlot::Three => self.effect_3.as_deref_mut(),
         }
     }
-
-    /// Find an available voice (one that is completely finished)
-    fn find_free_voice(&mut self) -> Option<&mut Voice> {
-        self.voices.iter_mut().find(|voice| !voice.active)
+    pub fn set_effect(&mut self, effect_slot: EffectSlot, effect: Option<Effect>) {
+        self.bump_effect_serial(effect_slot);
+        self.replace_effect(effect_slot, effect.map(Box::new));
     }
-    pub fn set_operator_ratio(&mut self, op_index: usize, ratio: f32) {
-        if op_index < self.operators.len() {
-            self.operators[op_index].set_ratio(ratio);
-        } else {
-            eprintln!("Operator index out of bounds");
+    pub fn remove_effect(&mut self, effect_slot: EffectSlot) {
+        self.set_effect(effect_slot, None);
+    }
+    fn replace_effect(&mut self, effect_slot: EffectSlot, effect: Option<Box<Effect>>) {
+        let slot = match effect_slot {
+            EffectSlot::One => &mut self.effect_1,
+            EffectSlot::Two => &mut self.effect_2,
+            EffectSlot::Three => &mut self.effect_3,
+        };
+        if let Some(replaced) = std::mem::replace(slot, effect) {
+            self.retire(Retired::Effect(replaced));
         }
     }
-    pub fn set_operator_fixed_frequency(&mut self, op_index: usize, frequency: f32) {
-        if op_index < self.operators.len() {
-            self.operators[op_index].set_fixed_frequency(frequency);
+    fn bump_effect_serial(&mut self, effect_slot: EffectSlot) -> u32 {
+        let serial = &mut self.effect_serials[effect_slot.number() - 1];
+        *serial = serial.wrapping_add(1);
+        *serial
+    }
+
+    /// Queue program changes and new reverbs as `BuildRequest`s instead of
+    /// building them here, for hosts that render on a real-time thread (see
+    /// the `handoff` module). Allocates the queues, so call it while setting up.
+    pub fn set_deferred_builds(&mut self, deferred: bool) {
+        self.deferred_builds = deferred;
+        let capacity = if deferred { HANDOFF_CAPACITY } else { 0 };
+        self.build_requests = Vec::with_capacity(capacity);
+        self.retired = Vec::with_capacity(capacity);
+    }
+    /// What to build off the audio thread, for `install`.
+    pub fn drain_build_requests(&mut self) -> impl Iterator<Item = BuildRequest> + '_ {
+        self.build_requests.drain(..)
+    }
+    /// Something `install` or a finished patch change replaced, to drop off
+    /// the audio thread.
+    pub fn pop_retired(&mut self) -> Option<Retired> {
+        self.retired.pop()
+    }
+    /// Build now, or queue the request if builds are deferred. A request for
+    /// the same part or effect slot as one still queued replaces it.
+    fn build(&mut self, request: BuildRequest) {
+        if !self.deferred_builds {
+            if let Some(handoff) = request.build(&self.presets) {
+                self.install(handoff);
+            }
+            return;
+        }
+        let same_target = |queued: &BuildRequest| match (queued, &request) {
+            (BuildRequest::Part { index: a, .. }, BuildRequest::Part { index: b, .. }) => a == b,
+            (BuildRequest::Reverb { slot: a, .. }, BuildRequest::Reverb { slot: b, .. }) => a == b,
+            _ => false,
+        };
+        if let Some(queued) = self
+            .build_requests
+            .iter_mut()
+            .find(|queued| same_target(queued))
+        {
+            *queued = request;
+        } else if self.build_requests.len() < self.build_requests.capacity() {
+            self.build_requests.push(request);
         } else {
-            eprintln!("Operator index out of bounds");
+            warn!(target: logging::AUDIO, "Build queue full, dropping {:?}", request);
         }
     }
-    pub fn set_operator_detune(&mut self, op_index: usize, detune: f32) {
-        if op_index < self.operators.len() {
-            self.operators[op_index].set_detune(detune);
-        } else {
-            eprintln!("Operator index out of bounds");
+    /// Swap in what a `BuildRequest` built. Doesn't allocate: what it replaces
+    /// goes to `pop_retired` if builds are deferred, and is dropped otherwise.
+    /// A reverb for a slot that has changed since the request is retired unused.
+    pub fn install(&mut self, handoff: Handoff) {
+        match handoff {
+            Handoff::Part(index, part) => match self.parts.get_mut(index) {
+                Some(target) => {
+                    if let Some(stale) = target.replace(part) {
+                        self.retire(Retired::Part(stale));
+                    }
+                }
+                None => self.retire(Retired::Part(part)),
+            },
+            Handoff::Effect(slot, serial, effect) => {
+                if serial == self.effect_serials[slot.number() - 1] {
+                    self.replace_effect(slot, Some(effect));
+                } else {
+                    self.retire(Retired::Effect(effect));
+                }
+            }
         }
     }
-    pub fn set_operator_modulation_index(&mut self, op_index: usize, modulation_index: f32) {
-        if op_index < self.operators.len() {
-            self.operators[op_index].set_modulation_index(modulation_index);
-        } else {
-            eprintln!("Operator index out of bounds");
+    fn retire(&mut self, retired: Retired) {
+        if self.retired.len() < self.retired.capacity() {
+            self.retired.push(retired);
+        } else if self.deferred_builds {
+            warn!(target: logging::AUDIO, "Retired queue full, dropping on the audio thread");
         }
     }
 
-    pub fn set_operator_envelope(&mut self, op_index: usize, a: f32, d: f32, s: f32, r: f32) {
-        if op_index < self.operators.len() {
-            self.operators[op_index].set_envelope(a, d, s, r);
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+    pub fn part(&self, index: usize) -> Option<&Part> {
+        self.parts.get(index)
+    }
+    pub fn part_mut(&mut self, index: usize) -> Option<&mut Part> {
+        self.parts.get_mut(index)
+    }
+    /// Add a part with its own `max_voices` voices, playing every note until
+    /// its channels and ranges are set. Returns its index.
+    pub fn add_part(&mut self, max_voices: usize) -> usize {
+        let mut part = Part::new(self.config.operators_per_voice, max_voices);
+        part.set_buffer_size(self.buffer_size);
+        self.parts.push(part);
+        self.parts.len() - 1
+    }
+    /// Remove a part, cutting off its notes. The first part can't be removed.
+    pub fn remove_part(&mut self, index: usize) -> Result<(), SynthError> {
+        self.check_part(index)?;
+        if index == 0 {
+            return Err(SynthError::FirstPartRequired);
+        }
+        self.parts.remove(index);
+        if self.selected_part >= index {
+            self.selected_part -= 1;
         }
+        Ok(())
     }
-
-    /// Set the waveform for a specific operator index.
-    pub fn set_operator_waveform(&mut self, op_index: usize, waveform: Waveform) {
-        if op_index < self.operators.len() {
-            self.operators[op_index].set_waveform(waveform);
-            // println!( // Keep logging minimal unless debugging
-            //     "Synth core: Set operator {} waveform to {:?}",
-            //     op_index, waveform
-            // );
-        } else {
-            eprintln!(
-                "Error: set_operator_waveform index {} out of bounds ({} operators)",
-                op_index,
-                self.operators.len()
-            );
+    /// Choose the part that operator, algorithm, parameter and patch changes apply to.
+    pub fn select_part(&mut self, index: usize) -> Result<(), SynthError> {
+        self.check_part(index)?;
+        self.selected_part = index;
+        Ok(())
+    }
+    fn check_part(&self, index: usize) -> Result<(), SynthError> {
+        if index >= self.parts.len() {
+            return Err(SynthError::PartOutOfRange {
+                index,
+                parts: self.parts.len(),
+            });
         }
+        Ok(())
     }
-    pub fn set_operator_filter(&mut self, op_index: usize, filter: Filter) {
-        if op_index < self.operators.len() {
-            self.operators[op_index].set_filter(filter);
-        } else {
-            eprintln!("Operator index out of bounds");
+    pub fn selected_part(&self) -> usize {
+        self.selected_part
+    }
+    fn operators(&self) -> &[Operator] {
+        self.parts[self.selected_part].operators()
+    }
+    fn operators_mut(&mut self) -> &mut [Operator] {
+        self.parts[self.selected_part].operators_mut()
+    }
+    fn check_operator(&self, op_index: usize) -> Result<(), SynthError> {
+        if op_index >= self.operators().len() {
+            return Err(SynthError::OperatorOutOfRange {
+                index: op_index,
+                operators: self.operators().len(),
+            });
         }
+        Ok(())
+    }
+    /// An operator of the selected part.
+    fn operator_mut(&mut self, op_index: usize) -> Result<&mut Operator, SynthError> {
+        self.check_operator(op_index)?;
+        Ok(&mut self.operators_mut()[op_index])
+    }
+    pub fn set_operator_ratio(&mut self, op_index: usize, ratio: f32) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?.set_ratio(ratio);
+        Ok(())
+    }
+    pub fn set_operator_fixed_frequency(
+        &mut self,
+        op_index: usize,
+        frequency: f32,
+    ) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?.set_fixed_frequency(frequency);
+        Ok(())
+    }
+    pub fn set_operator_detune(&mut self, op_index: usize, detune: f32) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?.set_detune(detune);
+        Ok(())
+    }
+    pub fn set_operator_modulation_index(
+        &mut self,
+        op_index: usize,
+        modulation_index: f32,
+    ) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?
+            .set_modulation_index(modulation_index);
+        Ok(())
     }
 
-    pub fn remove_operator_filter(&mut self, op_index: usize, filter_type: FilterType) {
-        if op_index < self.operators.len() {
-            self.operators[op_index].remove_filter(filter_type);
-        } else {
-            eprintln!("Operator index out of bounds");
-        }
+    pub fn set_operator_feedback(
+        &mut self,
+        op_index: usize,
+        feedback: f32,
+    ) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?.set_feedback(feedback);
+        Ok(())
+    }
+    /// How the selected part renders cycles in its algorithm. Parts start out
+    /// `Unrolled`; see `FeedbackMode`. A matrix too dense to unroll is an
+    /// error, and keeps the current mode.
+    pub fn set_feedback_mode(&mut self, mode: FeedbackMode) -> Result<(), SynthError> {
+        self.parts[self.selected_part].set_feedback_mode(mode)
     }
-    // TODO: Implement a better voice stealing strategy (e.g., oldest note, quietest voice)
-    fn steal_voice(&mut self) -> &mut Voice {
-        // Simple strategy: steal the first voice. Replace with a better heuristic.
-        eprintln!("Warning: Stealing voice 0"); // Log voice stealing
-        &mut self.voices[0]
+    pub fn feedback_mode(&self) -> FeedbackMode {
+        self.parts[self.selected_part].algorithm().feedback_mode()
     }
 
+    pub fn set_operator_envelope(
+        &mut self,
+        op_index: usize,
+        a: f32,
+        d: f32,
+        s: f32,
+        r: f32,
+    ) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?.set_envelope(a, d, s, r);
+        Ok(())
+    }
+
+    /// Set the waveform for a specific operator index.
+    pub fn set_operator_waveform(
+        &mut self,
+        op_index: usize,
+        waveform: Waveform,
+    ) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?.set_waveform(waveform);
+        Ok(())
+    }
+    pub fn set_operator_filter(
+        &mut self,
+        op_index: usize,
+        filter: Filter,
+    ) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?.set_filter(filter);
+        self.parts[self.selected_part].allocate_filters();
+        Ok(())
+    }
+
+    pub fn remove_operator_filter(
+        &mut self,
+        op_index: usize,
+        filter_type: FilterType,
+    ) -> Result<(), SynthError> {
+        self.operator_mut(op_index)?.remove_filter(filter_type);
+        self.parts[self.selected_part].allocate_filters();
+        Ok(())
+    }
     /// Set the master volume level (0.0 to 1.0)
     pub fn set_master_volume(&mut self, volume: f32) {
-        self.master_volume = volume.clamp(0.0, 1.0);
+        self.master_volume.set_target(volume.clamp(0.0, 1.0));
+    }
+
+    /// Switch to `tuning`. Sounding notes are retuned immediately.
+    pub fn set_tuning(&mut self, tuning: &Tuning) {
+        self.note_frequencies = *tuning.frequencies();
+        let voices = self.parts.iter_mut().flat_map(Part::voices_mut);
+        for voice in voices.filter(|v| v.active) {
+            if let Some(&frequency) = self.note_frequencies.get(voice.note_number as usize) {
+                if frequency > 0.0 {
+                    voice.note_frequency = frequency;
+                }
+            }
+        }
     }
 
     pub fn set_voice_config(&mut self, config: VoiceConfig) {
         self.voice_config = config;
     }
+
+    /// Switch the selected part to a program from `presets`. See `apply_patch`.
+    pub fn program_change(&mut self, bank: u16, program: u8) -> Result<(), SynthError> {
+        let part = self.selected_part;
+        self.program_change_parts(bank, program, |index, _| index == part)
+    }
+
+    /// Switch every part listening on the event's channel to the program.
+    fn channel_program_change(&mut self, event: &ProgramEvent) -> Result<(), SynthError> {
+        self.program_change_parts(event.bank, event.program, |_, part| {
+            part.listens_to(event.channel)
+        })
+    }
+
+    fn program_change_parts(
+        &mut self,
+        bank: u16,
+        program: u8,
+        applies_to: impl Fn(usize, &Part) -> bool,
+    ) -> Result<(), SynthError> {
+        let presets = std::mem::take(&mut self.presets);
+        let found = presets
+            .patch(bank, program)
+            .map(|patch| self.apply_shared_settings(patch))
+            .is_some();
+        self.presets = presets;
+        if !found {
+            return Err(SynthError::NoPatch { bank, program });
+        }
+        for index in 0..self.parts.len() {
+            if applies_to(index, &self.parts[index]) {
+                self.build(BuildRequest::Part {
+                    index,
+                    shape: self.parts[index].shape(self.buffer_size),
+                    bank,
+                    program,
+                    sample_rate: self.sample_rate,
+                });
+            }
+        }
+        self.presets.set_current(bank, program);
+        Ok(())
+    }
+
+    /// Switch the selected part to `patch` (see `Part::apply_patch`). Effects
+    /// and master volume are shared by every part, so they change at once.
+    /// Builds the part here whether or not builds are deferred.
+    pub fn apply_patch(&mut self, patch: &Patch) {
+        let index = self.selected_part;
+        let shape = self.parts[index].shape(self.buffer_size);
+        let part = Part::from_patch(shape, patch, self.sample_rate);
+        self.install(Handoff::Part(index, Box::new(part)));
+        self.apply_shared_settings(patch);
+    }
+
+    fn apply_shared_settings(&mut self, patch: &Patch) {
+        self.set_master_volume(patch.master_volume);
+        for (slot, effect) in EffectSlot::ALL.into_iter().zip(patch.effects) {
+            match effect {
+                EffectPatch::Empty => self.remove_effect(slot),
+                EffectPatch::Reverb {
+                    predelay_ms,
+                    decay_ms,
+                    wet_mix,
+                } => self.set_effect_reverb(predelay_ms, decay_ms, wet_mix, slot),
+            }
+        }
+    }
     /// Process operator events
     pub fn process_operator_events(&mut self, event: &OperatorEvent) {
         match event {
-            OperatorEvent::CycleWaveform { direction } => {
-                println!("Processing CycleWaveform event: {:?}", direction);
-                // Cycle the waveform for *all* operators managed by the engine
-                for (_i, operator) in self.operators.iter_mut().enumerate() {
+            OperatorEvent::CycleWaveform { direction, .. } => {
+                debug!(target: logging::AUDIO, "Cycling waveforms {:?}", direction);
+                // Cycle the waveform for *all* operators of the selected part
+                for operator in self.operators_mut().iter_mut() {
                     operator.cycle_waveform(*direction);
                     // Log the waveform of the first operator as an example
                     // println!(
@@ -206,7 +564,206 @@ impl Synth {
         }
     }
 
+    /// Apply pitch bend, pressure or timbre to the notes it targets. Channel
+    /// state is kept so that notes started later on that channel pick it up.
+    pub fn apply_expression(&mut self, event: &ExpressionEvent) {
+        match event.target {
+            ExpressionTarget::Global => self.global_expression.apply(event.kind),
+            ExpressionTarget::Channel(channel) => {
+                let channel = channel & 0x0F;
+                self.channel_expression[channel as usize].apply(event.kind);
+                for voice in self
+                    .parts
+                    .iter_mut()
+                    .flat_map(Part::voices_mut)
+                    .filter(|v| v.active && v.channel == channel)
+                {
+                    voice.expression.apply(event.kind);
+                }
+            }
+        }
+    }
+
+    /// Apply a single event immediately, ignoring its frame offset.
+    pub fn handle_event(&mut self, event: &TimedEvent) {
+        match event {
+            TimedEvent::Note(note) => {
+                if note.is_on {
+                    self.note_on(note);
+                } else {
+                    self.note_off(note);
+                }
+            }
+            TimedEvent::Operator(operator_event) => self.process_operator_events(operator_event),
+            TimedEvent::Expression(expression_event) => self.apply_expression(expression_event),
+            TimedEvent::Clock(clock_event) => self.handle_clock_event(clock_event),
+            TimedEvent::Param(param_event) => {
+                let _ = self.set_param(param_event.id, param_event.value);
+            }
+            TimedEvent::Program(program_event) => {
+                if let Err(e) = self.channel_program_change(program_event) {
+                    warn!(target: logging::AUDIO, "Program change failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Apply MIDI clock and transport messages. Start, stop and song position
+    /// are honoured whatever the clock source; pulses only while following MIDI clock.
+    pub fn handle_clock_event(&mut self, event: &ClockEvent) {
+        match event.message {
+            ClockMessage::Tick => {
+                if self.transport.clock_pulse() {
+                    self.arpeggiator.clock_pulse();
+                }
+            }
+            ClockMessage::Start => self.start_transport(),
+            ClockMessage::Continue => self.continue_transport(),
+            ClockMessage::Stop => self.stop_transport(),
+            ClockMessage::SongPosition(sixteenths) => {
+                self.transport.set_song_position(sixteenths);
+                let beats = self.transport.state().beats;
+                self.sequencer
+                    .locate(beats, self.transport.frames_per_beat());
+            }
+        }
+    }
+
+    pub fn transport_state(&self) -> TransportState {
+        self.transport.state()
+    }
+
+    /// Follow the internal tempo or incoming MIDI clock.
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        self.transport.set_source(source);
+    }
+
+    /// Start the transport and the sequencer from the top. Call after loading
+    /// patterns so the sequencer's action buffer is sized for them.
+    pub fn start_transport(&mut self) {
+        self.reserve_sequencer_actions();
+        self.transport.start();
+        self.sequencer.start();
+    }
+
+    /// Carry on from the current song position.
+    pub fn continue_transport(&mut self) {
+        self.reserve_sequencer_actions();
+        self.transport.resume();
+        self.sequencer.resume();
+    }
+
+    /// Stop the transport and the sequencer, releasing its notes and restoring
+    /// locked parameters.
+    pub fn stop_transport(&mut self) {
+        self.transport.stop();
+        self.sequencer_actions.clear();
+        self.sequencer.stop(&mut self.sequencer_actions);
+        self.apply_sequencer_actions();
+    }
+
+    /// Make room for the most actions one sequencer step can produce, so the
+    /// audio thread never allocates.
+    pub fn reserve_sequencer_actions(&mut self) {
+        let needed = self.sequencer.max_actions();
+        if self.sequencer_actions.capacity() < needed {
+            self.sequencer_actions
+                .reserve(needed - self.sequencer_actions.len());
+        }
+    }
+
+    fn apply_sequencer_actions(&mut self) {
+        for i in 0..self.sequencer_actions.len() {
+            match self.sequencer_actions[i] {
+                SequencerAction::NoteOn { note, velocity } => {
+                    if let Ok(event) = NoteEvent::new(note, velocity, true, NoteSource::Sequencer) {
+                        self.note_on(&event);
+                    }
+                }
+                SequencerAction::NoteOff { note } => {
+                    if let Ok(event) = NoteEvent::new(note, 0, false, NoteSource::Sequencer) {
+                        self.note_off(&event);
+                    }
+                }
+                SequencerAction::Lock { id, value } => {
+                    if let Ok(current) = self.get_param(id) {
+                        self.sequencer.remember_unlocked_value(id, current);
+                        let _ = self.set_param(id, value);
+                    }
+                }
+                SequencerAction::Unlock { id, value } => {
+                    let _ = self.set_param(id, value);
+                }
+            }
+        }
+        self.sequencer_actions.clear();
+    }
+
+    /// Render `output`, applying each event at its frame offset rather than at the
+    /// buffer boundary. Events should be sorted by offset; an event whose offset is
+    /// earlier than one already applied is applied as soon as possible, and offsets
+    /// past the end of the buffer are applied after the last frame.
+    pub fn process_with_events(
+        &mut self,
+        output: &mut [f32],
+        sample_rate: f32,
+        events: &[TimedEvent],
+    ) {
+        let mut position = 0;
+        for event in events {
+            let offset = event.frame_offset().clamp(position, output.len());
+            if offset > position {
+                self.process(&mut output[position..offset], sample_rate);
+                position = offset;
+            }
+            self.handle_event(event);
+        }
+        if position < output.len() {
+            self.process(&mut output[position..], sample_rate);
+        }
+    }
+
+    /// Render `output`, in blocks of at most `buffer_size` samples so that
+    /// no allocation happens on the audio path.
     pub fn process(&mut self, output: &mut [f32], sample_rate: f32) {
+        let block_size = self.buffer_size.max(1);
+        self.transport.set_sample_rate(sample_rate);
+        for block in output.chunks_mut(block_size) {
+            if self.sequencer.is_playing() || self.arpeggiator.is_active() {
+                self.process_clocked_block(block, sample_rate);
+            } else {
+                self.process_block(block, sample_rate);
+                self.transport.advance(block.len());
+            }
+        }
+    }
+
+    /// Render a block, splitting it wherever the sequencer or arpeggiator has
+    /// something due so that their timing doesn't depend on the block size.
+    fn process_clocked_block(&mut self, output: &mut [f32], sample_rate: f32) {
+        let mut position = 0;
+        while position < output.len() {
+            let frames_per_beat = self.transport.frames_per_beat();
+            self.sequencer
+                .collect_due(frames_per_beat, &mut self.sequencer_actions);
+            self.apply_sequencer_actions();
+            self.arpeggiator
+                .collect_due(&self.transport, &mut self.arpeggiator_events);
+            self.play_arpeggiator_events();
+            let frames = self
+                .sequencer
+                .frames_until_next_action(frames_per_beat)
+                .min(self.arpeggiator.frames_until_next_event(&self.transport))
+                .clamp(1, output.len() - position);
+            self.process_block(&mut output[position..position + frames], sample_rate);
+            self.transport.advance(frames);
+            self.sequencer.advance(frames);
+            self.arpeggiator.advance(frames);
+            position += frames;
+        }
+    }
+
+    fn process_block(&mut self, output: &mut [f32], sample_rate: f32) {
         // if self.sample_rate != sample_rate {
         //     self.sample_rate = sample_rate;
         //     if let Some(effect) = self.effect.as_mut() {
@@ -214,136 +771,323 @@ impl Synth {
         //     }
         // }
         output.fill(0.0); // Clear output buffer before mixing
-        let mut temp_buffer = vec![0.0; output.len()];
-        let voice_scaling_factor = self.get_voice_scaling_factor();
-        for voice in self.voices.iter_mut().filter(|v| v.active) {
-            voice.process(
-                &self.algorithm,
-                &self.operators,
-                &mut temp_buffer,
+        let send = &mut self.send_buffer[..output.len()];
+        send.fill(0.0);
+        let part_output = &mut self.part_buffer[..output.len()];
+        for part in self.parts.iter_mut() {
+            part_output.fill(0.0);
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(pool) = self
+                .render_pool
+                .as_mut()
+                .filter(|_| part.active_voices() > 1)
+            {
+                part.process_parallel(part_output, pool, sample_rate, &self.global_expression);
+            } else {
+                part.process(
+                    part_output,
+                    &mut self.voice_buffer,
+                    sample_rate,
+                    &self.global_expression,
+                );
+            }
+            #[cfg(target_arch = "wasm32")]
+            part.process(
+                part_output,
+                &mut self.voice_buffer,
                 sample_rate,
-                voice_scaling_factor,
+                &self.global_expression,
             );
-
-            for i in 0..output.len() {
-                output[i] += temp_buffer[i];
-                temp_buffer[i] = 0.0; // Clear temp buffer for next voice
+            let effect_send = part.effect_send();
+            for ((dry, wet), sample) in output.iter_mut().zip(send.iter_mut()).zip(part_output.iter()) {
+                *dry += *sample * (1.0 - effect_send);
+                *wet += *sample * effect_send;
             }
         }
-        for sample in output.iter_mut() {
+        self.master_volume.set_sample_rate(sample_rate);
+        for (dry, wet) in output.iter_mut().zip(send.iter_mut()) {
             // Modulation Index is allowed to go from 0 to (1/MODULATION_INDEX_GAIN_OFFSET),
             // back out that gain increase here
-            *sample *= self.master_volume * MODULATION_INDEX_GAIN_OFFSET;
+            let gain = self.master_volume.next_value() * MODULATION_INDEX_GAIN_OFFSET;
+            *dry *= gain;
+            *wet *= gain;
         }
         if let Some(effect_1) = self.effect_1.as_mut() {
-            effect_1.apply(output);
+            effect_1.apply(send);
         }
         if let Some(effect_2) = self.effect_2.as_mut() {
-            effect_2.apply(output);
+            effect_2.apply(send);
         }
         if let Some(effect_3) = self.effect_3.as_mut() {
-            effect_3.apply(output);
-        }
-    }
-    fn get_voice_scaling_factor(&self) -> f32 {
-        let carrier_indices = self.algorithm.get_carrier_indices();
-        let mut max_modulation_index: f32 = 0.0;
-        let mut total_modulation_index = 0.0;
-        for carrier_idx in carrier_indices {
-            let mod_index = self.operators[*carrier_idx].get_modulation_index();
-            if self.operators[*carrier_idx].get_waveform() == Waveform::Input {
-                // NOTE: if the operator is a pass through, sum the effective modulation indices of its Modulators
-                let modulator_indices = self.algorithm.get_modulator_indices(*carrier_idx);
-                for modulator_idx in modulator_indices {
-                    let modulator_effective_mod_index = mod_index
-                        * MODULATION_INDEX_GAIN_OFFSET
-                        * self.operators[modulator_idx].get_modulation_index();
-                    max_modulation_index = max_modulation_index.max(modulator_effective_mod_index);
-                    total_modulation_index += modulator_effective_mod_index;
+            effect_3.apply(send);
+        }
+        for (sample, wet) in output.iter_mut().zip(send.iter()) {
+            *sample += *wet;
+        }
+        for index in 0..self.parts.len() {
+            if let Some(finished) = self.parts[index].take_finished() {
+                self.retire(Retired::Part(finished));
+            }
+        }
+    }
+    /// Every parameter this synth exposes, with its range and scaling.
+    pub fn params(&self) -> Vec<ParamInfo> {
+        params::all_params(self.operators().len(), self.parts.len())
+    }
+
+    fn check_param(&self, id: ParamId) -> Result<(), SynthError> {
+        if let Some(op_index) = id.operator_index() {
+            self.check_operator(op_index)?;
+        }
+        if let Some(part_index) = id.part_index() {
+            self.check_part(part_index)?;
+        }
+        Ok(())
+    }
+
+    /// Current value of a parameter, in the units given by its `ParamInfo`.
+    /// Reverb parameters are only available while the slot holds a reverb,
+    /// and filter parameters while the operator has a low-pass filter.
+    pub fn get_param(&self, id: ParamId) -> Result<f32, SynthError> {
+        self.check_param(id)?;
+        let value = match id {
+            ParamId::MasterVolume => self.master_volume.target(),
+            ParamId::OperatorRatio(i) => self.operators()[i].get_ratio(),
+            ParamId::OperatorFixedFrequency(i) => {
+                self.operators()[i].get_fixed_frequency().unwrap_or(0.0)
+            }
+            ParamId::OperatorDetune(i) => self.operators()[i].get_detune(),
+            ParamId::OperatorModulationIndex(i) => self.operators()[i].get_modulation_index(),
+            ParamId::OperatorFeedback(i) => self.operators()[i].get_feedback(),
+            ParamId::OperatorWaveform(i) => self.operators()[i].get_waveform().index() as f32,
+            ParamId::OperatorAttack(i) => self.operators()[i].envelope.attack,
+            ParamId::OperatorDecay(i) => self.operators()[i].envelope.decay,
+            ParamId::OperatorSustain(i) => self.operators()[i].envelope.sustain,
+            ParamId::OperatorRelease(i) => self.operators()[i].envelope.release,
+            ParamId::OperatorFilterCutoff(i) | ParamId::OperatorFilterQ(i) => {
+                let lowpass = self.operators()[i]
+                    .lowpass()
+                    .ok_or(SynthError::NoLowPass(i))?;
+                match id {
+                    ParamId::OperatorFilterCutoff(_) => lowpass.cutoff(),
+                    _ => lowpass.q(),
+                }
+            }
+            ParamId::VoiceAttack => self.voice_config.attack,
+            ParamId::VoiceDecay => self.voice_config.decay,
+            ParamId::VoiceSustain => self.voice_config.sustain,
+            ParamId::VoiceRelease => self.voice_config.release.min(params::MAX_VOICE_TIME),
+            ParamId::PartEffectSend(i) => self.parts[i].effect_send(),
+            ParamId::PartKeyLow(i) => self.parts[i].key_range().0 as f32,
+            ParamId::PartKeyHigh(i) => self.parts[i].key_range().1 as f32,
+            ParamId::PartVelocityLow(i) => self.parts[i].velocity_range().0 as f32,
+            ParamId::PartVelocityHigh(i) => self.parts[i].velocity_range().1 as f32,
+            ParamId::PartFeedbackMode(i) => {
+                self.parts[i].algorithm().feedback_mode().index() as f32
+            }
+            ParamId::ReverbPredelay(slot)
+            | ParamId::ReverbDecay(slot)
+            | ParamId::ReverbWetMix(slot) => {
+                let Some(Effect {
+                    effect: EffectType::Reverb(reverb),
+                }) = self.effect_slot(&slot)
+                else {
+                    return Err(SynthError::NoReverb(slot));
+                };
+                match id {
+                    ParamId::ReverbPredelay(_) => reverb.predelay_ms(),
+                    ParamId::ReverbDecay(_) => reverb.decay_ms(),
+                    _ => reverb.wet_mix(),
                 }
-            } else {
-                max_modulation_index = max_modulation_index.max(mod_index);
-                total_modulation_index += mod_index;
+            }
+        };
+        Ok(value)
+    }
+
+    /// Set a parameter, clamped to its range. Setting a reverb parameter on an
+    /// empty slot inserts a reverb with default settings first; filter
+    /// parameters need the operator to have a low-pass filter already.
+    pub fn set_param(&mut self, id: ParamId, value: f32) -> Result<(), SynthError> {
+        self.check_param(id)?;
+        let value = id.clamp(value);
+        match id {
+            ParamId::MasterVolume => self.set_master_volume(value),
+            ParamId::OperatorRatio(i) => self.operators_mut()[i].set_ratio(value),
+            ParamId::OperatorFixedFrequency(i) => {
+                if value > 0.0 {
+                    self.operators_mut()[i].set_fixed_frequency(value);
+                } else {
+                    // Back to ratio mode, keeping the previous ratio
+                    let ratio = self.operators_mut()[i].get_ratio();
+                    self.operators_mut()[i].set_ratio(ratio);
+                }
+            }
+            ParamId::OperatorDetune(i) => self.operators_mut()[i].set_detune(value),
+            ParamId::OperatorModulationIndex(i) => self.operators_mut()[i].set_modulation_index(value),
+            ParamId::OperatorFeedback(i) => self.operators_mut()[i].set_feedback(value),
+            ParamId::OperatorWaveform(i) => {
+                let waveform = Waveform::from_index(value as u8)
+                    .ok_or(SynthError::InvalidWaveform(value as u8))?;
+                self.operators_mut()[i].set_waveform(waveform);
+            }
+            ParamId::OperatorAttack(i)
+            | ParamId::OperatorDecay(i)
+            | ParamId::OperatorSustain(i)
+            | ParamId::OperatorRelease(i) => {
+                let envelope = &self.operators()[i].envelope;
+                let (mut a, mut d, mut s, mut r) = (
+                    envelope.attack,
+                    envelope.decay,
+                    envelope.sustain,
+                    envelope.release,
+                );
+                match id {
+                    ParamId::OperatorAttack(_) => a = value,
+                    ParamId::OperatorDecay(_) => d = value,
+                    ParamId::OperatorSustain(_) => s = value,
+                    _ => r = value,
+                }
+                self.operators_mut()[i].set_envelope(a, d, s, r);
+            }
+            ParamId::OperatorFilterCutoff(i) | ParamId::OperatorFilterQ(i) => {
+                // Voices glide to the operator's filter settings
+                let lowpass = self.operators_mut()[i]
+                    .lowpass_mut()
+                    .ok_or(SynthError::NoLowPass(i))?;
+                match id {
+                    ParamId::OperatorFilterCutoff(_) => lowpass.set_cutoff(value),
+                    _ => lowpass.set_q(value),
+                }
+            }
+            ParamId::VoiceAttack => self.voice_config.attack = value,
+            ParamId::VoiceDecay => self.voice_config.decay = value,
+            ParamId::VoiceSustain => self.voice_config.sustain = value,
+            ParamId::VoiceRelease => {
+                self.voice_config.release = if value >= params::MAX_VOICE_TIME {
+                    f32::INFINITY
+                } else {
+                    value
+                };
+            }
+            ParamId::PartEffectSend(i) => self.parts[i].set_effect_send(value),
+            ParamId::PartKeyLow(i) | ParamId::PartKeyHigh(i) => {
+                let (mut low, mut high) = self.parts[i].key_range();
+                match id {
+                    ParamId::PartKeyLow(_) => low = value as u8,
+                    _ => high = value as u8,
+                }
+                self.parts[i].set_key_range(low, high);
+            }
+            ParamId::PartVelocityLow(i) | ParamId::PartVelocityHigh(i) => {
+                let (mut low, mut high) = self.parts[i].velocity_range();
+                match id {
+                    ParamId::PartVelocityLow(_) => low = value as u8,
+                    _ => high = value as u8,
+                }
+                self.parts[i].set_velocity_range(low, high);
+            }
+            ParamId::PartFeedbackMode(i) => {
+                let mode = FeedbackMode::from_index(value as u8)
+                    .ok_or_else(|| SynthError::unknown("feedback mode", &value.to_string()))?;
+                self.parts[i].set_feedback_mode(mode)?;
+            }
+            ParamId::ReverbPredelay(slot)
+            | ParamId::ReverbDecay(slot)
+            | ParamId::ReverbWetMix(slot) => {
+                let current = |param: ParamId| {
+                    self.get_param(param)
+                        .unwrap_or_else(|_| param.default_value())
+                };
+                let mut predelay_ms = current(ParamId::ReverbPredelay(slot));
+                let mut decay_ms = current(ParamId::ReverbDecay(slot));
+                let mut wet_mix = current(ParamId::ReverbWetMix(slot));
+                match id {
+                    ParamId::ReverbPredelay(_) => predelay_ms = value,
+                    ParamId::ReverbDecay(_) => decay_ms = value,
+                    _ => wet_mix = value,
+                }
+                self.set_effect_reverb(predelay_ms, decay_ms, wet_mix, slot);
             }
         }
-        max_modulation_index / total_modulation_index
+        self.midi_output.param(id, id.normalize(value));
+        Ok(())
+    }
+
+    /// Current value of a parameter mapped to 0..1 by its scaling curve.
+    pub fn get_param_normalized(&self, id: ParamId) -> Result<f32, SynthError> {
+        Ok(id.normalize(self.get_param(id)?))
+    }
+
+    pub fn set_param_normalized(&mut self, id: ParamId, normalized: f32) -> Result<(), SynthError> {
+        self.set_param(id, id.denormalize(normalized))
     }
 
     /// Set the buffer size for the synth engine
     pub fn set_buffer_size(&mut self, buffer_size: usize) {
-        println!("Buffer size set to: {}", buffer_size);
+        debug!(target: logging::SYNTH, "Buffer size set to {}", buffer_size);
         self.buffer_size = buffer_size;
+        self.voice_buffer.resize(buffer_size, 0.0);
+        self.part_buffer.resize(buffer_size, 0.0);
+        self.send_buffer.resize(buffer_size, 0.0);
+        for part in self.parts.iter_mut() {
+            part.set_buffer_size(buffer_size);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(pool) = self.render_pool.as_mut() {
+            pool.set_buffer_size(buffer_size);
+        }
+    }
+
+    /// Render voices on `workers` threads alongside the audio thread, which
+    /// pays off with many voices sounding; 0 renders them all on the audio
+    /// thread. Starts and stops threads, so call it while setting up rather
+    /// than from the audio callback.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_render_threads(&mut self, workers: usize) {
+        self.render_pool = (workers > 0).then(|| RenderPool::new(workers, self.buffer_size));
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_threads(&self) -> usize {
+        self.render_pool.as_ref().map_or(0, RenderPool::workers)
     }
 }
 impl Default for Synth {
     fn default() -> Self {
         let config = SynthConfig::default();
 
-        // Initialize operators
-        let mut operators: Vec<Operator> = (0..config.operators_per_voice)
-            .map(|_| Operator::new())
-            .collect();
-
-        // Carrier A
-        // --- Create and Configure ---
-        operators[0].set_waveform(Waveform::Sine);
-        // operators[0].set_envelope(0.01, 1.0, 0.7, 0.5);
-        // operators[0].set_gain(0.5);
-        // operators[0].set_ratio(1.0);
-        // operators[0].set_filter(Filter::new_pitched_comb(0.99));
-
-        // Carrier B (slightly detuned)
-        // operators[1].set_waveform(Waveform::Sine);
-        // operators[1].set_envelope(0.01, 1.0, 0.7, 0.5);
-        // operators[1].set_gain(0.5);
-        // operators[1].set_ratio(1.01);
-        // operators[1].set_modulation_index(2.0);
-
-        // Modulator A
-        // operators[2].set_waveform(Waveform::Sine);
-        // operators[2].set_envelope(0.005, 0.3, 0.0, 0.2);
-        // operators[2].set_gain(1.0);
-        // operators[2].set_ratio(2.0);
-        // operators[2].set_modulation_index(3.0);
-
-        // Modulator B
-        // operators[3].set_waveform(Waveform::Sine);
-        // operators[3].set_envelope(0.005, 0.3, 0.0, 0.2);
-        // operators[3].set_gain(1.0);
-        // operators[3].set_ratio(2.02);
-        // operators[3].set_modulation_index(3.0);
-
-        // Initialize with a default algorithm (e.g., a simple 2-operator stack)
-        // let default_algorithm = Algorithm::default_fanout_feedback(operators.len()).unwrap();
-        // let default_algorithm = Algorithm::default_feedback_1(operators.len()).unwrap();
-        let default_algorithm = Algorithm::default_simple(operators.len()).unwrap();
-        // let default_algorithm = Algorithm::default_stack_2(operators.len()).unwrap();
-        default_algorithm.print_evaluation_chains();
-        default_algorithm.print_structure();
-        // Initialize voices
-        let mut voices: Vec<Voice> = (0..config.max_voices)
-            .map(|_| Voice::new(operators.len()))
-            .collect();
-
-        for voice in voices.iter_mut() {
-            voice.update_algorithm(&default_algorithm);
-        }
-
-        let synth = Self {
-            voices,
+        let mut synth = Self {
+            parts: vec![Part::new(config.operators_per_voice, config.max_voices)],
+            selected_part: 0,
             config,
             voice_config: VoiceConfig::default(), // Default voice config
-            algorithm: default_algorithm,
-            operators, // Store the operators
-            master_volume: 0.8,
-            buffer_size: 1024, // Default, can be updated by set_buffer_size
+            master_volume: LinearSmoother::default().with_value(0.8),
+            buffer_size: 0,
+            voice_buffer: Vec::new(),
+            part_buffer: Vec::new(),
+            send_buffer: Vec::new(),
+            note_frequencies: *Tuning::default().frequencies(),
+            channel_expression: [Expression::default(); 16],
+            global_expression: Expression::default(),
+            transport: Transport::new(),
+            sequencer: Sequencer::new(),
+            sequencer_actions: Vec::new(),
+            arpeggiator: Arpeggiator::new(),
+            arpeggiator_events: Vec::with_capacity(ARPEGGIATOR_EVENT_CAPACITY),
+            midi_output: MidiOutput::new(),
+            presets: BankManager::new(),
             effect_1: None,
             effect_2: None,
             effect_3: None,
+            effect_serials: [0; 3],
+            deferred_builds: false,
+            build_requests: Vec::new(),
+            retired: Vec::new(),
             sample_rate: 44100.0,
+            #[cfg(not(target_arch = "wasm32"))]
+            render_pool: None,
         };
+        synth.set_buffer_size(1024); // Default, can be updated by set_buffer_size
         // synth.set_effect_reverb(20.0, 70.0, 1000.0, 0.5, 2, 2, EffectSlot::One);
-        #[allow(clippy::let_and_return)]
         synth
     }
 }
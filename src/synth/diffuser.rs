@@ -129,8 +129,8 @@ impl MultiChannelDiffuser {
         }
 
         // 3. Apply polarity flips and normalization
-        for i in 0..self.num_configured_channels {
-            input[i] *= self.channel_polarity[i] * self.normalization_factor;
+        for (sample, polarity) in input.iter_mut().zip(self.channel_polarity.iter()) {
+            *sample *= polarity * self.normalization_factor;
         }
     }
 
@@ -24,7 +24,7 @@ impl EnvelopeGenerator {
     pub fn set_params(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
         self.attack = attack.max(0.0);
         self.decay = decay.max(0.0);
-        self.sustain = sustain.max(0.0).min(1.0); // Clamp sustain level
+        self.sustain = sustain.clamp(0.0, 1.0); // Clamp sustain level
         self.release = release.max(0.0);
     }
     pub fn set_curve(&mut self, curve: f32) {
@@ -39,7 +39,7 @@ impl EnvelopeGenerator {
     /// Returns curved progress [0, 1]
     #[inline]
     fn apply_curve_attack(&self, linear_progress: f32) -> f32 {
-        let p = linear_progress.min(1.0).max(0.0); // Clamp progress for safety
+        let p = linear_progress.clamp(0.0, 1.0); // Clamp progress for safety
 
         if self.curve_blend_factor <= EPSILON {
             // Purely linear
@@ -59,7 +59,7 @@ impl EnvelopeGenerator {
     /// Returns the curved multiplier [1 -> 0] for the decaying value.
     #[inline]
     fn apply_curve_decay_release(&self, linear_progress: f32) -> f32 {
-        let p = linear_progress.min(1.0).max(0.0); // Clamp progress for safety
+        let p = linear_progress.clamp(0.0, 1.0); // Clamp progress for safety
 
         if self.curve_blend_factor <= EPSILON {
             // Purely linear (multiplier = 1 - p)
@@ -75,6 +75,16 @@ impl EnvelopeGenerator {
         }
     }
     pub fn evaluate(&self, time_since_on: f32, time_since_off: Option<f32>) -> f32 {
+        self.evaluate_with_sustain(time_since_on, time_since_off, self.sustain)
+    }
+    /// Like `evaluate`, but with the sustain level supplied by the caller
+    /// (e.g. a smoothed value while the level is being changed).
+    pub fn evaluate_with_sustain(
+        &self,
+        time_since_on: f32,
+        time_since_off: Option<f32>,
+        sustain: f32,
+    ) -> f32 {
         if let Some(time_since_off) = time_since_off {
             // --- Release Phase ---
             if self.release <= EPSILON || time_since_off >= self.release {
@@ -89,7 +99,7 @@ impl EnvelopeGenerator {
             let value_at_release_start = {
                 let time_held = time_since_on - time_since_off;
                 // Evaluate the state just before release, using curves
-                self.evaluate_non_release(time_held)
+                self.evaluate_non_release(time_held, sustain)
             };
 
             // Get the curved multiplier (decays from 1 towards 0)
@@ -99,11 +109,11 @@ impl EnvelopeGenerator {
             value_at_release_start * release_multiplier_curved
         } else {
             // --- Attack/Decay/Sustain Phase ---
-            self.evaluate_non_release(time_since_on)
+            self.evaluate_non_release(time_since_on, sustain)
         }
     }
     // Helper function to evaluate only Attack/Decay/Sustain phases
-    fn evaluate_non_release(&self, time_since_on: f32) -> f32 {
+    fn evaluate_non_release(&self, time_since_on: f32, sustain: f32) -> f32 {
         if time_since_on < self.attack {
             // --- Attack Phase ---
             if self.attack <= EPSILON {
@@ -118,7 +128,7 @@ impl EnvelopeGenerator {
             // --- Decay Phase ---
             if self.decay <= EPSILON {
                 // Instant decay
-                self.sustain
+                sustain
             } else {
                 let decay_elapsed = time_since_on - self.attack;
                 let decay_progress_linear = decay_elapsed / self.decay;
@@ -128,11 +138,11 @@ impl EnvelopeGenerator {
 
                 // Interpolate from 1.0 down to sustain using the multiplier
                 // Value = sustain + (1.0 - sustain) * multiplier
-                self.sustain + (1.0 - self.sustain) * decay_multiplier_curved
+                sustain + (1.0 - sustain) * decay_multiplier_curved
             }
         } else {
             // --- Sustain Phase ---
-            self.sustain
+            sustain
         }
     }
     pub fn finished(&self, time_since_off: Option<f32>) -> bool {
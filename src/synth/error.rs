@@ -0,0 +1,251 @@
+//! Errors from changing the synth: out-of-range indices, matrices that can't
+//! be built, and settings that don't parse. Loading presets, tunings, MIDI
+//! files and OSC messages have their own errors, which convert into
+//! `SynthError` so that callers juggling several can use `?` throughout.
+use super::core::EffectSlot;
+use super::note::NoteError;
+use super::osc::OscError;
+use super::preset::PresetError;
+use super::smf::SmfError;
+use super::tuning::TuningError;
+use crate::synth::prelude::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SynthError {
+    OperatorOutOfRange {
+        index: usize,
+        operators: usize,
+    },
+    PartOutOfRange {
+        index: usize,
+        parts: usize,
+    },
+    /// Every synth keeps its first part.
+    FirstPartRequired,
+    InvalidMatrix(String),
+    /// Unrolling the algorithm's cycles would take more than `max_nodes`
+    /// operator copies.
+    AlgorithmTooLarge {
+        max_nodes: usize,
+    },
+    NoPatch {
+        bank: u16,
+        program: u8,
+    },
+    NoReverb(EffectSlot),
+    /// The operator at this index has no low-pass filter to adjust.
+    NoLowPass(usize),
+    InvalidWaveform(u8),
+    /// A name that isn't one of its `kind`, e.g. an unknown parameter ID.
+    UnknownName {
+        kind: &'static str,
+        name: String,
+    },
+    InvalidJson(String),
+    InvalidPattern(String),
+    InvalidMidiMapping(String),
+    InvalidEnvelope(String),
+    /// A command line flag with a value that doesn't parse.
+    InvalidArgument(String),
+    Io(String),
+    Tuning(TuningError),
+    Preset(PresetError),
+    Smf(SmfError),
+    Osc(OscError),
+    Note(NoteError),
+}
+
+impl SynthError {
+    pub(crate) fn unknown(kind: &'static str, name: &str) -> Self {
+        SynthError::UnknownName {
+            kind,
+            name: name.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SynthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SynthError::OperatorOutOfRange { index, operators } => write!(
+                f,
+                "Operator index {} out of bounds ({} operators)",
+                index, operators
+            ),
+            SynthError::PartOutOfRange { index, parts } => {
+                write!(f, "Part index {} out of bounds ({} parts)", index, parts)
+            }
+            SynthError::FirstPartRequired => write!(f, "The first part can't be removed"),
+            SynthError::InvalidMatrix(e) => write!(f, "Invalid algorithm matrix: {}", e),
+            SynthError::AlgorithmTooLarge { max_nodes } => write!(
+                f,
+                "Unrolling the matrix takes over {} operator copies; use one-sample feedback instead",
+                max_nodes
+            ),
+            SynthError::NoPatch { bank, program } => {
+                write!(f, "No patch at bank {} program {}", bank, program)
+            }
+            SynthError::NoReverb(slot) => write!(f, "Effect slot {} has no reverb", slot.number()),
+            SynthError::NoLowPass(index) => {
+                write!(f, "Operator {} has no low-pass filter", index)
+            }
+            SynthError::InvalidWaveform(index) => write!(f, "Invalid waveform index {}", index),
+            SynthError::UnknownName { kind, name } => write!(f, "Unknown {} '{}'", kind, name),
+            SynthError::InvalidJson(e) => write!(f, "Invalid JSON: {}", e),
+            SynthError::InvalidPattern(e) => write!(f, "Invalid pattern: {}", e),
+            SynthError::InvalidMidiMapping(e) => write!(f, "Invalid MIDI mapping: {}", e),
+            SynthError::InvalidEnvelope(e) => write!(f, "Invalid envelope: {}", e),
+            SynthError::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
+            SynthError::Io(e) => write!(f, "{}", e),
+            SynthError::Tuning(e) => write!(f, "{}", e),
+            SynthError::Preset(e) => write!(f, "{}", e),
+            SynthError::Smf(e) => write!(f, "{}", e),
+            SynthError::Osc(e) => write!(f, "{}", e),
+            SynthError::Note(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SynthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SynthError::Tuning(e) => Some(e),
+            SynthError::Preset(e) => Some(e),
+            SynthError::Smf(e) => Some(e),
+            SynthError::Osc(e) => Some(e),
+            SynthError::Note(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<TuningError> for SynthError {
+    fn from(e: TuningError) -> Self {
+        SynthError::Tuning(e)
+    }
+}
+impl From<PresetError> for SynthError {
+    fn from(e: PresetError) -> Self {
+        SynthError::Preset(e)
+    }
+}
+impl From<SmfError> for SynthError {
+    fn from(e: SmfError) -> Self {
+        SynthError::Smf(e)
+    }
+}
+impl From<OscError> for SynthError {
+    fn from(e: OscError) -> Self {
+        SynthError::Osc(e)
+    }
+}
+impl From<NoteError> for SynthError {
+    fn from(e: NoteError) -> Self {
+        SynthError::Note(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::filter::Filter;
+    use crate::synth::note::{NoteEvent, NoteSource};
+    use crate::synth::params::ParamId;
+    use crate::synth::preset::BankManager;
+    use crate::synth::Synth;
+
+    #[test]
+    fn test_operator_setters_reject_out_of_range_indices() {
+        let mut synth = Synth::new();
+        let operators = synth.parts()[0].operators().len();
+        let out_of_range = Err(SynthError::OperatorOutOfRange {
+            index: operators,
+            operators,
+        });
+        assert_eq!(synth.set_operator_ratio(operators, 2.0), out_of_range);
+        assert_eq!(
+            synth.set_operator_envelope(operators, 0.1, 0.1, 1.0, 0.1),
+            out_of_range
+        );
+        assert_eq!(
+            synth.set_param(ParamId::OperatorDetune(operators), 1.0),
+            out_of_range
+        );
+        assert_eq!(synth.set_operator_ratio(0, 2.0), Ok(()));
+        assert_eq!(synth.get_param(ParamId::OperatorRatio(0)), Ok(2.0));
+    }
+
+    #[test]
+    fn test_filter_params_need_a_lowpass() {
+        let mut synth = Synth::new();
+        let cutoff = ParamId::OperatorFilterCutoff(0);
+        assert_eq!(synth.get_param(cutoff), Err(SynthError::NoLowPass(0)));
+        assert_eq!(
+            synth.set_param(cutoff, 500.0),
+            Err(SynthError::NoLowPass(0))
+        );
+
+        synth.part_mut(0).unwrap().operators_mut()[0]
+            .set_filter(Filter::new_lowpass_biquad(2000.0, 44100.0));
+        assert_eq!(synth.set_param(cutoff, 500.0), Ok(()));
+        assert_eq!(synth.set_param(ParamId::OperatorFilterQ(0), 2.0), Ok(()));
+        assert_eq!(synth.get_param(cutoff), Ok(500.0));
+        assert_eq!(synth.get_param(ParamId::OperatorFilterQ(0)), Ok(2.0));
+    }
+
+    #[test]
+    fn test_voice_release_top_of_range_holds() {
+        let mut synth = Synth::new();
+        let release = ParamId::VoiceRelease;
+        assert_eq!(synth.get_param(release), Ok(release.info().default));
+        synth.set_param(release, 0.5).unwrap();
+        assert_eq!(synth.voice_config.release, 0.5);
+        synth.set_param_normalized(release, 1.0).unwrap();
+        assert_eq!(synth.voice_config.release, f32::INFINITY);
+    }
+
+    #[test]
+    fn test_part_errors() {
+        let mut synth = Synth::new();
+        assert_eq!(
+            synth.select_part(1),
+            Err(SynthError::PartOutOfRange { index: 1, parts: 1 })
+        );
+        assert_eq!(synth.remove_part(0), Err(SynthError::FirstPartRequired));
+        assert_eq!(
+            synth.program_change(3, 4),
+            Err(SynthError::NoPatch {
+                bank: 3,
+                program: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_names() {
+        assert_eq!(
+            "op/0/volume".parse::<ParamId>().unwrap_err().to_string(),
+            "Unknown parameter ID 'op/0/volume'"
+        );
+    }
+
+    #[test]
+    fn test_other_errors_convert_for_question_mark() {
+        fn import(json: &str) -> Result<usize, SynthError> {
+            Ok(BankManager::new().import_json(0, json)?)
+        }
+        fn note(number: u8) -> Result<NoteEvent, SynthError> {
+            Ok(NoteEvent::new(number, 100, true, NoteSource::Midi)?)
+        }
+        assert!(matches!(
+            import("[{\"name\": \"Broken\"}]"),
+            Err(SynthError::Preset(PresetError::InvalidPatch(_)))
+        ));
+        let error = note(128).unwrap_err();
+        assert_eq!(error, SynthError::Note(NoteError::InvalidNoteNumber(128)));
+        assert_eq!(
+            error.to_string(),
+            NoteError::InvalidNoteNumber(128).to_string()
+        );
+    }
+}
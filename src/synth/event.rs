@@ -0,0 +1,83 @@
+use super::clock::ClockEvent;
+use super::expression::ExpressionEvent;
+use super::note::NoteEvent;
+use super::operator::OperatorEvent;
+use super::params::ParamEvent;
+use super::preset::ProgramEvent;
+
+/// An event scheduled at a frame offset within the buffer being rendered.
+/// See `Synth::process_with_events`.
+#[derive(Debug, Clone, Copy)]
+pub enum TimedEvent {
+    Note(NoteEvent),
+    Operator(OperatorEvent),
+    Expression(ExpressionEvent),
+    Clock(ClockEvent),
+    Param(ParamEvent),
+    Program(ProgramEvent),
+}
+
+impl TimedEvent {
+    pub fn frame_offset(&self) -> usize {
+        match self {
+            TimedEvent::Note(event) => event.frame_offset,
+            TimedEvent::Operator(event) => event.frame_offset(),
+            TimedEvent::Expression(event) => event.frame_offset,
+            TimedEvent::Clock(event) => event.frame_offset,
+            TimedEvent::Param(event) => event.frame_offset,
+            TimedEvent::Program(event) => event.frame_offset,
+        }
+    }
+    pub fn with_frame_offset(self, frame_offset: usize) -> Self {
+        match self {
+            TimedEvent::Note(event) => TimedEvent::Note(event.with_frame_offset(frame_offset)),
+            TimedEvent::Operator(event) => {
+                TimedEvent::Operator(event.with_frame_offset(frame_offset))
+            }
+            TimedEvent::Expression(event) => {
+                TimedEvent::Expression(event.with_frame_offset(frame_offset))
+            }
+            TimedEvent::Clock(event) => TimedEvent::Clock(event.with_frame_offset(frame_offset)),
+            TimedEvent::Param(event) => TimedEvent::Param(event.with_frame_offset(frame_offset)),
+            TimedEvent::Program(event) => {
+                TimedEvent::Program(event.with_frame_offset(frame_offset))
+            }
+        }
+    }
+}
+
+impl From<NoteEvent> for TimedEvent {
+    fn from(event: NoteEvent) -> Self {
+        TimedEvent::Note(event)
+    }
+}
+
+impl From<OperatorEvent> for TimedEvent {
+    fn from(event: OperatorEvent) -> Self {
+        TimedEvent::Operator(event)
+    }
+}
+
+impl From<ExpressionEvent> for TimedEvent {
+    fn from(event: ExpressionEvent) -> Self {
+        TimedEvent::Expression(event)
+    }
+}
+
+impl From<ClockEvent> for TimedEvent {
+    fn from(event: ClockEvent) -> Self {
+        TimedEvent::Clock(event)
+    }
+}
+
+impl From<ParamEvent> for TimedEvent {
+    fn from(event: ParamEvent) -> Self {
+        TimedEvent::Param(event)
+    }
+}
+
+impl From<ProgramEvent> for TimedEvent {
+    fn from(event: ProgramEvent) -> Self {
+        TimedEvent::Program(event)
+    }
+}
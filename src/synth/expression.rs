@@ -0,0 +1,71 @@
+/// Continuous per-note (or per-channel) expression, as sent by MPE controllers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Expression {
+    pub pitch_bend: f32, // In semitones
+    pub pressure: f32,   // 0.0-1.0
+    pub timbre: f32,     // 0.0-1.0, CC74 "slide"; 0.5 is neutral
+}
+
+impl Expression {
+    /// Combine per-note expression with expression applied to every note.
+    pub fn combined(&self, global: &Expression) -> Expression {
+        Expression {
+            pitch_bend: self.pitch_bend + global.pitch_bend,
+            pressure: self.pressure.max(global.pressure),
+            timbre: (self.timbre + global.timbre - 0.5).clamp(0.0, 1.0),
+        }
+    }
+    pub fn apply(&mut self, kind: ExpressionKind) {
+        match kind {
+            ExpressionKind::PitchBend(semitones) => self.pitch_bend = semitones,
+            ExpressionKind::Pressure(pressure) => self.pressure = pressure.clamp(0.0, 1.0),
+            ExpressionKind::Timbre(timbre) => self.timbre = timbre.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self {
+            pitch_bend: 0.0,
+            pressure: 0.0,
+            timbre: 0.5, // MPE assumes CC74 = 64 until told otherwise
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpressionKind {
+    PitchBend(f32),
+    Pressure(f32),
+    Timbre(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpressionTarget {
+    /// Every note, e.g. a master channel or a non-MPE controller.
+    Global,
+    /// Notes on one MIDI channel (0-15); with MPE that is a single note.
+    Channel(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExpressionEvent {
+    pub target: ExpressionTarget,
+    pub kind: ExpressionKind,
+    pub frame_offset: usize, // Frame within the next rendered buffer at which the event applies
+}
+
+impl ExpressionEvent {
+    pub fn new(target: ExpressionTarget, kind: ExpressionKind) -> Self {
+        Self {
+            target,
+            kind,
+            frame_offset: 0,
+        }
+    }
+    pub fn with_frame_offset(mut self, frame_offset: usize) -> Self {
+        self.frame_offset = frame_offset;
+        self
+    }
+}
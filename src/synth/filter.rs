@@ -1,5 +1,16 @@
 use crate::synth::prelude::{FRAC_1_SQRT_2, PI};
+use crate::utils::logging;
+use crate::utils::smoothing::{MultiplicativeSmoother, Smoother};
 use core::fmt;
+use log::warn;
+
+/// While the cutoff glides, coefficients are recomputed every this many samples.
+const COEFFICIENT_UPDATE_INTERVAL: u32 = 16;
+/// Longest comb delay, in samples: a 1.5 Hz pitched comb at 96 kHz.
+const MAX_COMB_DELAY: usize = 1 << 16;
+/// Low-pass resonance range; Q too close to zero blows the coefficients up.
+pub const MIN_Q: f32 = 0.1;
+pub const MAX_Q: f32 = 20.0;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum FilterType {
@@ -20,26 +31,48 @@ pub struct BiquadState {
 }
 #[derive(Clone, Debug, Default)]
 pub struct LowPassBiquadState {
-    #[allow(dead_code)] // TODO: remove later.. we may want to reference these later
     cutoff: f32,
-    #[allow(dead_code)]
     q: f32,
+    sample_rate: f32,
     b0: f32,
     b1: f32,
     b2: f32,
     a1: f32,
     a2: f32,
     state: BiquadState,
+    cutoff_smoother: MultiplicativeSmoother,
+    samples_until_update: u32,
 }
 impl LowPassBiquadState {
     pub fn new(cutoff: f32, sample_rate: f32) -> Self {
-        // Clamp cutoff to avoid issues, ensure it's below Nyquist
-        let cutoff = cutoff.max(1.0).min(sample_rate * 0.49);
         let sample_rate = sample_rate.max(1.0);
         let q = FRAC_1_SQRT_2; // Prevent Q too close to zero
+        let mut filter = Self {
+            q,
+            sample_rate,
+            cutoff_smoother: MultiplicativeSmoother::default(),
+            ..Default::default()
+        };
+        filter.cutoff_smoother.set_sample_rate(sample_rate);
+        filter.update_coefficients(cutoff);
+        filter.cutoff_smoother.reset(filter.cutoff);
+        filter
+    }
+    pub fn cutoff(&self) -> f32 {
+        self.cutoff
+    }
+    /// Glide the cutoff to `cutoff` instead of jumping, avoiding zipper noise.
+    pub fn set_target_cutoff(&mut self, cutoff: f32) {
+        let cutoff = cutoff.clamp(1.0, self.sample_rate * 0.49);
+        self.cutoff_smoother.set_target(cutoff);
+    }
+    fn update_coefficients(&mut self, cutoff: f32) {
+        // Clamp cutoff to avoid issues, ensure it's below Nyquist
+        let cutoff = cutoff.max(1.0).min(self.sample_rate * 0.49);
+        let q = self.q;
 
         // Calculate intermediate variables (from RBJ Audio EQ Cookbook)
-        let omega = 2.0 * PI * cutoff / sample_rate;
+        let omega = 2.0 * PI * cutoff / self.sample_rate;
         let cos_omega = omega.cos();
         let sin_omega = omega.sin();
         let alpha = sin_omega / (2.0 * q);
@@ -53,35 +86,52 @@ impl LowPassBiquadState {
         let a2 = 1.0 - alpha;
 
         // Normalize coefficients by a0
-        let b0 = b0 / a0;
-        let b1 = b1 / a0;
-        let b2 = b2 / a0;
-        let a1 = a1 / a0;
-        let a2 = a2 / a0;
-        Self {
-            cutoff,
-            q,
-            b0,
-            b1,
-            b2,
-            a1,
-            a2,
-            ..Default::default()
-        }
+        self.cutoff = cutoff;
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = a1 / a0;
+        self.a2 = a2 / a0;
     }
-    pub fn new_with_q(cutoff: f32, q: f32) -> Self {
-        Self {
-            cutoff,
-            q,
-            ..Default::default()
+    pub fn new_with_q(cutoff: f32, q: f32, sample_rate: f32) -> Self {
+        let mut filter = Self::new(cutoff, sample_rate);
+        filter.set_q(q);
+        filter
+    }
+    pub fn q(&self) -> f32 {
+        self.q
+    }
+    /// Change the resonance straight away; Q is clamped to between `MIN_Q` and `MAX_Q`.
+    pub fn set_q(&mut self, q: f32) {
+        let q = if q.is_nan() {
+            FRAC_1_SQRT_2
+        } else {
+            q.clamp(MIN_Q, MAX_Q)
+        };
+        if q != self.q {
+            self.q = q;
+            self.update_coefficients(self.cutoff);
         }
     }
+    /// Jump straight to `cutoff`, e.g. on a template filter no voice is playing through.
+    pub fn set_cutoff(&mut self, cutoff: f32) {
+        self.update_coefficients(cutoff);
+        self.cutoff_smoother.reset(self.cutoff);
+    }
 }
 impl FilterState for LowPassBiquadState {
     fn reset(&mut self) {
         self.state = BiquadState::default();
     }
     fn process(&mut self, input: f32) -> f32 {
+        if !self.cutoff_smoother.is_settled() {
+            let cutoff = self.cutoff_smoother.next_value();
+            if self.samples_until_update == 0 || self.cutoff_smoother.is_settled() {
+                self.update_coefficients(cutoff);
+                self.samples_until_update = COEFFICIENT_UPDATE_INTERVAL;
+            }
+            self.samples_until_update -= 1;
+        }
         // Apply the Biquad difference equation (Direct Form I)
         // y[n] = b0*x[n] + b1*x[n-1] + b2*x[n-2] - a1*y[n-1] - a2*y[n-2]
         let output = self.b0 * input + self.b1 * self.state.x1 + self.b2 * self.state.x2
@@ -107,7 +157,9 @@ pub struct CombState {
     ys_index: usize,
 }
 impl CombState {
+    /// `k` is clamped to between 1 and `MAX_COMB_DELAY` samples.
     pub fn new(alpha: f32, k: usize) -> Self {
+        let k = k.clamp(1, MAX_COMB_DELAY);
         Self {
             alpha,
             k,
@@ -115,19 +167,33 @@ impl CombState {
             ys_index: 0,
         }
     }
+    /// A copy with a delay line long enough for any `k`, so that a voice
+    /// can retune it without allocating.
+    fn voice_copy(&self) -> Self {
+        Self {
+            alpha: self.alpha,
+            k: self.k,
+            ys: vec![0.0; MAX_COMB_DELAY],
+            ys_index: 0,
+        }
+    }
+    fn copy_settings(&mut self, template: &CombState) {
+        self.alpha = template.alpha;
+        self.update_k(template.k);
+    }
+    /// Change the delay and clear the line. Only grows the line past its
+    /// current length, which allocates.
     pub fn update_k(&mut self, k: usize) {
-        if k > 0 {
-            self.k = k;
-        } else {
-            self.k = 1;
+        self.k = k.clamp(1, MAX_COMB_DELAY);
+        if self.ys.len() < self.k {
+            self.ys.resize(self.k, 0.0);
         }
-        self.ys.resize(k, 0.0);
-        self.ys_index = 0;
+        self.reset();
     }
 }
 impl FilterState for CombState {
     fn reset(&mut self) {
-        self.ys.fill(0.0);
+        self.ys[..self.k].fill(0.0);
         self.ys_index = 0;
     }
     fn process(&mut self, input: f32) -> f32 {
@@ -147,7 +213,10 @@ impl PitchedCombState {
             comb_state: CombState::new(alpha, k),
         }
     }
+    /// Tune the delay to `frequency`. Frequencies too low (or zero) for
+    /// `MAX_COMB_DELAY` get the longest delay.
     pub fn update_k_frequency(&mut self, sample_rate: f32, frequency: f32) {
+        // `as` saturates an infinite period and turns NaN into 0
         let k = (sample_rate / frequency).round() as usize;
         self.comb_state.update_k(k);
     }
@@ -161,7 +230,7 @@ impl FilterState for PitchedCombState {
         let k = state.k;
 
         // Safety checks
-        if k == 0 || state.ys.len() != k {
+        if k == 0 || state.ys.len() < k {
             return input;
         }
         // Ensure index wraps correctly before use
@@ -189,7 +258,7 @@ impl FilterState for PitchedCombState {
 
         // Store the DAMPED value back into the delay line at the current index
         if !output.is_finite() {
-            eprintln!("Warning: Non-finite value detected. Resetting.");
+            warn!(target: logging::AUDIO, "Non-finite comb filter output, resetting");
             self.reset(); // Ensure reset clears ys buffer appropriately
             return 0.0;
         }
@@ -229,9 +298,42 @@ impl Filter {
             Filter::PitchedComb(s) => s.process(input),
         }
     }
+    /// A copy for a voice to play through, cleared, with room for any comb
+    /// delay so that `copy_settings` never allocates.
+    pub fn voice_copy(&self) -> Self {
+        match self {
+            Filter::LowPassBiquad(s) => {
+                let mut lowpass = s.clone();
+                lowpass.reset();
+                Filter::LowPassBiquad(lowpass)
+            }
+            Filter::Comb(s) => Filter::Comb(s.voice_copy()),
+            Filter::PitchedComb(s) => Filter::PitchedComb(PitchedCombState {
+                comb_state: s.comb_state.voice_copy(),
+            }),
+        }
+    }
+    /// Take `template`'s settings in place and clear the state. Does nothing
+    /// if `template` is another type of filter.
+    pub fn copy_settings(&mut self, template: &Filter) {
+        match (self, template) {
+            (Filter::LowPassBiquad(s), Filter::LowPassBiquad(t)) => {
+                *s = t.clone();
+                s.reset();
+            }
+            (Filter::Comb(s), Filter::Comb(t)) => s.copy_settings(t),
+            (Filter::PitchedComb(s), Filter::PitchedComb(t)) => {
+                s.comb_state.copy_settings(&t.comb_state)
+            }
+            _ => {}
+        }
+    }
     pub fn new_lowpass_biquad(cutoff: f32, sample_rate: f32) -> Self {
         Filter::LowPassBiquad(LowPassBiquadState::new(cutoff, sample_rate))
     }
+    pub fn new_lowpass_biquad_with_q(cutoff: f32, q: f32, sample_rate: f32) -> Self {
+        Filter::LowPassBiquad(LowPassBiquadState::new_with_q(cutoff, q, sample_rate))
+    }
     pub fn new_comb(alpha: f32, k: usize) -> Self {
         Filter::Comb(CombState::new(alpha, k))
     }
@@ -239,3 +341,42 @@ impl Filter {
         Filter::PitchedComb(PitchedCombState::new(alpha, 1))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comb_delays_stay_in_bounds() {
+        let mut comb = Filter::new_comb(0.5, 0);
+        assert!(comb.process(1.0).is_finite());
+
+        // A zero or vanishing frequency asks for an endless delay
+        for frequency in [0.0, 1e-30, f32::NAN] {
+            let mut pitched = PitchedCombState::new(0.5, 1);
+            pitched.update_k_frequency(44100.0, frequency);
+            assert!((1..=MAX_COMB_DELAY).contains(&pitched.comb_state.k));
+            assert!(pitched.process(1.0).is_finite());
+        }
+    }
+
+    #[test]
+    fn test_lowpass_q_changes_resonance() {
+        let peak = |q: f32| {
+            let mut filter = LowPassBiquadState::new_with_q(1000.0, q, 44100.0);
+            // Steady-state gain of a sine at the cutoff
+            (0..44100)
+                .map(|n| filter.process((2.0 * PI * 1000.0 * n as f32 / 44100.0).sin()))
+                .skip(22050)
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+        };
+        assert!((peak(FRAC_1_SQRT_2) - FRAC_1_SQRT_2).abs() < 0.01);
+        assert!((peak(4.0) - 4.0).abs() < 0.1);
+
+        let mut filter = LowPassBiquadState::new(1000.0, 44100.0);
+        filter.set_q(0.0);
+        assert_eq!(filter.q(), MIN_Q);
+        filter.set_q(f32::NAN);
+        assert_eq!(filter.q(), FRAC_1_SQRT_2);
+    }
+}
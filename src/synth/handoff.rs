@@ -0,0 +1,78 @@
+//! Program changes and new reverbs allocate, so a synth rendering on a
+//! real-time thread has them built elsewhere. With deferred builds on (see
+//! `Synth::set_deferred_builds`), the synth queues a `BuildRequest` instead of
+//! building; the host builds it off the audio thread and passes the result
+//! back to `Synth::install`. Whatever that replaces comes out of
+//! `Synth::pop_retired`, to be dropped off the audio thread too.
+use super::core::EffectSlot;
+use super::effect::{Effect, EffectType};
+use super::part::{Part, PartShape};
+use super::preset::BankManager;
+use super::reverb::Reverb;
+
+/// Requests or retired items held between drains; more are dropped rather than allocating.
+pub const HANDOFF_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuildRequest {
+    /// Part `index`, laid out as `shape`, switching to a program.
+    Part {
+        index: usize,
+        shape: PartShape,
+        bank: u16,
+        program: u8,
+        sample_rate: f32,
+    },
+    /// A reverb for `slot`. `serial` tells the synth whether the slot has
+    /// changed again since it asked.
+    Reverb {
+        slot: EffectSlot,
+        serial: u32,
+        predelay_ms: f32,
+        decay_ms: f32,
+        wet_mix: f32,
+        sample_rate: f32,
+    },
+}
+
+/// What a `BuildRequest` built, ready for `Synth::install`.
+pub enum Handoff {
+    Part(usize, Box<Part>),
+    Effect(EffectSlot, u32, Box<Effect>),
+}
+
+/// What `Synth::install` replaced.
+pub enum Retired {
+    Part(Box<Part>),
+    Effect(Box<Effect>),
+}
+
+impl BuildRequest {
+    /// Build what the request asks for, taking programs from `presets` (a copy
+    /// of the synth's). A program missing from `presets` builds nothing.
+    pub fn build(&self, presets: &BankManager) -> Option<Handoff> {
+        match *self {
+            BuildRequest::Part {
+                index,
+                shape,
+                bank,
+                program,
+                sample_rate,
+            } => presets.patch(bank, program).map(|patch| {
+                Handoff::Part(index, Box::new(Part::from_patch(shape, patch, sample_rate)))
+            }),
+            BuildRequest::Reverb {
+                slot,
+                serial,
+                predelay_ms,
+                decay_ms,
+                wet_mix,
+                sample_rate,
+            } => {
+                let reverb = Reverb::new_fdn(predelay_ms, decay_ms, wet_mix, sample_rate);
+                let effect = Effect::new(EffectType::Reverb(reverb));
+                Some(Handoff::Effect(slot, serial, Box::new(effect)))
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Decoding of raw MIDI channel messages into synth events, shared by the
+//! native MIDI input and the WASM runtime.
+use super::clock::{ClockEvent, ClockMessage};
+use super::event::TimedEvent;
+use super::expression::{ExpressionEvent, ExpressionKind, ExpressionTarget};
+use super::mpe::{
+    ChannelRole, MpeConfig, MpeZone, DEFAULT_MASTER_BEND_RANGE, LOWER_MASTER_CHANNEL,
+    UPPER_MASTER_CHANNEL,
+};
+use super::note::{NoteEvent, NoteSource};
+use super::preset::ProgramEvent;
+
+const CC_BANK_SELECT_MSB: u8 = 0;
+const CC_DATA_ENTRY_MSB: u8 = 6;
+const CC_BANK_SELECT_LSB: u8 = 32;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_TIMBRE: u8 = 74;
+const CC_NRPN_LSB: u8 = 98;
+const CC_NRPN_MSB: u8 = 99;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+const RPN_PITCH_BEND_SENSITIVITY: (u8, u8) = (0, 0);
+const RPN_MPE_CONFIGURATION: (u8, u8) = (0, 6);
+
+/// Registered parameter selected on a channel by CC 101/100.
+#[derive(Debug, Clone, Copy, Default)]
+struct RpnState {
+    msb: Option<u8>,
+    lsb: Option<u8>,
+}
+
+impl RpnState {
+    fn selected(&self) -> Option<(u8, u8)> {
+        Some((self.msb?, self.lsb?))
+    }
+}
+
+/// Turns MIDI channel messages into `TimedEvent`s, tracking the per-channel
+/// state they depend on: MPE zones, pitch bend ranges set over RPN and the
+/// bank selected for program changes.
+#[derive(Debug, Clone)]
+pub struct MidiDecoder {
+    source: NoteSource,
+    mpe: MpeConfig,
+    rpn: [RpnState; 16],
+    bend_ranges: [f32; 16], // For channels outside any MPE zone
+    banks: [(u8, u8); 16],  // Bank Select MSB and LSB
+}
+
+impl MidiDecoder {
+    pub fn new(source: NoteSource) -> Self {
+        Self {
+            source,
+            mpe: MpeConfig::default(),
+            rpn: [RpnState::default(); 16],
+            bend_ranges: [DEFAULT_MASTER_BEND_RANGE; 16],
+            banks: [(0, 0); 16],
+        }
+    }
+    pub fn mpe_config(&self) -> &MpeConfig {
+        &self.mpe
+    }
+    pub fn set_mpe_config(&mut self, config: MpeConfig) {
+        self.mpe = config;
+    }
+
+    /// Decode one channel, clock or transport message. Messages that only change decoder
+    /// state (RPNs, MPE configuration, bank select) and unsupported messages return `None`.
+    pub fn decode(&mut self, message: &[u8]) -> Option<TimedEvent> {
+        let status = *message.first()?;
+        let clock = match status {
+            0xF8 => Some(ClockMessage::Tick),
+            0xFA => Some(ClockMessage::Start),
+            0xFB => Some(ClockMessage::Continue),
+            0xFC => Some(ClockMessage::Stop),
+            0xF2 => {
+                let lsb = *message.get(1)? as u16;
+                let msb = *message.get(2)? as u16;
+                Some(ClockMessage::SongPosition((msb << 7) | lsb))
+            }
+            _ => None,
+        };
+        if let Some(message) = clock {
+            return Some(ClockEvent::new(message).into());
+        }
+        if !(0x80..0xF0).contains(&status) {
+            return None;
+        }
+        let channel = status & 0x0F;
+        let data1 = message.get(1).copied().unwrap_or(0);
+        let data2 = message.get(2).copied();
+        match status & 0xF0 {
+            0x90 if data2? > 0 => Some(
+                NoteEvent::new(data1, data2?, true, self.source)
+                    .ok()?
+                    .with_channel(channel)
+                    .into(),
+            ),
+            0x80 | 0x90 => Some(
+                NoteEvent::new(data1, 0, false, self.source)
+                    .ok()?
+                    .with_channel(channel)
+                    .into(),
+            ),
+            0xE0 => {
+                let value = (((data2? as u16) << 7) | data1 as u16) as f32 - 8192.0;
+                let semitones = value / 8192.0 * self.bend_range(channel);
+                Some(self.expression(channel, ExpressionKind::PitchBend(semitones)))
+            }
+            0xD0 => Some(self.expression(channel, ExpressionKind::Pressure(data1 as f32 / 127.0))),
+            0xB0 => self.control_change(channel, data1, data2?),
+            0xC0 => {
+                let (msb, lsb) = self.banks[channel as usize];
+                let bank = ((msb as u16) << 7) | lsb as u16;
+                Some(ProgramEvent::new(channel, bank, data1 & 0x7F).into())
+            }
+            _ => None,
+        }
+    }
+
+    fn expression(&self, channel: u8, kind: ExpressionKind) -> TimedEvent {
+        let target = match self.mpe.channel_role(channel) {
+            ChannelRole::Master(_) => ExpressionTarget::Global,
+            _ => ExpressionTarget::Channel(channel),
+        };
+        TimedEvent::Expression(ExpressionEvent::new(target, kind))
+    }
+
+    fn control_change(&mut self, channel: u8, controller: u8, value: u8) -> Option<TimedEvent> {
+        let rpn = &mut self.rpn[channel as usize];
+        match controller {
+            CC_TIMBRE => {
+                return Some(self.expression(channel, ExpressionKind::Timbre(value as f32 / 127.0)))
+            }
+            CC_BANK_SELECT_MSB => self.banks[channel as usize].0 = value,
+            CC_BANK_SELECT_LSB => self.banks[channel as usize].1 = value,
+            CC_RPN_MSB => rpn.msb = Some(value),
+            CC_RPN_LSB => rpn.lsb = Some(value),
+            // An NRPN selection deselects the RPN so data entry isn't misapplied
+            CC_NRPN_MSB | CC_NRPN_LSB => *rpn = RpnState::default(),
+            CC_DATA_ENTRY_MSB => match rpn.selected() {
+                Some(RPN_PITCH_BEND_SENSITIVITY) => self.set_bend_range(channel, value as f32),
+                Some(RPN_MPE_CONFIGURATION) => match channel {
+                    LOWER_MASTER_CHANNEL => self.mpe.set_zone(MpeZone::Lower, value),
+                    UPPER_MASTER_CHANNEL => self.mpe.set_zone(MpeZone::Upper, value),
+                    _ => {}
+                },
+                _ => {}
+            },
+            // Pitch bend sensitivity LSB is in cents
+            CC_DATA_ENTRY_LSB if rpn.selected() == Some(RPN_PITCH_BEND_SENSITIVITY) => {
+                let semitones = self.bend_range(channel).trunc();
+                self.set_bend_range(channel, semitones + value.min(99) as f32 / 100.0);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn bend_range(&self, channel: u8) -> f32 {
+        match self.mpe.channel_role(channel) {
+            ChannelRole::Conventional => self.bend_ranges[channel as usize],
+            _ => self.mpe.bend_range(channel),
+        }
+    }
+
+    /// Pitch bend sensitivity sent on any member channel applies to the whole zone.
+    fn set_bend_range(&mut self, channel: u8, semitones: f32) {
+        match self.mpe.channel_role(channel) {
+            ChannelRole::Member(zone) => {
+                if let Some(zone) = self.mpe.zone_mut(zone) {
+                    zone.note_bend_range = semitones;
+                }
+            }
+            ChannelRole::Master(zone) => {
+                if let Some(zone) = self.mpe.zone_mut(zone) {
+                    zone.master_bend_range = semitones;
+                }
+            }
+            ChannelRole::Conventional => self.bend_ranges[channel as usize] = semitones,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expression(event: Option<TimedEvent>) -> ExpressionEvent {
+        match event {
+            Some(TimedEvent::Expression(event)) => event,
+            other => panic!("Expected an expression event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_mpe_configuration_and_per_note_bend() {
+        let mut decoder = MidiDecoder::new(NoteSource::Midi);
+        // MPE Configuration Message: lower zone with 7 member channels
+        for message in [[0xB0, 101, 0], [0xB0, 100, 6], [0xB0, 6, 7]] {
+            assert!(decoder.decode(&message).is_none());
+        }
+        assert_eq!(decoder.mpe_config().lower.unwrap().member_channels, 7);
+
+        // Full upward bend on member channel 2 uses the 48 semitone default
+        let bend = expression(decoder.decode(&[0xE2, 0x7F, 0x7F]));
+        assert_eq!(bend.target, ExpressionTarget::Channel(2));
+        match bend.kind {
+            ExpressionKind::PitchBend(semitones) => assert!((semitones - 48.0).abs() < 0.01),
+            other => panic!("Expected pitch bend, got {:?}", other),
+        }
+
+        // The master channel affects every note
+        let pressure = expression(decoder.decode(&[0xD0, 127]));
+        assert_eq!(pressure.target, ExpressionTarget::Global);
+
+        // Pitch bend sensitivity on a member channel changes the whole zone
+        for message in [[0xB3, 101, 0], [0xB3, 100, 0], [0xB3, 6, 24]] {
+            decoder.decode(&message);
+        }
+        assert_eq!(decoder.mpe_config().lower.unwrap().note_bend_range, 24.0);
+
+        match decoder.decode(&[0x95, 60, 100]) {
+            Some(TimedEvent::Note(note)) => assert_eq!(note.channel, 5),
+            other => panic!("Expected a note, got {:?}", other),
+        }
+    }
+}
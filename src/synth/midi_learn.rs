@@ -0,0 +1,501 @@
+//! MIDI learn: bind incoming controllers (7-bit CCs, 14-bit CC pairs and
+//! NRPNs) to synth parameters, each with its own range and response curve.
+//!
+//! Runtimes feed raw channel messages to `MidiLearn::handle_message` ahead of
+//! the `MidiDecoder`, and apply the parameter changes it returns. A message
+//! that changes a parameter is used up, so a learned CC 74 moves its parameter
+//! without also sweeping the MPE timbre.
+use super::error::SynthError;
+use super::params::ParamId;
+use std::fmt;
+use std::str::FromStr;
+
+const CC_DATA_ENTRY_MSB: u8 = 6;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_NRPN_LSB: u8 = 98;
+const CC_NRPN_MSB: u8 = 99;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+/// CCs 120-127 are channel mode messages, not controllers.
+const CC_CHANNEL_MODE: u8 = 120;
+/// CCs 0-31 can pair with CC + 32 as their least significant 7 bits.
+const CC_LSB_OFFSET: u8 = 32;
+const MAX_14_BIT: f32 = 16383.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlSource {
+    Cc {
+        channel: u8,
+        controller: u8,
+    },
+    /// A 14-bit pair: `controller` (0-31) sends the high 7 bits, `controller + 32` the low ones.
+    Cc14 {
+        channel: u8,
+        controller: u8,
+    },
+    Nrpn {
+        channel: u8,
+        number: u16,
+    },
+}
+
+impl ControlSource {
+    pub fn channel(&self) -> u8 {
+        match *self {
+            ControlSource::Cc { channel, .. }
+            | ControlSource::Cc14 { channel, .. }
+            | ControlSource::Nrpn { channel, .. } => channel,
+        }
+    }
+}
+
+/// How the controller's travel maps onto the parameter's normalized range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlCurve {
+    #[default]
+    Linear,
+    /// Fine control at the bottom of the travel.
+    Exponential,
+    /// Fine control at the top of the travel.
+    Logarithmic,
+}
+
+impl ControlCurve {
+    fn apply(&self, x: f32) -> f32 {
+        match self {
+            ControlCurve::Linear => x,
+            ControlCurve::Exponential => x * x,
+            ControlCurve::Logarithmic => 1.0 - (1.0 - x) * (1.0 - x),
+        }
+    }
+}
+
+impl FromStr for ControlCurve {
+    type Err = SynthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(ControlCurve::Linear),
+            "exponential" => Ok(ControlCurve::Exponential),
+            "logarithmic" => Ok(ControlCurve::Logarithmic),
+            _ => Err(SynthError::unknown("control curve", s)),
+        }
+    }
+}
+
+impl fmt::Display for ControlCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ControlCurve::Linear => "linear",
+            ControlCurve::Exponential => "exponential",
+            ControlCurve::Logarithmic => "logarithmic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControlMapping {
+    pub source: ControlSource,
+    pub id: ParamId,
+    /// Normalized parameter values at the bottom and top of the controller's
+    /// travel; `min` above `max` inverts it.
+    pub min: f32,
+    pub max: f32,
+    pub curve: ControlCurve,
+}
+
+impl ControlMapping {
+    pub fn new(source: ControlSource, id: ParamId) -> Self {
+        Self {
+            source,
+            id,
+            min: 0.0,
+            max: 1.0,
+            curve: ControlCurve::Linear,
+        }
+    }
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.min = min.clamp(0.0, 1.0);
+        self.max = max.clamp(0.0, 1.0);
+        self
+    }
+    pub fn with_curve(mut self, curve: ControlCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Normalized parameter value for a controller position from 0 to 1.
+    pub fn normalized_value(&self, position: f32) -> f32 {
+        let shaped = self.curve.apply(position.clamp(0.0, 1.0));
+        self.min + (self.max - self.min) * shaped
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        let (kind, number) = match self.source {
+            ControlSource::Cc { controller, .. } => ("cc", controller as u16),
+            ControlSource::Cc14 { controller, .. } => ("cc14", controller as u16),
+            ControlSource::Nrpn { number, .. } => ("nrpn", number),
+        };
+        serde_json::json!({
+            "param": self.id.to_string(),
+            "type": kind,
+            "channel": self.source.channel(),
+            "number": number,
+            "min": self.min,
+            "max": self.max,
+            "curve": self.curve.to_string(),
+        })
+    }
+
+    fn from_json(value: &serde_json::Value) -> Result<Self, SynthError> {
+        let id = value
+            .get("param")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| SynthError::InvalidMidiMapping("no param".to_string()))?
+            .parse::<ParamId>()?;
+        let number = value
+            .get("number")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| SynthError::InvalidMidiMapping("no controller number".to_string()))?;
+        let channel = value.get("channel").and_then(|v| v.as_u64()).unwrap_or(0);
+        if channel > 15 {
+            return Err(SynthError::InvalidMidiMapping(format!(
+                "invalid channel {} for {}",
+                channel, id
+            )));
+        }
+        let channel = channel as u8;
+        let source = match value.get("type").and_then(|v| v.as_str()).unwrap_or("cc") {
+            "cc" if number < 128 => ControlSource::Cc {
+                channel,
+                controller: number as u8,
+            },
+            "cc14" if number < CC_LSB_OFFSET as u64 => ControlSource::Cc14 {
+                channel,
+                controller: number as u8,
+            },
+            "nrpn" if number <= MAX_14_BIT as u64 => ControlSource::Nrpn {
+                channel,
+                number: number as u16,
+            },
+            kind => {
+                return Err(SynthError::InvalidMidiMapping(format!(
+                    "invalid {} controller number {} for {}",
+                    kind, number, id
+                )))
+            }
+        };
+        let bound = |key: &str, default: f32| {
+            value
+                .get(key)
+                .and_then(|v| v.as_f64())
+                .map_or(default, |v| v as f32)
+        };
+        let curve = match value.get("curve").and_then(|v| v.as_str()) {
+            Some(curve) => curve.parse()?,
+            None => ControlCurve::Linear,
+        };
+        Ok(ControlMapping::new(source, id)
+            .with_range(bound("min", 0.0), bound("max", 1.0))
+            .with_curve(curve))
+    }
+}
+
+/// Per-channel controller state needed to assemble 14-bit values.
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    cc_msb: [u8; CC_LSB_OFFSET as usize],
+    nrpn_msb: Option<u8>,
+    nrpn_lsb: Option<u8>,
+    rpn_selected: bool, // Data entry then belongs to the RPN, which the decoder handles
+    data_msb: u8,
+}
+
+impl ChannelState {
+    fn nrpn(&self) -> Option<u16> {
+        Some(((self.nrpn_msb? as u16) << 7) | self.nrpn_lsb? as u16)
+    }
+}
+
+/// What to bind the next controller to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LearnTarget {
+    id: ParamId,
+    min: f32,
+    max: f32,
+    curve: ControlCurve,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MidiLearn {
+    mappings: Vec<ControlMapping>,
+    learning: Option<LearnTarget>,
+    /// The CC just learned, upgraded to a 14-bit pair if its LSB follows.
+    last_learned: Option<usize>,
+    channels: [ChannelState; 16],
+}
+
+impl MidiLearn {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind the next controller that moves to `id`. Any existing mapping
+    /// for `id` is replaced once it does.
+    pub fn start_learning(&mut self, id: ParamId, min: f32, max: f32, curve: ControlCurve) {
+        self.learning = Some(LearnTarget {
+            id,
+            min: min.clamp(0.0, 1.0),
+            max: max.clamp(0.0, 1.0),
+            curve,
+        });
+    }
+    pub fn cancel_learning(&mut self) {
+        self.learning = None;
+    }
+    /// The parameter waiting for a controller, if learning.
+    pub fn learning(&self) -> Option<ParamId> {
+        self.learning.map(|target| target.id)
+    }
+
+    pub fn mappings(&self) -> &[ControlMapping] {
+        &self.mappings
+    }
+    /// Add a mapping, replacing any other for the same parameter or controller.
+    pub fn map(&mut self, mapping: ControlMapping) {
+        self.mappings
+            .retain(|m| m.id != mapping.id && m.source != mapping.source);
+        self.mappings.push(mapping);
+        self.last_learned = None;
+    }
+    pub fn unmap(&mut self, id: ParamId) {
+        self.mappings.retain(|m| m.id != id);
+        self.last_learned = None;
+    }
+    pub fn clear(&mut self) {
+        self.mappings.clear();
+        self.last_learned = None;
+    }
+
+    /// Feed a raw MIDI message. Returns the parameter and its new normalized
+    /// value if the message moved a mapped controller.
+    pub fn handle_message(&mut self, message: &[u8]) -> Option<(ParamId, f32)> {
+        let status = *message.first()?;
+        if status & 0xF0 != 0xB0 {
+            return None;
+        }
+        let channel = status & 0x0F;
+        let controller = *message.get(1)? & 0x7F;
+        let value = *message.get(2)? & 0x7F;
+        let state = &mut self.channels[channel as usize];
+
+        // Work out which sources this message moves, and to where
+        let mut moved: [Option<(ControlSource, f32)>; 2] = [None, None];
+        match controller {
+            CC_NRPN_MSB | CC_NRPN_LSB => {
+                if controller == CC_NRPN_MSB {
+                    state.nrpn_msb = Some(value);
+                } else {
+                    state.nrpn_lsb = Some(value);
+                }
+                state.rpn_selected = false;
+                return None;
+            }
+            CC_RPN_MSB | CC_RPN_LSB => {
+                state.nrpn_msb = None;
+                state.nrpn_lsb = None;
+                state.rpn_selected = true;
+                return None;
+            }
+            CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB if state.rpn_selected => return None,
+            CC_CHANNEL_MODE.. => return None,
+            CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB if state.nrpn().is_some() => {
+                let number = state.nrpn().unwrap_or(0);
+                let lsb = if controller == CC_DATA_ENTRY_MSB {
+                    state.data_msb = value;
+                    0
+                } else {
+                    value
+                };
+                let position = (((state.data_msb as u16) << 7) | lsb as u16) as f32 / MAX_14_BIT;
+                moved[0] = Some((ControlSource::Nrpn { channel, number }, position));
+            }
+            _ => {}
+        }
+        if moved[0].is_none() {
+            moved[0] = Some((
+                ControlSource::Cc {
+                    channel,
+                    controller,
+                },
+                value as f32 / 127.0,
+            ));
+            if controller < CC_LSB_OFFSET {
+                state.cc_msb[controller as usize] = value;
+                let position = ((value as u16) << 7) as f32 / MAX_14_BIT;
+                moved[1] = Some((
+                    ControlSource::Cc14 {
+                        channel,
+                        controller,
+                    },
+                    position,
+                ));
+            } else if controller < 2 * CC_LSB_OFFSET {
+                let msb_controller = controller - CC_LSB_OFFSET;
+                let msb = state.cc_msb[msb_controller as usize];
+                let position = (((msb as u16) << 7) | value as u16) as f32 / MAX_14_BIT;
+                moved[1] = Some((
+                    ControlSource::Cc14 {
+                        channel,
+                        controller: msb_controller,
+                    },
+                    position,
+                ));
+                self.upgrade_to_14_bit(channel, msb_controller);
+            }
+        }
+
+        if let Some(target) = self.learning {
+            // Bind to the most specific source: NRPN data entry, or the CC
+            let (source, position) = moved[0]?;
+            let source = match source {
+                ControlSource::Cc { controller, .. }
+                    if (CC_LSB_OFFSET..2 * CC_LSB_OFFSET).contains(&controller) =>
+                {
+                    // A lone LSB means the MSB was missed; wait for the knob's next move
+                    return None;
+                }
+                source => source,
+            };
+            self.learning = None;
+            self.map(
+                ControlMapping::new(source, target.id)
+                    .with_range(target.min, target.max)
+                    .with_curve(target.curve),
+            );
+            if matches!(source, ControlSource::Cc { .. }) {
+                self.last_learned = Some(self.mappings.len() - 1);
+            }
+            let mapping = self.mappings.last()?;
+            return Some((mapping.id, mapping.normalized_value(position)));
+        }
+
+        let (mapping, position) = moved.iter().flatten().find_map(|&(source, position)| {
+            self.mappings
+                .iter()
+                .find(|m| m.source == source)
+                .map(|m| (m, position))
+        })?;
+        Some((mapping.id, mapping.normalized_value(position)))
+    }
+
+    /// Most controllers that send 14-bit values send the MSB and then the LSB,
+    /// so an LSB straight after learning an MSB CC makes it a 14-bit mapping.
+    fn upgrade_to_14_bit(&mut self, channel: u8, controller: u8) {
+        let Some(index) = self.last_learned.take() else {
+            return;
+        };
+        let mapping = &mut self.mappings[index];
+        if mapping.source
+            == (ControlSource::Cc {
+                channel,
+                controller,
+            })
+        {
+            mapping.source = ControlSource::Cc14 {
+                channel,
+                controller,
+            };
+        }
+    }
+
+    /// Mappings as a JSON array, for saving with a patch or controller profile.
+    pub fn to_json(&self) -> String {
+        serde_json::Value::Array(self.mappings.iter().map(|m| m.to_json()).collect()).to_string()
+    }
+
+    /// Replace the mappings with those in a JSON array from `to_json`.
+    pub fn load_json(&mut self, json: &str) -> Result<(), SynthError> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| SynthError::InvalidJson(e.to_string()))?;
+        let mappings = value
+            .as_array()
+            .ok_or_else(|| SynthError::InvalidMidiMapping("not an array".to_string()))?
+            .iter()
+            .map(ControlMapping::from_json)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.clear();
+        for mapping in mappings {
+            self.map(mapping);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATIO: ParamId = ParamId::OperatorRatio(0);
+
+    #[test]
+    fn test_learns_the_next_cc_with_range_and_curve() {
+        let mut learn = MidiLearn::new();
+        assert_eq!(learn.handle_message(&[0xB0, 74, 127]), None);
+
+        learn.start_learning(RATIO, 0.2, 0.6, ControlCurve::Exponential);
+        let (id, value) = learn.handle_message(&[0xB3, 74, 127]).unwrap();
+        assert_eq!((id, value), (RATIO, 0.6));
+        assert_eq!(learn.learning(), None);
+
+        let (_, value) = learn.handle_message(&[0xB3, 74, 0]).unwrap();
+        assert_eq!(value, 0.2);
+        // Other channels and controllers stay unmapped
+        assert_eq!(learn.handle_message(&[0xB0, 74, 64]), None);
+        assert_eq!(learn.handle_message(&[0xB3, 75, 64]), None);
+    }
+
+    #[test]
+    fn test_msb_then_lsb_learns_a_14_bit_pair() {
+        let mut learn = MidiLearn::new();
+        learn.start_learning(RATIO, 0.0, 1.0, ControlCurve::Linear);
+        learn.handle_message(&[0xB0, 1, 64]);
+        learn.handle_message(&[0xB0, 33, 0]);
+        assert_eq!(
+            learn.mappings()[0].source,
+            ControlSource::Cc14 {
+                channel: 0,
+                controller: 1
+            }
+        );
+        learn.handle_message(&[0xB0, 1, 127]);
+        let (_, value) = learn.handle_message(&[0xB0, 33, 127]).unwrap();
+        assert_eq!(value, 1.0);
+    }
+
+    #[test]
+    fn test_learns_nrpn_and_round_trips_json() {
+        let mut learn = MidiLearn::new();
+        learn.start_learning(RATIO, 1.0, 0.0, ControlCurve::Linear);
+        for message in [[0xB1, 99, 2], [0xB1, 98, 5]] {
+            assert_eq!(learn.handle_message(&message), None);
+        }
+        let (_, value) = learn.handle_message(&[0xB1, 6, 0]).unwrap();
+        assert_eq!(value, 1.0);
+        assert_eq!(
+            learn.mappings()[0].source,
+            ControlSource::Nrpn {
+                channel: 1,
+                number: 261
+            }
+        );
+
+        let mut loaded = MidiLearn::new();
+        loaded.load_json(&learn.to_json()).unwrap();
+        assert_eq!(loaded.mappings(), learn.mappings());
+        assert!(loaded
+            .load_json(r#"[{"param": "op/0/ratio", "type": "cc14", "number": 40}]"#)
+            .is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! MIDI messages for what the synth plays: notes from every source (including
+//! the sequencer and arpeggiator) and parameter changes as CCs. The audio
+//! thread collects them; the runtime drains and sends them to a MIDI output.
+use super::note::NoteEvent;
+use super::params::ParamId;
+
+/// Messages held between drains; more are dropped rather than allocating.
+pub const MIDI_OUTPUT_CAPACITY: usize = 1024;
+const CC_VOLUME: u8 = 7;
+/// CCs 20-31 are undefined in the MIDI spec, so free for operator modulation indices.
+const CC_FIRST_MODULATION_INDEX: u8 = 20;
+const MAPPED_OPERATORS: usize = 12;
+
+#[derive(Debug, Clone)]
+pub struct MidiOutput {
+    enabled: bool,
+    channel: u8, // For CCs; notes keep their own channel
+    messages: Vec<[u8; 3]>,
+    cc_map: Vec<(ParamId, u8)>,
+}
+
+impl Default for MidiOutput {
+    fn default() -> Self {
+        let mut cc_map = vec![(ParamId::MasterVolume, CC_VOLUME)];
+        cc_map.extend((0..MAPPED_OPERATORS).map(|i| {
+            (
+                ParamId::OperatorModulationIndex(i),
+                CC_FIRST_MODULATION_INDEX + i as u8,
+            )
+        }));
+        Self {
+            enabled: false,
+            channel: 0,
+            messages: Vec::new(),
+            cc_map,
+        }
+    }
+}
+
+impl MidiOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start or stop collecting messages. Call before audio starts: enabling
+    /// allocates the message buffer.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.messages.clear();
+        if enabled {
+            self.messages.reserve(MIDI_OUTPUT_CAPACITY);
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Channel (0-15) that parameter CCs are sent on.
+    pub fn set_channel(&mut self, channel: u8) {
+        self.channel = channel & 0x0F;
+    }
+
+    /// Send changes to `id` as `cc`, or stop sending them with `None`.
+    pub fn map_cc(&mut self, id: ParamId, cc: Option<u8>) {
+        self.cc_map.retain(|(mapped, _)| *mapped != id);
+        if let Some(cc) = cc {
+            self.cc_map.push((id, cc & 0x7F));
+        }
+    }
+    pub fn cc_for(&self, id: ParamId) -> Option<u8> {
+        self.cc_map
+            .iter()
+            .find(|(mapped, _)| *mapped == id)
+            .map(|&(_, cc)| cc)
+    }
+
+    pub fn note(&mut self, event: &NoteEvent) {
+        let status = if event.is_on { 0x90 } else { 0x80 };
+        let velocity = if event.is_on { event.velocity } else { 0 };
+        self.push([status | (event.channel & 0x0F), event.note_number, velocity]);
+    }
+
+    /// A parameter change, as its CC if it has one. `normalized` is 0..1.
+    pub fn param(&mut self, id: ParamId, normalized: f32) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(cc) = self.cc_for(id) {
+            let value = (normalized.clamp(0.0, 1.0) * 127.0).round() as u8;
+            self.push([0xB0 | self.channel, cc, value]);
+        }
+    }
+
+    fn push(&mut self, message: [u8; 3]) {
+        if self.enabled && self.messages.len() < self.messages.capacity() {
+            self.messages.push(message);
+        }
+    }
+
+    /// Take the messages collected since the last drain, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = [u8; 3]> + '_ {
+        self.messages.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::note::NoteSource;
+
+    #[test]
+    fn test_collects_notes_and_mapped_ccs_only_when_enabled() {
+        let mut output = MidiOutput::new();
+        let note = NoteEvent::new(60, 100, true, NoteSource::Sequencer).unwrap();
+        output.note(&note);
+        assert_eq!(output.drain().count(), 0);
+
+        output.set_enabled(true);
+        output.set_channel(2);
+        output.note(&note.with_channel(1));
+        output.param(ParamId::OperatorModulationIndex(1), 0.5);
+        output.param(ParamId::OperatorRatio(0), 1.0); // Not mapped
+        output.map_cc(ParamId::MasterVolume, None);
+        output.param(ParamId::MasterVolume, 1.0);
+        let messages: Vec<[u8; 3]> = output.drain().collect();
+        assert_eq!(messages, vec![[0x91, 60, 100], [0xB2, 21, 64]]);
+    }
+}
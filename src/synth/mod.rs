@@ -1,4 +1,6 @@
 pub mod algorithm;
+pub mod arpeggiator;
+pub mod clock;
 pub mod config;
 pub mod context;
 pub mod core;
@@ -6,12 +8,30 @@ pub mod delayline;
 pub mod diffuser;
 pub mod effect;
 pub mod envelope;
+pub mod error;
+pub mod event;
+pub mod expression;
 pub mod filter;
+pub mod handoff;
+pub mod midi;
+pub mod midi_learn;
+pub mod midi_out;
+pub mod mpe;
 pub mod note;
 pub mod operator;
+pub mod osc;
+pub mod params;
+pub mod part;
 pub mod prelude;
+pub mod preset;
+pub mod render;
 pub mod reverb;
+pub mod simd;
 pub use core::Synth;
+pub mod sequencer;
+pub mod smf;
+pub mod transport;
+pub mod tuning;
 pub mod voice;
 pub mod voice_config;
 pub mod waveform;
@@ -0,0 +1,149 @@
+//! MIDI Polyphonic Expression zone configuration.
+//!
+//! The lower zone uses channel 1 (index 0) as its master channel and the
+//! channels above it as member channels; the upper zone uses channel 16
+//! (index 15) and the channels below it. Each note on a member channel gets
+//! its own pitch bend, pressure and timbre.
+
+pub const LOWER_MASTER_CHANNEL: u8 = 0;
+pub const UPPER_MASTER_CHANNEL: u8 = 15;
+/// Default per-note pitch bend range in semitones, from the MPE specification.
+pub const DEFAULT_NOTE_BEND_RANGE: f32 = 48.0;
+/// Default master channel pitch bend range in semitones.
+pub const DEFAULT_MASTER_BEND_RANGE: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpeZone {
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneConfig {
+    pub member_channels: u8, // 1-15
+    pub note_bend_range: f32,
+    pub master_bend_range: f32,
+}
+
+impl ZoneConfig {
+    pub fn new(member_channels: u8) -> Self {
+        Self {
+            member_channels: member_channels.clamp(1, 15),
+            note_bend_range: DEFAULT_NOTE_BEND_RANGE,
+            master_bend_range: DEFAULT_MASTER_BEND_RANGE,
+        }
+    }
+}
+
+/// What a channel is used for under the current configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelRole {
+    Master(MpeZone),
+    Member(MpeZone),
+    /// Not part of any zone; handled like a regular MIDI channel.
+    Conventional,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MpeConfig {
+    pub lower: Option<ZoneConfig>,
+    pub upper: Option<ZoneConfig>,
+}
+
+impl MpeConfig {
+    /// A single lower zone using all 15 member channels, as most MPE controllers default to.
+    pub fn lower_zone() -> Self {
+        Self {
+            lower: Some(ZoneConfig::new(15)),
+            upper: None,
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.lower.is_some() || self.upper.is_some()
+    }
+    pub fn zone(&self, zone: MpeZone) -> Option<&ZoneConfig> {
+        match zone {
+            MpeZone::Lower => self.lower.as_ref(),
+            MpeZone::Upper => self.upper.as_ref(),
+        }
+    }
+    pub fn zone_mut(&mut self, zone: MpeZone) -> Option<&mut ZoneConfig> {
+        match zone {
+            MpeZone::Lower => self.lower.as_mut(),
+            MpeZone::Upper => self.upper.as_mut(),
+        }
+    }
+
+    /// Configure a zone, as an MPE Configuration Message does. Zero member
+    /// channels disables the zone. A new zone shrinks the other one so they
+    /// never share channels.
+    pub fn set_zone(&mut self, zone: MpeZone, member_channels: u8) {
+        if member_channels == 0 {
+            match zone {
+                MpeZone::Lower => self.lower = None,
+                MpeZone::Upper => self.upper = None,
+            }
+            return;
+        }
+        let config = ZoneConfig::new(member_channels);
+        let (this, other) = match zone {
+            MpeZone::Lower => (&mut self.lower, &mut self.upper),
+            MpeZone::Upper => (&mut self.upper, &mut self.lower),
+        };
+        // Keep bend ranges already set for this zone
+        let config = match this {
+            Some(existing) => ZoneConfig {
+                member_channels: config.member_channels,
+                ..*existing
+            },
+            None => config,
+        };
+        *this = Some(config);
+        // Two zones share 16 channels, including both masters
+        let available = 14u8.saturating_sub(config.member_channels);
+        if let Some(other_config) = other {
+            if available == 0 {
+                *other = None;
+            } else {
+                other_config.member_channels = other_config.member_channels.min(available);
+            }
+        }
+    }
+
+    pub fn channel_role(&self, channel: u8) -> ChannelRole {
+        if let Some(lower) = self.lower {
+            if channel == LOWER_MASTER_CHANNEL {
+                return ChannelRole::Master(MpeZone::Lower);
+            }
+            if channel > LOWER_MASTER_CHANNEL
+                && channel <= LOWER_MASTER_CHANNEL + lower.member_channels
+            {
+                return ChannelRole::Member(MpeZone::Lower);
+            }
+        }
+        if let Some(upper) = self.upper {
+            if channel == UPPER_MASTER_CHANNEL {
+                return ChannelRole::Master(MpeZone::Upper);
+            }
+            if channel < UPPER_MASTER_CHANNEL
+                && channel >= UPPER_MASTER_CHANNEL - upper.member_channels
+            {
+                return ChannelRole::Member(MpeZone::Upper);
+            }
+        }
+        ChannelRole::Conventional
+    }
+
+    /// Pitch bend range in semitones for messages on `channel`.
+    pub fn bend_range(&self, channel: u8) -> f32 {
+        match self.channel_role(channel) {
+            ChannelRole::Member(zone) => self
+                .zone(zone)
+                .map_or(DEFAULT_NOTE_BEND_RANGE, |z| z.note_bend_range),
+            ChannelRole::Master(zone) => self
+                .zone(zone)
+                .map_or(DEFAULT_MASTER_BEND_RANGE, |z| z.master_bend_range),
+            ChannelRole::Conventional => DEFAULT_MASTER_BEND_RANGE,
+        }
+    }
+}
@@ -7,6 +7,8 @@ pub struct NoteEvent {
     pub is_on: bool,
     pub frequency: f32,
     pub source: NoteSource,
+    pub channel: u8,         // MIDI channel (0-15); MPE gives every note its own
+    pub frame_offset: usize, // Frame within the next rendered buffer at which the event applies
 }
 
 impl NoteEvent {
@@ -35,9 +37,21 @@ impl NoteEvent {
             is_on,
             frequency,
             source,
+            channel: 0,
+            frame_offset: 0,
         })
     }
 
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = channel & 0x0F;
+        self
+    }
+
+    pub fn with_frame_offset(mut self, frame_offset: usize) -> Self {
+        self.frame_offset = frame_offset;
+        self
+    }
+
     pub fn validate(&self) -> Result<(), NoteError> {
         if self.note_number >= 128 {
             return Err(NoteError::InvalidNoteNumber(self.note_number));
@@ -57,14 +71,14 @@ fn midi_frequencies() -> &'static [f32; 128] {
 
     FREQUENCIES.get_or_init(|| {
         let mut frequencies = [0.0; 128];
-        for note in 0..128 {
-            frequencies[note] = 440.0 * 2.0f32.powf((note as f32 - 69.0) / 12.0);
+        for (note, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = 440.0 * 2.0f32.powf((note as f32 - 69.0) / 12.0);
         }
         frequencies
     })
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum NoteError {
     InvalidNoteNumber(u8),
     InvalidVelocity(u8),
@@ -90,5 +104,7 @@ pub enum NoteSource {
     Sequencer,
     Keyboard,
     Midi,
+    Arpeggiator,
+    Osc,
     // Add other sources as needed
 }
@@ -1,9 +1,19 @@
 use super::context::ProcessContext;
 use super::core::MODULATION_INDEX_GAIN_OFFSET;
 use super::envelope::EnvelopeGenerator;
-use super::filter::{Filter, FilterType};
+use super::filter::{Filter, FilterType, LowPassBiquadState};
+use super::simd::{self, SineChunk};
 use super::waveform::{Waveform, WaveformGenerator};
 use crate::synth::prelude::TAU;
+use crate::utils::logging;
+use crate::utils::smoothing::{
+    LinearSmoother, MultiplicativeSmoother, OnePoleSmoother, Smoother,
+};
+use log::{trace, warn};
+
+/// Longest stretch over which the vectorized path interpolates the envelope
+/// between two exact evaluations.
+const ENVELOPE_STEP: usize = 8;
 
 #[derive(Clone, Copy, Debug)]
 pub enum CycleDirection {
@@ -13,34 +23,88 @@ pub enum CycleDirection {
 
 #[derive(Clone, Copy, Debug)]
 pub enum OperatorEvent {
-    CycleWaveform { direction: CycleDirection },
+    CycleWaveform {
+        direction: CycleDirection,
+        frame_offset: usize,
+    },
     // We can add more operator events here in the future
 }
+impl OperatorEvent {
+    /// Frame within the next rendered buffer at which the event applies.
+    pub fn frame_offset(&self) -> usize {
+        match self {
+            OperatorEvent::CycleWaveform { frame_offset, .. } => *frame_offset,
+        }
+    }
+    pub fn with_frame_offset(mut self, offset: usize) -> Self {
+        match &mut self {
+            OperatorEvent::CycleWaveform { frame_offset, .. } => *frame_offset = offset,
+        }
+        self
+    }
+}
 
-const RATIO_SMOOTHING_FACTOR: f32 = 0.001;
-const MODULATION_INDEX_SMOOTHING_FACTOR: f32 = 0.001; // Add a factor for modulation index
-
+/// Per-voice operator state. The smoothers glide each continuously settable
+/// parameter from its previous value to the one currently set on the `Operator`.
 #[derive(Clone, Debug)]
 pub struct OperatorState {
     current_phase: f32,
-    current_ratio: Option<f32>,
-    current_modulation_index: Option<f32>, // Add field for smoothed mod index
+    ratio: MultiplicativeSmoother,
+    fixed_frequency: MultiplicativeSmoother,
+    detune: LinearSmoother, // In cents
+    modulation_index: OnePoleSmoother,
+    sustain: OnePoleSmoother,
     filters: Option<Vec<Filter>>,
+    feedback_history: [f32; 2], // Last two output samples, newest first
     pub finished: bool,
 }
 impl Default for OperatorState {
     fn default() -> Self {
         Self {
             current_phase: 0.0,
-            current_ratio: None,
-            current_modulation_index: None, // Initialize as None
+            ratio: MultiplicativeSmoother::default(),
+            fixed_frequency: MultiplicativeSmoother::default(),
+            detune: LinearSmoother::default(),
+            modulation_index: OnePoleSmoother::default(),
+            sustain: OnePoleSmoother::default(),
             filters: None,
+            feedback_history: [0.0; 2],
             finished: false,
         }
     }
 }
+impl OperatorState {
+    /// Back to a fresh voice's state, keeping the filter chain's memory.
+    pub(crate) fn reset(&mut self) {
+        let filters = self.filters.take();
+        *self = Self {
+            filters,
+            ..Self::default()
+        };
+    }
+    /// Remember an output sample for feedback paths reading this operator.
+    pub(crate) fn push_output(&mut self, sample: f32) {
+        self.feedback_history = [sample, self.feedback_history[0]];
+    }
+    /// The output from one sample ago, or with `averaged` the mean of the
+    /// last two samples.
+    pub(crate) fn delayed_output(&self, averaged: bool) -> f32 {
+        if averaged {
+            (self.feedback_history[0] + self.feedback_history[1]) * 0.5
+        } else {
+            self.feedback_history[0]
+        }
+    }
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.ratio.set_sample_rate(sample_rate);
+        self.fixed_frequency.set_sample_rate(sample_rate);
+        self.detune.set_sample_rate(sample_rate);
+        self.modulation_index.set_sample_rate(sample_rate);
+        self.sustain.set_sample_rate(sample_rate);
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Operator {
     pub waveform_generator: WaveformGenerator,
     frequency_ratio: f32,         // Ratio relative to the voice's base frequency
@@ -48,6 +112,7 @@ pub struct Operator {
     fixed_frequency: Option<f32>, // Optional fixed frequency in Hz
     pub envelope: EnvelopeGenerator, // Operator-specific envelope (optional)
     pub modulation_index: f32,
+    feedback: f32, // Scales the output fed back to this operator through a cycle
     pub gain: f32, // Output gain of this operator
     pub filters: Option<Vec<Filter>>,
 }
@@ -69,37 +134,90 @@ impl Operator {
             // Basic validation
             return;
         }
-        let sample_rate = context.sample_rate;
-        // --- State Initialization ---
-        state.current_ratio.get_or_insert(self.frequency_ratio);
-        state
-            .current_modulation_index
-            .get_or_insert(self.modulation_index);
+        self.update_targets(context, state);
+        // Feedback paths render one sample at a time, which is quicker scalar
+        if buffer_len >= ENVELOPE_STEP && self.renders_vectorized(state) {
+            self.process_vectorized(context, modulation, state, output);
+        } else {
+            self.process_scalar(context, modulation, state, output);
+        }
+    }
+
+    /// Render one sample, for feedback paths. `update_targets` must have been
+    /// called once for the buffer the sample is in.
+    pub(crate) fn process_sample(
+        &self,
+        context: &ProcessContext,
+        modulation: f32,
+        state: &mut OperatorState,
+    ) -> f32 {
+        let mut output = 0.0;
+        self.process_scalar(
+            context,
+            std::slice::from_ref(&modulation),
+            state,
+            std::slice::from_mut(&mut output),
+        );
+        output
+    }
 
-        let mut current_smoothed_ratio = state.current_ratio.unwrap_or(self.frequency_ratio);
-        let mut current_smoothed_modulation_index = state
-            .current_modulation_index
-            .unwrap_or(self.modulation_index); // Get current smoothed mod index
+    /// Point the voice's smoothers and filters at the operator's settings.
+    pub(crate) fn update_targets(&self, context: &ProcessContext, state: &mut OperatorState) {
+        // --- Smoothing Targets ---
+        // The first targets set on a fresh voice apply immediately
+        state.set_sample_rate(context.sample_rate);
+        state.ratio.set_target(self.frequency_ratio);
+        if let Some(fixed_freq) = self.fixed_frequency {
+            state.fixed_frequency.set_target(fixed_freq);
+        }
+        state.detune.set_target(self.detune);
+        state
+            .modulation_index
+            .set_target(self.modulation_index * context.modulation_index_scale);
+        state.sustain.set_target(self.envelope.sustain);
 
         self.manage_states(state, context);
+        self.sync_filter_targets(state, context.cutoff_scale);
+    }
 
-        // Generate the waveform using the WaveformGenerator
+    /// Sine operators without filters, whose parameters aren't gliding, go
+    /// through `process_vectorized`.
+    fn renders_vectorized(&self, state: &OperatorState) -> bool {
+        self.waveform_generator.waveform == Waveform::Sine
+            && state
+                .filters
+                .as_ref()
+                .is_none_or(|filters| filters.is_empty())
+            && state.ratio.is_settled()
+            && (self.fixed_frequency.is_none() || state.fixed_frequency.is_settled())
+            && state.detune.is_settled()
+            && state.modulation_index.is_settled()
+            && state.sustain.is_settled()
+    }
+
+    /// The reference rendering path, which works one sample at a time.
+    #[inline(always)]
+    fn process_scalar(
+        &self,
+        context: &ProcessContext,
+        modulation: &[f32],
+        state: &mut OperatorState,
+        output: &mut [f32],
+    ) {
+        let sample_rate = context.sample_rate;
         let mut silent_buffer = true;
         for (i, sample) in output.iter_mut().enumerate() {
-            // --- Smooth Ratio ---
-            current_smoothed_ratio +=
-                (self.frequency_ratio - current_smoothed_ratio) * RATIO_SMOOTHING_FACTOR;
-            // --- Smooth Modulation Index ---
-            current_smoothed_modulation_index += (self.modulation_index
-                - current_smoothed_modulation_index)
-                * MODULATION_INDEX_SMOOTHING_FACTOR;
+            let ratio = state.ratio.next_value();
+            let modulation_index = state.modulation_index.next_value();
+            let detune = state.detune.next_value();
+            let sustain = state.sustain.next_value();
 
             // Determine the actual frequency for this operator
             let actual_frequency = match self.fixed_frequency {
-                Some(fixed_freq) => fixed_freq,
-                None => context.base_frequency * current_smoothed_ratio,
+                Some(_) => state.fixed_frequency.next_value(),
+                None => context.base_frequency * ratio,
             };
-            let detuned_frequency = Operator::cents_to_hz(actual_frequency, self.detune);
+            let detuned_frequency = Operator::cents_to_hz(actual_frequency, detune);
             let phase_increment = TAU * detuned_frequency / sample_rate;
             state.current_phase += phase_increment;
             state.current_phase %= TAU;
@@ -111,20 +229,14 @@ impl Operator {
                 self.waveform_generator.evaluate(modulated_phase)
             };
 
-            // --- Envelope Calculation ---
-            let current_sample_abs_idx = context.samples_elapsed_since_trigger + i as u64;
-            let time_since_on = current_sample_abs_idx as f32 / sample_rate;
-            let time_since_off = context
-                .note_off_sample_index
-                .map(|off_idx| current_sample_abs_idx.saturating_sub(off_idx) as f32 / sample_rate);
-            let raw_output = wave * current_smoothed_modulation_index;
+            let raw_output = wave * modulation_index;
             let mut filtered_output = raw_output;
             if let Some(filter_chain) = state.filters.as_mut() {
                 filtered_output = filter_chain
                     .iter_mut()
                     .fold(raw_output, |acc, filter| filter.process(acc));
             }
-            let env = self.envelope.evaluate(time_since_on, time_since_off);
+            let env = self.envelope_at(context, i, sustain);
             let env_output = filtered_output * env;
 
             if env_output.abs() > 1.0e-9 {
@@ -132,38 +244,174 @@ impl Operator {
             }
             *sample = env_output * self.gain;
         }
-        // Store the final smoothed values back into the state
-        state.current_ratio = Some(current_smoothed_ratio);
-        state.current_modulation_index = Some(current_smoothed_modulation_index);
+        if self.finished(context) && silent_buffer {
+            state.finished = true;
+        }
+    }
 
+    /// Renders a whole buffer of a sine with constant frequency and index
+    /// using `simd::render_sine`. The envelope is evaluated exactly every
+    /// `ENVELOPE_STEP` samples and at every segment boundary, and
+    /// interpolated linearly in between.
+    fn process_vectorized(
+        &self,
+        context: &ProcessContext,
+        modulation: &[f32],
+        state: &mut OperatorState,
+        output: &mut [f32],
+    ) {
+        let sample_rate = context.sample_rate;
+        let frequency = match self.fixed_frequency {
+            Some(_) => state.fixed_frequency.current(),
+            None => context.base_frequency * state.ratio.current(),
+        };
+        let phase_increment =
+            TAU * Operator::cents_to_hz(frequency, state.detune.current()) / sample_rate;
+        let sustain = state.sustain.current();
+        let amplitude = state.modulation_index.current() * self.gain;
+
+        // Sample indices, counted from the note on, where the envelope changes segment
+        let samples_at = |seconds: f32| (seconds * sample_rate).ceil() as u64;
+        let boundaries = match context.note_off_sample_index {
+            Some(off) => [off + samples_at(self.envelope.release), u64::MAX],
+            None => [
+                samples_at(self.envelope.attack),
+                samples_at(self.envelope.attack + self.envelope.decay),
+            ],
+        };
+
+        let mut start = 0;
+        let mut next_envelope = None; // Already evaluated at `start`
+        while start < output.len() {
+            let mut end = (start + ENVELOPE_STEP).min(output.len());
+            let mut ends_segment = false;
+            for &boundary in &boundaries {
+                let boundary = boundary.saturating_sub(context.samples_elapsed_since_trigger);
+                if boundary > start as u64 && boundary <= end as u64 {
+                    end = boundary as usize;
+                    ends_segment = true;
+                }
+            }
+
+            // Interpolate towards the first sample after the chunk, or the
+            // last one in it if the next sample starts another segment
+            let envelope = next_envelope
+                .take()
+                .unwrap_or_else(|| self.envelope_at(context, start, sustain));
+            let envelope_increment = if !ends_segment && end < output.len() {
+                let end_envelope = self.envelope_at(context, end, sustain);
+                next_envelope = Some(end_envelope);
+                (end_envelope - envelope) / (end - start) as f32
+            } else if end - 1 > start {
+                let last_envelope = self.envelope_at(context, end - 1, sustain);
+                (last_envelope - envelope) / (end - 1 - start) as f32
+            } else {
+                0.0
+            };
+            let chunk = SineChunk {
+                phase: state.current_phase,
+                phase_increment,
+                envelope,
+                envelope_increment,
+                amplitude,
+            };
+            simd::render_sine(&chunk, &modulation[start..end], &mut output[start..end]);
+
+            state.current_phase =
+                (state.current_phase + (end - start) as f32 * phase_increment) % TAU;
+            start = end;
+        }
+
+        let silent_buffer = output.iter().all(|sample| sample.abs() <= 1.0e-9);
         if self.finished(context) && silent_buffer {
             state.finished = true;
         }
     }
 
+    /// Envelope level at sample `i` of the buffer being rendered.
+    fn envelope_at(&self, context: &ProcessContext, i: usize, sustain: f32) -> f32 {
+        let current_sample_abs_idx = context.samples_elapsed_since_trigger + i as u64;
+        let time_since_on = current_sample_abs_idx as f32 / context.sample_rate;
+        let time_since_off = context.note_off_sample_index.map(|off_idx| {
+            current_sample_abs_idx.saturating_sub(off_idx) as f32 / context.sample_rate
+        });
+        self.envelope
+            .evaluate_with_sustain(time_since_on, time_since_off, sustain)
+    }
+
+    /// Give a voice's state a filter chain like this operator's, allocated up
+    /// front so that starting a note only copies settings into it.
+    pub(crate) fn allocate_filters(&self, state: &mut OperatorState) {
+        let matches = match (state.filters.as_ref(), self.filters.as_ref()) {
+            (Some(voice_filters), Some(filters)) => {
+                voice_filters.len() == filters.len()
+                    && voice_filters
+                        .iter()
+                        .zip(filters)
+                        .all(|(voice_filter, filter)| voice_filter.get_type() == filter.get_type())
+            }
+            (voice_filters, filters) => voice_filters.is_none() && filters.is_none(),
+        };
+        if !matches {
+            state.filters = self
+                .filters
+                .as_ref()
+                .map(|filters| filters.iter().map(Filter::voice_copy).collect());
+        }
+    }
+
     fn manage_states(&self, state: &mut OperatorState, context: &ProcessContext) {
         if context.samples_elapsed_since_trigger == 0 {
-            state.filters = self.filters.clone();
-            if let Some(active_filters) = state.filters.as_mut() {
-                for filter in active_filters.iter_mut() {
-                    if let Filter::PitchedComb(s) = filter {
-                        s.update_k_frequency(
-                            context.sample_rate,
-                            context.base_frequency * state.current_ratio.unwrap_or(1.0),
-                        );
-                    }
+            // Only allocates if the filters changed since the last `allocate_filters`
+            self.allocate_filters(state);
+            let (Some(voice_filters), Some(filters)) =
+                (state.filters.as_mut(), self.filters.as_ref())
+            else {
+                return;
+            };
+            for (voice_filter, filter) in voice_filters.iter_mut().zip(filters) {
+                voice_filter.copy_settings(filter);
+                if let Filter::PitchedComb(s) = voice_filter {
+                    s.update_k_frequency(
+                        context.sample_rate,
+                        context.base_frequency * state.ratio.current(),
+                    );
+                }
+            }
+        }
+    }
+    /// Point the voice's filters at the operator's current settings, scaled by
+    /// the note's expression, so that changes glide instead of stepping.
+    fn sync_filter_targets(&self, state: &mut OperatorState, cutoff_scale: f32) {
+        let (Some(voice_filters), Some(operator_filters)) =
+            (state.filters.as_mut(), self.filters.as_ref())
+        else {
+            return;
+        };
+        for voice_filter in voice_filters.iter_mut() {
+            if let Filter::LowPassBiquad(voice_lowpass) = voice_filter {
+                if let Some(Filter::LowPassBiquad(target)) = operator_filters
+                    .iter()
+                    .find(|f| f.get_type() == FilterType::LowPassBiquad)
+                {
+                    voice_lowpass.set_target_cutoff(target.cutoff() * cutoff_scale);
+                    voice_lowpass.set_q(target.q());
                 }
             }
         }
     }
     pub fn set_amplitude(&mut self, amp: f32) {
-        println!("Setting amplitude: {}", amp);
+        trace!(target: logging::SYNTH, "Operator amplitude set to {}", amp);
         self.gain = amp;
     }
     pub fn set_envelope(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
-        println!(
-            "Operator envelope set to: {}, {}, {}, {}",
-            attack, decay, sustain, release
+        trace!(
+            target: logging::SYNTH,
+            "Operator envelope set to {}, {}, {}, {}",
+            attack,
+            decay,
+            sustain,
+            release
         );
         self.envelope.set_params(attack, decay, sustain, release);
     }
@@ -174,11 +422,11 @@ impl Operator {
     pub fn cycle_waveform(&mut self, direction: CycleDirection) {
         match direction {
             CycleDirection::Forward => {
-                println!("Cycling waveform forward");
+                trace!(target: logging::SYNTH, "Cycling waveform forward");
                 self.waveform_generator.get_next_waveform();
             }
             CycleDirection::Backward => {
-                println!("Cycling waveform backward");
+                trace!(target: logging::SYNTH, "Cycling waveform backward");
                 self.waveform_generator.get_previous_waveform();
             }
         };
@@ -187,7 +435,6 @@ impl Operator {
 
     // Method to update the waveform directly
     pub fn set_waveform(&mut self, waveform: Waveform) {
-        println!("Operator waveform set to: {:?}", waveform);
         self.waveform_generator.set_waveform(waveform);
     }
     pub fn get_waveform(&self) -> Waveform {
@@ -195,7 +442,7 @@ impl Operator {
     }
 
     pub fn set_gain(&mut self, gain: f32) {
-        println!("Operator gain set to: {}", gain);
+        trace!(target: logging::SYNTH, "Operator gain set to {}", gain);
         self.gain = gain;
     }
 
@@ -222,6 +469,25 @@ impl Operator {
             filter_chain.push(filter);
         }
     }
+    /// The operator's low-pass filter, if it has one.
+    pub fn lowpass(&self) -> Option<&LowPassBiquadState> {
+        self.filters
+            .iter()
+            .flatten()
+            .find_map(|filter| match filter {
+                Filter::LowPassBiquad(lowpass) => Some(lowpass),
+                _ => None,
+            })
+    }
+    pub fn lowpass_mut(&mut self) -> Option<&mut LowPassBiquadState> {
+        self.filters
+            .iter_mut()
+            .flatten()
+            .find_map(|filter| match filter {
+                Filter::LowPassBiquad(lowpass) => Some(lowpass),
+                _ => None,
+            })
+    }
     pub fn remove_filter(&mut self, filter_type: FilterType) {
         if let Some(filter_chain) = &mut self.filters {
             // Remove the filter from the chain
@@ -229,19 +495,17 @@ impl Operator {
         }
     }
     pub fn set_ratio(&mut self, ratio: f32) {
-        println!("Operator frequency ratio set to: {}", ratio);
         self.fixed_frequency = None;
         if ratio < 0.0 {
-            eprintln!("Frequency ratio must be non-negative. Frequency clamped to 0.0");
+            warn!(target: logging::SYNTH, "Frequency ratio {} clamped to 0.0", ratio);
             self.frequency_ratio = 0.0;
         } else {
             self.frequency_ratio = ratio;
         }
     }
     pub fn set_fixed_frequency(&mut self, frequency: f32) {
-        println!("Operator fixed frequency set to: {}", frequency);
         if frequency < 0.0 {
-            eprintln!("Fixed frequency must be non-negative. Frequency clamped to 0.0");
+            warn!(target: logging::SYNTH, "Fixed frequency {} clamped to 0.0", frequency);
             self.fixed_frequency = Some(0.0);
         } else {
             self.fixed_frequency = Some(frequency);
@@ -249,13 +513,32 @@ impl Operator {
     }
 
     pub fn set_modulation_index(&mut self, modulation_index: f32) {
-        println!("Operator modulation index set to: {}", modulation_index);
         // NOTE: allow negative modulation index
         self.modulation_index = modulation_index;
     }
     pub fn get_modulation_index(&self) -> f32 {
         self.modulation_index
     }
+    /// How much of the signal arriving through a feedback path (a cycle in
+    /// the algorithm, such as the operator modulating itself) reaches this
+    /// operator, from 0.0 to 1.0. The fed back signal carries its source's
+    /// modulation index, so 0.1 at an index of 10 is a feedback index of 1.
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(0.0, 1.0);
+    }
+    pub fn get_feedback(&self) -> f32 {
+        self.feedback
+    }
+    pub fn get_ratio(&self) -> f32 {
+        self.frequency_ratio
+    }
+    pub fn get_detune(&self) -> f32 {
+        self.detune
+    }
+    /// `None` while the operator follows the note frequency through its ratio.
+    pub fn get_fixed_frequency(&self) -> Option<f32> {
+        self.fixed_frequency
+    }
     pub fn finished(&self, context: &ProcessContext) -> bool {
         let time_since_off = context
             .note_off_sample_index
@@ -277,9 +560,87 @@ impl Default for Operator {
             fixed_frequency: None, // Default to using ratio
             detune: 0.0,
             modulation_index: 10.0,
+            feedback: 0.1,
             envelope: EnvelopeGenerator::new(),
             gain: 1.0,
             filters: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders a note that is held, then released, under a changing
+    /// modulation input, through `process` or only the scalar path.
+    fn render(operator: &Operator, vectorized: bool) -> Vec<f32> {
+        const BLOCK: usize = 100; // Not a multiple of the SIMD width
+        const RELEASE_BLOCK: usize = 40;
+        let mut state = OperatorState::default();
+        let mut rendered = Vec::new();
+        for block in 0..60 {
+            let context = ProcessContext {
+                sample_rate: 44100.0,
+                base_frequency: 440.0,
+                velocity_scale: 1.0,
+                modulation_index_scale: 1.0,
+                cutoff_scale: 1.0,
+                samples_elapsed_since_trigger: (block * BLOCK) as u64,
+                note_off_sample_index: (block >= RELEASE_BLOCK)
+                    .then_some((RELEASE_BLOCK * BLOCK) as u64),
+                operators: &[],
+            };
+            let modulation: Vec<f32> = (0..BLOCK)
+                .map(|i| ((block * BLOCK + i) as f32 * 0.02).sin() * 2.0)
+                .collect();
+            let mut output = vec![0.0; BLOCK];
+            if vectorized {
+                operator.process(&context, &modulation, &mut state, &mut output);
+            } else {
+                operator.update_targets(&context, &mut state);
+                operator.process_scalar(&context, &modulation, &mut state, &mut output);
+            }
+            rendered.extend_from_slice(&output);
+        }
+        rendered
+    }
+
+    fn max_difference(a: &[f32], b: &[f32]) -> f32 {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_vectorized_sine_matches_scalar() {
+        // Relative to the peak output, which is the modulation index
+        const ERROR_BOUND: f32 = 2e-3;
+        let mut operator = Operator::new();
+        let peak = operator.get_modulation_index();
+        // The default 1 ms attack is where interpolating the envelope costs most
+        let difference = max_difference(&render(&operator, true), &render(&operator, false));
+        assert!(difference < ERROR_BOUND * peak, "difference {}", difference);
+
+        operator.set_envelope(0.02, 0.03, 0.5, 0.05);
+        operator.set_ratio(3.5);
+        operator.set_detune(7.0);
+        let difference = max_difference(&render(&operator, true), &render(&operator, false));
+        assert!(difference < ERROR_BOUND * peak, "difference {}", difference);
+    }
+
+    #[test]
+    fn test_other_waveforms_and_gliding_parameters_render_scalar() {
+        let mut operator = Operator::new();
+        let state = OperatorState::default();
+        operator.set_waveform(Waveform::Square);
+        assert!(!operator.renders_vectorized(&state));
+        operator.set_waveform(Waveform::Sine);
+
+        let mut state = OperatorState::default();
+        state.modulation_index.set_target(1.0);
+        state.modulation_index.set_target(2.0);
+        assert!(!operator.renders_vectorized(&state));
+    }
+}
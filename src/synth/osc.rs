@@ -0,0 +1,392 @@
+//! Open Sound Control: decoding and encoding OSC 1.0 packets, and mapping
+//! messages onto synth events.
+//!
+//! Parameters use their path IDs as addresses (`/op/2/ratio 1.5`, see
+//! `ParamId`), and notes and programs have addresses of their own:
+//!
+//! - `/note/on <note> <velocity> [channel]`
+//! - `/note/off <note> [channel]`
+//! - `/program <program> [bank] [channel]`
+//!
+//! Velocities are MIDI values when sent as integers, or 0.0-1.0 as floats.
+use super::event::TimedEvent;
+use super::note::{NoteEvent, NoteSource};
+use super::params::{ParamEvent, ParamId};
+use super::preset::ProgramEvent;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+/// The timetag meaning "as soon as it arrives".
+pub const IMMEDIATELY: u64 = 1;
+/// Seconds from the NTP epoch (1900) that OSC timetags count from to the Unix epoch.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscError {
+    Truncated,
+    InvalidString,
+    UnsupportedType(char),
+    UnknownAddress(String),
+    InvalidArguments(String),
+}
+
+impl fmt::Display for OscError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OscError::Truncated => write!(f, "Truncated OSC packet"),
+            OscError::InvalidString => write!(f, "Invalid OSC string"),
+            OscError::UnsupportedType(tag) => write!(f, "Unsupported OSC type tag '{}'", tag),
+            OscError::UnknownAddress(address) => write!(f, "Unknown OSC address {}", address),
+            OscError::InvalidArguments(address) => {
+                write!(f, "Invalid arguments for OSC address {}", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OscError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    Nil,
+    Impulse,
+}
+
+impl OscArg {
+    /// The argument as a number, if it is one. Booleans count as 0 and 1.
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            OscArg::Int(value) => Some(value as f32),
+            OscArg::Long(value) => Some(value as f32),
+            OscArg::Float(value) => Some(value),
+            OscArg::Double(value) => Some(value as f32),
+            OscArg::Bool(value) => Some(if value { 1.0 } else { 0.0 }),
+            _ => None,
+        }
+    }
+    /// The argument as an integer; floats are rounded.
+    pub fn as_i32(&self) -> Option<i32> {
+        match *self {
+            OscArg::Int(value) => Some(value),
+            OscArg::Long(value) => i32::try_from(value).ok(),
+            _ => self.as_f32().map(|value| value.round() as i32),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.into(),
+            args,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_string(&mut bytes, &self.address);
+        let mut tags = String::from(",");
+        for arg in &self.args {
+            tags.push(match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Long(_) => 'h',
+                OscArg::Float(_) => 'f',
+                OscArg::Double(_) => 'd',
+                OscArg::String(_) => 's',
+                OscArg::Blob(_) => 'b',
+                OscArg::Bool(true) => 'T',
+                OscArg::Bool(false) => 'F',
+                OscArg::Nil => 'N',
+                OscArg::Impulse => 'I',
+            });
+        }
+        write_string(&mut bytes, &tags);
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Long(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Float(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Double(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+                OscArg::String(value) => write_string(&mut bytes, value),
+                OscArg::Blob(blob) => {
+                    bytes.extend_from_slice(&(blob.len() as i32).to_be_bytes());
+                    bytes.extend_from_slice(blob);
+                    bytes.resize(padded(bytes.len()), 0);
+                }
+                OscArg::Bool(_) | OscArg::Nil | OscArg::Impulse => {}
+            }
+        }
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscPacket {
+    Message(OscMessage),
+    /// Messages to apply together at `timetag` (NTP format; see `IMMEDIATELY`).
+    Bundle {
+        timetag: u64,
+        packets: Vec<OscPacket>,
+    },
+}
+
+impl OscPacket {
+    pub fn decode(bytes: &[u8]) -> Result<Self, OscError> {
+        let mut reader = Reader { bytes, position: 0 };
+        if bytes.starts_with(BUNDLE_TAG) {
+            reader.position = BUNDLE_TAG.len();
+            let timetag = u64::from_be_bytes(reader.take_array()?);
+            let mut packets = Vec::new();
+            while reader.position < bytes.len() {
+                let size = i32::from_be_bytes(reader.take_array()?);
+                let size = usize::try_from(size).map_err(|_| OscError::Truncated)?;
+                packets.push(OscPacket::decode(reader.take(size)?)?);
+            }
+            return Ok(OscPacket::Bundle { timetag, packets });
+        }
+
+        let address = reader.read_string()?;
+        if !address.starts_with('/') {
+            return Err(OscError::InvalidString);
+        }
+        // Some old clients send no type tags at all
+        let tags = if reader.position < bytes.len() {
+            reader.read_string()?
+        } else {
+            String::from(",")
+        };
+        let mut args = Vec::new();
+        for tag in tags
+            .strip_prefix(',')
+            .ok_or(OscError::InvalidString)?
+            .chars()
+        {
+            args.push(match tag {
+                'i' => OscArg::Int(i32::from_be_bytes(reader.take_array()?)),
+                'h' => OscArg::Long(i64::from_be_bytes(reader.take_array()?)),
+                'f' => OscArg::Float(f32::from_be_bytes(reader.take_array()?)),
+                'd' => OscArg::Double(f64::from_be_bytes(reader.take_array()?)),
+                's' | 'S' => OscArg::String(reader.read_string()?),
+                'b' => {
+                    let size = i32::from_be_bytes(reader.take_array()?);
+                    let size = usize::try_from(size).map_err(|_| OscError::Truncated)?;
+                    let blob = reader.take(size)?.to_vec();
+                    reader.take(padded(size) - size)?;
+                    OscArg::Blob(blob)
+                }
+                'T' => OscArg::Bool(true),
+                'F' => OscArg::Bool(false),
+                'N' => OscArg::Nil,
+                'I' => OscArg::Impulse,
+                other => return Err(OscError::UnsupportedType(other)),
+            });
+        }
+        Ok(OscPacket::Message(OscMessage { address, args }))
+    }
+}
+
+/// When a timetag falls due, or `None` for `IMMEDIATELY`.
+pub fn timetag_to_system_time(timetag: u64) -> Option<SystemTime> {
+    if timetag == IMMEDIATELY {
+        return None;
+    }
+    let seconds = (timetag >> 32).saturating_sub(NTP_UNIX_OFFSET);
+    let nanos = ((timetag & 0xFFFF_FFFF) * 1_000_000_000) >> 32;
+    Some(UNIX_EPOCH + Duration::new(seconds, nanos as u32))
+}
+
+/// The synth event an OSC message stands for.
+pub fn message_to_event(message: &OscMessage) -> Result<TimedEvent, OscError> {
+    let address = message.address.as_str();
+    let invalid = || OscError::InvalidArguments(message.address.clone());
+    let int_arg = |index: usize| message.args.get(index).and_then(OscArg::as_i32);
+    let channel = |index: usize| -> Result<u8, OscError> {
+        match message.args.get(index) {
+            Some(arg) => arg
+                .as_i32()
+                .and_then(|channel| u8::try_from(channel).ok())
+                .filter(|channel| *channel < 16)
+                .ok_or_else(invalid),
+            None => Ok(0),
+        }
+    };
+    let note = || {
+        int_arg(0)
+            .and_then(|note| u8::try_from(note).ok())
+            .ok_or_else(invalid)
+    };
+
+    match address {
+        "/note/on" => {
+            let velocity = match message.args.get(1) {
+                Some(OscArg::Float(value)) => (value.clamp(0.0, 1.0) * 127.0).round() as u8,
+                Some(OscArg::Double(value)) => (value.clamp(0.0, 1.0) * 127.0).round() as u8,
+                Some(arg) => arg.as_i32().ok_or_else(invalid)?.clamp(0, 127) as u8,
+                None => return Err(invalid()),
+            };
+            // Velocity 0 is a note off, as in MIDI
+            let event = NoteEvent::new(note()?, velocity, velocity > 0, NoteSource::Osc)
+                .map_err(|_| invalid())?;
+            Ok(event.with_channel(channel(2)?).into())
+        }
+        "/note/off" => {
+            let event =
+                NoteEvent::new(note()?, 0, false, NoteSource::Osc).map_err(|_| invalid())?;
+            Ok(event.with_channel(channel(1)?).into())
+        }
+        "/program" => {
+            let program = int_arg(0)
+                .and_then(|program| u8::try_from(program).ok())
+                .filter(|program| *program < 128)
+                .ok_or_else(invalid)?;
+            let bank = match message.args.get(1) {
+                Some(arg) => arg
+                    .as_i32()
+                    .and_then(|bank| u16::try_from(bank).ok())
+                    .ok_or_else(invalid)?,
+                None => 0,
+            };
+            Ok(ProgramEvent::new(channel(2)?, bank, program).into())
+        }
+        _ => {
+            let id = address
+                .parse::<ParamId>()
+                .map_err(|_| OscError::UnknownAddress(message.address.clone()))?;
+            let value = message
+                .args
+                .first()
+                .and_then(OscArg::as_f32)
+                .ok_or_else(invalid)?;
+            Ok(ParamEvent::new(id, id.clamp(value)).into())
+        }
+    }
+}
+
+/// The message reporting a parameter's value back to a client.
+pub fn param_message(event: &ParamEvent) -> OscMessage {
+    OscMessage::new(format!("/{}", event.id), vec![OscArg::Float(event.value)])
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], OscError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(OscError::Truncated)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], OscError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+    /// A null-terminated string, padded to a multiple of four bytes.
+    fn read_string(&mut self) -> Result<String, OscError> {
+        let rest = &self.bytes[self.position..];
+        let len = rest
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(OscError::Truncated)?;
+        let string = std::str::from_utf8(&rest[..len]).map_err(|_| OscError::InvalidString)?;
+        self.take(padded(len + 1))?;
+        Ok(string.to_string())
+    }
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(4) * 4
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend_from_slice(string.as_bytes());
+    bytes.push(0);
+    bytes.resize(padded(bytes.len()), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_bundles_and_maps_addresses() {
+        let ratio = OscMessage::new("/op/2/ratio", vec![OscArg::Float(1.5)]);
+        let note = OscMessage::new("/note/on", vec![OscArg::Int(60), OscArg::Float(1.0)]);
+        let mut bundle = BUNDLE_TAG.to_vec();
+        bundle.extend_from_slice(&IMMEDIATELY.to_be_bytes());
+        for message in [&ratio, &note] {
+            let bytes = message.encode();
+            bundle.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(&bytes);
+        }
+
+        let Ok(OscPacket::Bundle { timetag, packets }) = OscPacket::decode(&bundle) else {
+            panic!("expected a bundle");
+        };
+        assert_eq!(timetag, IMMEDIATELY);
+        assert_eq!(
+            packets,
+            vec![OscPacket::Message(ratio), OscPacket::Message(note)]
+        );
+        let OscPacket::Message(ratio) = &packets[0] else {
+            unreachable!()
+        };
+        match message_to_event(ratio) {
+            Ok(TimedEvent::Param(event)) => {
+                assert_eq!(event.id, ParamId::OperatorRatio(2));
+                assert_eq!(event.value, 1.5);
+            }
+            other => panic!("expected a parameter change, got {:?}", other),
+        }
+        let OscPacket::Message(note) = &packets[1] else {
+            unreachable!()
+        };
+        match message_to_event(note) {
+            Ok(TimedEvent::Note(event)) => {
+                assert!(event.is_on);
+                assert_eq!((event.note_number, event.velocity), (60, 127));
+            }
+            other => panic!("expected a note, got {:?}", other),
+        }
+        assert_eq!(
+            OscPacket::decode(&bundle[..bundle.len() - 2]),
+            Err(OscError::Truncated)
+        );
+        assert!(matches!(
+            message_to_event(&OscMessage::new("/op/2/colour", vec![])),
+            Err(OscError::UnknownAddress(_))
+        ));
+    }
+
+    #[test]
+    fn test_timetags_count_from_1900() {
+        // 2001-09-09T01:46:40Z, plus half a second
+        let timetag = (1_000_000_000 + NTP_UNIX_OFFSET) << 32 | 0x8000_0000;
+        assert_eq!(
+            timetag_to_system_time(timetag),
+            Some(UNIX_EPOCH + Duration::new(1_000_000_000, 500_000_000))
+        );
+        assert_eq!(timetag_to_system_time(IMMEDIATELY), None);
+    }
+}
@@ -0,0 +1,484 @@
+//! Registry of every automatable synth parameter.
+//!
+//! Each parameter has a stable, path-like ID (`op/2/ratio`, `part/0/effect_send`,
+//! `fx/1/reverb/decay`)
+//! along with its display name, unit, range, default and scaling curve. MIDI
+//! learn, OSC, automation and the web UI should use this registry rather than
+//! keeping their own copies of the ranges.
+use super::core::EffectSlot;
+use super::error::SynthError;
+use super::filter::{MAX_Q, MIN_Q};
+use crate::synth::prelude::FRAC_1_SQRT_2;
+use std::fmt;
+use std::str::FromStr;
+
+/// How a parameter's range maps onto the normalized 0..1 range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamScale {
+    Linear,
+    /// Equal normalized steps multiply the value by the same amount. Needs `min > 0`.
+    Exponential,
+    /// `value = min + (max - min) * normalized^exponent`, giving finer control
+    /// near `min` for exponents above 1.
+    Power(f32),
+    /// Whole numbers from `min` to `max`, e.g. a waveform selector.
+    Stepped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParamId {
+    MasterVolume,
+    OperatorRatio(usize),
+    /// Zero switches the operator back to ratio mode.
+    OperatorFixedFrequency(usize),
+    OperatorDetune(usize),
+    OperatorModulationIndex(usize),
+    /// Only heard when the algorithm has a cycle through the operator.
+    OperatorFeedback(usize),
+    /// Uses the `Waveform::index` mapping.
+    OperatorWaveform(usize),
+    OperatorAttack(usize),
+    OperatorDecay(usize),
+    OperatorSustain(usize),
+    OperatorRelease(usize),
+    /// Filter parameters need the operator to have a low-pass filter.
+    OperatorFilterCutoff(usize),
+    OperatorFilterQ(usize),
+    /// The voice envelope in `VoiceConfig`, applied to notes started afterwards.
+    VoiceAttack,
+    VoiceDecay,
+    VoiceSustain,
+    /// The top of the range holds notes until their operators finish.
+    VoiceRelease,
+    PartEffectSend(usize),
+    /// Key and velocity bounds reorder themselves, like `Part::set_key_range`.
+    PartKeyLow(usize),
+    PartKeyHigh(usize),
+    PartVelocityLow(usize),
+    PartVelocityHigh(usize),
+    /// Uses the `FeedbackMode::index` mapping.
+    PartFeedbackMode(usize),
+    ReverbPredelay(EffectSlot),
+    ReverbDecay(EffectSlot),
+    ReverbWetMix(EffectSlot),
+}
+
+/// Set a parameter at a frame offset within the next rendered buffer, e.g.
+/// from a mapped MIDI controller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamEvent {
+    pub id: ParamId,
+    pub value: f32, // In the parameter's units
+    pub frame_offset: usize,
+}
+
+impl ParamEvent {
+    pub fn new(id: ParamId, value: f32) -> Self {
+        Self {
+            id,
+            value,
+            frame_offset: 0,
+        }
+    }
+    pub fn with_frame_offset(mut self, frame_offset: usize) -> Self {
+        self.frame_offset = frame_offset;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamInfo {
+    pub id: ParamId,
+    pub name: String,
+    pub unit: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub scale: ParamScale,
+}
+
+/// Longest voice envelope time, in seconds; a release this long holds the note.
+pub(crate) const MAX_VOICE_TIME: f32 = 10.0;
+
+const VOICE_PARAMS: [ParamId; 4] = [
+    ParamId::VoiceAttack,
+    ParamId::VoiceDecay,
+    ParamId::VoiceSustain,
+    ParamId::VoiceRelease,
+];
+const OPERATOR_PARAMS: [fn(usize) -> ParamId; 12] = [
+    ParamId::OperatorRatio,
+    ParamId::OperatorFixedFrequency,
+    ParamId::OperatorDetune,
+    ParamId::OperatorModulationIndex,
+    ParamId::OperatorFeedback,
+    ParamId::OperatorWaveform,
+    ParamId::OperatorAttack,
+    ParamId::OperatorDecay,
+    ParamId::OperatorSustain,
+    ParamId::OperatorRelease,
+    ParamId::OperatorFilterCutoff,
+    ParamId::OperatorFilterQ,
+];
+const PART_PARAMS: [fn(usize) -> ParamId; 6] = [
+    ParamId::PartEffectSend,
+    ParamId::PartKeyLow,
+    ParamId::PartKeyHigh,
+    ParamId::PartVelocityLow,
+    ParamId::PartVelocityHigh,
+    ParamId::PartFeedbackMode,
+];
+const REVERB_PARAMS: [fn(EffectSlot) -> ParamId; 3] = [
+    ParamId::ReverbPredelay,
+    ParamId::ReverbDecay,
+    ParamId::ReverbWetMix,
+];
+
+/// Every parameter of a synth with `operator_count` operators and `part_count`
+/// parts, in a stable order.
+pub fn all_params(operator_count: usize, part_count: usize) -> Vec<ParamInfo> {
+    let mut params = vec![ParamId::MasterVolume.info()];
+    params.extend(VOICE_PARAMS.iter().map(ParamId::info));
+    for op_index in 0..operator_count {
+        params.extend(OPERATOR_PARAMS.iter().map(|param| param(op_index).info()));
+    }
+    for part_index in 0..part_count {
+        params.extend(PART_PARAMS.iter().map(|param| param(part_index).info()));
+    }
+    for slot in EffectSlot::ALL {
+        params.extend(REVERB_PARAMS.iter().map(|param| param(slot).info()));
+    }
+    params
+}
+
+impl ParamId {
+    pub fn operator_index(&self) -> Option<usize> {
+        match *self {
+            ParamId::OperatorRatio(i)
+            | ParamId::OperatorFixedFrequency(i)
+            | ParamId::OperatorDetune(i)
+            | ParamId::OperatorModulationIndex(i)
+            | ParamId::OperatorFeedback(i)
+            | ParamId::OperatorWaveform(i)
+            | ParamId::OperatorAttack(i)
+            | ParamId::OperatorDecay(i)
+            | ParamId::OperatorSustain(i)
+            | ParamId::OperatorRelease(i)
+            | ParamId::OperatorFilterCutoff(i)
+            | ParamId::OperatorFilterQ(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn part_index(&self) -> Option<usize> {
+        match *self {
+            ParamId::PartEffectSend(i)
+            | ParamId::PartKeyLow(i)
+            | ParamId::PartKeyHigh(i)
+            | ParamId::PartVelocityLow(i)
+            | ParamId::PartVelocityHigh(i)
+            | ParamId::PartFeedbackMode(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn info(&self) -> ParamInfo {
+        let (unit, min, max, default, scale) = self.range();
+        ParamInfo {
+            id: *self,
+            name: self.name(),
+            unit,
+            min,
+            max,
+            default,
+            scale,
+        }
+    }
+
+    /// Human readable name, e.g. "Operator 1 Ratio".
+    pub fn name(&self) -> String {
+        match self {
+            ParamId::MasterVolume => "Master Volume".to_string(),
+            ParamId::OperatorRatio(i) => format!("Operator {} Ratio", i + 1),
+            ParamId::OperatorFixedFrequency(i) => format!("Operator {} Fixed Frequency", i + 1),
+            ParamId::OperatorDetune(i) => format!("Operator {} Detune", i + 1),
+            ParamId::OperatorModulationIndex(i) => format!("Operator {} Modulation Index", i + 1),
+            ParamId::OperatorFeedback(i) => format!("Operator {} Feedback", i + 1),
+            ParamId::OperatorWaveform(i) => format!("Operator {} Waveform", i + 1),
+            ParamId::OperatorAttack(i) => format!("Operator {} Attack", i + 1),
+            ParamId::OperatorDecay(i) => format!("Operator {} Decay", i + 1),
+            ParamId::OperatorSustain(i) => format!("Operator {} Sustain", i + 1),
+            ParamId::OperatorRelease(i) => format!("Operator {} Release", i + 1),
+            ParamId::OperatorFilterCutoff(i) => format!("Operator {} Filter Cutoff", i + 1),
+            ParamId::OperatorFilterQ(i) => format!("Operator {} Filter Q", i + 1),
+            ParamId::VoiceAttack => "Voice Attack".to_string(),
+            ParamId::VoiceDecay => "Voice Decay".to_string(),
+            ParamId::VoiceSustain => "Voice Sustain".to_string(),
+            ParamId::VoiceRelease => "Voice Release".to_string(),
+            ParamId::PartEffectSend(i) => format!("Part {} Effect Send", i + 1),
+            ParamId::PartKeyLow(i) => format!("Part {} Key Low", i + 1),
+            ParamId::PartKeyHigh(i) => format!("Part {} Key High", i + 1),
+            ParamId::PartVelocityLow(i) => format!("Part {} Velocity Low", i + 1),
+            ParamId::PartVelocityHigh(i) => format!("Part {} Velocity High", i + 1),
+            ParamId::PartFeedbackMode(i) => format!("Part {} Feedback Mode", i + 1),
+            ParamId::ReverbPredelay(slot) => format!("Effect {} Reverb Predelay", slot.number()),
+            ParamId::ReverbDecay(slot) => format!("Effect {} Reverb Decay", slot.number()),
+            ParamId::ReverbWetMix(slot) => format!("Effect {} Reverb Wet Mix", slot.number()),
+        }
+    }
+
+    /// Clamp `value` to the parameter's range, without allocating.
+    pub fn clamp(&self, value: f32) -> f32 {
+        let (_, min, max, _, scale) = self.range();
+        let value = value.clamp(min, max);
+        match scale {
+            ParamScale::Stepped => value.round(),
+            _ => value,
+        }
+    }
+
+    /// The parameter's default, like `ParamInfo::default` but without allocating.
+    pub fn default_value(&self) -> f32 {
+        self.range().3
+    }
+
+    /// Map a value in the parameter's range to 0..1, like `ParamInfo::normalize`
+    /// but without allocating.
+    pub fn normalize(&self, value: f32) -> f32 {
+        let (_, min, max, _, scale) = self.range();
+        normalize(min, max, scale, self.clamp(value))
+    }
+
+    /// Map a normalized 0..1 value into the parameter's range, without allocating.
+    pub fn denormalize(&self, normalized: f32) -> f32 {
+        let (_, min, max, _, scale) = self.range();
+        self.clamp(denormalize(min, max, scale, normalized))
+    }
+
+    /// (unit, min, max, default, scale)
+    fn range(&self) -> (&'static str, f32, f32, f32, ParamScale) {
+        use ParamScale::*;
+        match self {
+            ParamId::MasterVolume => ("", 0.0, 1.0, 0.8, Linear),
+            ParamId::OperatorRatio(_) => ("", 1.0 / 128.0, 32.0, 1.0, Exponential),
+            ParamId::OperatorFixedFrequency(_) => ("Hz", 0.0, 20000.0, 0.0, Power(3.0)),
+            ParamId::OperatorDetune(_) => ("cents", -1200.0, 1200.0, 0.0, Linear),
+            ParamId::OperatorModulationIndex(_) => ("", 0.0, 10.0, 10.0, Linear),
+            ParamId::OperatorFeedback(_) => ("", 0.0, 1.0, 0.1, Linear),
+            ParamId::OperatorWaveform(_) => ("", 0.0, 6.0, 0.0, Stepped),
+            ParamId::OperatorAttack(_) => ("s", 0.0, 10.0, 0.001, Power(3.0)),
+            ParamId::OperatorDecay(_) => ("s", 0.0, 10.0, 1.0, Power(3.0)),
+            ParamId::OperatorSustain(_) => ("", 0.0, 1.0, 0.6, Linear),
+            ParamId::OperatorRelease(_) => ("s", 0.0, 10.0, 0.1, Power(3.0)),
+            ParamId::OperatorFilterCutoff(_) => ("Hz", 20.0, 20000.0, 1000.0, Exponential),
+            ParamId::OperatorFilterQ(_) => ("", MIN_Q, MAX_Q, FRAC_1_SQRT_2, Exponential),
+            ParamId::VoiceAttack | ParamId::VoiceDecay => {
+                ("s", 0.0, MAX_VOICE_TIME, 0.0, Power(3.0))
+            }
+            ParamId::VoiceSustain => ("", 0.0, 1.0, 1.0, Linear),
+            ParamId::VoiceRelease => ("s", 0.0, MAX_VOICE_TIME, MAX_VOICE_TIME, Power(3.0)),
+            ParamId::PartEffectSend(_) => ("", 0.0, 1.0, 1.0, Linear),
+            ParamId::PartKeyLow(_) => ("", 0.0, 127.0, 0.0, Stepped),
+            ParamId::PartKeyHigh(_) => ("", 0.0, 127.0, 127.0, Stepped),
+            ParamId::PartVelocityLow(_) => ("", 1.0, 127.0, 1.0, Stepped),
+            ParamId::PartVelocityHigh(_) => ("", 1.0, 127.0, 127.0, Stepped),
+            ParamId::PartFeedbackMode(_) => ("", 0.0, 2.0, 0.0, Stepped),
+            ParamId::ReverbPredelay(_) => ("ms", 0.0, 250.0, 10.0, Linear),
+            ParamId::ReverbDecay(_) => ("ms", 100.0, 10000.0, 2000.0, Exponential),
+            ParamId::ReverbWetMix(_) => ("", 0.0, 1.0, 0.5, Linear),
+        }
+    }
+}
+
+/// Formats as the stable path ID, e.g. `op/0/ratio`. Operators and parts are
+/// zero-based like the rest of the API; effect slots are numbered 1 to 3.
+impl fmt::Display for ParamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamId::MasterVolume => write!(f, "master/volume"),
+            ParamId::OperatorRatio(i) => write!(f, "op/{}/ratio", i),
+            ParamId::OperatorFixedFrequency(i) => write!(f, "op/{}/fixed_frequency", i),
+            ParamId::OperatorDetune(i) => write!(f, "op/{}/detune", i),
+            ParamId::OperatorModulationIndex(i) => write!(f, "op/{}/modulation_index", i),
+            ParamId::OperatorFeedback(i) => write!(f, "op/{}/feedback", i),
+            ParamId::OperatorWaveform(i) => write!(f, "op/{}/waveform", i),
+            ParamId::OperatorAttack(i) => write!(f, "op/{}/attack", i),
+            ParamId::OperatorDecay(i) => write!(f, "op/{}/decay", i),
+            ParamId::OperatorSustain(i) => write!(f, "op/{}/sustain", i),
+            ParamId::OperatorRelease(i) => write!(f, "op/{}/release", i),
+            ParamId::OperatorFilterCutoff(i) => write!(f, "op/{}/filter/cutoff", i),
+            ParamId::OperatorFilterQ(i) => write!(f, "op/{}/filter/q", i),
+            ParamId::VoiceAttack => write!(f, "voice/attack"),
+            ParamId::VoiceDecay => write!(f, "voice/decay"),
+            ParamId::VoiceSustain => write!(f, "voice/sustain"),
+            ParamId::VoiceRelease => write!(f, "voice/release"),
+            ParamId::PartEffectSend(i) => write!(f, "part/{}/effect_send", i),
+            ParamId::PartKeyLow(i) => write!(f, "part/{}/key_low", i),
+            ParamId::PartKeyHigh(i) => write!(f, "part/{}/key_high", i),
+            ParamId::PartVelocityLow(i) => write!(f, "part/{}/velocity_low", i),
+            ParamId::PartVelocityHigh(i) => write!(f, "part/{}/velocity_high", i),
+            ParamId::PartFeedbackMode(i) => write!(f, "part/{}/feedback_mode", i),
+            ParamId::ReverbPredelay(slot) => write!(f, "fx/{}/reverb/predelay", slot.number()),
+            ParamId::ReverbDecay(slot) => write!(f, "fx/{}/reverb/decay", slot.number()),
+            ParamId::ReverbWetMix(slot) => write!(f, "fx/{}/reverb/wet_mix", slot.number()),
+        }
+    }
+}
+
+impl FromStr for ParamId {
+    type Err = SynthError;
+
+    /// Parses a path ID; a leading `/` is accepted so OSC addresses can be used directly.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim_start_matches('/').split('/').collect();
+        let invalid = || SynthError::unknown("parameter ID", s);
+        match parts.as_slice() {
+            ["master", "volume"] => Ok(ParamId::MasterVolume),
+            ["voice", name] => match *name {
+                "attack" => Ok(ParamId::VoiceAttack),
+                "decay" => Ok(ParamId::VoiceDecay),
+                "sustain" => Ok(ParamId::VoiceSustain),
+                "release" => Ok(ParamId::VoiceRelease),
+                _ => Err(invalid()),
+            },
+            ["op", index, name] => {
+                let i = index.parse::<usize>().map_err(|_| invalid())?;
+                match *name {
+                    "ratio" => Ok(ParamId::OperatorRatio(i)),
+                    "fixed_frequency" => Ok(ParamId::OperatorFixedFrequency(i)),
+                    "detune" => Ok(ParamId::OperatorDetune(i)),
+                    "modulation_index" => Ok(ParamId::OperatorModulationIndex(i)),
+                    "feedback" => Ok(ParamId::OperatorFeedback(i)),
+                    "waveform" => Ok(ParamId::OperatorWaveform(i)),
+                    "attack" => Ok(ParamId::OperatorAttack(i)),
+                    "decay" => Ok(ParamId::OperatorDecay(i)),
+                    "sustain" => Ok(ParamId::OperatorSustain(i)),
+                    "release" => Ok(ParamId::OperatorRelease(i)),
+                    _ => Err(invalid()),
+                }
+            }
+            ["op", index, "filter", name] => {
+                let i = index.parse::<usize>().map_err(|_| invalid())?;
+                match *name {
+                    "cutoff" => Ok(ParamId::OperatorFilterCutoff(i)),
+                    "q" => Ok(ParamId::OperatorFilterQ(i)),
+                    _ => Err(invalid()),
+                }
+            }
+            ["part", index, name] => {
+                let i = index.parse::<usize>().map_err(|_| invalid())?;
+                match *name {
+                    "effect_send" => Ok(ParamId::PartEffectSend(i)),
+                    "key_low" => Ok(ParamId::PartKeyLow(i)),
+                    "key_high" => Ok(ParamId::PartKeyHigh(i)),
+                    "velocity_low" => Ok(ParamId::PartVelocityLow(i)),
+                    "velocity_high" => Ok(ParamId::PartVelocityHigh(i)),
+                    "feedback_mode" => Ok(ParamId::PartFeedbackMode(i)),
+                    _ => Err(invalid()),
+                }
+            }
+            ["fx", number, "reverb", name] => {
+                let slot = number
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(EffectSlot::from_number)
+                    .ok_or_else(invalid)?;
+                match *name {
+                    "predelay" => Ok(ParamId::ReverbPredelay(slot)),
+                    "decay" => Ok(ParamId::ReverbDecay(slot)),
+                    "wet_mix" => Ok(ParamId::ReverbWetMix(slot)),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl ParamInfo {
+    pub fn clamp(&self, value: f32) -> f32 {
+        let value = value.clamp(self.min, self.max);
+        match self.scale {
+            ParamScale::Stepped => value.round(),
+            _ => value,
+        }
+    }
+    /// Map a value in the parameter's range to 0..1.
+    pub fn normalize(&self, value: f32) -> f32 {
+        normalize(self.min, self.max, self.scale, self.clamp(value))
+    }
+    /// Map a normalized 0..1 value back into the parameter's range.
+    pub fn denormalize(&self, normalized: f32) -> f32 {
+        self.clamp(denormalize(self.min, self.max, self.scale, normalized))
+    }
+}
+
+fn normalize(min: f32, max: f32, scale: ParamScale, value: f32) -> f32 {
+    if max <= min {
+        return 0.0;
+    }
+    let normalized = match scale {
+        ParamScale::Linear | ParamScale::Stepped => (value - min) / (max - min),
+        ParamScale::Exponential => (value / min).ln() / (max / min).ln(),
+        ParamScale::Power(exponent) => ((value - min) / (max - min)).powf(1.0 / exponent),
+    };
+    normalized.clamp(0.0, 1.0)
+}
+
+fn denormalize(min: f32, max: f32, scale: ParamScale, normalized: f32) -> f32 {
+    let normalized = normalized.clamp(0.0, 1.0);
+    match scale {
+        ParamScale::Linear | ParamScale::Stepped => min + (max - min) * normalized,
+        ParamScale::Exponential => min * (max / min).powf(normalized),
+        ParamScale::Power(exponent) => min + (max - min) * normalized.powf(exponent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_round_trip() {
+        for info in all_params(4, 2) {
+            let parsed: ParamId = info.id.to_string().parse().unwrap();
+            assert_eq!(parsed, info.id);
+        }
+        assert_eq!(
+            "/op/2/ratio".parse::<ParamId>(),
+            Ok(ParamId::OperatorRatio(2))
+        );
+        assert_eq!(
+            "part/1/key_low".parse::<ParamId>(),
+            Ok(ParamId::PartKeyLow(1))
+        );
+        assert!("op/x/ratio".parse::<ParamId>().is_err());
+        assert!("op/0/filter/alpha".parse::<ParamId>().is_err());
+        assert!("fx/4/reverb/decay".parse::<ParamId>().is_err());
+    }
+
+    #[test]
+    fn test_normalize_round_trip() {
+        for info in all_params(1, 1) {
+            for step in 0..=10 {
+                let normalized = step as f32 / 10.0;
+                let value = info.denormalize(normalized);
+                assert!(value >= info.min && value <= info.max, "{}", info.name);
+                if info.scale != ParamScale::Stepped {
+                    let back = info.normalize(value);
+                    assert!(
+                        (back - normalized).abs() < 1.0e-4,
+                        "{}: {} -> {} -> {}",
+                        info.name,
+                        normalized,
+                        value,
+                        back
+                    );
+                }
+            }
+        }
+        // Exponential: the midpoint is the geometric mean
+        let decay = ParamId::ReverbDecay(EffectSlot::One).info();
+        assert!((decay.denormalize(0.5) - 1000.0).abs() < 0.1);
+    }
+}
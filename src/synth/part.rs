@@ -0,0 +1,458 @@
+//! Parts: independent sounds within one `Synth`, each with its own algorithm,
+//! operators and voices. A note plays on every part whose channels, key range
+//! and velocity range it falls in, so parts can split the keyboard or layer.
+use super::algorithm::{Algorithm, FeedbackMode};
+use super::core::MODULATION_INDEX_GAIN_OFFSET;
+use super::error::SynthError;
+use super::expression::Expression;
+use super::filter::Filter;
+use super::note::NoteEvent;
+use super::operator::Operator;
+use super::preset::{FilterPatch, OperatorPatch, Patch};
+use super::voice::Voice;
+use super::voice_config::VoiceConfig;
+use super::waveform::Waveform;
+use crate::utils::logging;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::render_pool::RenderPool;
+use log::{debug, warn};
+
+pub const ALL_CHANNELS: u16 = 0xFFFF;
+
+/// How a part is laid out, so that a replacement can be built to match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartShape {
+    pub operators: usize,
+    pub voices: usize,
+    pub buffer_size: usize,
+}
+
+pub struct Part {
+    voices: Vec<Voice>,
+    algorithm: Algorithm,
+    operators: Vec<Operator>,
+    // What was playing before the last patch change, until its notes finish
+    previous: Option<Box<Part>>,
+    channels: u16, // One bit per MIDI channel
+    key_range: (u8, u8),
+    velocity_range: (u8, u8),
+    effect_send: f32,
+}
+
+impl Part {
+    /// A part playing operator 0 as a sine on every channel, key and velocity.
+    pub fn new(num_operators: usize, max_voices: usize) -> Self {
+        let mut operators: Vec<Operator> = (0..num_operators).map(|_| Operator::new()).collect();
+        if let Some(carrier) = operators.first_mut() {
+            carrier.set_waveform(Waveform::Sine);
+        }
+        let algorithm = Algorithm::default_simple(num_operators).unwrap();
+        let mut voices: Vec<Voice> = (0..max_voices).map(|_| Voice::new(num_operators)).collect();
+        for voice in voices.iter_mut() {
+            voice.update_algorithm(&algorithm);
+        }
+        Self {
+            voices,
+            algorithm,
+            operators,
+            previous: None,
+            channels: ALL_CHANNELS,
+            key_range: (0, 127),
+            velocity_range: (1, 127),
+            effect_send: 1.0,
+        }
+    }
+
+    /// A part laid out as `shape`, playing `patch` on every channel, key and
+    /// velocity until it replaces another (see `replace`).
+    pub fn from_patch(shape: PartShape, patch: &Patch, sample_rate: f32) -> Self {
+        let mut part = Part::new(shape.operators, shape.voices);
+        part.set_buffer_size(shape.buffer_size);
+        part.load_patch(patch, sample_rate);
+        part
+    }
+
+    /// This part's layout, with voices rendering `buffer_size` samples at a time.
+    pub fn shape(&self, buffer_size: usize) -> PartShape {
+        PartShape {
+            operators: self.operators.len(),
+            voices: self.voices.len(),
+            buffer_size,
+        }
+    }
+
+    /// Listen on one MIDI channel (0-15), or all of them with `None`.
+    pub fn set_channel(&mut self, channel: Option<u8>) {
+        self.channels = channel.map_or(ALL_CHANNELS, |channel| 1 << (channel & 0x0F));
+    }
+    /// Listen on the channels whose bits are set.
+    pub fn set_channels(&mut self, channels: u16) {
+        self.channels = channels;
+    }
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+    pub fn listens_to(&self, channel: u8) -> bool {
+        self.channels & (1 << (channel & 0x0F)) != 0
+    }
+    /// Play only notes from `low` to `high` inclusive.
+    pub fn set_key_range(&mut self, low: u8, high: u8) {
+        self.key_range = (low.min(high), low.max(high));
+    }
+    pub fn key_range(&self) -> (u8, u8) {
+        self.key_range
+    }
+    /// Play only notes struck with a velocity from `low` to `high` inclusive.
+    pub fn set_velocity_range(&mut self, low: u8, high: u8) {
+        self.velocity_range = (low.min(high), low.max(high));
+    }
+    pub fn velocity_range(&self) -> (u8, u8) {
+        self.velocity_range
+    }
+    /// Whether a note on plays on this part.
+    pub fn accepts(&self, event: &NoteEvent) -> bool {
+        self.listens_to(event.channel)
+            && (self.key_range.0..=self.key_range.1).contains(&event.note_number)
+            && (self.velocity_range.0..=self.velocity_range.1).contains(&event.velocity)
+    }
+
+    /// How much of the part goes through the effects, from 0.0 (dry) to 1.0.
+    pub fn set_effect_send(&mut self, send: f32) {
+        self.effect_send = send.clamp(0.0, 1.0);
+    }
+    pub fn effect_send(&self) -> f32 {
+        self.effect_send
+    }
+
+    pub fn algorithm(&self) -> &Algorithm {
+        &self.algorithm
+    }
+    pub fn set_algorithm(&mut self, combined_matrix: &[Vec<u32>]) -> Result<(), SynthError> {
+        self.algorithm.set_matrix(combined_matrix)?;
+        for voice in self.voices.iter_mut() {
+            voice.update_algorithm(&self.algorithm);
+        }
+        self.allocate_filters();
+        Ok(())
+    }
+    pub(crate) fn set_feedback_mode(&mut self, mode: FeedbackMode) -> Result<(), SynthError> {
+        if mode == self.algorithm.feedback_mode() {
+            return Ok(());
+        }
+        self.algorithm.set_feedback_mode(mode)?;
+        for voice in self.voices.iter_mut() {
+            voice.update_algorithm(&self.algorithm);
+        }
+        self.allocate_filters();
+        Ok(())
+    }
+    pub fn operators(&self) -> &[Operator] {
+        &self.operators
+    }
+    pub fn operators_mut(&mut self) -> &mut [Operator] {
+        &mut self.operators
+    }
+
+    /// Switch the algorithm and operators to `patch`. Notes already sounding
+    /// carry on with the old patch through their release, so the change
+    /// doesn't cut them off; a second change before they finish does.
+    pub fn apply_patch(&mut self, patch: &Patch, sample_rate: f32) {
+        let buffer_size = self.voices.first().map_or(0, Voice::buffer_size);
+        let incoming = Part::from_patch(self.shape(buffer_size), patch, sample_rate);
+        drop(self.replace(Box::new(incoming)));
+    }
+
+    /// Swap `incoming` in, keeping this part's channels, ranges and effect
+    /// send. Notes already sounding carry on with the part being replaced
+    /// until `take_finished` hands it back. Returns what is dropped now: the
+    /// part from the change before, if its notes were still sounding.
+    /// Doesn't allocate, so the audio thread can call it.
+    pub(crate) fn replace(&mut self, mut incoming: Box<Part>) -> Option<Box<Part>> {
+        incoming.channels = self.channels;
+        incoming.key_range = self.key_range;
+        incoming.velocity_range = self.velocity_range;
+        incoming.effect_send = self.effect_send;
+        std::mem::swap(self, &mut *incoming);
+        // `incoming` now holds the part being replaced
+        let stale = incoming.previous.take();
+        self.previous = Some(incoming);
+        stale
+    }
+
+    /// The part from before the last patch change, once its notes have finished.
+    pub(crate) fn take_finished(&mut self) -> Option<Box<Part>> {
+        self.previous
+            .take_if(|previous| previous.active_voices() == 0)
+    }
+
+    fn load_patch(&mut self, patch: &Patch, sample_rate: f32) {
+        let num_operators = self.operators.len();
+        let mut matrix = patch.algorithm.clone();
+        if matrix.is_empty() {
+            // Operator 0 straight to the output, as in a new web UI patch
+            matrix = vec![vec![0; num_operators + 1]; num_operators];
+            if let Some(row) = matrix.first_mut() {
+                row[num_operators] = 1;
+            }
+        }
+        // Every matrix builds with one-sample feedback, but may be too dense
+        // to unroll, so switch to the unrolled mode only once it's in place
+        let result = if patch.feedback_mode == FeedbackMode::Unrolled {
+            self.set_algorithm(&matrix)
+                .and_then(|_| self.set_feedback_mode(patch.feedback_mode))
+        } else {
+            self.set_feedback_mode(patch.feedback_mode)
+                .and_then(|_| self.set_algorithm(&matrix))
+        };
+        if let Err(e) = result {
+            warn!(target: logging::SYNTH, "Patch algorithm not applied: {}", e);
+        }
+        let default_operator = OperatorPatch::default();
+        for (op_index, operator) in self.operators.iter_mut().enumerate() {
+            let settings = patch.operators.get(op_index).unwrap_or(&default_operator);
+            operator.set_ratio(settings.ratio);
+            if settings.fixed_frequency != 0.0 {
+                operator.set_fixed_frequency(settings.fixed_frequency);
+            }
+            operator.set_detune(settings.detune);
+            operator.set_modulation_index(settings.modulation_index);
+            operator.set_feedback(settings.feedback);
+            operator.set_waveform(settings.waveform);
+            let [attack, decay, sustain, release] = settings.envelope;
+            operator.set_envelope(attack, decay, sustain, release);
+            operator.filters = None;
+            for filter in &settings.filters {
+                operator.set_filter(match *filter {
+                    FilterPatch::LowPass { cutoff, q } => {
+                        Filter::new_lowpass_biquad_with_q(cutoff, q, sample_rate)
+                    }
+                    FilterPatch::Comb { alpha, k } => Filter::new_comb(alpha, k),
+                    FilterPatch::PitchedComb { alpha } => Filter::new_pitched_comb(alpha),
+                });
+            }
+        }
+        self.allocate_filters();
+    }
+
+    /// Start a note, stealing a voice if all are busy. The event's frequency
+    /// should already be in the synth's tuning.
+    pub(crate) fn note_on(
+        &mut self,
+        event: &NoteEvent,
+        config: &VoiceConfig,
+        expression: Expression,
+    ) {
+        let voice = match self.voices.iter().position(|voice| !voice.active) {
+            Some(index) => &mut self.voices[index],
+            // TODO: Implement a better voice stealing strategy (e.g., oldest note, quietest voice)
+            None => {
+                debug!(target: logging::AUDIO, "Stealing voice 0");
+                match self.voices.first_mut() {
+                    Some(voice) => voice,
+                    None => return,
+                }
+            }
+        };
+        voice.activate(event, config, expression);
+    }
+
+    pub(crate) fn note_off(&mut self, event: &NoteEvent) {
+        for voice in self.voices.iter_mut() {
+            if (!voice.releasing || voice.active)
+                && voice.note_number == event.note_number
+                && voice.note_source == Some(event.source)
+                && voice.channel == event.channel
+            {
+                voice.release();
+            }
+        }
+        if let Some(previous) = self.previous.as_mut() {
+            previous.note_off(event);
+        }
+    }
+
+    /// Every voice, including those still playing the previous patch.
+    pub(crate) fn voices_mut(&mut self) -> impl Iterator<Item = &mut Voice> {
+        let previous = self
+            .previous
+            .iter_mut()
+            .flat_map(|previous| previous.voices.iter_mut());
+        self.voices.iter_mut().chain(previous)
+    }
+
+    /// Voices currently sounding, including those still playing the previous patch.
+    pub fn active_voices(&self) -> usize {
+        let previous = self
+            .previous
+            .as_ref()
+            .map_or(0, |previous| previous.active_voices());
+        self.voices.iter().filter(|v| v.active).count() + previous
+    }
+
+    pub(crate) fn set_buffer_size(&mut self, buffer_size: usize) {
+        for voice in self.voices_mut() {
+            voice.set_buffer_size(buffer_size);
+        }
+        self.allocate_filters();
+    }
+
+    /// Preallocate every voice's filters for the operators' current ones.
+    /// Call after changing an operator's filters, or the next note on each
+    /// voice allocates.
+    pub fn allocate_filters(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.allocate_filters(&self.algorithm, &self.operators);
+        }
+    }
+
+    /// Mix every sounding voice into `output`, using `voice_buffer` (at least
+    /// as long as `output`) for each voice in turn.
+    pub(crate) fn process(
+        &mut self,
+        output: &mut [f32],
+        voice_buffer: &mut [f32],
+        sample_rate: f32,
+        global_expression: &Expression,
+    ) {
+        let (voices, renderer) = self.voices_and_renderer(sample_rate, global_expression);
+        let temp_buffer = &mut voice_buffer[..output.len()];
+        for voice in voices.iter_mut().filter(|v| v.active) {
+            renderer.render(voice, temp_buffer, output);
+        }
+        if let Some(previous) = self.previous.as_mut() {
+            previous.process(output, voice_buffer, sample_rate, global_expression);
+        }
+    }
+
+    fn voices_and_renderer<'a>(
+        &'a mut self,
+        sample_rate: f32,
+        global_expression: &'a Expression,
+    ) -> (&'a mut [Voice], VoiceRenderer<'a>) {
+        let renderer = VoiceRenderer {
+            algorithm: &self.algorithm,
+            operators: &self.operators,
+            scaling_factor: get_voice_scaling_factor(&self.algorithm, &self.operators),
+            sample_rate,
+            global_expression,
+        };
+        (&mut self.voices, renderer)
+    }
+
+    /// Like `process`, but with the voices shared out between the threads
+    /// of `pool`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn process_parallel(
+        &mut self,
+        output: &mut [f32],
+        pool: &mut RenderPool,
+        sample_rate: f32,
+        global_expression: &Expression,
+    ) {
+        let (voices, renderer) = self.voices_and_renderer(sample_rate, global_expression);
+        let voice_count = voices.len();
+        let voices = SharedVoices(voices.as_mut_ptr());
+        pool.render(voice_count, output, &|index, voice_buffer, accumulator| {
+            // SAFETY: the pool hands out each index below `voice_count` once
+            // per batch, and the voices stay borrowed until it is done
+            let voice = unsafe { &mut *voices.get(index) };
+            if voice.active {
+                renderer.render(voice, voice_buffer, accumulator);
+            }
+        });
+        if let Some(previous) = self.previous.as_mut() {
+            previous.process_parallel(output, pool, sample_rate, global_expression);
+        }
+    }
+}
+
+/// Pointer to a part's voices that the threads of a `RenderPool` share.
+#[cfg(not(target_arch = "wasm32"))]
+struct SharedVoices(*mut Voice);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SharedVoices {
+    fn get(&self, index: usize) -> *mut Voice {
+        self.0.wrapping_add(index)
+    }
+}
+
+// SAFETY: see `Part::process_parallel`; each voice is used by one thread
+#[cfg(not(target_arch = "wasm32"))]
+unsafe impl Sync for SharedVoices {}
+
+/// What a part's voices render with during one block.
+struct VoiceRenderer<'a> {
+    algorithm: &'a Algorithm,
+    operators: &'a [Operator],
+    scaling_factor: f32,
+    sample_rate: f32,
+    global_expression: &'a Expression,
+}
+
+impl VoiceRenderer<'_> {
+    /// Render `voice` into `voice_buffer`, add it to `output`, and leave
+    /// `voice_buffer` zeroed for the next voice.
+    fn render(&self, voice: &mut Voice, voice_buffer: &mut [f32], output: &mut [f32]) {
+        voice.process(
+            self.algorithm,
+            self.operators,
+            voice_buffer,
+            self.sample_rate,
+            self.scaling_factor,
+            self.global_expression,
+        );
+
+        for (sample, voice_sample) in output.iter_mut().zip(voice_buffer.iter_mut()) {
+            *sample += *voice_sample;
+            *voice_sample = 0.0; // Clear temp buffer for next voice
+        }
+    }
+}
+
+fn get_voice_scaling_factor(algorithm: &Algorithm, operators: &[Operator]) -> f32 {
+    let carrier_indices = algorithm.get_carrier_indices();
+    let mut max_modulation_index: f32 = 0.0;
+    let mut total_modulation_index = 0.0;
+    for carrier_idx in carrier_indices {
+        let mod_index = operators[*carrier_idx].get_modulation_index();
+        if operators[*carrier_idx].get_waveform() == Waveform::Input {
+            // NOTE: if the operator is a pass through, sum the effective modulation indices of its Modulators
+            for modulator_idx in algorithm.modulators_of(*carrier_idx) {
+                let modulator_effective_mod_index = mod_index
+                    * MODULATION_INDEX_GAIN_OFFSET
+                    * operators[modulator_idx].get_modulation_index();
+                max_modulation_index = max_modulation_index.max(modulator_effective_mod_index);
+                total_modulation_index += modulator_effective_mod_index;
+            }
+        } else {
+            max_modulation_index = max_modulation_index.max(mod_index);
+            total_modulation_index += mod_index;
+        }
+    }
+    max_modulation_index / total_modulation_index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::note::NoteSource;
+
+    #[test]
+    fn test_accepts_notes_in_its_channels_and_ranges() {
+        let mut part = Part::new(2, 1);
+        part.set_channel(Some(1));
+        part.set_key_range(72, 48);
+        part.set_velocity_range(64, 127);
+        let note = |number, velocity| {
+            NoteEvent::new(number, velocity, true, NoteSource::Midi)
+                .unwrap()
+                .with_channel(1)
+        };
+        assert_eq!(part.key_range(), (48, 72));
+        assert!(part.accepts(&note(60, 100)));
+        assert!(!part.accepts(&note(47, 100)));
+        assert!(!part.accepts(&note(60, 63)));
+        assert!(!part.accepts(&note(60, 100).with_channel(0)));
+    }
+}
@@ -14,10 +14,10 @@ pub use std::sync::OnceLock;
 
 // collections
 #[cfg(target_arch = "wasm32")]
-pub use hashbrown::{HashMap, HashSet};
+pub use hashbrown::{hash_map::Entry, HashMap, HashSet};
 
 #[cfg(not(target_arch = "wasm32"))]
-pub use std::collections::{HashMap, HashSet};
+pub use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 // PI constant
 pub use core::f32::consts::FRAC_1_SQRT_2;
@@ -0,0 +1,524 @@
+//! Presets: numbered patches in banks of 128, picked by MIDI Bank Select
+//! (CC 0 and 32) and Program Change. Patches use the web UI's format, so a
+//! `default-patches.json` can be imported as a bank and banks exported back.
+use super::algorithm::FeedbackMode;
+use super::core::EffectSlot;
+use super::error::SynthError;
+use super::params::ParamId;
+use super::waveform::Waveform;
+use crate::synth::prelude::fmt;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const PROGRAMS_PER_BANK: usize = 128;
+/// The web UI's master volume (0-100) for patches that don't set one.
+const DEFAULT_MASTER_VOLUME: f32 = 80.0;
+/// The web UI's master volume spans this many dB below full scale.
+const MASTER_VOLUME_RANGE_DB: f32 = -60.0;
+const DEFAULT_ENVELOPE: [f32; 4] = [0.001, 1.0, 0.6, 0.1];
+const EFFECT_SLOTS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresetError {
+    InvalidJson(String),
+    InvalidPatch(String),
+    Io(String),
+}
+
+impl PresetError {
+    /// Name the patch an `InvalidPatch` error came from.
+    fn in_patch(self, name: &str) -> Self {
+        match self {
+            PresetError::InvalidPatch(e) => PresetError::InvalidPatch(format!("'{}': {}", name, e)),
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresetError::InvalidJson(e) => write!(f, "Invalid patch JSON: {}", e),
+            PresetError::InvalidPatch(e) => write!(f, "Invalid patch: {}", e),
+            PresetError::Io(e) => write!(f, "Failed to read patches: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PresetError {}
+
+/// A MIDI Program Change, with the bank selected on its channel beforehand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramEvent {
+    pub channel: u8,
+    /// Bank Select MSB and LSB combined: `msb << 7 | lsb`.
+    pub bank: u16,
+    pub program: u8,
+    pub frame_offset: usize,
+}
+
+impl ProgramEvent {
+    pub fn new(channel: u8, bank: u16, program: u8) -> Self {
+        Self {
+            channel,
+            bank,
+            program,
+            frame_offset: 0,
+        }
+    }
+    pub fn with_frame_offset(mut self, frame_offset: usize) -> Self {
+        self.frame_offset = frame_offset;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterPatch {
+    LowPass { cutoff: f32, q: f32 },
+    Comb { alpha: f32, k: usize },
+    PitchedComb { alpha: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperatorPatch {
+    pub ratio: f32,
+    /// 0.0 when the operator follows the note through its ratio.
+    pub fixed_frequency: f32,
+    pub detune: f32,
+    pub feedback: f32,
+    /// As the synth uses it; patches store the web UI's knob position.
+    pub modulation_index: f32,
+    pub waveform: Waveform,
+    /// Attack, decay, sustain and release.
+    pub envelope: [f32; 4],
+    pub filters: Vec<FilterPatch>,
+}
+
+impl Default for OperatorPatch {
+    fn default() -> Self {
+        Self {
+            ratio: 1.0,
+            fixed_frequency: 0.0,
+            detune: 0.0,
+            feedback: 0.1,
+            modulation_index: modulation_index_from_knob(10.0),
+            waveform: Waveform::Sine,
+            envelope: DEFAULT_ENVELOPE,
+            filters: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EffectPatch {
+    Empty,
+    Reverb {
+        predelay_ms: f32,
+        decay_ms: f32,
+        wet_mix: f32,
+    },
+}
+
+/// One patch, as stored by the web UI: `{ "section", "name", "state" }`.
+/// Missing settings take the web UI's defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    pub name: String,
+    pub section: String,
+    /// Operator rows with the output as the last column; empty for the default
+    /// of operator 0 straight to the output.
+    pub algorithm: Vec<Vec<u32>>,
+    /// How cycles in `algorithm` are rendered; unrolled unless the patch
+    /// opts in to one-sample feedback with `feedbackMode`.
+    pub feedback_mode: FeedbackMode,
+    pub operators: Vec<OperatorPatch>,
+    /// Linear gain, 0.0 to 1.0.
+    pub master_volume: f32,
+    /// One per effect slot, starting at slot 1.
+    pub effects: [EffectPatch; EFFECT_SLOTS],
+    json: Value, // Exported unchanged, so nothing the synth ignores is lost
+}
+
+impl Patch {
+    pub fn from_json(json: &Value) -> Result<Self, PresetError> {
+        let name = json.get("name").and_then(Value::as_str).unwrap_or_default();
+        let invalid = |e: &str| PresetError::InvalidPatch(e.to_string()).in_patch(name);
+        let state = json.get("state").ok_or_else(|| invalid("no state"))?;
+        let algorithm = match state.get("algorithm") {
+            Some(rows) => rows
+                .as_array()
+                .ok_or_else(|| invalid("algorithm is not an array"))?
+                .iter()
+                .map(|row| {
+                    row.as_array()
+                        .ok_or_else(|| invalid("algorithm row is not an array"))?
+                        .iter()
+                        .map(|cell| {
+                            cell.as_u64()
+                                .map(|cell| cell as u32)
+                                .ok_or_else(|| invalid("algorithm cell is not a count"))
+                        })
+                        .collect()
+                })
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let feedback_mode = match state.get("feedbackMode") {
+            Some(mode) => mode
+                .as_str()
+                .ok_or_else(|| invalid("feedbackMode is not a string"))?
+                .parse()
+                .map_err(|e: SynthError| invalid(&e.to_string()))?,
+            None => FeedbackMode::default(),
+        };
+        let operators = match state.get("operators") {
+            Some(operators) => operators
+                .as_array()
+                .ok_or_else(|| invalid("operators is not an array"))?
+                .iter()
+                .map(|operator| operator_from_json(operator).map_err(|e| e.in_patch(name)))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let master_volume =
+            number(state, "masterVolume", DEFAULT_MASTER_VOLUME).map_err(|e| e.in_patch(name))?;
+        let mut effects = [EffectPatch::Empty; EFFECT_SLOTS];
+        if let Some(slots) = state.get("effects") {
+            let slots = slots
+                .as_array()
+                .ok_or_else(|| invalid("effects is not an array"))?;
+            for (effect, slot) in effects.iter_mut().zip(slots) {
+                *effect = effect_from_json(slot).map_err(|e| e.in_patch(name))?;
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            section: json
+                .get("section")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            algorithm,
+            feedback_mode,
+            operators,
+            master_volume: master_volume_from_slider(master_volume),
+            effects,
+            json: json.clone(),
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        self.json.clone()
+    }
+}
+
+/// The web UI's modulation index knob is squared so small values are easier to set.
+fn modulation_index_from_knob(knob: f32) -> f32 {
+    knob.signum() * 10.0 * (knob / 10.0).powi(2)
+}
+
+/// The web UI's 0-100 master volume slider is in dB.
+fn master_volume_from_slider(slider: f32) -> f32 {
+    let gain_db = MASTER_VOLUME_RANGE_DB * (1.0 - slider / 100.0);
+    10f32.powf(gain_db / 20.0)
+}
+
+fn number(json: &Value, key: &str, default: f32) -> Result<f32, PresetError> {
+    match json.get(key) {
+        None | Some(Value::Null) => Ok(default),
+        Some(value) => value
+            .as_f64()
+            .map(|value| value as f32)
+            .ok_or_else(|| PresetError::InvalidPatch(format!("{} is not a number", key))),
+    }
+}
+
+fn operator_from_json(json: &Value) -> Result<OperatorPatch, PresetError> {
+    let defaults = OperatorPatch::default();
+    let waveform = match json.get("waveform").and_then(Value::as_u64) {
+        Some(index) => Waveform::from_index(index.min(u8::MAX as u64) as u8)
+            .ok_or_else(|| PresetError::InvalidPatch(format!("unknown waveform {}", index)))?,
+        None => defaults.waveform,
+    };
+    let mut envelope = defaults.envelope;
+    if let Some(stages) = json.get("envelope") {
+        for (value, key) in envelope
+            .iter_mut()
+            .zip(["attack", "decay", "sustain", "release"])
+        {
+            *value = number(stages, key, *value)?;
+        }
+    }
+    let filters = match json.get("filters") {
+        Some(filters) => filters
+            .as_array()
+            .ok_or_else(|| PresetError::InvalidPatch("filters is not an array".to_string()))?
+            .iter()
+            .map(filter_from_json)
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    Ok(OperatorPatch {
+        ratio: number(json, "ratio", defaults.ratio)?,
+        fixed_frequency: number(json, "fixedFrequency", defaults.fixed_frequency)?,
+        detune: number(json, "detune", defaults.detune)?,
+        feedback: number(json, "feedback", defaults.feedback)?,
+        modulation_index: modulation_index_from_knob(number(json, "modulationIndex", 10.0)?),
+        waveform,
+        envelope,
+        filters,
+    })
+}
+
+fn filter_from_json(json: &Value) -> Result<FilterPatch, PresetError> {
+    let params = json.get("params").unwrap_or(&Value::Null);
+    match json.get("type").and_then(Value::as_str) {
+        Some("LowPass") => Ok(FilterPatch::LowPass {
+            cutoff: number(params, "cutoff", 1000.0)?,
+            q: number(params, "q", 0.707)?,
+        }),
+        Some("Comb") => Ok(FilterPatch::Comb {
+            alpha: number(params, "alpha", 0.5)?,
+            k: number(params, "k", 100.0)?.max(1.0) as usize,
+        }),
+        Some("PitchedComb") => Ok(FilterPatch::PitchedComb {
+            alpha: number(params, "alpha", 0.5)?,
+        }),
+        other => Err(PresetError::InvalidPatch(format!(
+            "unknown filter type {:?}",
+            other
+        ))),
+    }
+}
+
+fn effect_from_json(json: &Value) -> Result<EffectPatch, PresetError> {
+    let params = json.get("params").unwrap_or(&Value::Null);
+    match json.get("type").and_then(Value::as_str) {
+        // Clamped like the parameters, as the predelay sizes the delay lines
+        Some("Reverb") => Ok(EffectPatch::Reverb {
+            predelay_ms: ParamId::ReverbPredelay(EffectSlot::One).clamp(number(
+                params,
+                "predelayMs",
+                10.0,
+            )?),
+            decay_ms: ParamId::ReverbDecay(EffectSlot::One)
+                .clamp(number(params, "decayMs", 1000.0)?),
+            wet_mix: ParamId::ReverbWetMix(EffectSlot::One).clamp(number(params, "wetMix", 0.3)?),
+        }),
+        Some("Empty") | None => Ok(EffectPatch::Empty),
+        Some(other) => Err(PresetError::InvalidPatch(format!(
+            "unknown effect type {}",
+            other
+        ))),
+    }
+}
+
+/// Patches from JSON holding either one patch or an array of them.
+fn patches_from_json(json: &str) -> Result<Vec<Patch>, PresetError> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| PresetError::InvalidJson(e.to_string()))?;
+    match &value {
+        Value::Array(patches) => patches.iter().map(Patch::from_json).collect(),
+        patch => Ok(vec![Patch::from_json(patch)?]),
+    }
+}
+
+/// Banks of numbered patches, and which one is playing.
+#[derive(Debug, Clone, Default)]
+pub struct BankManager {
+    banks: BTreeMap<u16, Vec<Patch>>,
+    current: Option<(u16, u8)>,
+}
+
+impl BankManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Import patches in the `default-patches.json` shape as programs 0 up of
+    /// `bank`, replacing it. Past 128 patches they continue into the next banks.
+    /// Returns how many patches were imported.
+    pub fn import_json(&mut self, bank: u16, json: &str) -> Result<usize, PresetError> {
+        let patches = patches_from_json(json)?;
+        Ok(self.insert(bank, patches))
+    }
+
+    /// Import every `.json` file in `dir`, in file name order, as if their
+    /// patches were one array passed to `import_json`. Each file holds one
+    /// patch or an array of them.
+    pub fn load_directory(&mut self, bank: u16, dir: &Path) -> Result<usize, PresetError> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .map_err(|e| PresetError::Io(e.to_string()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        let mut patches = Vec::new();
+        for path in paths {
+            let json = std::fs::read_to_string(&path)
+                .map_err(|e| PresetError::Io(format!("{}: {}", path.display(), e)))?;
+            patches.extend(patches_from_json(&json)?);
+        }
+        Ok(self.insert(bank, patches))
+    }
+
+    fn insert(&mut self, bank: u16, patches: Vec<Patch>) -> usize {
+        let count = patches.len();
+        let mut patches = patches.into_iter().peekable();
+        let mut number = bank;
+        loop {
+            self.banks
+                .insert(number, patches.by_ref().take(PROGRAMS_PER_BANK).collect());
+            if patches.peek().is_none() {
+                return count;
+            }
+            number = number.wrapping_add(1);
+        }
+    }
+
+    /// A bank as JSON in the `default-patches.json` shape.
+    pub fn export_json(&self, bank: u16) -> String {
+        let patches = self
+            .banks
+            .get(&bank)
+            .map(|patches| patches.iter().map(Patch::to_json).collect())
+            .unwrap_or_default();
+        serde_json::to_string_pretty(&Value::Array(patches)).unwrap_or_default()
+    }
+
+    pub fn patch(&self, bank: u16, program: u8) -> Option<&Patch> {
+        self.banks.get(&bank)?.get(program as usize)
+    }
+
+    /// Bank numbers that hold patches, in order.
+    pub fn banks(&self) -> impl Iterator<Item = u16> + '_ {
+        self.banks.keys().copied()
+    }
+
+    pub fn programs(&self, bank: u16) -> &[Patch] {
+        self.banks.get(&bank).map_or(&[], Vec::as_slice)
+    }
+
+    /// The bank and program last selected, if any.
+    pub fn current(&self) -> Option<(u16, u8)> {
+        self.current
+    }
+    pub fn set_current(&mut self, bank: u16, program: u8) {
+        self.current = Some((bank, program));
+    }
+
+    /// The current bank and program as JSON, with the patch, or `null`.
+    pub fn current_json(&self) -> Value {
+        match self.current {
+            Some((bank, program)) => json!({
+                "bank": bank,
+                "program": program,
+                "patch": self.patch(bank, program).map(Patch::to_json),
+            }),
+            None => Value::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCHES: &str = r#"[
+        { "section": "Keys", "name": "Bell", "state": {
+            "algorithm": [[0, 1], [0, 0]],
+            "feedbackMode": "averaged",
+            "operators": [{ "ratio": 3.5, "modulationIndex": 5, "waveform": 1,
+                "envelope": { "attack": 0.01 },
+                "filters": [{ "type": "LowPass", "params": { "cutoff": 3500, "q": 1.0 } }] }],
+            "masterVolume": 100,
+            "effects": [{ "type": "Reverb", "params": { "predelayMs": 5, "decayMs": 800, "wetMix": 0.2 } }]
+        } },
+        { "name": "Plain", "state": {} }
+    ]"#;
+
+    #[test]
+    fn test_parses_patches_with_web_ui_defaults() {
+        let mut presets = BankManager::new();
+        assert_eq!(presets.import_json(2, PATCHES), Ok(2));
+
+        let bell = presets.patch(2, 0).unwrap();
+        assert_eq!(
+            (bell.name.as_str(), bell.section.as_str()),
+            ("Bell", "Keys")
+        );
+        assert_eq!(bell.algorithm, vec![vec![0, 1], vec![0, 0]]);
+        assert_eq!(bell.feedback_mode, FeedbackMode::Averaged);
+        let operator = &bell.operators[0];
+        assert_eq!(operator.ratio, 3.5);
+        assert_eq!(operator.modulation_index, 2.5);
+        assert_eq!(operator.waveform, Waveform::Triangle);
+        assert_eq!(operator.envelope, [0.01, 1.0, 0.6, 0.1]);
+        assert_eq!(
+            operator.filters,
+            vec![FilterPatch::LowPass {
+                cutoff: 3500.0,
+                q: 1.0
+            }]
+        );
+        assert_eq!(bell.master_volume, 1.0);
+        assert_eq!(
+            bell.effects[0],
+            EffectPatch::Reverb {
+                predelay_ms: 5.0,
+                decay_ms: 800.0,
+                wet_mix: 0.2
+            }
+        );
+        assert_eq!(bell.effects[1], EffectPatch::Empty);
+
+        let plain = presets.patch(2, 1).unwrap();
+        assert!(plain.algorithm.is_empty() && plain.operators.is_empty());
+        assert_eq!(plain.feedback_mode, FeedbackMode::Unrolled);
+        assert!((plain.master_volume - 0.2512).abs() < 1e-4); // -12 dB from the default slider
+        assert!(presets.patch(2, 2).is_none());
+    }
+
+    #[test]
+    fn test_export_round_trips_and_large_imports_span_banks() {
+        let mut presets = BankManager::new();
+        presets.import_json(0, PATCHES).unwrap();
+        let exported = presets.export_json(0);
+        let original: Value = serde_json::from_str(PATCHES).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&exported).unwrap(), original);
+
+        let many = format!(
+            "[{}]",
+            vec![r#"{ "name": "P", "state": {} }"#; PROGRAMS_PER_BANK + 2].join(",")
+        );
+        assert_eq!(presets.import_json(5, &many), Ok(PROGRAMS_PER_BANK + 2));
+        assert_eq!(presets.programs(5).len(), PROGRAMS_PER_BANK);
+        assert_eq!(presets.programs(6).len(), 2);
+        assert_eq!(presets.banks().collect::<Vec<_>>(), vec![0, 5, 6]);
+
+        assert!(matches!(
+            presets.import_json(
+                0,
+                r#"[{ "name": "Bad", "state": { "operators": [{ "waveform": 99 }] } }]"#
+            ),
+            Err(PresetError::InvalidPatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_reverb_settings_are_clamped_to_parameter_ranges() {
+        let mut presets = BankManager::new();
+        let huge = r#"{ "name": "Huge", "state": { "effects": [{ "type": "Reverb",
+            "params": { "predelayMs": 1e30, "decayMs": -5, "wetMix": 3 } }] } }"#;
+        presets.import_json(0, huge).unwrap();
+        assert_eq!(
+            presets.patch(0, 0).unwrap().effects[0],
+            EffectPatch::Reverb {
+                predelay_ms: 250.0,
+                decay_ms: 100.0,
+                wet_mix: 1.0
+            }
+        );
+    }
+}
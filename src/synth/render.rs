@@ -0,0 +1,50 @@
+use super::event::TimedEvent;
+use super::Synth;
+
+/// An event at an absolute frame position, for rendering without an audio device.
+#[derive(Debug, Clone, Copy)]
+pub struct ScheduledEvent {
+    pub frame: u64,
+    pub event: TimedEvent,
+}
+
+impl ScheduledEvent {
+    pub fn new(frame: u64, event: impl Into<TimedEvent>) -> Self {
+        Self {
+            frame,
+            event: event.into(),
+        }
+    }
+}
+
+/// Render `num_frames` of audio offline, applying each event at its exact frame.
+/// `events` must be sorted by frame. Events land on their exact frame whatever
+/// `block_size` is.
+pub fn render_offline(
+    synth: &mut Synth,
+    events: &[ScheduledEvent],
+    num_frames: usize,
+    sample_rate: f32,
+    block_size: usize,
+) -> Vec<f32> {
+    let block_size = block_size.max(1);
+    let mut output = vec![0.0; num_frames];
+    let mut block_events: Vec<TimedEvent> = Vec::new();
+    let mut next_event = 0;
+
+    for (block_index, block) in output.chunks_mut(block_size).enumerate() {
+        let block_start = (block_index * block_size) as u64;
+        let block_end = block_start + block.len() as u64;
+
+        block_events.clear();
+        while next_event < events.len() && events[next_event].frame < block_end {
+            let scheduled = &events[next_event];
+            let offset = scheduled.frame.saturating_sub(block_start) as usize;
+            block_events.push(scheduled.event.with_frame_offset(offset));
+            next_event += 1;
+        }
+
+        synth.process_with_events(block, sample_rate, &block_events);
+    }
+    output
+}
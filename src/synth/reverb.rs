@@ -1,5 +1,8 @@
 use super::delayline::ModulatedDelayLine;
 use super::diffuser::MultiChannelDiffuser;
+use crate::utils::logging;
+use crate::utils::smoothing::{OnePoleSmoother, Smoother, DEFAULT_SMOOTHING_MS};
+use log::warn;
 use std::f32; // Use f32
 use std::vec::Vec;
 
@@ -10,6 +13,8 @@ pub enum ReverbType {
 }
 const MIN_CHANNELS: usize = 2;
 const MAX_CHANNELS: usize = 128;
+/// Delay lines in the reverb the synth builds.
+pub const DEFAULT_CHANNELS: usize = 16;
 
 pub const MULTIPLIER_1: u64 = 69069; // A common odd multiplier from LCGs
 pub const MULTIPLIER_2: u64 = 1664525; // Another common odd LCG multiplier
@@ -112,8 +117,9 @@ pub fn find_prime_delays_from_deltas(deltas: &[usize]) -> Vec<usize> {
 
         if p == usize::MAX {
             // If p reaches MAX, something is wrong or num_delays is too high for u32 primes
-            eprintln!(
-                "Warning: p reached u32::MAX while searching for primes. Returning {} of {} requested.",
+            warn!(
+                target: logging::SYNTH,
+                "p reached u32::MAX while searching for primes. Returning {} of {} requested.",
                 result_primes.len(),
                 num_delays
             );
@@ -170,11 +176,14 @@ pub fn get_delay_target_deltas(
 // Input -> delay lines -> output AND delay filters (if I implement) -> feedback matrix -> mix w/ input ->
 // repeat
 pub struct Fdn {
-    wet_mix: f32,
+    predelay_ms: f32,
+    decay_ms: f32,
+    wet_mix: OnePoleSmoother,
     delay_lines: Vec<ModulatedDelayLine>,
+    delay_samples: Vec<usize>,
     permute_buffer: Vec<f32>,
     input_channels: Vec<f32>,
-    decay_coeffs: Vec<f32>,
+    decay_coeffs: Vec<OnePoleSmoother>,
     feedback: Vec<f32>,
     feedback_mix_buffer: Vec<f32>,
     channels: usize,
@@ -190,9 +199,14 @@ pub struct Fdn {
 }
 
 impl Fdn {
-    fn new(predelay_ms: f32, decay_ms: f32, wet_mix: f32, sample_rate: f32) -> Self {
+    fn new(
+        predelay_ms: f32,
+        decay_ms: f32,
+        wet_mix: f32,
+        sample_rate: f32,
+        channels: usize,
+    ) -> Self {
         let spread_ms: f32 = 500.0;
-        let channels: usize = 16;
         let diffusion_steps: usize = 4;
 
         let predelay = predelay_ms / 1000.0;
@@ -211,7 +225,10 @@ impl Fdn {
         );
         let decay_coeffs = delay_samples
             .iter()
-            .map(|&d| 0.001_f32.powf(d as f32 / sample_rate / rt60))
+            .map(|&d| {
+                OnePoleSmoother::new(DEFAULT_SMOOTHING_MS, sample_rate)
+                    .with_value(Self::decay_coefficient(d, rt60, sample_rate))
+            })
             .collect::<Vec<_>>();
         let mut delay_lines = Vec::with_capacity(channels);
         for i in 0..channels {
@@ -241,7 +258,10 @@ impl Fdn {
 
         let (p_in, p_out) = get_permutations(channels, 42);
         Self {
-            wet_mix,
+            predelay_ms,
+            decay_ms,
+            wet_mix: OnePoleSmoother::new(DEFAULT_SMOOTHING_MS, sample_rate).with_value(wet_mix),
+            delay_samples,
             feedback: vec![0.0; channels],
             feedback_mix_buffer: vec![0.0; channels],
             permute_buffer: vec![0.0; channels],
@@ -258,8 +278,25 @@ impl Fdn {
             delay_lines,
         }
     }
+    fn decay_coefficient(delay_samples: usize, rt60: f32, sample_rate: f32) -> f32 {
+        0.001_f32.powf(delay_samples as f32 / sample_rate / rt60)
+    }
+    /// Change decay time and wet mix in place. Both glide to their new values;
+    /// changing the predelay needs a new `Fdn` since it sets the delay lengths.
+    fn set_params(&mut self, decay_ms: f32, wet_mix: f32) {
+        self.decay_ms = decay_ms;
+        let rt60 = decay_ms / 1000.0;
+        for (coeff, &delay) in self.decay_coeffs.iter_mut().zip(self.delay_samples.iter()) {
+            coeff.set_target(Self::decay_coefficient(delay, rt60, self.sample_rate));
+        }
+        self.wet_mix.set_target(wet_mix);
+    }
     fn configure(&mut self, sample_rate: f32) {
         self.sample_rate = sample_rate;
+        self.wet_mix.set_sample_rate(sample_rate);
+        for coeff in self.decay_coeffs.iter_mut() {
+            coeff.set_sample_rate(sample_rate);
+        }
         self.reset();
     }
     fn reset(&mut self) {
@@ -282,7 +319,7 @@ impl Fdn {
             // retrieve feedback and push mixed input into delay line
             self.delay_outputs[i] =
                 self.delay_lines[i].process(self.feedback[i] + self.input_channels[i]);
-            let delayed_output = self.delay_outputs[i] * self.decay_coeffs[i];
+            let delayed_output = self.delay_outputs[i] * self.decay_coeffs[i].next_value();
             // let filtered_output = self.feedback_lowpass_filters[i].process(delayed_output);
             let filtered_output = delayed_output; // No filtering for now
             wet += filtered_output;
@@ -318,7 +355,8 @@ impl Fdn {
         let filtered_output = wet_main_reverb;
 
         let combined_wet = filtered_output;
-        *input = *input * (1.0 - self.wet_mix) + self.wet_mix * combined_wet;
+        let wet_mix = self.wet_mix.next_value();
+        *input = *input * (1.0 - wet_mix) + wet_mix * combined_wet;
     }
 }
 
@@ -352,6 +390,27 @@ impl Reverb {
     //     Reverb::FDN(s) => s.set_dry_wet(mix),
     // }
     // }
+    pub fn predelay_ms(&self) -> f32 {
+        match self {
+            Reverb::FDN(s) => s.predelay_ms,
+        }
+    }
+    pub fn decay_ms(&self) -> f32 {
+        match self {
+            Reverb::FDN(s) => s.decay_ms,
+        }
+    }
+    /// The wet mix currently being moved towards.
+    pub fn wet_mix(&self) -> f32 {
+        match self {
+            Reverb::FDN(s) => s.wet_mix.target(),
+        }
+    }
+    pub fn set_params(&mut self, decay_ms: f32, wet_mix: f32) {
+        match self {
+            Reverb::FDN(s) => s.set_params(decay_ms, wet_mix),
+        }
+    }
     pub fn configure(&mut self, sample_rate: f32) {
         // Removed RNG argument
         match self {
@@ -366,6 +425,35 @@ impl Reverb {
         sample_rate: f32, // add sample_rate as a parameter
     ) -> Self {
         // Removed RNG argument
-        Reverb::FDN(Fdn::new(predelay_ms, decay_ms, wet_mix, sample_rate))
+        Self::new_fdn_with_channels(
+            predelay_ms,
+            decay_ms,
+            wet_mix,
+            sample_rate,
+            DEFAULT_CHANNELS,
+        )
+    }
+    /// Like `new_fdn`, with `channels` delay lines (rounded to the nearest power of two
+    /// between 2 and 128). More channels give a denser tail at a higher cost.
+    pub fn new_fdn_with_channels(
+        predelay_ms: f32,
+        decay_ms: f32,
+        wet_mix: f32,
+        sample_rate: f32,
+        channels: usize,
+    ) -> Self {
+        Reverb::FDN(Fdn::new(
+            predelay_ms,
+            decay_ms,
+            wet_mix,
+            sample_rate,
+            channels,
+        ))
+    }
+    /// Number of delay lines in the feedback network.
+    pub fn channels(&self) -> usize {
+        match self {
+            Reverb::FDN(s) => s.channels,
+        }
     }
 }
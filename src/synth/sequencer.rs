@@ -0,0 +1,499 @@
+//! Pattern step sequencer, run sample-accurately inside `Synth::process` so
+//! it behaves the same on native, WASM and offline rendering. Tempo, start and
+//! stop come from the synth's `Transport`.
+//!
+//! The sequencer only decides *what* happens *when*; it hands `SequencerAction`s
+//! back to the synth, which plays them as `NoteSource::Sequencer` notes and
+//! parameter changes.
+use super::error::SynthError;
+use super::params::ParamId;
+
+/// Notes that can be held at once, e.g. by steps with a gate longer than one step.
+const MAX_PENDING_NOTES: usize = 32;
+const DEFAULT_STEPS_PER_BEAT: u32 = 4;
+
+fn invalid(reason: &str) -> SynthError {
+    SynthError::InvalidPattern(reason.to_string())
+}
+const MAX_SWING: f32 = 0.75;
+
+/// Temporarily overrides a parameter while its step plays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamLock {
+    pub id: ParamId,
+    pub value: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub note: Option<u8>, // None for a rest
+    pub velocity: u8,
+    /// Note length as a fraction of the step; above 1.0 ties into later steps.
+    pub gate: f32,
+    /// Chance from 0.0 to 1.0 that the step plays.
+    pub probability: f32,
+    pub locks: Vec<ParamLock>,
+}
+
+impl Step {
+    pub fn note(note: u8, velocity: u8) -> Self {
+        Self {
+            note: Some(note),
+            velocity,
+            ..Self::rest()
+        }
+    }
+    pub fn rest() -> Self {
+        Self {
+            note: None,
+            velocity: 100,
+            gate: 0.5,
+            probability: 1.0,
+            locks: Vec::new(),
+        }
+    }
+    pub fn with_gate(mut self, gate: f32) -> Self {
+        self.gate = gate;
+        self
+    }
+    pub fn with_probability(mut self, probability: f32) -> Self {
+        self.probability = probability;
+        self
+    }
+    pub fn with_lock(mut self, id: ParamId, value: f32) -> Self {
+        self.locks.push(ParamLock { id, value });
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub steps: Vec<Step>,
+    pub steps_per_beat: u32, // 4 plays sixteenth notes
+}
+
+impl Pattern {
+    pub fn new(steps: Vec<Step>) -> Self {
+        Self {
+            steps,
+            steps_per_beat: DEFAULT_STEPS_PER_BEAT,
+        }
+    }
+    pub fn with_steps_per_beat(mut self, steps_per_beat: u32) -> Self {
+        self.steps_per_beat = steps_per_beat;
+        self
+    }
+
+    /// Parse a pattern from JSON:
+    /// `{ "stepsPerBeat": 4, "steps": [{ "note": 60, "velocity": 100, "gate": 0.5,
+    /// "probability": 1.0, "locks": [{ "id": "op/1/ratio", "value": 2.0 }] }, ...] }`.
+    /// Every field except `steps` is optional, and a step without `note` is a rest.
+    pub fn from_json(json: &str) -> Result<Self, SynthError> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| SynthError::InvalidJson(e.to_string()))?;
+        let steps_per_beat = value
+            .get("stepsPerBeat")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_STEPS_PER_BEAT as u64) as u32;
+        let steps = value
+            .get("steps")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| invalid("no steps array"))?
+            .iter()
+            .map(Self::step_from_json)
+            .collect::<Result<Vec<_>, _>>()?;
+        let pattern = Pattern::new(steps).with_steps_per_beat(steps_per_beat);
+        pattern.validate()?;
+        Ok(pattern)
+    }
+
+    fn step_from_json(value: &serde_json::Value) -> Result<Step, SynthError> {
+        let number = |key: &str| value.get(key).and_then(|v| v.as_f64());
+        let mut step = Step::rest();
+        step.note = number("note").map(|n| n as u8);
+        step.velocity = number("velocity").map_or(step.velocity, |v| v as u8);
+        step.gate = number("gate").map_or(step.gate, |g| g as f32);
+        step.probability = number("probability").map_or(step.probability, |p| p as f32);
+        if let Some(locks) = value.get("locks").and_then(|v| v.as_array()) {
+            for lock in locks {
+                let id = lock
+                    .get("id")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| invalid("parameter lock has no id"))?
+                    .parse::<ParamId>()?;
+                let value = lock
+                    .get("value")
+                    .and_then(|v| v.as_f64())
+                    .ok_or_else(|| invalid("parameter lock has no value"))?;
+                step.locks.push(ParamLock {
+                    id,
+                    value: value as f32,
+                });
+            }
+        }
+        Ok(step)
+    }
+
+    /// Patterns need at least one step, valid notes, and locks on operator
+    /// parameters only (other parameters can't change without allocating).
+    pub fn validate(&self) -> Result<(), SynthError> {
+        if self.steps.is_empty() {
+            return Err(invalid("no steps"));
+        }
+        if self.steps_per_beat == 0 {
+            return Err(invalid("steps per beat must be at least 1"));
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if step.note.is_some_and(|n| n >= 128) || step.velocity >= 128 {
+                return Err(SynthError::InvalidPattern(format!(
+                    "step {} has an invalid note or velocity",
+                    i
+                )));
+            }
+            if let Some(lock) = step.locks.iter().find(|l| l.id.operator_index().is_none()) {
+                return Err(SynthError::InvalidPattern(format!(
+                    "step {} locks {}, but only operator parameters can be locked",
+                    i, lock.id
+                )));
+            }
+        }
+        Ok(())
+    }
+    fn max_locks(&self) -> usize {
+        self.steps.iter().map(|s| s.locks.len()).max().unwrap_or(0)
+    }
+}
+
+/// Something the synth should do now, on behalf of the sequencer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SequencerAction {
+    NoteOn {
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        note: u8,
+    },
+    /// Apply a parameter lock.
+    Lock {
+        id: ParamId,
+        value: f32,
+    },
+    /// Put a locked parameter back to the value it had before.
+    Unlock {
+        id: ParamId,
+        value: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Sequencer {
+    patterns: Vec<Pattern>,
+    /// Pattern indices played in order, then repeated. Empty plays pattern 0.
+    chain: Vec<usize>,
+    swing: f32,
+    playing: bool,
+    // --- Playback position ---
+    chain_position: usize,
+    step_index: usize,
+    frames_into_step: f64, // Since the step's unswung start
+    step_fired: bool,
+    pending_note_offs: Vec<(u8, f64)>, // (note, frames until its note off)
+    active_locks: Vec<(ParamId, f32)>, // (param, value before the first lock)
+    random_state: u32,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            chain: Vec::new(),
+            swing: 0.0,
+            playing: false,
+            chain_position: 0,
+            step_index: 0,
+            frames_into_step: 0.0,
+            step_fired: false,
+            pending_note_offs: Vec::with_capacity(MAX_PENDING_NOTES),
+            active_locks: Vec::new(),
+            random_state: 0x9E37_79B9,
+        }
+    }
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `pattern` at `index`, growing the pattern list with copies of it if needed.
+    pub fn set_pattern(&mut self, index: usize, pattern: Pattern) -> Result<(), SynthError> {
+        pattern.validate()?;
+        self.active_locks.reserve(pattern.max_locks());
+        if index < self.patterns.len() {
+            self.patterns[index] = pattern;
+        } else {
+            self.patterns.resize(index, pattern.clone());
+            self.patterns.push(pattern);
+        }
+        self.clamp_position();
+        Ok(())
+    }
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+    /// Set the order in which patterns play. Indices must refer to stored patterns.
+    pub fn set_chain(&mut self, chain: Vec<usize>) -> Result<(), SynthError> {
+        if let Some(&index) = chain.iter().find(|&&i| i >= self.patterns.len()) {
+            return Err(SynthError::InvalidPattern(format!(
+                "chain refers to missing pattern {}",
+                index
+            )));
+        }
+        self.chain = chain;
+        self.clamp_position();
+        Ok(())
+    }
+    /// Delay every second step by this fraction of a step. 0.0 is straight and
+    /// 1/3 gives a triplet feel.
+    pub fn set_swing(&mut self, swing: f32) {
+        self.swing = swing.clamp(0.0, MAX_SWING);
+    }
+    /// Seed the random generator used for step probability, for repeatable renders.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.random_state = seed.max(1);
+    }
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+    pub fn current_step(&self) -> usize {
+        self.step_index
+    }
+    /// Actions needed on top of the synth's own preallocation in the worst case.
+    pub fn max_actions(&self) -> usize {
+        let max_locks = self
+            .patterns
+            .iter()
+            .map(Pattern::max_locks)
+            .max()
+            .unwrap_or(0);
+        MAX_PENDING_NOTES + 2 * max_locks + 2
+    }
+
+    /// Start from the first step of the chain.
+    pub fn start(&mut self) {
+        if self.patterns.is_empty() {
+            return;
+        }
+        self.playing = true;
+        self.chain_position = 0;
+        self.step_index = 0;
+        self.frames_into_step = 0.0;
+        self.step_fired = false;
+    }
+
+    /// Carry on from where playback stopped.
+    pub fn resume(&mut self) {
+        if !self.patterns.is_empty() {
+            self.playing = true;
+        }
+    }
+
+    /// Move to `beats` quarter notes into the chain, e.g. from a MIDI song position.
+    pub fn locate(&mut self, beats: f64, frames_per_beat: f64) {
+        let chain_len = self.chain.len().max(1);
+        let mut remaining = beats.max(0.0);
+        // Skip whole passes through the chain, then walk pattern by pattern
+        let chain_beats: f64 = (0..chain_len)
+            .filter_map(|i| self.patterns.get(self.chain.get(i).copied().unwrap_or(0)))
+            .map(|p| p.steps.len() as f64 / p.steps_per_beat as f64)
+            .sum();
+        if chain_beats <= 0.0 {
+            return;
+        }
+        remaining %= chain_beats;
+        for position in 0..chain_len {
+            self.chain_position = position;
+            let Some(pattern) = self.current_pattern() else {
+                continue;
+            };
+            let steps = remaining * pattern.steps_per_beat as f64;
+            if steps < pattern.steps.len() as f64 {
+                self.step_index = steps as usize;
+                self.frames_into_step = steps.fract() * self.step_length(frames_per_beat);
+                self.step_fired = self.frames_into_step > self.step_onset(frames_per_beat);
+                return;
+            }
+            remaining -= pattern.steps.len() as f64 / pattern.steps_per_beat as f64;
+        }
+        self.clamp_position();
+    }
+
+    /// Stop playback, releasing held notes and restoring locked parameters.
+    pub fn stop(&mut self, actions: &mut Vec<SequencerAction>) {
+        self.playing = false;
+        for (note, _) in self.pending_note_offs.drain(..) {
+            actions.push(SequencerAction::NoteOff { note });
+        }
+        for (id, value) in self.active_locks.drain(..) {
+            actions.push(SequencerAction::Unlock { id, value });
+        }
+    }
+
+    /// Record the value a parameter had before its first lock, so it can be restored.
+    pub fn remember_unlocked_value(&mut self, id: ParamId, value: f32) {
+        if !self.active_locks.iter().any(|(locked, _)| *locked == id)
+            && self.active_locks.len() < self.active_locks.capacity()
+        {
+            self.active_locks.push((id, value));
+        }
+    }
+
+    fn clamp_position(&mut self) {
+        if self.chain_position >= self.chain.len().max(1) {
+            self.chain_position = 0;
+        }
+        if self.step_index >= self.current_pattern().map_or(0, |p| p.steps.len()) {
+            self.step_index = 0;
+        }
+    }
+    fn current_pattern(&self) -> Option<&Pattern> {
+        let index = self.chain.get(self.chain_position).copied().unwrap_or(0);
+        self.patterns.get(index)
+    }
+    fn step_length(&self, frames_per_beat: f64) -> f64 {
+        let steps_per_beat = self
+            .current_pattern()
+            .map_or(DEFAULT_STEPS_PER_BEAT, |p| p.steps_per_beat);
+        frames_per_beat / steps_per_beat as f64
+    }
+    fn step_onset(&self, frames_per_beat: f64) -> f64 {
+        if self.step_index % 2 == 1 {
+            self.swing as f64 * self.step_length(frames_per_beat)
+        } else {
+            0.0
+        }
+    }
+
+    /// Frames until something is due, or `usize::MAX` while stopped.
+    pub fn frames_until_next_action(&self, frames_per_beat: f64) -> usize {
+        if !self.playing {
+            return usize::MAX;
+        }
+        let mut next = self.step_length(frames_per_beat) - self.frames_into_step;
+        if !self.step_fired {
+            next = next.min(self.step_onset(frames_per_beat) - self.frames_into_step);
+        }
+        for (_, remaining) in &self.pending_note_offs {
+            next = next.min(*remaining);
+        }
+        next.ceil().max(0.0) as usize
+    }
+
+    /// Move the playback position forward.
+    pub fn advance(&mut self, frames: usize) {
+        if !self.playing {
+            return;
+        }
+        self.frames_into_step += frames as f64;
+        for (_, remaining) in self.pending_note_offs.iter_mut() {
+            *remaining -= frames as f64;
+        }
+    }
+
+    /// Push every action due at the current position onto `actions`.
+    pub fn collect_due(&mut self, frames_per_beat: f64, actions: &mut Vec<SequencerAction>) {
+        if !self.playing {
+            return;
+        }
+        // Note offs first, so a repeated note retriggers cleanly
+        let mut i = 0;
+        while i < self.pending_note_offs.len() {
+            if self.pending_note_offs[i].1 <= 0.0 {
+                let (note, _) = self.pending_note_offs.swap_remove(i);
+                actions.push(SequencerAction::NoteOff { note });
+            } else {
+                i += 1;
+            }
+        }
+        let step_length = self.step_length(frames_per_beat);
+        while self.frames_into_step >= step_length {
+            self.frames_into_step -= step_length;
+            self.next_step();
+        }
+        if !self.step_fired && self.frames_into_step >= self.step_onset(frames_per_beat) {
+            self.step_fired = true;
+            self.fire_step(step_length, actions);
+        }
+    }
+
+    fn next_step(&mut self) {
+        self.step_fired = false;
+        self.step_index += 1;
+        if self.step_index >= self.current_pattern().map_or(0, |p| p.steps.len()) {
+            self.step_index = 0;
+            self.chain_position = (self.chain_position + 1) % self.chain.len().max(1);
+        }
+    }
+
+    fn fire_step(&mut self, step_length: f64, actions: &mut Vec<SequencerAction>) {
+        let pattern_index = self.chain.get(self.chain_position).copied().unwrap_or(0);
+        let Some(step) = self
+            .patterns
+            .get(pattern_index)
+            .and_then(|p| p.steps.get(self.step_index))
+        else {
+            return;
+        };
+        let plays =
+            step.probability >= 1.0 || next_random(&mut self.random_state) < step.probability;
+
+        // Restore parameters this step doesn't lock
+        let mut i = 0;
+        while i < self.active_locks.len() {
+            let (id, value) = self.active_locks[i];
+            if plays && step.locks.iter().any(|lock| lock.id == id) {
+                i += 1;
+            } else {
+                self.active_locks.swap_remove(i);
+                actions.push(SequencerAction::Unlock { id, value });
+            }
+        }
+        if !plays {
+            return;
+        }
+        for lock in &step.locks {
+            actions.push(SequencerAction::Lock {
+                id: lock.id,
+                value: lock.value,
+            });
+        }
+        if let Some(note) = step.note {
+            if let Some(i) = self.pending_note_offs.iter().position(|(n, _)| *n == note) {
+                // Retriggering a tied note ends it first
+                self.pending_note_offs.swap_remove(i);
+                actions.push(SequencerAction::NoteOff { note });
+            } else if self.pending_note_offs.len() == MAX_PENDING_NOTES {
+                // Never grow on the audio thread; end the oldest held note instead
+                let (oldest, _) = self.pending_note_offs.remove(0);
+                actions.push(SequencerAction::NoteOff { note: oldest });
+            }
+            actions.push(SequencerAction::NoteOn {
+                note,
+                velocity: step.velocity,
+            });
+            let length = (step.gate.max(0.0) as f64 * step_length).max(1.0);
+            self.pending_note_offs.push((note, length));
+        }
+    }
+}
+
+/// xorshift32, returning a value in 0.0..1.0. Seeded per sequencer so renders repeat.
+fn next_random(state: &mut u32) -> f32 {
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    (x >> 8) as f32 / (1u32 << 24) as f32
+}
@@ -0,0 +1,550 @@
+//! Standard MIDI Files (format 0 and 1): parsing, and a merged timeline of
+//! channel messages in seconds with tempo changes applied.
+use super::midi::MidiDecoder;
+use super::note::NoteSource;
+use super::render::ScheduledEvent;
+use crate::synth::prelude::fmt;
+use std::path::Path;
+
+/// 120 BPM, the tempo until the first tempo event.
+const DEFAULT_MICROS_PER_QUARTER: u32 = 500_000;
+const ALL_CHANNELS: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmfError {
+    NotMidiFile,
+    /// Format 2 files hold independent patterns rather than one song.
+    UnsupportedFormat(u16),
+    Truncated,
+    InvalidData(String),
+    Io(String),
+}
+
+impl fmt::Display for SmfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmfError::NotMidiFile => write!(f, "Not a Standard MIDI File"),
+            SmfError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported MIDI file format {}", format)
+            }
+            SmfError::Truncated => write!(f, "MIDI file is truncated"),
+            SmfError::InvalidData(e) => write!(f, "Invalid MIDI file: {}", e),
+            SmfError::Io(e) => write!(f, "Failed to read MIDI file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SmfError {}
+
+/// How delta times are measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Division {
+    TicksPerQuarter(u16),
+    /// Fixed time per tick: SMPTE frames per second and ticks per frame.
+    /// Tempo events don't change the timing of these files.
+    Smpte {
+        frames_per_second: f64,
+        ticks_per_frame: u8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackEventKind {
+    /// A channel message, padded with zero if shorter than three bytes.
+    Channel([u8; 3]),
+    /// Set tempo meta event.
+    Tempo { micros_per_quarter: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackEvent {
+    pub tick: u64, // Absolute, from the start of the track
+    pub kind: TrackEventKind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    pub name: Option<String>,
+    pub events: Vec<TrackEvent>,
+}
+
+/// Which tracks and channels to play.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackFilter {
+    tracks: Option<Vec<usize>>, // Track indices from 0; None plays them all
+    channels: u16,              // Bit n set plays channel n (0-15)
+}
+
+impl Default for PlaybackFilter {
+    fn default() -> Self {
+        Self {
+            tracks: None,
+            channels: ALL_CHANNELS,
+        }
+    }
+}
+
+impl PlaybackFilter {
+    pub fn all() -> Self {
+        Self::default()
+    }
+    pub fn with_tracks(mut self, tracks: Vec<usize>) -> Self {
+        self.tracks = Some(tracks);
+        self
+    }
+    /// Play only these channels, numbered 0-15.
+    pub fn with_channels(mut self, channels: &[u8]) -> Self {
+        self.channels = channels
+            .iter()
+            .filter(|&&channel| channel < 16)
+            .fold(0, |mask, &channel| mask | 1 << channel);
+        self
+    }
+    pub fn plays_track(&self, track: usize) -> bool {
+        self.tracks
+            .as_ref()
+            .is_none_or(|tracks| tracks.contains(&track))
+    }
+    pub fn plays_channel(&self, channel: u8) -> bool {
+        self.channels & (1 << (channel & 0x0F)) != 0
+    }
+}
+
+/// A channel message at a time in seconds from the start of the song.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelineEvent {
+    pub seconds: f64,
+    pub message: [u8; 3],
+}
+
+/// Where a tempo takes effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TempoChange {
+    tick: u64,
+    seconds: f64,
+    micros_per_quarter: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiFile {
+    pub format: u16,
+    pub division: Division,
+    pub tracks: Vec<Track>,
+    tempo_map: Vec<TempoChange>, // Sorted by tick; always starts at tick 0
+}
+
+impl MidiFile {
+    pub fn from_file(path: &Path) -> Result<Self, SmfError> {
+        let bytes = std::fs::read(path).map_err(|e| SmfError::Io(e.to_string()))?;
+        Self::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, SmfError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4).ok() != Some(b"MThd".as_slice()) {
+            return Err(SmfError::NotMidiFile);
+        }
+        let header_len = reader.u32()? as usize;
+        if header_len < 6 {
+            return Err(SmfError::InvalidData("header is too short".to_string()));
+        }
+        let header = reader.take(header_len)?;
+        let format = u16::from_be_bytes([header[0], header[1]]);
+        let track_count = u16::from_be_bytes([header[2], header[3]]);
+        if format > 1 {
+            return Err(SmfError::UnsupportedFormat(format));
+        }
+        let division = match u16::from_be_bytes([header[4], header[5]]) {
+            0 => {
+                return Err(SmfError::InvalidData(
+                    "zero ticks per quarter note".to_string(),
+                ))
+            }
+            ticks if ticks & 0x8000 == 0 => Division::TicksPerQuarter(ticks),
+            smpte => {
+                let frames_per_second = match -((smpte >> 8) as u8 as i8) {
+                    29 => 29.97,
+                    fps @ (24 | 25 | 30) => fps as f64,
+                    fps => {
+                        return Err(SmfError::InvalidData(format!(
+                            "unknown SMPTE frame rate {}",
+                            fps
+                        )))
+                    }
+                };
+                let ticks_per_frame = smpte as u8;
+                if ticks_per_frame == 0 {
+                    return Err(SmfError::InvalidData("zero ticks per frame".to_string()));
+                }
+                Division::Smpte {
+                    frames_per_second,
+                    ticks_per_frame,
+                }
+            }
+        };
+
+        let mut tracks = Vec::with_capacity(track_count as usize);
+        while !reader.is_empty() && tracks.len() < track_count as usize {
+            let id = reader.take(4)?;
+            let len = reader.u32()? as usize;
+            let data = reader.take(len)?;
+            // Unknown chunk types are skipped, as the spec asks
+            if id == b"MTrk" {
+                tracks.push(parse_track(data)?);
+            }
+        }
+
+        let mut file = Self {
+            format,
+            division,
+            tracks,
+            tempo_map: Vec::new(),
+        };
+        file.tempo_map = file.build_tempo_map();
+        Ok(file)
+    }
+
+    /// Tempo changes from every track, resolved to the seconds they happen at.
+    fn build_tempo_map(&self) -> Vec<TempoChange> {
+        let mut changes: Vec<(u64, u32)> = self
+            .tracks
+            .iter()
+            .flat_map(|track| &track.events)
+            .filter_map(|event| match event.kind {
+                TrackEventKind::Tempo { micros_per_quarter } => {
+                    Some((event.tick, micros_per_quarter))
+                }
+                TrackEventKind::Channel(_) => None,
+            })
+            .collect();
+        changes.sort_by_key(|&(tick, _)| tick);
+
+        let mut map = vec![TempoChange {
+            tick: 0,
+            seconds: 0.0,
+            micros_per_quarter: DEFAULT_MICROS_PER_QUARTER,
+        }];
+        for (tick, micros_per_quarter) in changes {
+            let seconds = self.seconds_in(map.last().unwrap(), tick);
+            let change = TempoChange {
+                tick,
+                seconds,
+                micros_per_quarter,
+            };
+            match map.last_mut() {
+                // A later change at the same tick replaces the earlier one
+                Some(last) if last.tick == tick => *last = change,
+                _ => map.push(change),
+            }
+        }
+        map
+    }
+
+    fn seconds_in(&self, segment: &TempoChange, tick: u64) -> f64 {
+        let ticks = tick.saturating_sub(segment.tick) as f64;
+        match self.division {
+            Division::TicksPerQuarter(ppq) => {
+                segment.seconds
+                    + ticks * segment.micros_per_quarter as f64 / (1_000_000.0 * ppq as f64)
+            }
+            Division::Smpte {
+                frames_per_second,
+                ticks_per_frame,
+            } => tick as f64 / (frames_per_second * ticks_per_frame as f64),
+        }
+    }
+
+    /// Time of a tick from the start of the song, following tempo changes.
+    pub fn tick_to_seconds(&self, tick: u64) -> f64 {
+        let index = self.tempo_map.partition_point(|change| change.tick <= tick);
+        self.seconds_in(&self.tempo_map[index.saturating_sub(1)], tick)
+    }
+
+    /// Time of a position in quarter notes from the start of the song, e.g. a loop point.
+    pub fn beats_to_seconds(&self, beats: f64) -> f64 {
+        let ticks_per_quarter = match self.division {
+            Division::TicksPerQuarter(ppq) => ppq as f64,
+            // No musical time in SMPTE files; count quarter notes at 120 BPM
+            Division::Smpte {
+                frames_per_second,
+                ticks_per_frame,
+            } => frames_per_second * ticks_per_frame as f64 / 2.0,
+        };
+        self.tick_to_seconds((beats.max(0.0) * ticks_per_quarter).round() as u64)
+    }
+
+    /// Length of the song: the time of the last event in any track.
+    pub fn duration_seconds(&self) -> f64 {
+        let last_tick = self
+            .tracks
+            .iter()
+            .filter_map(|track| track.events.last())
+            .map(|event| event.tick)
+            .max()
+            .unwrap_or(0);
+        self.tick_to_seconds(last_tick)
+    }
+
+    /// Channel messages of the chosen tracks and channels, merged in time order.
+    /// Tempo changes apply whichever tracks are chosen.
+    pub fn timeline(&self, filter: &PlaybackFilter) -> Vec<TimelineEvent> {
+        let mut events: Vec<(u64, [u8; 3])> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| filter.plays_track(*index))
+            .flat_map(|(_, track)| &track.events)
+            .filter_map(|event| match event.kind {
+                TrackEventKind::Channel(message) if filter.plays_channel(message[0]) => {
+                    Some((event.tick, message))
+                }
+                _ => None,
+            })
+            .collect();
+        // Stable, so simultaneous events keep their track order
+        events.sort_by_key(|&(tick, _)| tick);
+        events
+            .into_iter()
+            .map(|(tick, message)| TimelineEvent {
+                seconds: self.tick_to_seconds(tick),
+                message,
+            })
+            .collect()
+    }
+
+    /// The chosen tracks and channels as synth events at exact frames, for
+    /// `render_offline`.
+    pub fn scheduled_events(
+        &self,
+        filter: &PlaybackFilter,
+        sample_rate: f32,
+    ) -> Vec<ScheduledEvent> {
+        let mut decoder = MidiDecoder::new(NoteSource::Midi);
+        self.timeline(filter)
+            .iter()
+            .filter_map(|event| {
+                let frame = (event.seconds * sample_rate as f64).round() as u64;
+                decoder
+                    .decode(&event.message)
+                    .map(|decoded| ScheduledEvent::new(frame, decoded))
+            })
+            .collect()
+    }
+}
+
+fn parse_track(data: &[u8]) -> Result<Track, SmfError> {
+    let mut reader = Reader::new(data);
+    let mut track = Track::default();
+    let mut tick = 0u64;
+    let mut running_status: Option<u8> = None;
+
+    while !reader.is_empty() {
+        tick += reader.variable_length()? as u64;
+        let mut status = reader.u8()?;
+        let first_data = if status & 0x80 == 0 {
+            // Running status: this byte is data for the previous status
+            let data = status;
+            status = running_status
+                .ok_or_else(|| SmfError::InvalidData("data byte without a status".to_string()))?;
+            Some(data)
+        } else {
+            None
+        };
+
+        match status {
+            0xFF => {
+                running_status = None;
+                let meta_type = reader.u8()?;
+                let len = reader.variable_length()? as usize;
+                let data = reader.take(len)?;
+                match meta_type {
+                    0x2F => break, // End of track
+                    0x51 if len == 3 => track.events.push(TrackEvent {
+                        tick,
+                        kind: TrackEventKind::Tempo {
+                            micros_per_quarter: u32::from_be_bytes([0, data[0], data[1], data[2]]),
+                        },
+                    }),
+                    0x03 if track.name.is_none() => {
+                        track.name = Some(String::from_utf8_lossy(data).into_owned());
+                    }
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                // SysEx isn't played back
+                running_status = None;
+                let len = reader.variable_length()? as usize;
+                reader.take(len)?;
+            }
+            0x80..=0xEF => {
+                running_status = Some(status);
+                let data_len = if matches!(status & 0xF0, 0xC0 | 0xD0) {
+                    1
+                } else {
+                    2
+                };
+                let mut message = [status, 0, 0];
+                let mut filled = 1;
+                if let Some(data) = first_data {
+                    message[1] = data;
+                    filled = 2;
+                }
+                while filled <= data_len {
+                    message[filled] = reader.u8()? & 0x7F;
+                    filled += 1;
+                }
+                track.events.push(TrackEvent {
+                    tick,
+                    kind: TrackEventKind::Channel(message),
+                });
+            }
+            other => {
+                return Err(SmfError::InvalidData(format!(
+                    "unexpected status byte {:#04X}",
+                    other
+                )))
+            }
+        }
+    }
+    Ok(track)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SmfError> {
+        if len > self.bytes.len() {
+            return Err(SmfError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, SmfError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, SmfError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+    /// A variable-length quantity: 7 bits per byte, at most four bytes.
+    fn variable_length(&mut self) -> Result<u32, SmfError> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SmfError::InvalidData(
+            "variable-length quantity is too long".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    /// Format 1, 96 ticks per quarter: a tempo track that halves the tempo
+    /// after one beat, and a note track on channels 1 and 2.
+    fn two_track_file() -> Vec<u8> {
+        let mut file = chunk(b"MThd", &[0, 1, 0, 2, 0, 96]);
+        file.extend(chunk(
+            b"MTrk",
+            &[
+                0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 500000 us: 120 BPM
+                0x60, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // After 96 ticks: 60 BPM
+                0x00, 0xFF, 0x2F, 0x00,
+            ],
+        ));
+        file.extend(chunk(
+            b"MTrk",
+            &[
+                0x00, 0xFF, 0x03, 0x04, b'L', b'e', b'a', b'd', // Track name
+                0x00, 0x90, 60, 100, // Note on, channel 1
+                0x81, 0x40, 62, 90, // Running status, 192 ticks later
+                0x00, 0x91, 36, 80, // Channel 2
+                0x60, 0x80, 60, 0, // 96 ticks later
+                0x00, 0xFF, 0x2F, 0x00,
+            ],
+        ));
+        file
+    }
+
+    #[test]
+    fn test_parses_tracks_with_running_status() {
+        let file = MidiFile::parse(&two_track_file()).unwrap();
+        assert_eq!(file.format, 1);
+        assert_eq!(file.division, Division::TicksPerQuarter(96));
+        assert_eq!(file.tracks.len(), 2);
+        assert_eq!(file.tracks[1].name.as_deref(), Some("Lead"));
+        assert_eq!(
+            file.tracks[1].events[1],
+            TrackEvent {
+                tick: 192,
+                kind: TrackEventKind::Channel([0x90, 62, 90]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_tempo_changes_apply_to_the_timeline() {
+        let file = MidiFile::parse(&two_track_file()).unwrap();
+        let seconds: Vec<f64> = file
+            .timeline(&PlaybackFilter::all())
+            .iter()
+            .map(|event| event.seconds)
+            .collect();
+        // One beat at 120 BPM, then one and two beats at 60 BPM
+        assert_eq!(seconds, vec![0.0, 1.5, 1.5, 2.5]);
+        assert_eq!(file.beats_to_seconds(2.0), 1.5);
+        assert_eq!(file.duration_seconds(), 2.5);
+    }
+
+    #[test]
+    fn test_filter_selects_tracks_and_channels() {
+        let file = MidiFile::parse(&two_track_file()).unwrap();
+        let channel_two = file.timeline(&PlaybackFilter::all().with_channels(&[1]));
+        assert_eq!(channel_two.len(), 1);
+        assert_eq!(channel_two[0].message, [0x91, 36, 80]);
+        // The tempo track has no notes, but its tempo still applies
+        let tempo_only = PlaybackFilter::all().with_tracks(vec![0]);
+        assert!(file.timeline(&tempo_only).is_empty());
+        let note_track = file.timeline(&PlaybackFilter::all().with_tracks(vec![1]));
+        assert_eq!(note_track.last().unwrap().seconds, 2.5);
+    }
+
+    #[test]
+    fn test_rejects_format_2_and_truncated_files() {
+        let mut format_2 = two_track_file();
+        format_2[9] = 2;
+        assert_eq!(
+            MidiFile::parse(&format_2),
+            Err(SmfError::UnsupportedFormat(2))
+        );
+        let file = two_track_file();
+        assert_eq!(
+            MidiFile::parse(&file[..file.len() - 3]),
+            Err(SmfError::Truncated)
+        );
+        assert_eq!(MidiFile::parse(b"RIFF"), Err(SmfError::NotMidiFile));
+    }
+}
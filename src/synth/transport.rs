@@ -0,0 +1,196 @@
+//! Tempo and song position, from an internal tempo or incoming MIDI clock.
+//! The sequencer and arpeggiator take their timing from here, and anything
+//! tempo-synced (LFO rates, delay times) can ask for the length or phase of a
+//! note division.
+use super::clock::PULSES_PER_QUARTER_NOTE;
+
+const DEFAULT_TEMPO_BPM: f32 = 120.0;
+/// MIDI song position pointers count sixteenth notes.
+const PULSES_PER_SIXTEENTH: u32 = PULSES_PER_QUARTER_NOTE / 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    Internal,
+    /// Follow 0xF8 timing clock pulses.
+    Midi,
+}
+
+/// A snapshot of the transport, for display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransportState {
+    pub playing: bool,
+    pub source: ClockSource,
+    pub tempo_bpm: f32,
+    /// Quarter notes since the start of the song.
+    pub beats: f64,
+}
+
+impl TransportState {
+    /// Sixteenth notes since the start of the song, as in a MIDI song position pointer.
+    pub fn song_position(&self) -> u32 {
+        (self.beats * 4.0) as u32
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transport {
+    source: ClockSource,
+    tempo_bpm: f32, // Internal tempo
+    sample_rate: f32,
+    playing: bool,
+    position: f64,              // Clock pulses since the start of the song
+    midi_frames_per_pulse: f64, // Measured from incoming MIDI clock; 0.0 until known
+    frames_since_pulse: f64,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self {
+            source: ClockSource::Internal,
+            tempo_bpm: DEFAULT_TEMPO_BPM,
+            sample_rate: 44100.0,
+            playing: false,
+            position: 0.0,
+            midi_frames_per_pulse: 0.0,
+            frames_since_pulse: 0.0,
+        }
+    }
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+    pub fn set_source(&mut self, source: ClockSource) {
+        self.source = source;
+        self.midi_frames_per_pulse = 0.0;
+    }
+    /// Set the internal tempo. Ignored for timing while following MIDI clock.
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.tempo_bpm = bpm.clamp(1.0, 999.0);
+    }
+    /// The tempo in use: the internal one, or as measured from MIDI clock.
+    pub fn tempo(&self) -> f32 {
+        (60.0 * self.sample_rate as f64 / self.frames_per_beat()) as f32
+    }
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+    pub fn state(&self) -> TransportState {
+        TransportState {
+            playing: self.playing,
+            source: self.source,
+            tempo_bpm: self.tempo(),
+            beats: self.position / PULSES_PER_QUARTER_NOTE as f64,
+        }
+    }
+
+    /// Play from the start of the song.
+    pub fn start(&mut self) {
+        self.position = 0.0;
+        self.playing = true;
+    }
+    /// Play from the current song position.
+    pub fn resume(&mut self) {
+        self.playing = true;
+    }
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+    /// Move to a MIDI song position, in sixteenth notes.
+    pub fn set_song_position(&mut self, sixteenths: u16) {
+        self.position = sixteenths as f64 * PULSES_PER_SIXTEENTH as f64;
+    }
+    /// Clock pulses since the start of the song.
+    pub fn position_pulses(&self) -> f64 {
+        self.position
+    }
+
+    /// Frames per quarter note at the current tempo.
+    pub fn frames_per_beat(&self) -> f64 {
+        if self.source == ClockSource::Midi && self.midi_frames_per_pulse > 0.0 {
+            return self.midi_frames_per_pulse * PULSES_PER_QUARTER_NOTE as f64;
+        }
+        self.sample_rate as f64 * 60.0 / self.tempo_bpm as f64
+    }
+    /// Length in frames of a note division given in clock pulses (see `clock::division_pulses`),
+    /// e.g. for a tempo-synced delay time.
+    pub fn division_frames(&self, pulses: u32) -> f64 {
+        self.frames_per_beat() * pulses as f64 / PULSES_PER_QUARTER_NOTE as f64
+    }
+    /// Position within the current cycle of a note division, from 0.0 to 1.0,
+    /// e.g. for a tempo-synced LFO. Locked to the song position while playing.
+    pub fn division_phase(&self, pulses: u32) -> f32 {
+        if pulses == 0 {
+            return 0.0;
+        }
+        (self.position / pulses as f64).fract() as f32
+    }
+
+    /// Count one MIDI clock pulse, measuring the tempo from their spacing.
+    /// Returns true if the transport follows MIDI clock.
+    pub fn clock_pulse(&mut self) -> bool {
+        if self.source != ClockSource::Midi {
+            return false;
+        }
+        if self.frames_since_pulse > 0.0 {
+            // Smooth out jitter in when pulses arrive
+            self.midi_frames_per_pulse = if self.midi_frames_per_pulse > 0.0 {
+                0.9 * self.midi_frames_per_pulse + 0.1 * self.frames_since_pulse
+            } else {
+                self.frames_since_pulse
+            };
+        }
+        self.frames_since_pulse = 0.0;
+        if self.playing {
+            self.position = self.position.floor() + 1.0;
+        }
+        true
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Move time forward by `frames`.
+    pub fn advance(&mut self, frames: usize) {
+        self.frames_since_pulse += frames as f64;
+        if self.playing && self.source == ClockSource::Internal {
+            self.position +=
+                frames as f64 * PULSES_PER_QUARTER_NOTE as f64 / self.frames_per_beat();
+        } else if self.playing && self.midi_frames_per_pulse > 0.0 {
+            // Interpolate between pulses, without running past the next one
+            let fraction = (self.frames_since_pulse / self.midi_frames_per_pulse).min(0.999);
+            self.position = self.position.floor() + fraction;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tempo_follows_midi_clock() {
+        let mut transport = Transport::new();
+        transport.set_source(ClockSource::Midi);
+        transport.set_sample_rate(48000.0);
+        transport.start();
+        // 100 BPM at 48 kHz is 1200 frames per pulse
+        for _ in 0..48 {
+            transport.advance(1200);
+            transport.clock_pulse();
+        }
+        assert!((transport.tempo() - 100.0).abs() < 0.01);
+        assert_eq!(transport.state().beats, 2.0);
+        assert_eq!(transport.division_frames(6), 7200.0);
+
+        transport.set_song_position(8);
+        assert_eq!(transport.state().song_position(), 8);
+        assert_eq!(transport.division_phase(PULSES_PER_QUARTER_NOTE * 4), 0.5);
+    }
+}
@@ -0,0 +1,550 @@
+//! Microtuning: Scala scales (.scl), keyboard mappings (.kbm) and MIDI Tuning
+//! Standard (MTS) SysEx retuning.
+//!
+//! A `Tuning` resolves to a table of 128 note frequencies. The synth only ever
+//! reads that table, so switching tunings while playing is a plain copy.
+use crate::synth::prelude::fmt;
+
+const NOTE_COUNT: usize = 128;
+const DEFAULT_REFERENCE_NOTE: i32 = 69;
+const DEFAULT_REFERENCE_FREQUENCY: f64 = 440.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TuningError {
+    InvalidScale(String),
+    InvalidKeyboardMapping(String),
+    /// A reference frequency that isn't a positive number of Hz.
+    InvalidReferencePitch(String),
+    /// The keyboard mapping's reference note doesn't map to a scale degree.
+    UnmappedReferenceNote(i32),
+    InvalidSysEx(String),
+    UnsupportedSysEx,
+    Io(String),
+}
+
+impl fmt::Display for TuningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TuningError::InvalidScale(e) => write!(f, "Invalid Scala scale: {}", e),
+            TuningError::InvalidKeyboardMapping(e) => write!(f, "Invalid keyboard mapping: {}", e),
+            TuningError::InvalidReferencePitch(e) => write!(f, "Invalid reference pitch: {}", e),
+            TuningError::UnmappedReferenceNote(n) => {
+                write!(f, "Reference note {} is not mapped to a scale degree", n)
+            }
+            TuningError::InvalidSysEx(e) => write!(f, "Invalid MTS SysEx message: {}", e),
+            TuningError::UnsupportedSysEx => write!(f, "Not an MTS tuning message"),
+            TuningError::Io(e) => write!(f, "Failed to read tuning file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TuningError {}
+
+/// Non-comment lines of a Scala file. Lines starting with `!` are comments.
+fn content_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.starts_with('!'))
+}
+
+/// A Scala scale: the pitches of degrees 1..=N in cents, where the last one is
+/// the period (usually the octave). Degree 0 is the implicit 1/1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    pitches_cents: Vec<f64>,
+}
+
+impl Scale {
+    pub fn equal_temperament(notes_per_octave: usize) -> Self {
+        let notes = notes_per_octave.max(1);
+        Self {
+            description: format!("{}-tone equal temperament", notes),
+            pitches_cents: (1..=notes)
+                .map(|i| 1200.0 * i as f64 / notes as f64)
+                .collect(),
+        }
+    }
+
+    /// Parse the contents of a `.scl` file.
+    pub fn parse_scl(text: &str) -> Result<Self, TuningError> {
+        let mut lines = content_lines(text);
+        let description = lines
+            .next()
+            .ok_or_else(|| TuningError::InvalidScale("missing description".to_string()))?
+            .trim()
+            .to_string();
+        let count_line = lines
+            .next()
+            .ok_or_else(|| TuningError::InvalidScale("missing note count".to_string()))?;
+        let count = count_line
+            .split_whitespace()
+            .next()
+            .and_then(|n| n.parse::<usize>().ok())
+            .ok_or_else(|| {
+                TuningError::InvalidScale(format!("invalid note count '{}'", count_line.trim()))
+            })?;
+        if count == 0 {
+            return Err(TuningError::InvalidScale("scale has no notes".to_string()));
+        }
+        let pitches_cents = lines
+            .filter(|line| !line.trim().is_empty())
+            .take(count)
+            .map(Self::parse_pitch)
+            .collect::<Result<Vec<_>, _>>()?;
+        if pitches_cents.len() != count {
+            return Err(TuningError::InvalidScale(format!(
+                "expected {} notes, found {}",
+                count,
+                pitches_cents.len()
+            )));
+        }
+        Ok(Self {
+            description,
+            pitches_cents,
+        })
+    }
+
+    /// A pitch is in cents if it contains a `.`, otherwise it is a ratio (`3/2` or `2`).
+    fn parse_pitch(line: &str) -> Result<f64, TuningError> {
+        let token = line.split_whitespace().next().unwrap_or("");
+        let invalid = || TuningError::InvalidScale(format!("invalid pitch '{}'", token));
+        if token.contains('.') {
+            return token.parse::<f64>().map_err(|_| invalid());
+        }
+        let (numerator, denominator) = match token.split_once('/') {
+            Some((n, d)) => (n, d),
+            None => (token, "1"),
+        };
+        let numerator = numerator.parse::<f64>().map_err(|_| invalid())?;
+        let denominator = denominator.parse::<f64>().map_err(|_| invalid())?;
+        if numerator <= 0.0 || denominator <= 0.0 {
+            return Err(invalid());
+        }
+        Ok(1200.0 * (numerator / denominator).log2())
+    }
+
+    /// Number of degrees per period.
+    pub fn len(&self) -> usize {
+        self.pitches_cents.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pitches_cents.is_empty()
+    }
+    pub fn period_cents(&self) -> f64 {
+        self.pitches_cents.last().copied().unwrap_or(1200.0)
+    }
+
+    /// Cents above degree 0 for any degree, repeating the scale every period.
+    pub fn degree_cents(&self, degree: i32) -> f64 {
+        let len = self.len().max(1) as i32;
+        let period = degree.div_euclid(len);
+        let step = degree.rem_euclid(len) as usize;
+        let base = if step == 0 {
+            0.0
+        } else {
+            self.pitches_cents[step - 1]
+        };
+        period as f64 * self.period_cents() + base
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self::equal_temperament(12)
+    }
+}
+
+/// A Scala keyboard mapping, assigning MIDI keys to scale degrees.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    /// Keys per repetition of `mapping`; 0 maps each key to the next degree.
+    pub map_size: usize,
+    pub first_note: i32,
+    pub last_note: i32,
+    /// The key that plays `mapping[0]`.
+    pub middle_note: i32,
+    pub reference_note: i32,
+    pub reference_frequency: f64,
+    /// Degree treated as the formal octave; 0 means the scale's period.
+    pub octave_degree: usize,
+    /// Scale degree per key, `None` for keys that don't sound.
+    pub mapping: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    /// Parse the contents of a `.kbm` file.
+    pub fn parse_kbm(text: &str) -> Result<Self, TuningError> {
+        let mut values = content_lines(text)
+            .map(|line| line.split_whitespace().next().unwrap_or(""))
+            .filter(|token| !token.is_empty());
+        let mut next = |name: &str| {
+            values
+                .next()
+                .ok_or_else(|| TuningError::InvalidKeyboardMapping(format!("missing {}", name)))
+        };
+        fn number<T: std::str::FromStr>(token: &str, name: &str) -> Result<T, TuningError> {
+            token.parse::<T>().map_err(|_| {
+                TuningError::InvalidKeyboardMapping(format!("invalid {} '{}'", name, token))
+            })
+        }
+
+        let map_size = number::<usize>(next("map size")?, "map size")?;
+        let first_note = number::<i32>(next("first note")?, "first note")?;
+        let last_note = number::<i32>(next("last note")?, "last note")?;
+        let middle_note = number::<i32>(next("middle note")?, "middle note")?;
+        let reference_note = number::<i32>(next("reference note")?, "reference note")?;
+        let reference_frequency =
+            number::<f64>(next("reference frequency")?, "reference frequency")?;
+        let octave_degree = number::<usize>(next("octave degree")?, "octave degree")?;
+        if reference_frequency <= 0.0 {
+            return Err(TuningError::InvalidKeyboardMapping(
+                "reference frequency must be positive".to_string(),
+            ));
+        }
+        // Missing trailing entries are unmapped
+        let mut mapping = Vec::with_capacity(map_size);
+        for _ in 0..map_size {
+            mapping.push(match values.next() {
+                None | Some("x") | Some("X") => None,
+                Some(token) => Some(number::<i32>(token, "mapping entry")?),
+            });
+        }
+        Ok(Self {
+            map_size,
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    /// Cents of `note` above degree 0 at the middle note, ignoring the key range.
+    fn note_cents(&self, scale: &Scale, note: i32) -> Option<f64> {
+        let offset = note - self.middle_note;
+        if self.map_size == 0 {
+            return Some(scale.degree_cents(offset));
+        }
+        let repeats = offset.div_euclid(self.map_size as i32);
+        let key = offset.rem_euclid(self.map_size as i32) as usize;
+        let degree = self.mapping.get(key).copied().flatten()?;
+        let octave_degree = match self.octave_degree {
+            0 => scale.len(),
+            degree => degree,
+        };
+        Some(repeats as f64 * scale.degree_cents(octave_degree as i32) + scale.degree_cents(degree))
+    }
+}
+
+impl Default for KeyboardMapping {
+    /// One key per degree starting at middle C, with A4 at 440 Hz.
+    fn default() -> Self {
+        Self {
+            map_size: 0,
+            first_note: 0,
+            last_note: NOTE_COUNT as i32 - 1,
+            middle_note: 60,
+            reference_note: DEFAULT_REFERENCE_NOTE,
+            reference_frequency: DEFAULT_REFERENCE_FREQUENCY,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+/// A scale and keyboard mapping resolved to per-note frequencies, plus any
+/// per-note changes received over MTS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    scale: Scale,
+    mapping: KeyboardMapping,
+    frequencies: [f32; NOTE_COUNT], // 0.0 for unmapped keys
+}
+
+impl Tuning {
+    pub fn new(scale: Scale, mapping: KeyboardMapping) -> Result<Self, TuningError> {
+        let mut tuning = Self {
+            scale,
+            mapping,
+            frequencies: [0.0; NOTE_COUNT],
+        };
+        tuning.recompute()?;
+        Ok(tuning)
+    }
+
+    /// Parse `.scl` text and optional `.kbm` text.
+    pub fn from_scala(scl: &str, kbm: Option<&str>) -> Result<Self, TuningError> {
+        let mapping = match kbm {
+            Some(kbm) => KeyboardMapping::parse_kbm(kbm)?,
+            None => KeyboardMapping::default(),
+        };
+        Self::new(Scale::parse_scl(scl)?, mapping)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_scala_files(
+        scl_path: &std::path::Path,
+        kbm_path: Option<&std::path::Path>,
+    ) -> Result<Self, TuningError> {
+        let read = |path: &std::path::Path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| TuningError::Io(format!("{}: {}", path.display(), e)))
+        };
+        let scl = read(scl_path)?;
+        let kbm = kbm_path.map(read).transpose()?;
+        Self::from_scala(&scl, kbm.as_deref())
+    }
+
+    /// Recompute the table from the scale and mapping, discarding MTS changes.
+    fn recompute(&mut self) -> Result<(), TuningError> {
+        let reference_note = self.mapping.reference_note;
+        let reference_cents = self
+            .mapping
+            .note_cents(&self.scale, reference_note)
+            .ok_or(TuningError::UnmappedReferenceNote(reference_note))?;
+        for (note, frequency) in self.frequencies.iter_mut().enumerate() {
+            let note = note as i32;
+            let in_range = (self.mapping.first_note..=self.mapping.last_note).contains(&note);
+            *frequency = match self.mapping.note_cents(&self.scale, note) {
+                Some(cents) if in_range => {
+                    (self.mapping.reference_frequency
+                        * 2f64.powf((cents - reference_cents) / 1200.0)) as f32
+                }
+                _ => 0.0,
+            };
+        }
+        Ok(())
+    }
+
+    pub fn scale(&self) -> &Scale {
+        &self.scale
+    }
+    pub fn keyboard_mapping(&self) -> &KeyboardMapping {
+        &self.mapping
+    }
+    pub fn frequencies(&self) -> &[f32; NOTE_COUNT] {
+        &self.frequencies
+    }
+    /// Frequency of `note` in Hz, or `None` if the key is unmapped.
+    pub fn frequency(&self, note: u8) -> Option<f32> {
+        self.frequencies
+            .get(note as usize)
+            .copied()
+            .filter(|f| *f > 0.0)
+    }
+
+    /// Frequency of the mapping's reference note, e.g. A4 = 432 Hz.
+    pub fn reference_frequency(&self) -> f32 {
+        self.mapping.reference_frequency as f32
+    }
+    pub fn set_reference_frequency(&mut self, frequency: f32) -> Result<(), TuningError> {
+        if frequency <= 0.0 || !frequency.is_finite() {
+            return Err(TuningError::InvalidReferencePitch(frequency.to_string()));
+        }
+        self.mapping.reference_frequency = frequency as f64;
+        self.recompute()
+    }
+    pub fn set_scale(&mut self, scale: Scale) -> Result<(), TuningError> {
+        let previous = std::mem::replace(&mut self.scale, scale);
+        self.recompute().inspect_err(|_| {
+            self.scale = previous;
+            let _ = self.recompute();
+        })
+    }
+    pub fn set_keyboard_mapping(&mut self, mapping: KeyboardMapping) -> Result<(), TuningError> {
+        let previous = std::mem::replace(&mut self.mapping, mapping);
+        self.recompute().inspect_err(|_| {
+            self.mapping = previous;
+            let _ = self.recompute();
+        })
+    }
+
+    /// Apply an MTS message, with or without the surrounding F0/F7. Supports
+    /// bulk dumps, single note changes (with and without bank) and 1- and 2-byte
+    /// scale/octave tuning. Returns the number of notes whose tuning changed.
+    ///
+    /// MTS pitches are 12-TET semitones, which keep the reference pitch: with
+    /// A4 at 432 Hz, semitone 69 is 432 Hz.
+    pub fn apply_mts_sysex(&mut self, message: &[u8]) -> Result<usize, TuningError> {
+        let message = message.strip_prefix(&[0xF0]).unwrap_or(message);
+        let message = message.strip_suffix(&[0xF7]).unwrap_or(message);
+        // Universal non-real-time (7E) or real-time (7F), any device, sub-ID 08
+        let [0x7E | 0x7F, _device, 0x08, sub_id, data @ ..] = message else {
+            return Err(TuningError::UnsupportedSysEx);
+        };
+        let truncated = || TuningError::InvalidSysEx("message is truncated".to_string());
+        match sub_id {
+            // Bulk dump: program, 16-byte name, 128 frequencies, checksum
+            0x01 => {
+                let frequencies = data.get(17..17 + NOTE_COUNT * 3).ok_or_else(truncated)?;
+                let mut changed = 0;
+                for (note, bytes) in frequencies.chunks_exact(3).enumerate() {
+                    changed += self.set_mts_frequency(note as u8, bytes) as usize;
+                }
+                Ok(changed)
+            }
+            // Single note tuning change: program, count, [key, xx, yy, zz]...
+            0x02 => self.apply_note_changes(data.get(1..).ok_or_else(truncated)?),
+            // Same with a leading bank number
+            0x07 => self.apply_note_changes(data.get(2..).ok_or_else(truncated)?),
+            // Scale/octave tuning: 3-byte channel mask, then 12 offsets from 12-TET
+            0x08 => {
+                let offsets = data.get(3..15).ok_or_else(truncated)?;
+                let mut cents = [0.0; 12];
+                for (cents, &value) in cents.iter_mut().zip(offsets) {
+                    *cents = value as f32 - 64.0;
+                }
+                Ok(self.apply_octave_offsets(&cents))
+            }
+            0x09 => {
+                let offsets = data.get(3..27).ok_or_else(truncated)?;
+                let mut cents = [0.0; 12];
+                for (cents, bytes) in cents.iter_mut().zip(offsets.chunks_exact(2)) {
+                    let value = ((bytes[0] as u16) << 7) | bytes[1] as u16;
+                    *cents = (value as f32 - 8192.0) * 100.0 / 8192.0;
+                }
+                Ok(self.apply_octave_offsets(&cents))
+            }
+            _ => Err(TuningError::UnsupportedSysEx),
+        }
+    }
+
+    fn apply_note_changes(&mut self, data: &[u8]) -> Result<usize, TuningError> {
+        let (&count, changes) = data
+            .split_first()
+            .ok_or_else(|| TuningError::InvalidSysEx("missing change count".to_string()))?;
+        let changes = changes
+            .get(..count as usize * 4)
+            .ok_or_else(|| TuningError::InvalidSysEx("message is truncated".to_string()))?;
+        let mut changed = 0;
+        for change in changes.chunks_exact(4) {
+            changed += self.set_mts_frequency(change[0], &change[1..]) as usize;
+        }
+        Ok(changed)
+    }
+
+    /// Set a note from MTS frequency data: a 12-TET semitone plus a 14-bit
+    /// fraction of a semitone. `7F 7F 7F` means "no change".
+    fn set_mts_frequency(&mut self, note: u8, bytes: &[u8]) -> bool {
+        let [semitone, msb, lsb] = [bytes[0], bytes[1], bytes[2]];
+        if (semitone, msb, lsb) == (0x7F, 0x7F, 0x7F) || note as usize >= NOTE_COUNT {
+            return false;
+        }
+        let fraction = (((msb as u16) << 7) | lsb as u16) as f64 / 16384.0;
+        self.frequencies[note as usize] =
+            self.equal_tempered_frequency(semitone as f64 + fraction) as f32;
+        true
+    }
+
+    /// Retune to 12-TET with a cents offset per pitch class, C first.
+    fn apply_octave_offsets(&mut self, cents: &[f32; 12]) -> usize {
+        for note in 0..NOTE_COUNT {
+            let semitones = note as f64 + cents[note % 12] as f64 / 100.0;
+            self.frequencies[note] = self.equal_tempered_frequency(semitones) as f32;
+        }
+        NOTE_COUNT
+    }
+
+    /// Frequency of a (possibly fractional) MIDI note in 12-TET, with the
+    /// mapping's reference note at the reference frequency.
+    fn equal_tempered_frequency(&self, semitones: f64) -> f64 {
+        let reference_note = self.mapping.reference_note as f64;
+        self.mapping.reference_frequency * 2f64.powf((semitones - reference_note) / 12.0)
+    }
+}
+
+impl Default for Tuning {
+    /// 12-tone equal temperament with A4 at 440 Hz.
+    fn default() -> Self {
+        Self::new(Scale::default(), KeyboardMapping::default())
+            .expect("Default keyboard mapping always maps its reference note")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1.0e-3 * expected.max(1.0),
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_default_is_twelve_tet() {
+        let tuning = Tuning::default();
+        for note in 0..128u8 {
+            let expected = 440.0 * 2f32.powf((note as f32 - 69.0) / 12.0);
+            assert_close(tuning.frequency(note).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_scala_just_scale_with_kbm() {
+        let scl = "! just.scl\n!\nJust major\n 7\n!\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n";
+        // White keys only, C4 = 1/1 at 261.63 Hz, black keys unmapped
+        let kbm = "12\n0\n127\n60\n60\n261.625565\n7\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n";
+        let middle_c = 261.62557;
+        let tuning = Tuning::from_scala(scl, Some(kbm)).unwrap();
+        assert_eq!(tuning.scale().len(), 7);
+        assert_close(tuning.frequency(67).unwrap(), middle_c * 1.5);
+        assert_close(tuning.frequency(72).unwrap(), middle_c * 2.0);
+        assert_close(tuning.frequency(57).unwrap(), middle_c * 5.0 / 6.0);
+        assert_eq!(tuning.frequency(61), None);
+
+        let mut tuning = Tuning::from_scala(scl, None).unwrap();
+        tuning.set_reference_frequency(432.0).unwrap();
+        assert_close(tuning.frequency(69).unwrap(), 432.0);
+        assert!(Scale::parse_scl("Broken\n2\n3/2\n").is_err());
+    }
+
+    #[test]
+    fn test_mts_single_note_change() {
+        let mut tuning = Tuning::default();
+        // Real-time single note change: key 60 -> semitone 60 + half a semitone
+        let message = [
+            0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 60, 60, 0x40, 0x00, 0xF7,
+        ];
+        assert_eq!(tuning.apply_mts_sysex(&message), Ok(1));
+        assert_close(
+            tuning.frequency(60).unwrap(),
+            440.0 * 2f32.powf((60.5 - 69.0) / 12.0),
+        );
+        assert_eq!(
+            tuning.apply_mts_sysex(&[0xF0, 0x43, 0x00, 0xF7]),
+            Err(TuningError::UnsupportedSysEx)
+        );
+    }
+
+    #[test]
+    fn test_mts_keeps_the_reference_pitch() {
+        let mut tuning = Tuning::default();
+        tuning.set_reference_frequency(432.0).unwrap();
+        // Key 70 -> semitone 69
+        let single_note = [
+            0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 70, 69, 0x00, 0x00, 0xF7,
+        ];
+        assert_eq!(tuning.apply_mts_sysex(&single_note), Ok(1));
+        assert_close(tuning.frequency(70).unwrap(), 432.0);
+
+        // 1-byte octave tuning, all channels, no offsets
+        let mut octave = vec![0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x03, 0x7F, 0x7F];
+        octave.extend([64; 12]);
+        octave.push(0xF7);
+        assert_eq!(tuning.apply_mts_sysex(&octave), Ok(128));
+        assert_close(tuning.frequency(69).unwrap(), 432.0);
+        assert_close(tuning.frequency(81).unwrap(), 864.0);
+
+        assert_eq!(
+            tuning.set_reference_frequency(0.0),
+            Err(TuningError::InvalidReferencePitch("0".to_string()))
+        );
+    }
+}
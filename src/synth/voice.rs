@@ -1,6 +1,7 @@
-use super::algorithm::Algorithm;
+use super::algorithm::{Algorithm, AlgorithmScratch};
 use super::context::ProcessContext;
-// use super::envelope::EnvelopeGenerator;
+use super::envelope::EnvelopeGenerator;
+use super::expression::Expression;
 use super::note::{NoteEvent, NoteSource};
 use super::operator::{Operator, OperatorState};
 use super::voice_config::VoiceConfig;
@@ -15,11 +16,14 @@ pub struct Voice {
     pub note_frequency: f32,         // Frequency derived from note_number
     pub note_velocity: u8,           // MIDI velocity (0-127)
     pub note_source: Option<NoteSource>, // Where the note came from (keyboard, sequencer)
+    pub channel: u8,                 // MIDI channel the note arrived on
+    pub expression: Expression,      // Per-note pitch bend, pressure and timbre
     velocity_scale: f32,             // Relative velocity of the note (0.0-1.0)
-    // envelope: EnvelopeGenerator, // Main amplitude envelope for the voice
+    envelope: EnvelopeGenerator,     // Main amplitude envelope for the voice
     samples_elapsed_since_trigger: u64, // Counter for phase calculation
     note_off_sample_index: Option<u64>, // Sample index when the note was released
     config: VoiceConfig,                // Configuration for the voice
+    scratch: AlgorithmScratch,          // Preallocated working memory for the algorithm
 }
 
 impl Voice {
@@ -32,12 +36,15 @@ impl Voice {
             note_number: 0,
             note_frequency: 0.0, // Will be set on activation
             note_source: None,
+            channel: 0,
+            expression: Expression::default(),
             note_velocity: 0,
             velocity_scale: 0.0,
-            // envelope: EnvelopeGenerator::new(),
+            envelope: EnvelopeGenerator::new(),
             samples_elapsed_since_trigger: 0,
             note_off_sample_index: None,
             config: VoiceConfig::default(),
+            scratch: AlgorithmScratch::new(num_nodes, 0),
         }
     }
     /// Fully resets the voice to an inactive state.
@@ -49,31 +56,33 @@ impl Voice {
         self.note_velocity = 0;
         self.velocity_scale = 0.0;
         self.note_source = None;
+        self.channel = 0;
+        self.expression = Expression::default();
         self.samples_elapsed_since_trigger = 0;
         self.note_off_sample_index = None;
-        self.node_states.iter_mut().for_each(|state| {
-            *state = OperatorState::default();
-        });
+        self.node_states.iter_mut().for_each(OperatorState::reset);
     }
     /// Activates the voice for a given note.
-    /// Resets the sample counter and triggers the envelope.
-    pub fn activate(&mut self, note_event: &NoteEvent, config: &VoiceConfig) {
+    /// Resets the sample counter and triggers the envelope. `expression` is
+    /// the note's channel state, which MPE controllers set before the note on.
+    pub fn activate(
+        &mut self,
+        note_event: &NoteEvent,
+        config: &VoiceConfig,
+        expression: Expression,
+    ) {
         self.reset();
         self.active = true;
         self.note_number = note_event.note_number;
         self.note_source = Some(note_event.source);
+        self.channel = note_event.channel;
+        self.expression = expression;
         self.note_frequency = note_event.frequency;
         self.note_velocity = note_event.velocity;
         self.velocity_scale = config.velocity_to_scale(self.note_velocity);
         self.config = config.clone();
-
-        // self.envelope
-        //     .set_params(config.attack, config.decay, config.sustain, config.release);
-        //
-        println!(
-            "Voice activated note {}, sample counter reset",
-            self.note_number
-        );
+        self.envelope
+            .set_params(config.attack, config.decay, config.sustain, config.release);
     }
 
     /// Initiates the release phase of the voice's main envelope.
@@ -81,7 +90,6 @@ impl Voice {
         if !self.releasing {
             self.releasing = true;
             self.note_off_sample_index = Some(self.samples_elapsed_since_trigger);
-            println!("Voice released note {}", self.note_number);
         }
     }
     /// Called by Synth when the global algorithm changes.
@@ -95,12 +103,32 @@ impl Voice {
         self.node_states.clear(); // Clear existing state
         self.node_states
             .resize_with(new_len, OperatorState::default); // Resize and fill with defaults
+        self.scratch.resize(new_len, self.scratch.buffer_size());
+    }
+    /// The longest buffer the voice can render without allocating.
+    pub fn buffer_size(&self) -> usize {
+        self.scratch.buffer_size()
+    }
+    /// Preallocates the algorithm scratch memory for buffers of up to `buffer_size` samples.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        self.scratch
+            .resize(self.node_states.len(), buffer_size);
+    }
+    /// Preallocates each node's filter chain for its operator's filters, so
+    /// that starting a note doesn't allocate.
+    pub fn allocate_filters(&mut self, algorithm: &Algorithm, operators: &[Operator]) {
+        for (state, op_index) in self.node_states.iter_mut().zip(algorithm.node_operators()) {
+            if let Some(operator) = operators.get(op_index) {
+                operator.allocate_filters(state);
+            }
+        }
     }
     /// Processes a buffer of audio for this voice using the provided algorithm and operators.
     /// `algorithm`: The FM algorithm defining operator connections.
     /// `operators`: The set of operators configured in the SynthEngine.
     /// `output`: The buffer to add this voice's contribution to.
     /// `sample_rate`: The audio sample rate.
+    /// `global_expression`: Expression applied to every note, e.g. from an MPE master channel.
     pub fn process(
         &mut self,
         algorithm: &Algorithm,
@@ -108,10 +136,16 @@ impl Voice {
         output: &mut [f32],
         sample_rate: f32,
         scaling_factor: f32,
+        global_expression: &Expression,
     ) {
+        let expression = self.expression.combined(global_expression);
         let context = ProcessContext {
             sample_rate,
-            base_frequency: self.note_frequency,
+            base_frequency: self.note_frequency * 2f32.powf(expression.pitch_bend / 12.0),
+            modulation_index_scale: self
+                .config
+                .pressure_to_modulation_scale(expression.pressure),
+            cutoff_scale: self.config.timbre_to_cutoff_scale(expression.timbre),
             samples_elapsed_since_trigger: self.samples_elapsed_since_trigger,
             note_off_sample_index: self.note_off_sample_index,
             operators,
@@ -119,35 +153,36 @@ impl Voice {
         };
 
         // If the voice is fully finished (inactive AND envelope done), skip processing.
-        if self.is_finished(algorithm) {
+        if self.is_finished(algorithm, sample_rate) {
             self.reset();
             return;
         }
-        algorithm.process(&context, &mut self.node_states, output);
+        algorithm.process(&context, &mut self.node_states, &mut self.scratch, output);
 
         let buffer_len = output.len();
-        for i in 0..buffer_len {
-            // let samples_at_this_point = self.samples_elapsed_since_trigger + i as u64;
-            // let time_on = samples_at_this_point as f32 / sample_rate;
-            // let time_off = self
-            //     .note_off_sample_index
-            //     .map(|off| samples_at_this_point.saturating_sub(off) as f32 / sample_rate);
-
-            // let env_value = self.envelope.evaluate(time_on, time_off);
-            // output[i] *= env_value * self.velocity_scale;
-            output[i] *= self.velocity_scale * scaling_factor;
+        let gain = self.velocity_scale * scaling_factor;
+        for (i, sample) in output.iter_mut().enumerate() {
+            let samples_at_this_point = self.samples_elapsed_since_trigger + i as u64;
+            let time_on = samples_at_this_point as f32 / sample_rate;
+            let time_off = self
+                .note_off_sample_index
+                .map(|off| samples_at_this_point.saturating_sub(off) as f32 / sample_rate);
+            *sample *= self.envelope.evaluate(time_on, time_off) * gain;
         }
 
         self.samples_elapsed_since_trigger += buffer_len as u64;
 
-        if self.releasing && self.is_finished(algorithm) {
-            println!("Voice fully inactive (note {} released)", self.note_number);
+        if self.releasing && self.is_finished(algorithm, sample_rate) {
             self.reset();
         }
     }
 
-    /// Checks if the voice is completely finished (inactive and envelope has finished).
-    pub fn is_finished(&self, algorithm: &Algorithm) -> bool {
-        algorithm.finished(&self.node_states)
+    /// Checks if the voice is completely finished: its carriers' envelopes or
+    /// its own amplitude envelope have finished their release.
+    pub fn is_finished(&self, algorithm: &Algorithm, sample_rate: f32) -> bool {
+        let time_since_off = self
+            .note_off_sample_index
+            .map(|off| self.samples_elapsed_since_trigger.saturating_sub(off) as f32 / sample_rate);
+        algorithm.finished(&self.node_states) || self.envelope.finished(time_since_off)
     }
 }
@@ -1,16 +1,64 @@
+use super::error::SynthError;
+
 /// Configuration parameters for voice behavior.
 /// This includes envelope settings and velocity sensitivity behavior.
 #[derive(Debug, Clone)]
 pub struct VoiceConfig {
+    /// Amplitude envelope of the whole voice, applied to the summed carriers
+    /// on top of the operator envelopes. Times are in seconds. The default
+    /// holds at full level and never ends, leaving the shape to the
+    /// operators; a finite release ends the voice when it runs out.
+    ///
+    /// These fields used to default to a 0.01 s attack, 0.1 s decay, 0.8
+    /// sustain and 0.3 s release, but nothing applied them. Applying those
+    /// values now would turn every patch down by 2 dB and cut off operator
+    /// releases longer than 0.3 s. A neutral default keeps patches sounding
+    /// as they were designed.
     pub attack: f32,
     pub decay: f32,
     pub sustain: f32,
     pub release: f32,
     pub velocity_sensitive_envelope: bool,
     pub velocity_sensitive_curve: f32,
+    /// How far pressure scales every operator's modulation index (and so a
+    /// carrier's level): 0 ignores pressure, 1 is silent without pressure.
+    pub pressure_sensitivity: f32,
+    /// Filter cutoff sweep in octaves either side of neutral timbre (CC74).
+    pub timbre_cutoff_octaves: f32,
 }
 
 impl VoiceConfig {
+    /// Set the voice envelope, rejecting negative or NaN times and a
+    /// sustain outside 0..=1. An infinite release leaves it to the operators.
+    pub fn set_envelope(
+        &mut self,
+        attack: f32,
+        decay: f32,
+        sustain: f32,
+        release: f32,
+    ) -> Result<(), SynthError> {
+        let invalid_time =
+            |stage, time| SynthError::InvalidEnvelope(format!("{} time {} s", stage, time));
+        for (stage, time) in [("attack", attack), ("decay", decay)] {
+            if !time.is_finite() || time < 0.0 {
+                return Err(invalid_time(stage, time));
+            }
+        }
+        if release.is_nan() || release < 0.0 {
+            return Err(invalid_time("release", release));
+        }
+        if !(0.0..=1.0).contains(&sustain) {
+            return Err(SynthError::InvalidEnvelope(format!(
+                "sustain level {}",
+                sustain
+            )));
+        }
+        self.attack = attack;
+        self.decay = decay;
+        self.sustain = sustain;
+        self.release = release;
+        Ok(())
+    }
     /// Compute gain factor from velocity based on current config.
     pub fn velocity_to_scale(&self, velocity: u8) -> f32 {
         // Convert MIDI velocity (0-127) to a scale factor (0.0-1.0)
@@ -23,17 +71,25 @@ impl VoiceConfig {
         let normalized = vel as f32 / 127.0;
         normalized.powf(self.velocity_sensitive_curve)
     }
+    pub fn pressure_to_modulation_scale(&self, pressure: f32) -> f32 {
+        1.0 - self.pressure_sensitivity.clamp(0.0, 1.0) * (1.0 - pressure)
+    }
+    pub fn timbre_to_cutoff_scale(&self, timbre: f32) -> f32 {
+        2f32.powf(self.timbre_cutoff_octaves * (2.0 * timbre - 1.0))
+    }
 }
 
 impl Default for VoiceConfig {
     fn default() -> Self {
         Self {
-            attack: 0.01,
-            decay: 0.1,
-            sustain: 0.8,
-            release: 0.3,
+            attack: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            release: f32::INFINITY,
             velocity_sensitive_envelope: true,
             velocity_sensitive_curve: 1.5,
+            pressure_sensitivity: 0.0,
+            timbre_cutoff_octaves: 2.0,
         }
     }
 }
@@ -11,6 +11,33 @@ pub enum Waveform {
     Input,
 }
 
+impl Waveform {
+    /// Stable integer code shared with the web UI and the parameter registry.
+    pub fn index(&self) -> u8 {
+        match self {
+            Waveform::Sine => 0,
+            Waveform::Triangle => 1,
+            Waveform::Square => 2,
+            Waveform::Sawtooth => 3,
+            Waveform::Noise => 4,
+            Waveform::Input => 5,
+            Waveform::SawtoothSmooth => 6,
+        }
+    }
+    pub fn from_index(index: u8) -> Option<Self> {
+        match index {
+            0 => Some(Waveform::Sine),
+            1 => Some(Waveform::Triangle),
+            2 => Some(Waveform::Square),
+            3 => Some(Waveform::Sawtooth),
+            4 => Some(Waveform::Noise),
+            5 => Some(Waveform::Input),
+            6 => Some(Waveform::SawtoothSmooth),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)] // Added Debug and Clone
 pub struct WaveformGenerator {
     pub waveform: Waveform, // Made public for inspection/logging if needed
@@ -0,0 +1,229 @@
+//! Diagnostics through the `log` facade, with a level per category. A
+//! category is a log target: `log::debug!(target: logging::AUDIO, ...)`.
+//!
+//! Filters read like `info,audio=debug`: a level for every category, then
+//! levels for single categories. `DEFAULT_FILTER` turns the audio category
+//! off, so nothing on the audio path logs unless asked to; a disabled message
+//! costs one atomic load and is never formatted.
+//!
+//! On native, messages are queued for a background thread that writes them
+//! to stderr, so logging never blocks. Messages that don't fit in the queue
+//! are dropped and counted. On WASM they go to the browser console.
+use crate::synth::error::SynthError;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+
+/// Anything that runs per note or per buffer on the audio thread.
+pub const AUDIO: &str = "audio";
+/// Algorithms, operators, parts, effects and patches being changed.
+pub const SYNTH: &str = "synth";
+/// MIDI, computer keyboard, OSC and MIDI file input, and MIDI output.
+pub const INPUT: &str = "input";
+/// Audio devices and the native and WASM runtimes.
+pub const RUNTIME: &str = "runtime";
+
+const CATEGORIES: [&str; 4] = [AUDIO, SYNTH, INPUT, RUNTIME];
+pub const DEFAULT_FILTER: &str = "info,audio=off";
+
+const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+/// Index into `LEVELS` for each category, then for targets outside them.
+static FILTER: [AtomicUsize; CATEGORIES.len() + 1] =
+    [const { AtomicUsize::new(0) }; CATEGORIES.len() + 1];
+
+static INSTALL: Once = Once::new();
+
+/// Install the logger, once per process, and set the filter.
+pub fn init(filter: &str) -> Result<(), SynthError> {
+    INSTALL.call_once(|| {
+        // Fails only if the application installed a logger of its own
+        let _ = log::set_boxed_logger(Box::new(Logger::new()));
+    });
+    set_filter(filter)
+}
+
+/// Change the filter. An invalid filter is an error, and changes nothing.
+pub fn set_filter(filter: &str) -> Result<(), SynthError> {
+    let mut default = LevelFilter::Off;
+    let mut overrides = [None; CATEGORIES.len()];
+    for directive in filter.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        match directive.split_once('=') {
+            Some((category, level)) => {
+                let index = CATEGORIES
+                    .iter()
+                    .position(|&c| c == category.trim())
+                    .ok_or_else(|| SynthError::unknown("log category", category.trim()))?;
+                overrides[index] = Some(parse_level(level.trim())?);
+            }
+            None => default = parse_level(directive)?,
+        }
+    }
+    let levels = overrides.map(|level| level.unwrap_or(default));
+    for (slot, level) in FILTER.iter().zip(levels.iter().chain([&default])) {
+        slot.store(*level as usize, Ordering::Relaxed);
+    }
+    log::set_max_level(levels.into_iter().fold(default, Ord::max));
+    Ok(())
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, SynthError> {
+    LevelFilter::from_str(level).map_err(|_| SynthError::unknown("log level", level))
+}
+
+fn enabled(metadata: &Metadata) -> bool {
+    let index = CATEGORIES
+        .iter()
+        .position(|&c| c == metadata.target())
+        .unwrap_or(CATEGORIES.len());
+    metadata.level() <= LEVELS[FILTER[index].load(Ordering::Relaxed)]
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+use native::Logger;
+#[cfg(target_arch = "wasm32")]
+use wasm::Logger;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use super::*;
+    use std::io::Write;
+    use std::sync::mpsc::{self, SyncSender};
+    use std::thread;
+
+    /// Messages waiting to be written before new ones are dropped.
+    const QUEUE_CAPACITY: usize = 1024;
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    pub struct Logger {
+        queue: SyncSender<String>,
+    }
+
+    impl Logger {
+        pub fn new() -> Self {
+            let (queue, messages) = mpsc::sync_channel::<String>(QUEUE_CAPACITY);
+            let spawned = thread::Builder::new()
+                .name("log".to_string())
+                .spawn(move || {
+                    for message in messages {
+                        let mut stderr = std::io::stderr().lock();
+                        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+                        if dropped > 0 {
+                            let _ = writeln!(stderr, "WARN  log: {} messages dropped", dropped);
+                        }
+                        let _ = writeln!(stderr, "{}", message);
+                    }
+                });
+            if spawned.is_err() {
+                // Without a writer every message counts as dropped
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+            Self { queue }
+        }
+    }
+
+    impl Log for Logger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            enabled(metadata)
+        }
+
+        fn log(&self, record: &Record) {
+            if !enabled(record.metadata()) {
+                return;
+            }
+            let message = format!(
+                "{:<5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            if self.queue.try_send(message).is_err() {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use super::*;
+    use log::Level;
+    use wasm_bindgen::JsValue;
+    use web_sys::console;
+
+    pub struct Logger;
+
+    impl Logger {
+        pub fn new() -> Self {
+            Logger
+        }
+    }
+
+    impl Log for Logger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            enabled(metadata)
+        }
+
+        fn log(&self, record: &Record) {
+            if !enabled(record.metadata()) {
+                return;
+            }
+            let message = JsValue::from_str(&format!("{}: {}", record.target(), record.args()));
+            match record.level() {
+                Level::Error => console::error_1(&message),
+                Level::Warn => console::warn_1(&message),
+                Level::Info => console::info_1(&message),
+                Level::Debug | Level::Trace => console::debug_1(&message),
+            }
+        }
+
+        fn flush(&self) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    fn target_enabled(target: &str, level: Level) -> bool {
+        enabled(&Metadata::builder().target(target).level(level).build())
+    }
+
+    #[test]
+    fn test_filters() {
+        set_filter(DEFAULT_FILTER).unwrap();
+        assert!(target_enabled(SYNTH, Level::Info));
+        assert!(!target_enabled(SYNTH, Level::Debug));
+        assert!(!target_enabled(AUDIO, Level::Error));
+        assert!(target_enabled("other", Level::Warn));
+        assert_eq!(log::max_level(), LevelFilter::Info);
+
+        set_filter("warn, audio=trace").unwrap();
+        assert!(target_enabled(AUDIO, Level::Trace));
+        assert!(!target_enabled(INPUT, Level::Info));
+        assert_eq!(log::max_level(), LevelFilter::Trace);
+
+        assert_eq!(
+            set_filter("info,midi=debug"),
+            Err(SynthError::unknown("log category", "midi"))
+        );
+        assert_eq!(
+            set_filter("loud"),
+            Err(SynthError::unknown("log level", "loud"))
+        );
+        // Unchanged by the invalid filters
+        assert!(target_enabled(AUDIO, Level::Trace));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod logging;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod render_pool;
+pub mod smoothing;
+pub mod spsc;
+pub mod triple_buffer;
@@ -0,0 +1,272 @@
+//! Pool of worker threads that render a batch of jobs alongside the audio
+//! thread, each adding into its own accumulator.
+//!
+//! Nothing on the audio path locks or allocates. A batch is published by
+//! bumping a generation counter, and jobs are claimed one at a time from a
+//! shared atomic index, so a thread that finishes early takes the next job
+//! instead of waiting for a fixed share. Idle workers spin briefly before
+//! parking, so back to back batches (one per part, every block) don't pay
+//! for a wake-up each time.
+
+use std::cell::UnsafeCell;
+use std::hint::spin_loop;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Renders job `index` into the first buffer, which starts zeroed and must be
+/// left zeroed, adding the result to the second (the thread's accumulator).
+pub type RenderJob<'a> = dyn Fn(usize, &mut [f32], &mut [f32]) + Sync + 'a;
+
+/// How many times an idle worker checks for a new batch before parking.
+const SPINS_BEFORE_PARKING: usize = 20_000;
+/// How many times the calling thread checks for the workers to finish before
+/// giving up its core to them.
+const SPINS_BEFORE_YIELDING: usize = 1_000;
+
+/// Working memory of one thread taking part in a batch.
+struct Lane {
+    scratch: Vec<f32>,
+    accumulator: Vec<f32>,
+}
+
+struct Shared {
+    generation: AtomicUsize, // Bumped to start a batch
+    next_job: AtomicUsize,
+    job_count: AtomicUsize,
+    finished_workers: AtomicUsize,
+    shutdown: AtomicBool,
+    // Written by the pool owner only while no batch is running
+    job: UnsafeCell<Option<&'static RenderJob<'static>>>,
+    len: UnsafeCell<usize>,
+    lanes: Box<[UnsafeCell<Lane>]>, // Lane 0 belongs to the calling thread
+}
+
+// SAFETY: `job` and `len` are written before a batch is published through
+// `generation` and only read by workers after they observe it. Each lane is
+// used by one thread during a batch, and by the owner only between batches
+// (after `finished_workers` shows every worker is done).
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    /// Claim and render jobs on `lane` until none are left.
+    ///
+    /// SAFETY: only the thread that owns `lane` may call this, during a batch.
+    unsafe fn run_lane(&self, lane: usize) {
+        let job = (*self.job.get()).expect("batch published without a job");
+        let len = *self.len.get();
+        let lane = &mut *self.lanes[lane].get();
+        let accumulator = &mut lane.accumulator[..len];
+        let scratch = &mut lane.scratch[..len];
+        accumulator.fill(0.0);
+        let job_count = self.job_count.load(Ordering::Relaxed);
+        loop {
+            let index = self.next_job.fetch_add(1, Ordering::Relaxed);
+            if index >= job_count {
+                break;
+            }
+            job(index, scratch, accumulator);
+        }
+    }
+
+    /// `run_lane`, aborting the process if a job panics. Unwinding out of a
+    /// batch would either leave the owner waiting forever for a dead worker,
+    /// or let `render` return while workers still hold the job it borrowed.
+    ///
+    /// SAFETY: as for `run_lane`.
+    unsafe fn run_lane_or_abort(&self, lane: usize) {
+        if panic::catch_unwind(AssertUnwindSafe(|| self.run_lane(lane))).is_err() {
+            process::abort();
+        }
+    }
+}
+
+pub struct RenderPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    buffer_size: usize,
+}
+
+impl RenderPool {
+    /// Start `workers` threads that help render buffers of up to
+    /// `buffer_size` samples.
+    pub fn new(workers: usize, buffer_size: usize) -> Self {
+        let lanes = (0..=workers)
+            .map(|_| {
+                UnsafeCell::new(Lane {
+                    scratch: vec![0.0; buffer_size],
+                    accumulator: vec![0.0; buffer_size],
+                })
+            })
+            .collect();
+        let shared = Arc::new(Shared {
+            generation: AtomicUsize::new(0),
+            next_job: AtomicUsize::new(0),
+            job_count: AtomicUsize::new(0),
+            finished_workers: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+            job: UnsafeCell::new(None),
+            len: UnsafeCell::new(0),
+            lanes,
+        });
+        let workers = (1..=workers)
+            .map(|lane| {
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(format!("render-worker-{}", lane))
+                    .spawn(move || worker_loop(&shared, lane))
+                    .expect("failed to spawn render worker")
+            })
+            .collect();
+        Self {
+            shared,
+            workers,
+            buffer_size,
+        }
+    }
+
+    /// Worker threads, not counting the thread calling `render`.
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Reallocate the per-thread buffers. Not for the audio thread.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        self.buffer_size = buffer_size;
+        for lane in self.shared.lanes.iter() {
+            // SAFETY: no batch is running, so no worker is using its lane
+            let lane = unsafe { &mut *lane.get() };
+            lane.scratch.resize(buffer_size, 0.0);
+            lane.accumulator.resize(buffer_size, 0.0);
+        }
+    }
+
+    /// Run jobs `0..job_count` across the pool and the calling thread, and
+    /// add the sum of every thread's accumulator to `output`. Returns once
+    /// all jobs are done. `output` may be at most `buffer_size` long.
+    pub fn render(&mut self, job_count: usize, output: &mut [f32], job: &RenderJob<'_>) {
+        let len = output.len();
+        assert!(
+            len <= self.buffer_size,
+            "render pool buffers hold {} samples, asked for {}",
+            self.buffer_size,
+            len
+        );
+        let shared = &*self.shared;
+        // SAFETY: the pool waits below for every worker to finish with the
+        // job, so it never outlives this call despite the erased lifetime.
+        // Nothing between publishing the batch and that wait can unwind: a
+        // panicking job aborts the process (see `run_lane_or_abort`).
+        let job: &'static RenderJob<'static> = unsafe { std::mem::transmute(job) };
+        // SAFETY: no batch is running, so no worker reads these
+        unsafe {
+            *shared.job.get() = Some(job);
+            *shared.len.get() = len;
+        }
+        shared.next_job.store(0, Ordering::Relaxed);
+        shared.job_count.store(job_count, Ordering::Relaxed);
+        shared.finished_workers.store(0, Ordering::Relaxed);
+        shared.generation.fetch_add(1, Ordering::Release);
+        for worker in &self.workers {
+            worker.thread().unpark();
+        }
+
+        // SAFETY: lane 0 is the calling thread's, and a batch is running
+        unsafe { shared.run_lane_or_abort(0) };
+        let mut spins = 0;
+        while shared.finished_workers.load(Ordering::Acquire) < self.workers.len() {
+            if spins < SPINS_BEFORE_YIELDING {
+                spins += 1;
+                spin_loop();
+            } else {
+                // A worker is probably waiting for a core, possibly this one
+                thread::yield_now();
+            }
+        }
+
+        // SAFETY: every worker is done, so no batch is running
+        unsafe { *shared.job.get() = None };
+        for lane in shared.lanes.iter() {
+            // SAFETY: as above
+            let lane = unsafe { &*lane.get() };
+            for (sample, accumulated) in output.iter_mut().zip(&lane.accumulator[..len]) {
+                *sample += accumulated;
+            }
+        }
+    }
+}
+
+impl Drop for RenderPool {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        for worker in self.workers.drain(..) {
+            worker.thread().unpark();
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker_loop(shared: &Shared, lane: usize) {
+    let mut seen_generation = 0;
+    loop {
+        let mut spins = 0;
+        loop {
+            if shared.shutdown.load(Ordering::Acquire) {
+                return;
+            }
+            let generation = shared.generation.load(Ordering::Acquire);
+            if generation != seen_generation {
+                seen_generation = generation;
+                break;
+            }
+            if spins < SPINS_BEFORE_PARKING {
+                spins += 1;
+                spin_loop();
+            } else {
+                // Returns at once if unparked since the last check
+                thread::park();
+            }
+        }
+        // SAFETY: this worker owns `lane`, and a batch was just published
+        unsafe { shared.run_lane_or_abort(lane) };
+        shared.finished_workers.fetch_add(1, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_job_is_rendered_once() {
+        let mut pool = RenderPool::new(3, 64);
+        let mut output = vec![0.0; 50];
+        let job_runs: Vec<AtomicUsize> = (0..100).map(|_| AtomicUsize::new(0)).collect();
+        for _ in 0..10 {
+            output.fill(0.0);
+            pool.render(
+                job_runs.len(),
+                &mut output,
+                &|index, scratch, accumulator| {
+                    job_runs[index].fetch_add(1, Ordering::Relaxed);
+                    scratch.fill(index as f32);
+                    for (sample, value) in accumulator.iter_mut().zip(scratch.iter_mut()) {
+                        *sample += *value;
+                        *value = 0.0;
+                    }
+                },
+            );
+            let expected = (0..100).sum::<usize>() as f32;
+            assert!(output.iter().all(|&sample| sample == expected));
+        }
+        assert!(job_runs
+            .iter()
+            .all(|runs| runs.load(Ordering::Relaxed) == 10));
+    }
+}
@@ -0,0 +1,356 @@
+//! Parameter smoothers used to remove zipper noise when a value changes while
+//! audio is running. All smoothing times are in milliseconds and account for
+//! the sample rate, so they sound the same at 44.1 kHz and 96 kHz.
+
+/// Default smoothing time for continuously settable parameters.
+pub const DEFAULT_SMOOTHING_MS: f32 = 20.0;
+const DEFAULT_SAMPLE_RATE: f32 = 44100.0;
+
+pub trait Smoother {
+    /// Set the value to glide towards. The first target set on a fresh smoother
+    /// is applied immediately, so new voices don't glide in from zero.
+    fn set_target(&mut self, target: f32);
+    /// Advance by one sample and return the new value.
+    fn next_value(&mut self) -> f32;
+    fn current(&self) -> f32;
+    fn target(&self) -> f32;
+    /// Jump straight to `value`.
+    fn reset(&mut self, value: f32);
+    fn set_sample_rate(&mut self, sample_rate: f32);
+    fn set_time_ms(&mut self, time_ms: f32);
+
+    fn is_settled(&self) -> bool {
+        self.current() == self.target()
+    }
+    /// Fill `output` with successive smoothed values.
+    fn fill(&mut self, output: &mut [f32]) {
+        for sample in output.iter_mut() {
+            *sample = self.next_value();
+        }
+    }
+}
+
+fn ramp_steps(time_ms: f32, sample_rate: f32) -> u32 {
+    (time_ms * 0.001 * sample_rate).round().max(1.0) as u32
+}
+
+/// Moves towards the target in equal steps over a fixed time.
+#[derive(Debug, Clone)]
+pub struct LinearSmoother {
+    current: f32,
+    target: f32,
+    increment: f32,
+    steps_remaining: u32,
+    time_ms: f32,
+    sample_rate: f32,
+    initialized: bool,
+}
+
+impl LinearSmoother {
+    pub fn new(time_ms: f32, sample_rate: f32) -> Self {
+        Self {
+            current: 0.0,
+            target: 0.0,
+            increment: 0.0,
+            steps_remaining: 0,
+            time_ms: time_ms.max(0.0),
+            sample_rate: sample_rate.max(1.0),
+            initialized: false,
+        }
+    }
+    pub fn with_value(mut self, value: f32) -> Self {
+        self.reset(value);
+        self
+    }
+}
+
+impl Default for LinearSmoother {
+    fn default() -> Self {
+        Self::new(DEFAULT_SMOOTHING_MS, DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Smoother for LinearSmoother {
+    fn set_target(&mut self, target: f32) {
+        if !self.initialized {
+            self.reset(target);
+            return;
+        }
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        self.steps_remaining = ramp_steps(self.time_ms, self.sample_rate);
+        self.increment = (target - self.current) / self.steps_remaining as f32;
+    }
+    #[inline]
+    fn next_value(&mut self) -> f32 {
+        if self.steps_remaining > 0 {
+            self.steps_remaining -= 1;
+            self.current = if self.steps_remaining == 0 {
+                self.target
+            } else {
+                self.current + self.increment
+            };
+        }
+        self.current
+    }
+    fn current(&self) -> f32 {
+        self.current
+    }
+    fn target(&self) -> f32 {
+        self.target
+    }
+    fn reset(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.increment = 0.0;
+        self.steps_remaining = 0;
+        self.initialized = true;
+    }
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate.max(1.0);
+    }
+    fn set_time_ms(&mut self, time_ms: f32) {
+        self.time_ms = time_ms.max(0.0);
+    }
+}
+
+/// Exponential approach towards the target; `time_ms` is the time constant
+/// (about 63% of the way there).
+#[derive(Debug, Clone)]
+pub struct OnePoleSmoother {
+    current: f32,
+    target: f32,
+    coefficient: f32,
+    time_ms: f32,
+    sample_rate: f32,
+    initialized: bool,
+}
+
+impl OnePoleSmoother {
+    pub fn new(time_ms: f32, sample_rate: f32) -> Self {
+        let mut smoother = Self {
+            current: 0.0,
+            target: 0.0,
+            coefficient: 0.0,
+            time_ms: time_ms.max(0.0),
+            sample_rate: sample_rate.max(1.0),
+            initialized: false,
+        };
+        smoother.update_coefficient();
+        smoother
+    }
+    pub fn with_value(mut self, value: f32) -> Self {
+        self.reset(value);
+        self
+    }
+    fn update_coefficient(&mut self) {
+        let time_constant_samples = self.time_ms * 0.001 * self.sample_rate;
+        self.coefficient = if time_constant_samples <= 0.0 {
+            0.0
+        } else {
+            (-1.0 / time_constant_samples).exp()
+        };
+    }
+}
+
+impl Default for OnePoleSmoother {
+    fn default() -> Self {
+        Self::new(DEFAULT_SMOOTHING_MS, DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Smoother for OnePoleSmoother {
+    fn set_target(&mut self, target: f32) {
+        if !self.initialized {
+            self.reset(target);
+        } else {
+            self.target = target;
+        }
+    }
+    #[inline]
+    fn next_value(&mut self) -> f32 {
+        if self.current != self.target {
+            self.current = self.target + (self.current - self.target) * self.coefficient;
+            // Snap once the remaining difference is inaudible
+            if (self.current - self.target).abs() <= 1.0e-6 * self.target.abs().max(1.0) {
+                self.current = self.target;
+            }
+        }
+        self.current
+    }
+    fn current(&self) -> f32 {
+        self.current
+    }
+    fn target(&self) -> f32 {
+        self.target
+    }
+    fn reset(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.initialized = true;
+    }
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        let sample_rate = sample_rate.max(1.0);
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.update_coefficient();
+        }
+    }
+    fn set_time_ms(&mut self, time_ms: f32) {
+        self.time_ms = time_ms.max(0.0);
+        self.update_coefficient();
+    }
+}
+
+/// Moves towards the target by a constant ratio per sample, so a glide from
+/// 100 Hz to 200 Hz takes as long as one from 1 kHz to 2 kHz. Falls back to a
+/// linear ramp when either end is not strictly positive.
+#[derive(Debug, Clone)]
+pub struct MultiplicativeSmoother {
+    current: f32,
+    target: f32,
+    factor: f32,
+    increment: f32, // Used by the linear fallback
+    linear: bool,
+    steps_remaining: u32,
+    time_ms: f32,
+    sample_rate: f32,
+    initialized: bool,
+}
+
+impl MultiplicativeSmoother {
+    pub fn new(time_ms: f32, sample_rate: f32) -> Self {
+        Self {
+            current: 0.0,
+            target: 0.0,
+            factor: 1.0,
+            increment: 0.0,
+            linear: false,
+            steps_remaining: 0,
+            time_ms: time_ms.max(0.0),
+            sample_rate: sample_rate.max(1.0),
+            initialized: false,
+        }
+    }
+    pub fn with_value(mut self, value: f32) -> Self {
+        self.reset(value);
+        self
+    }
+}
+
+impl Default for MultiplicativeSmoother {
+    fn default() -> Self {
+        Self::new(DEFAULT_SMOOTHING_MS, DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Smoother for MultiplicativeSmoother {
+    fn set_target(&mut self, target: f32) {
+        if !self.initialized {
+            self.reset(target);
+            return;
+        }
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        self.steps_remaining = ramp_steps(self.time_ms, self.sample_rate);
+        let steps = self.steps_remaining as f32;
+        self.linear = self.current <= 0.0 || target <= 0.0;
+        if self.linear {
+            self.increment = (target - self.current) / steps;
+        } else {
+            self.factor = (target / self.current).powf(1.0 / steps);
+        }
+    }
+    #[inline]
+    fn next_value(&mut self) -> f32 {
+        if self.steps_remaining > 0 {
+            self.steps_remaining -= 1;
+            self.current = if self.steps_remaining == 0 {
+                self.target
+            } else if self.linear {
+                self.current + self.increment
+            } else {
+                self.current * self.factor
+            };
+        }
+        self.current
+    }
+    fn current(&self) -> f32 {
+        self.current
+    }
+    fn target(&self) -> f32 {
+        self.target
+    }
+    fn reset(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.factor = 1.0;
+        self.increment = 0.0;
+        self.steps_remaining = 0;
+        self.initialized = true;
+    }
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate.max(1.0);
+    }
+    fn set_time_ms(&mut self, time_ms: f32) {
+        self.time_ms = time_ms.max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_target_is_immediate() {
+        let mut smoother = LinearSmoother::default();
+        smoother.set_target(5.0);
+        assert_eq!(smoother.next_value(), 5.0);
+    }
+
+    #[test]
+    fn test_linear_reaches_target_in_time() {
+        let mut smoother = LinearSmoother::new(10.0, 1000.0).with_value(0.0);
+        smoother.set_target(1.0);
+        let values: Vec<f32> = (0..10).map(|_| smoother.next_value()).collect();
+        assert!((values[4] - 0.5).abs() < 1.0e-6);
+        assert_eq!(values[9], 1.0);
+        assert!(smoother.is_settled());
+    }
+
+    #[test]
+    fn test_one_pole_time_constant_scales_with_sample_rate() {
+        for sample_rate in [44100.0, 96000.0] {
+            let mut smoother = OnePoleSmoother::new(10.0, sample_rate).with_value(0.0);
+            smoother.set_target(1.0);
+            let samples = (0.010 * sample_rate) as usize;
+            let mut value = 0.0;
+            for _ in 0..samples {
+                value = smoother.next_value();
+            }
+            assert!(
+                (value - (1.0 - (-1.0f32).exp())).abs() < 0.01,
+                "Expected ~63% after one time constant at {} Hz, got {}",
+                sample_rate,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_multiplicative_is_geometric() {
+        let mut smoother = MultiplicativeSmoother::new(2.0, 1000.0).with_value(100.0);
+        smoother.set_target(400.0);
+        assert!((smoother.next_value() - 200.0).abs() < 1.0e-3);
+        assert_eq!(smoother.next_value(), 400.0);
+
+        // Zero can't be reached geometrically, so it ramps linearly instead
+        smoother.set_target(0.0);
+        assert!((smoother.next_value() - 200.0).abs() < 1.0e-3);
+        assert_eq!(smoother.next_value(), 0.0);
+    }
+}
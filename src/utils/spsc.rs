@@ -0,0 +1,176 @@
+//! Bounded, lock-free single-producer/single-consumer queue.
+//!
+//! Used to hand commands from the control thread to the audio thread without
+//! taking a lock or allocating once the queue has been created.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct RingBuffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // Monotonic counters; the slot index is `counter % capacity`.
+    head: AtomicUsize, // Next slot to read (owned by the consumer)
+    tail: AtomicUsize, // Next slot to write (owned by the producer)
+}
+
+// SAFETY: the producer only writes slots in `[head + len, head + capacity)` and the
+// consumer only reads slots in `[head, tail)`, so a slot is never accessed by both
+// sides at once. Publication is ordered through the acquire/release counters.
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl<T> RingBuffer<T> {
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        for counter in head..tail {
+            let slot = &mut self.slots[counter % self.slots.len()];
+            // SAFETY: every slot between head and tail holds an initialized value.
+            unsafe { slot.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// Writing half of the queue. Lives on the control thread.
+pub struct Producer<T> {
+    buffer: Arc<RingBuffer<T>>,
+}
+
+/// Reading half of the queue. Lives on the audio thread.
+pub struct Consumer<T> {
+    buffer: Arc<RingBuffer<T>>,
+}
+
+// SAFETY: each half is the only user of its side of the ring buffer.
+unsafe impl<T: Send> Send for Producer<T> {}
+unsafe impl<T: Send> Send for Consumer<T> {}
+
+/// Create a queue that holds at most `capacity` items (minimum 1).
+pub fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let slots = (0..capacity.max(1))
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+    let buffer = Arc::new(RingBuffer {
+        slots,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            buffer: buffer.clone(),
+        },
+        Consumer { buffer },
+    )
+}
+
+impl<T> Producer<T> {
+    /// Push a value, handing it back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let tail = self.buffer.tail.load(Ordering::Relaxed);
+        let head = self.buffer.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= self.buffer.capacity() {
+            return Err(value);
+        }
+        let slot = &self.buffer.slots[tail % self.buffer.capacity()];
+        // SAFETY: the slot is outside `[head, tail)`, so the consumer can't be reading it.
+        unsafe { (*slot.get()).write(value) };
+        self.buffer
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.buffer.tail.load(Ordering::Relaxed);
+        let head = self.buffer.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Pop the oldest value, if any. Never blocks and never allocates.
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.buffer.head.load(Ordering::Relaxed);
+        let tail = self.buffer.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let slot = &self.buffer.slots[head % self.buffer.capacity()];
+        // SAFETY: the slot is inside `[head, tail)`, so the producer has finished writing it
+        // and won't touch it again until `head` moves past it.
+        let value = unsafe { (*slot.get()).assume_init_read() };
+        self.buffer
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.buffer.head.load(Ordering::Relaxed);
+        let tail = self.buffer.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop_in_order_and_full() {
+        let (mut tx, mut rx) = channel(2);
+        assert!(tx.push(1).is_ok());
+        assert!(tx.push(2).is_ok());
+        assert_eq!(tx.push(3), Err(3), "Queue of capacity 2 should reject a third item");
+        assert_eq!(rx.pop(), Some(1));
+        assert!(tx.push(3).is_ok());
+        assert_eq!(rx.pop(), Some(2));
+        assert_eq!(rx.pop(), Some(3));
+        assert_eq!(rx.pop(), None);
+    }
+
+    #[test]
+    fn test_cross_thread_delivery() {
+        let (mut tx, mut rx) = channel(16);
+        let producer = std::thread::spawn(move || {
+            for i in 0..10_000u32 {
+                let mut value = i;
+                while let Err(v) = tx.push(value) {
+                    value = v;
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0u32;
+        while expected < 10_000 {
+            match rx.pop() {
+                Some(v) => {
+                    assert_eq!(v, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+//! Lock-free triple buffer for publishing parameter snapshots.
+//!
+//! The writer fills a private back buffer and atomically swaps it in; the reader
+//! picks up the latest complete snapshot without blocking, allocating or dropping.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+const INDEX_MASK: u8 = 0b011;
+const DIRTY_BIT: u8 = 0b100;
+
+struct Shared<T> {
+    buffers: [UnsafeCell<T>; 3],
+    // Index of the buffer that is neither being written nor read, plus a dirty flag
+    // set when it holds a snapshot the reader hasn't seen yet.
+    back: AtomicU8,
+}
+
+// SAFETY: writer and reader each own one buffer index exclusively; ownership of the
+// third index only changes hands through the atomic `back` swap.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct SnapshotWriter<T> {
+    shared: Arc<Shared<T>>,
+    index: u8,
+}
+
+pub struct SnapshotReader<T> {
+    shared: Arc<Shared<T>>,
+    index: u8,
+}
+
+unsafe impl<T: Send> Send for SnapshotWriter<T> {}
+unsafe impl<T: Send> Send for SnapshotReader<T> {}
+
+pub fn triple_buffer<T: Clone>(initial: T) -> (SnapshotWriter<T>, SnapshotReader<T>) {
+    let shared = Arc::new(Shared {
+        buffers: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        back: AtomicU8::new(2),
+    });
+    (
+        SnapshotWriter {
+            shared: shared.clone(),
+            index: 0,
+        },
+        SnapshotReader { shared, index: 1 },
+    )
+}
+
+impl<T> SnapshotWriter<T> {
+    /// Publish a new snapshot. The previous value of the recycled buffer is dropped
+    /// here, on the writer's thread.
+    pub fn publish(&mut self, value: T) {
+        // SAFETY: `self.index` is owned exclusively by the writer.
+        unsafe { *self.shared.buffers[self.index as usize].get() = value };
+        let previous = self
+            .shared
+            .back
+            .swap(self.index | DIRTY_BIT, Ordering::AcqRel);
+        self.index = previous & INDEX_MASK;
+    }
+}
+
+impl<T> SnapshotReader<T> {
+    /// Returns the newest snapshot if one was published since the last call.
+    pub fn update(&mut self) -> Option<&T> {
+        if self.shared.back.load(Ordering::Relaxed) & DIRTY_BIT == 0 {
+            return None;
+        }
+        let previous = self.shared.back.swap(self.index, Ordering::AcqRel);
+        self.index = previous & INDEX_MASK;
+        Some(self.read())
+    }
+
+    /// The snapshot the reader currently holds.
+    pub fn read(&self) -> &T {
+        // SAFETY: `self.index` is owned exclusively by the reader.
+        unsafe { &*self.shared.buffers[self.index as usize].get() }
+    }
+}
@@ -0,0 +1,173 @@
+//! Property tests for `Algorithm::set_matrix` over random UI matrices: any
+//! shape is answered with `Ok` or `Err` rather than a panic, and the algorithm
+//! renders finite audio through each of its carriers either way.
+use proptest::prelude::*;
+use rustfmsynth::synth::algorithm::{Algorithm, AlgorithmScratch, FeedbackMode};
+use rustfmsynth::synth::context::ProcessContext;
+use rustfmsynth::synth::error::SynthError;
+use rustfmsynth::synth::operator::{Operator, OperatorState};
+
+const MAX_OPERATORS: usize = 8;
+const BUFFER_SIZE: usize = 128;
+const SAMPLE_RATE: f32 = 44100.0;
+
+/// A UI matrix for `ui_operators` operators: `[source][target]` connections,
+/// with the carriers in the last column.
+fn ui_matrix(connections: &[Vec<bool>], carriers: &[bool]) -> Vec<Vec<u32>> {
+    connections
+        .iter()
+        .zip(carriers)
+        .map(|(row, &carrier)| {
+            row.iter()
+                .chain([&carrier])
+                .map(|&connected| connected as u32)
+                .collect()
+        })
+        .collect()
+}
+
+/// (algorithm operators, connections, carriers), with the UI showing up to
+/// as many operators as the algorithm has.
+fn valid_matrix() -> impl Strategy<Value = (usize, Vec<Vec<bool>>, Vec<bool>)> {
+    (1..=MAX_OPERATORS)
+        .prop_flat_map(|operators| (Just(operators), 1..=operators))
+        .prop_flat_map(|(operators, ui_operators)| {
+            (
+                Just(operators),
+                prop::collection::vec(
+                    prop::collection::vec(any::<bool>(), ui_operators),
+                    ui_operators,
+                ),
+                prop::collection::vec(any::<bool>(), ui_operators),
+            )
+        })
+}
+
+fn feedback_mode() -> impl Strategy<Value = FeedbackMode> {
+    prop_oneof![
+        Just(FeedbackMode::Unrolled),
+        Just(FeedbackMode::OneSample),
+        Just(FeedbackMode::Averaged),
+    ]
+}
+
+/// Render a held note for a few buffers, then release it for a few more.
+fn render(algorithm: &Algorithm, operators: &[Operator]) -> Vec<f32> {
+    let mut states = vec![OperatorState::default(); algorithm.length()];
+    let mut scratch = AlgorithmScratch::new(algorithm.length(), BUFFER_SIZE);
+    let mut output = vec![0.0; 8 * BUFFER_SIZE];
+    let release = 4 * BUFFER_SIZE as u64;
+    for (index, buffer) in output.chunks_mut(BUFFER_SIZE).enumerate() {
+        let elapsed = (index * BUFFER_SIZE) as u64;
+        let context = ProcessContext {
+            sample_rate: SAMPLE_RATE,
+            base_frequency: 220.0,
+            velocity_scale: 1.0,
+            modulation_index_scale: 1.0,
+            cutoff_scale: 1.0,
+            samples_elapsed_since_trigger: elapsed,
+            note_off_sample_index: (elapsed >= release).then_some(release),
+            operators,
+        };
+        algorithm.process(&context, &mut states, &mut scratch, buffer);
+    }
+    output
+}
+
+proptest! {
+    #[test]
+    fn test_valid_matrices_set_connections_and_carriers(
+        (operators, connections, carriers) in valid_matrix(),
+    ) {
+        let mut algorithm = Algorithm::default_fanout_feedback(operators).unwrap();
+        // Every matrix builds with one-sample feedback; unrolling may run out of room
+        algorithm.set_feedback_mode(FeedbackMode::OneSample).unwrap();
+        prop_assert_eq!(algorithm.set_matrix(&ui_matrix(&connections, &carriers)), Ok(()));
+
+        let expected_carriers: Vec<usize> = (0..carriers.len()).filter(|&i| carriers[i]).collect();
+        prop_assert_eq!(algorithm.get_carrier_indices(), &expected_carriers);
+        for (source, row) in algorithm.get_matrix().iter().enumerate() {
+            for (target, connection) in row.iter().enumerate() {
+                let expected = connections
+                    .get(source)
+                    .and_then(|row| row.get(target))
+                    .copied()
+                    .unwrap_or(false);
+                prop_assert_eq!(connection.is_some(), expected, "{} -> {}", source, target);
+            }
+        }
+    }
+
+    #[test]
+    fn test_valid_matrices_render_finite_audio_from_every_carrier(
+        (operators, connections, carriers) in valid_matrix(),
+        mode in feedback_mode(),
+    ) {
+        let mut algorithm = Algorithm::default_simple(operators).unwrap();
+        prop_assert_eq!(algorithm.set_feedback_mode(mode), Ok(()));
+        if algorithm.set_matrix(&ui_matrix(&connections, &carriers)).is_err() {
+            // Only unrolling can run out of room, and then nothing changes
+            prop_assert_eq!(mode, FeedbackMode::Unrolled);
+            prop_assert_eq!(algorithm.get_carrier_indices(), &vec![0]);
+            prop_assert!(algorithm.get_matrix().iter().flatten().all(Option::is_none));
+        }
+        let mut ops = vec![Operator::new(); operators];
+
+        let output = render(&algorithm, &ops);
+        prop_assert!(output.iter().all(|sample| sample.is_finite()));
+
+        // Each carrier is heard on its own
+        for carrier in algorithm.get_carrier_indices().clone() {
+            for (index, operator) in ops.iter_mut().enumerate() {
+                operator.gain = if index == carrier { 1.0 } else { 0.0 };
+            }
+            let output = render(&algorithm, &ops);
+            prop_assert!(
+                output.iter().any(|&sample| sample != 0.0),
+                "carrier {} is silent",
+                carrier
+            );
+        }
+    }
+
+    #[test]
+    fn test_misshapen_matrices_are_errors(
+        operators in 1..=MAX_OPERATORS,
+        rows in prop::collection::vec(prop::collection::vec(0..3u32, 0..=MAX_OPERATORS + 2), 0..=MAX_OPERATORS + 2),
+    ) {
+        let mut algorithm = Algorithm::default_stack_2(operators).unwrap();
+        let well_formed = rows.len() <= operators && rows.iter().all(|row| row.len() == rows.len() + 1);
+        prop_assert_eq!(algorithm.set_matrix(&rows).is_ok(), well_formed);
+    }
+}
+
+#[test]
+fn test_empty_matrix_changes_nothing() {
+    let mut algorithm = Algorithm::default_fanout(4).unwrap();
+    let carriers = algorithm.get_carrier_indices().clone();
+    assert_eq!(algorithm.set_matrix(&[]), Ok(()));
+    assert_eq!(algorithm.get_carrier_indices(), &carriers);
+}
+
+#[test]
+fn test_dense_matrix_is_too_large_to_unroll() {
+    let dense = ui_matrix(&vec![vec![true; 8]; 8], &[true; 8]);
+    let mut algorithm = Algorithm::default_simple(8).unwrap();
+    algorithm.set_feedback_mode(FeedbackMode::Unrolled).unwrap();
+    assert!(matches!(
+        algorithm.set_matrix(&dense),
+        Err(SynthError::AlgorithmTooLarge { .. })
+    ));
+    assert_eq!(algorithm.get_carrier_indices(), &vec![0]);
+
+    algorithm
+        .set_feedback_mode(FeedbackMode::OneSample)
+        .unwrap();
+    assert_eq!(algorithm.set_matrix(&dense), Ok(()));
+    // Unrolling what is set now would overflow, so the mode stays
+    assert!(matches!(
+        algorithm.set_feedback_mode(FeedbackMode::Unrolled),
+        Err(SynthError::AlgorithmTooLarge { .. })
+    ));
+    assert_eq!(algorithm.feedback_mode(), FeedbackMode::OneSample);
+}
@@ -0,0 +1,33 @@
+use rustfmsynth::runtime::native::{engine, EngineCommand};
+use rustfmsynth::synth::event::TimedEvent;
+use rustfmsynth::synth::params::{ParamEvent, ParamId};
+
+const SAMPLE_RATE: f32 = 44100.0;
+
+#[test]
+fn test_params_set_on_the_audio_thread_survive_other_snapshots() {
+    let (mut controller, mut engine) = engine(16);
+    let mut output = vec![0.0; 64];
+    for (id, value) in [(ParamId::MasterVolume, 0.3), (ParamId::VoiceAttack, 0.5)] {
+        let event = TimedEvent::Param(ParamEvent::new(id, value));
+        assert!(controller.send(EngineCommand::new(event, None)));
+    }
+    engine.process(&mut output, SAMPLE_RATE);
+
+    // An MTS single-note retune publishes a tuning snapshot
+    let retune = [
+        0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 69, 70, 0x00, 0x00, 0xF7,
+    ];
+    assert_eq!(controller.apply_mts_sysex(&retune), Ok(1));
+    engine.process(&mut output, SAMPLE_RATE);
+
+    let synth = engine.synth_mut();
+    assert_eq!(synth.get_param(ParamId::MasterVolume), Ok(0.3));
+    assert_eq!(synth.get_param(ParamId::VoiceAttack), Ok(0.5));
+
+    controller.set_master_volume(0.6);
+    engine.process(&mut output, SAMPLE_RATE);
+    let synth = engine.synth_mut();
+    assert_eq!(synth.get_param(ParamId::MasterVolume), Ok(0.6));
+    assert_eq!(synth.get_param(ParamId::VoiceAttack), Ok(0.5));
+}
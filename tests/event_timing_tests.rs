@@ -0,0 +1,61 @@
+use rustfmsynth::synth::event::TimedEvent;
+use rustfmsynth::synth::note::{NoteEvent, NoteSource};
+use rustfmsynth::synth::render::{render_offline, ScheduledEvent};
+use rustfmsynth::synth::Synth;
+
+const SAMPLE_RATE: f32 = 44100.0;
+
+fn first_audible_frame(buffer: &[f32]) -> Option<usize> {
+    buffer.iter().position(|s| s.abs() > 0.0)
+}
+
+#[test]
+fn test_note_onset_is_independent_of_block_size() {
+    let note_frame = 1000;
+    let events = [ScheduledEvent::new(
+        note_frame,
+        NoteEvent::new(69, 100, true, NoteSource::Sequencer).unwrap(),
+    )];
+
+    let mut onsets = Vec::new();
+    for block_size in [64, 256, 1024] {
+        let mut synth = Synth::new();
+        let output = render_offline(&mut synth, &events, 4096, SAMPLE_RATE, block_size);
+        onsets.push(first_audible_frame(&output).expect("Note should be audible"));
+    }
+
+    assert!(
+        onsets.iter().all(|&onset| onset == onsets[0]),
+        "Onset moved with block size: {:?}",
+        onsets
+    );
+    // The envelope starts at zero, so the first non-zero sample follows the note frame
+    assert!(
+        (note_frame as usize..note_frame as usize + 2).contains(&onsets[0]),
+        "Expected onset at frame {}, got {}",
+        note_frame,
+        onsets[0]
+    );
+}
+
+#[test]
+fn test_process_with_events_splits_buffer_at_offsets() {
+    let mut synth = Synth::new();
+    synth.set_buffer_size(512);
+    let mut output = vec![0.0; 512];
+    let events: [TimedEvent; 1] = [NoteEvent::new(60, 100, true, NoteSource::Midi)
+        .unwrap()
+        .with_frame_offset(300)
+        .into()];
+
+    synth.process_with_events(&mut output, SAMPLE_RATE, &events);
+
+    assert!(
+        output[..300].iter().all(|s| *s == 0.0),
+        "Nothing should sound before the event offset"
+    );
+    assert!(
+        output[300..].iter().any(|s| *s != 0.0),
+        "Note should sound after the event offset"
+    );
+}
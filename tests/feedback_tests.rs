@@ -0,0 +1,119 @@
+use rustfmsynth::synth::algorithm::FeedbackMode;
+use rustfmsynth::synth::note::{NoteEvent, NoteSource};
+use rustfmsynth::synth::params::ParamId;
+use rustfmsynth::synth::Synth;
+use std::f32::consts::TAU;
+
+const SAMPLE_RATE: f32 = 44100.0;
+const FUNDAMENTAL: f32 = 220.0; // A3
+
+/// Render a held A3 from operator 0 modulating itself. In the unrolled mode
+/// the feedback index is the operator's modulation index; otherwise it is the
+/// modulation index times the feedback amount.
+fn render_self_feedback(mode: FeedbackMode, modulation_index: f32, feedback: f32) -> Vec<f32> {
+    let mut synth = Synth::new();
+    synth.set_feedback_mode(mode).unwrap();
+    let operators = synth.parts()[0].operators().len();
+    let mut matrix = vec![vec![0; operators + 1]; operators];
+    matrix[0][0] = 1;
+    matrix[0][operators] = 1;
+    synth.set_algorithm(&matrix).unwrap();
+    synth
+        .set_operator_envelope(0, 0.001, 0.01, 1.0, 0.1)
+        .unwrap();
+    synth
+        .set_param(ParamId::OperatorModulationIndex(0), modulation_index)
+        .unwrap();
+    synth
+        .set_param(ParamId::OperatorFeedback(0), feedback)
+        .unwrap();
+    synth.note_on(&NoteEvent::new(57, 127, true, NoteSource::Midi).unwrap());
+
+    let mut output = vec![0.0; SAMPLE_RATE as usize];
+    synth.process(&mut output, SAMPLE_RATE);
+    // Skip the attack and the parameter smoothing
+    output.split_off(SAMPLE_RATE as usize / 4)
+}
+
+/// Amplitudes of the first `count` harmonics, relative to the fundamental,
+/// from a Hann-windowed DFT at each harmonic's frequency.
+fn harmonics(signal: &[f32], count: usize) -> Vec<f32> {
+    let len = signal.len() as f32;
+    let amplitude = |frequency: f32| {
+        let (mut re, mut im) = (0.0f64, 0.0f64);
+        for (n, sample) in signal.iter().enumerate() {
+            let window = 0.5 - 0.5 * (TAU * n as f32 / len).cos();
+            let phase = TAU * frequency * n as f32 / SAMPLE_RATE;
+            re += f64::from(sample * window * phase.cos());
+            im += f64::from(sample * window * phase.sin());
+        }
+        (re * re + im * im).sqrt() as f32
+    };
+    let fundamental = amplitude(FUNDAMENTAL);
+    (1..=count)
+        .map(|harmonic| amplitude(FUNDAMENTAL * harmonic as f32) / fundamental)
+        .collect()
+}
+
+#[test]
+fn test_gentle_feedback_matches_unrolled_spectrum() {
+    // At a small index both are sin(x + 0.2 sin x) to first order, so the
+    // second harmonic is about 0.1 either way; they part from the third on.
+    let unrolled = harmonics(&render_self_feedback(FeedbackMode::Unrolled, 0.2, 1.0), 3);
+    let one_sample = harmonics(&render_self_feedback(FeedbackMode::OneSample, 1.0, 0.2), 3);
+    assert!(
+        (unrolled[1] - 0.1).abs() < 0.01 && (one_sample[1] - 0.1).abs() < 0.01,
+        "unrolled {:?} vs one-sample {:?}",
+        unrolled,
+        one_sample
+    );
+    assert!(one_sample[2] > unrolled[2]);
+}
+
+#[test]
+fn test_strong_feedback_is_brighter_than_unrolled() {
+    // At an index of 1, true feedback approaches a sawtooth, with harmonics
+    // falling off as 1/n, while one unrolled copy is plain FM whose upper
+    // harmonics die away quickly.
+    let unrolled = harmonics(&render_self_feedback(FeedbackMode::Unrolled, 1.0, 1.0), 5);
+    let one_sample = harmonics(&render_self_feedback(FeedbackMode::OneSample, 1.0, 1.0), 5);
+    assert!(
+        one_sample[3] > 3.0 * unrolled[3] && one_sample[4] > 3.0 * unrolled[4],
+        "unrolled {:?} vs one-sample {:?}",
+        unrolled,
+        one_sample
+    );
+    for harmonic in 1..5 {
+        let expected = 1.0 / (harmonic + 1) as f32;
+        assert!(
+            one_sample[harmonic] > 0.5 * expected && one_sample[harmonic] < 1.5 * expected,
+            "one-sample harmonics should be close to 1/n: {:?}",
+            one_sample
+        );
+    }
+}
+
+#[test]
+fn test_averaging_calms_high_feedback() {
+    // Heavy feedback makes the one-sample loop hunt at half the sample rate
+    let nyquist_energy = |signal: &[f32]| {
+        let alternating: f32 = signal
+            .iter()
+            .enumerate()
+            .map(|(n, sample)| if n % 2 == 0 { *sample } else { -sample })
+            .sum();
+        alternating.abs() / signal.len() as f32
+    };
+    let rms =
+        |signal: &[f32]| (signal.iter().map(|s| s * s).sum::<f32>() / signal.len() as f32).sqrt();
+    let one_sample = render_self_feedback(FeedbackMode::OneSample, 10.0, 0.3);
+    let averaged = render_self_feedback(FeedbackMode::Averaged, 10.0, 0.3);
+    let one_sample_nyquist = nyquist_energy(&one_sample) / rms(&one_sample);
+    let averaged_nyquist = nyquist_energy(&averaged) / rms(&averaged);
+    assert!(
+        averaged_nyquist < 0.1 * one_sample_nyquist,
+        "one-sample {} vs averaged {}",
+        one_sample_nyquist,
+        averaged_nyquist
+    );
+}
@@ -0,0 +1,322 @@
+//! Golden-audio regression tests: a fixed phrase is rendered through every
+//! patch shipped in `web/public/default-patches.json` and compared against the
+//! reference render stored in `tests/golden/`.
+//!
+//! Renders are compared by loudness and spectrum frame by frame, within a
+//! tolerance, so rounding-level changes (a different SIMD path, reordered
+//! sums) pass while audible ones fail. After an intentional change to the
+//! sound, re-bless the references and check the new WAVs in:
+//!
+//! ```text
+//! BLESS_GOLDEN=1 cargo test --test golden_tests
+//! ```
+use rustfmsynth::synth::note::{NoteEvent, NoteSource};
+use rustfmsynth::synth::render::{render_offline, ScheduledEvent};
+use rustfmsynth::synth::Synth;
+use std::f32::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SHIPPED_PATCHES: &str = include_str!("../web/public/default-patches.json");
+const BANK: u16 = 0;
+const SAMPLE_RATE: f32 = 22050.0;
+const FRAMES: usize = SAMPLE_RATE as usize; // One second
+const BLOCK_SIZE: usize = 256;
+
+/// Analysis window, in samples. Windows don't overlap.
+const WINDOW: usize = 1024;
+/// Spectral bands, spaced evenly in pitch from `LOWEST_BAND_HZ` to Nyquist.
+const BANDS: usize = 48;
+const LOWEST_BAND_HZ: f32 = 40.0;
+/// Levels below this (dBFS) count as silence.
+const SILENCE_DB: f32 = -70.0;
+/// Bands further than this below a window's loudest band are ignored.
+const SPECTRAL_RANGE_DB: f32 = 60.0;
+/// Largest difference in any window's RMS level.
+const RMS_TOLERANCE_DB: f32 = 1.0;
+/// Largest log-spectral distance (RMS over bands) of any window.
+const SPECTRAL_TOLERANCE_DB: f32 = 3.0;
+
+fn reference_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn blessing() -> bool {
+    std::env::var_os("BLESS_GOLDEN").is_some()
+}
+
+fn patch_names() -> Vec<String> {
+    let mut synth = Synth::new();
+    synth.presets.import_json(BANK, SHIPPED_PATCHES).unwrap();
+    let names = synth.presets.programs(BANK).iter();
+    names.map(|patch| patch.name.clone()).collect()
+}
+
+/// File name for a patch: "Classic E-Piano 2" becomes "classic_e_piano_2".
+fn slug(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect();
+    words.join("_")
+}
+
+/// A bass note and a chord on top, held, then released with time for the
+/// tails to ring out.
+fn phrase(transpose: u8) -> Vec<ScheduledEvent> {
+    let quarter = FRAMES as u64 / 4;
+    let note = |frame, note: u8, on| {
+        let event = NoteEvent::new(note + transpose, 100, on, NoteSource::Sequencer).unwrap();
+        ScheduledEvent::new(frame, event)
+    };
+    vec![
+        note(0, 45, true),
+        note(quarter, 57, true),
+        note(quarter, 61, true),
+        note(quarter, 64, true),
+        note(2 * quarter, 45, false),
+        note(2 * quarter + quarter / 2, 57, false),
+        note(2 * quarter + quarter / 2, 61, false),
+        note(2 * quarter + quarter / 2, 64, false),
+    ]
+}
+
+fn render(program: u8, transpose: u8) -> Vec<f32> {
+    let mut synth = Synth::new();
+    synth.presets.import_json(BANK, SHIPPED_PATCHES).unwrap();
+    synth.program_change(BANK, program).unwrap();
+    let events = phrase(transpose);
+    render_offline(&mut synth, &events, FRAMES, SAMPLE_RATE, BLOCK_SIZE)
+}
+
+// --- Comparison ---
+
+/// Loudness and spectrum of each analysis window, in dB.
+struct Analysis {
+    rms_db: Vec<f32>,
+    bands_db: Vec<[f32; BANDS]>,
+}
+
+fn db(power: f32) -> f32 {
+    (10.0 * power.max(1e-20).log10()).max(SILENCE_DB)
+}
+
+fn analyze(samples: &[f32]) -> Analysis {
+    let band_edges: Vec<f32> = (0..=BANDS)
+        .map(|band| {
+            let nyquist = SAMPLE_RATE / 2.0;
+            LOWEST_BAND_HZ * (nyquist / LOWEST_BAND_HZ).powf(band as f32 / BANDS as f32)
+        })
+        .collect();
+    let hz_per_bin = SAMPLE_RATE / WINDOW as f32;
+    let mut analysis = Analysis {
+        rms_db: Vec::new(),
+        bands_db: Vec::new(),
+    };
+    for window in samples.chunks_exact(WINDOW) {
+        let power = window.iter().map(|s| s * s).sum::<f32>() / WINDOW as f32;
+        analysis.rms_db.push(db(power));
+
+        let spectrum = power_spectrum(window);
+        let mut bands = [0.0; BANDS];
+        for (bin, power) in spectrum.iter().enumerate() {
+            let hz = bin as f32 * hz_per_bin;
+            if let Some(band) = band_edges.windows(2).position(|e| e[0] <= hz && hz < e[1]) {
+                bands[band] += power;
+            }
+        }
+        analysis.bands_db.push(bands.map(db));
+    }
+    analysis
+}
+
+/// Power of each bin up to Nyquist, of the window with a Hann taper.
+fn power_spectrum(window: &[f32]) -> Vec<f32> {
+    let n = window.len();
+    let mut re: Vec<f32> = window
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos()))
+        .collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    let scale = 4.0 / (n * n) as f32; // Hann gain, one-sided spectrum
+    (0..n / 2)
+        .map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]) * scale)
+        .collect()
+}
+
+/// In-place radix-2 FFT. `re.len()` must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Describe the first window where `render` strays from `reference`.
+fn compare(reference: &[f32], render: &[f32]) -> Result<(), String> {
+    if reference.len() != render.len() {
+        return Err(format!(
+            "length {} differs from the reference's {}",
+            render.len(),
+            reference.len()
+        ));
+    }
+    let reference = analyze(reference);
+    let render = analyze(render);
+    for window in 0..reference.rms_db.len() {
+        let seconds = (window * WINDOW) as f32 / SAMPLE_RATE;
+        let (expected, actual) = (reference.rms_db[window], render.rms_db[window]);
+        if (expected - actual).abs() > RMS_TOLERANCE_DB {
+            return Err(format!(
+                "at {:.2}s the level is {:.1} dB, the reference {:.1} dB",
+                seconds, actual, expected
+            ));
+        }
+
+        let (expected, actual) = (&reference.bands_db[window], &render.bands_db[window]);
+        let loudest = expected
+            .iter()
+            .chain(actual)
+            .copied()
+            .fold(SILENCE_DB, f32::max);
+        let floor = (loudest - SPECTRAL_RANGE_DB).max(SILENCE_DB);
+        let squared: f32 = expected
+            .iter()
+            .zip(actual)
+            .map(|(e, a)| (e.max(floor) - a.max(floor)).powi(2))
+            .sum();
+        let distance = (squared / BANDS as f32).sqrt();
+        if distance > SPECTRAL_TOLERANCE_DB {
+            return Err(format!(
+                "at {:.2}s the spectrum is {:.1} dB away from the reference",
+                seconds, distance
+            ));
+        }
+    }
+    Ok(())
+}
+
+// --- Reference files (mono 32-bit float WAV) ---
+
+fn write_wav(path: &Path, samples: &[f32]) {
+    let data_len = (samples.len() * 4) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&3u16.to_le_bytes()); // IEEE float
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    bytes.extend_from_slice(&(SAMPLE_RATE as u32).to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE as u32 * 4).to_le_bytes());
+    bytes.extend_from_slice(&4u16.to_le_bytes());
+    bytes.extend_from_slice(&32u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    fs::write(path, bytes).unwrap();
+}
+
+/// Samples of a WAV written by `write_wav`.
+fn read_wav(path: &Path) -> Result<Vec<f32>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    let header = bytes.get(..44).ok_or("file too short for a WAV header")?;
+    if &header[..4] != b"RIFF" || &header[8..16] != b"WAVEfmt " || &header[36..40] != b"data" {
+        return Err(format!("{} is not a WAV from write_wav", path.display()));
+    }
+    let sample_rate = u32::from_le_bytes(header[24..28].try_into().unwrap());
+    if sample_rate != SAMPLE_RATE as u32 {
+        return Err(format!("reference is at {} Hz", sample_rate));
+    }
+    Ok(bytes[44..]
+        .chunks_exact(4)
+        .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+        .collect())
+}
+
+#[test]
+fn test_shipped_patches_match_reference_renders() {
+    let names = patch_names();
+    assert!(!names.is_empty(), "No shipped patches found");
+    let dir = reference_dir();
+    if blessing() {
+        fs::create_dir_all(&dir).unwrap();
+    }
+
+    let mut failures = Vec::new();
+    for (program, name) in names.iter().enumerate() {
+        let path = dir.join(format!("{}.wav", slug(name)));
+        let output = render(program as u8, 0);
+        assert!(
+            output.iter().all(|sample| sample.is_finite()),
+            "{} renders non-finite samples",
+            name
+        );
+        if blessing() {
+            write_wav(&path, &output);
+            continue;
+        }
+        if let Err(error) = read_wav(&path).and_then(|reference| compare(&reference, &output)) {
+            failures.push(format!("{}: {}", name, error));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "Renders differ from the references. If the change is intended, re-bless \
+         them with `BLESS_GOLDEN=1 cargo test --test golden_tests`.\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_comparison_tolerates_block_size_and_catches_transposition() {
+    let reference = render(0, 0);
+
+    let events = phrase(0);
+    let mut synth = Synth::new();
+    synth.presets.import_json(BANK, SHIPPED_PATCHES).unwrap();
+    synth.program_change(BANK, 0).unwrap();
+    let reblocked = render_offline(&mut synth, &events, FRAMES, SAMPLE_RATE, 100);
+    assert_eq!(compare(&reference, &reblocked), Ok(()));
+
+    assert!(compare(&reference, &render(0, 1)).is_err());
+    assert!(compare(&reference, &vec![0.0; FRAMES]).is_err());
+}
+
+#[test]
+fn test_slug() {
+    assert_eq!(slug("Classic E-Piano 2"), "classic_e_piano_2");
+    assert_eq!(slug("SciFi Sweep"), "scifi_sweep");
+}
@@ -0,0 +1,52 @@
+use rustfmsynth::synth::midi::MidiDecoder;
+use rustfmsynth::synth::mpe::MpeConfig;
+use rustfmsynth::synth::note::NoteSource;
+use rustfmsynth::synth::Synth;
+
+const SAMPLE_RATE: f32 = 44100.0;
+
+/// Estimate the frequency of a sine from its upward zero crossings.
+fn estimate_frequency(buffer: &[f32]) -> f32 {
+    let crossings = buffer
+        .windows(2)
+        .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+        .count();
+    crossings as f32 * SAMPLE_RATE / buffer.len() as f32
+}
+
+fn render_with_messages(messages: &[&[u8]]) -> Vec<f32> {
+    let mut synth = Synth::new();
+    let mut decoder = MidiDecoder::new(NoteSource::Midi);
+    decoder.set_mpe_config(MpeConfig::lower_zone());
+    for message in messages {
+        if let Some(event) = decoder.decode(message) {
+            synth.handle_event(&event);
+        }
+    }
+    let mut output = vec![0.0; SAMPLE_RATE as usize / 2];
+    synth.process(&mut output, SAMPLE_RATE);
+    output
+}
+
+#[test]
+fn test_per_note_pitch_bend_only_moves_its_own_note() {
+    // +12 semitones is a quarter of the 48 semitone per-note range
+    let bend_up_octave: &[u8] = &[0xE1, 0x00, 0x50];
+
+    let bent = render_with_messages(&[bend_up_octave, &[0x91, 57, 100]]);
+    let frequency = estimate_frequency(&bent);
+    assert!(
+        (frequency - 440.0).abs() < 5.0,
+        "Bent A3 should sound at ~440 Hz, got {}",
+        frequency
+    );
+
+    // The same bend on another member channel leaves this note alone
+    let unbent = render_with_messages(&[&[0xE2, 0x00, 0x50], &[0x91, 57, 100]]);
+    let frequency = estimate_frequency(&unbent);
+    assert!(
+        (frequency - 220.0).abs() < 5.0,
+        "Unbent A3 should sound at ~220 Hz, got {}",
+        frequency
+    );
+}
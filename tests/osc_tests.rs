@@ -0,0 +1,109 @@
+use rustfmsynth::input::OscServer;
+use rustfmsynth::runtime::EngineCommand;
+use rustfmsynth::synth::event::TimedEvent;
+use rustfmsynth::synth::osc::{OscArg, OscMessage, OscPacket};
+use rustfmsynth::synth::params::{ParamEvent, ParamId};
+use std::net::UdpSocket;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+fn timetag(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap();
+    let fraction = (u64::from(since_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+    (since_epoch.as_secs() + NTP_UNIX_OFFSET) << 32 | fraction
+}
+
+fn bundle(timetag: u64, messages: &[OscMessage]) -> Vec<u8> {
+    let mut bytes = b"#bundle\0".to_vec();
+    bytes.extend_from_slice(&timetag.to_be_bytes());
+    for message in messages {
+        let encoded = message.encode();
+        bytes.extend_from_slice(&(encoded.len() as i32).to_be_bytes());
+        bytes.extend_from_slice(&encoded);
+    }
+    bytes
+}
+
+/// Update the server until a command arrives or a second passes.
+fn next_command(server: &mut OscServer, commands: &Receiver<EngineCommand>) -> EngineCommand {
+    let start = Instant::now();
+    loop {
+        server.update();
+        if let Ok(command) = commands.try_recv() {
+            return command;
+        }
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "no command arrived"
+        );
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn test_bundles_are_held_until_their_timetag() {
+    let (command_tx, commands) = channel();
+    let mut server = OscServer::bind("127.0.0.1:0", command_tx).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let note_on = OscMessage::new("/note/on", vec![OscArg::Int(60), OscArg::Int(100)]);
+    let ratio = OscMessage::new("/op/2/ratio", vec![OscArg::Float(1.5)]);
+
+    let sent = Instant::now();
+    let due = SystemTime::now() + Duration::from_millis(100);
+    client
+        .send_to(
+            &bundle(timetag(due), &[note_on]),
+            server.local_addr().unwrap(),
+        )
+        .unwrap();
+    client
+        .send_to(&ratio.encode(), server.local_addr().unwrap())
+        .unwrap();
+
+    let command = next_command(&mut server, &commands);
+    assert!(
+        matches!(command.event, TimedEvent::Param(event) if event.id == ParamId::OperatorRatio(2))
+    );
+    let command = next_command(&mut server, &commands);
+    assert!(
+        matches!(command.event, TimedEvent::Note(event) if event.is_on && event.note_number == 60)
+    );
+    assert!(sent.elapsed() >= Duration::from_millis(90));
+}
+
+#[test]
+fn test_subscribed_clients_get_parameter_feedback() {
+    let (command_tx, commands) = channel();
+    let mut server = OscServer::bind("127.0.0.1:0", command_tx).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let subscribe = OscMessage::new("/feedback", vec![OscArg::Int(1)]);
+    let volume = OscMessage::new("/master/volume", vec![OscArg::Float(0.5)]);
+    for message in [subscribe, volume] {
+        client
+            .send_to(&message.encode(), server.local_addr().unwrap())
+            .unwrap();
+    }
+
+    let command = next_command(&mut server, &commands);
+    server.report(&command);
+    server.report(&EngineCommand::now(ParamEvent::new(
+        ParamId::OperatorDetune(0),
+        7.0,
+    )));
+    let mut buffer = [0; 256];
+    for expected in [
+        OscMessage::new("/master/volume", vec![OscArg::Float(0.5)]),
+        OscMessage::new("/op/0/detune", vec![OscArg::Float(7.0)]),
+    ] {
+        let len = client.recv(&mut buffer).unwrap();
+        assert_eq!(
+            OscPacket::decode(&buffer[..len]),
+            Ok(OscPacket::Message(expected))
+        );
+    }
+}
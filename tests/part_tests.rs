@@ -0,0 +1,126 @@
+use rustfmsynth::synth::midi::MidiDecoder;
+use rustfmsynth::synth::note::NoteSource;
+use rustfmsynth::synth::params::ParamId;
+use rustfmsynth::synth::Synth;
+
+const SAMPLE_RATE: f32 = 44100.0;
+
+/// Estimate the frequency of a sine from its upward zero crossings.
+fn estimate_frequency(buffer: &[f32]) -> f32 {
+    let crossings = buffer
+        .windows(2)
+        .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+        .count();
+    crossings as f32 * SAMPLE_RATE / buffer.len() as f32
+}
+
+fn play(synth: &mut Synth, messages: &[&[u8]]) -> Vec<f32> {
+    let mut decoder = MidiDecoder::new(NoteSource::Midi);
+    for message in messages {
+        if let Some(event) = decoder.decode(message) {
+            synth.handle_event(&event);
+        }
+    }
+    let mut output = vec![0.0; SAMPLE_RATE as usize / 2];
+    synth.process(&mut output, SAMPLE_RATE);
+    output
+}
+
+/// The first part below middle C, and a second part an octave up from it.
+fn split_synth() -> Synth {
+    let mut synth = Synth::new();
+    synth.part_mut(0).unwrap().set_key_range(0, 59);
+    let upper = synth.add_part(4);
+    synth.part_mut(upper).unwrap().set_key_range(60, 127);
+    synth.select_part(upper).unwrap();
+    synth.set_param(ParamId::OperatorRatio(0), 2.0).unwrap();
+    synth
+}
+
+#[test]
+fn test_keyboard_split_plays_each_side_on_its_own_part() {
+    let mut synth = split_synth();
+    let low = play(&mut synth, &[&[0x90, 57, 100]]);
+    let frequency = estimate_frequency(&low);
+    assert!(
+        (frequency - 220.0).abs() < 5.0,
+        "A3 on the lower part, got {}",
+        frequency
+    );
+    assert_eq!(synth.parts()[0].active_voices(), 1);
+    assert_eq!(synth.parts()[1].active_voices(), 0);
+
+    let mut synth = split_synth();
+    let high = play(&mut synth, &[&[0x90, 69, 100]]);
+    let frequency = estimate_frequency(&high);
+    assert!(
+        (frequency - 880.0).abs() < 10.0,
+        "A4 an octave up, got {}",
+        frequency
+    );
+    assert_eq!(synth.parts()[0].active_voices(), 0);
+    assert_eq!(synth.parts()[1].active_voices(), 1);
+}
+
+#[test]
+fn test_layers_by_channel_and_velocity() {
+    let mut synth = Synth::new();
+    let layer = synth.add_part(4);
+    let soft = synth.add_part(4);
+    synth.part_mut(layer).unwrap().set_channel(Some(1));
+    synth.part_mut(soft).unwrap().set_velocity_range(1, 63);
+
+    play(&mut synth, &[&[0x91, 60, 100]]);
+    let voices: Vec<usize> = synth.parts().iter().map(|p| p.active_voices()).collect();
+    assert_eq!(voices, vec![1, 1, 0]);
+
+    // Soft notes on channel 0 reach the first part and the soft layer only
+    play(&mut synth, &[&[0x90, 64, 40]]);
+    let voices: Vec<usize> = synth.parts().iter().map(|p| p.active_voices()).collect();
+    assert_eq!(voices, vec![2, 1, 1]);
+}
+
+#[test]
+fn test_program_change_switches_parts_on_its_channel() {
+    let mut synth = Synth::new();
+    synth
+        .presets
+        .import_json(
+            0,
+            r#"[{ "name": "Fifth", "state": { "operators": [{ "ratio": 1.5 }] } }]"#,
+        )
+        .unwrap();
+    let bass = synth.add_part(4);
+    synth.part_mut(bass).unwrap().set_channel(Some(1));
+    synth.part_mut(0).unwrap().set_channel(Some(0));
+
+    play(&mut synth, &[&[0xC1, 0]]);
+    assert_eq!(synth.parts()[bass].operators()[0].get_ratio(), 1.5);
+    assert_eq!(synth.parts()[0].operators()[0].get_ratio(), 1.0);
+}
+
+#[test]
+fn test_part_params_through_the_registry() {
+    let mut synth = Synth::new();
+    let upper = synth.add_part(4);
+    synth.set_param(ParamId::PartKeyLow(upper), 60.0).unwrap();
+    synth
+        .set_param(ParamId::PartEffectSend(upper), 0.25)
+        .unwrap();
+    synth
+        .set_param(ParamId::PartFeedbackMode(upper), 2.0)
+        .unwrap();
+    assert_eq!(synth.parts()[upper].key_range(), (60, 127));
+    assert_eq!(synth.parts()[upper].effect_send(), 0.25);
+    assert_eq!(synth.get_param(ParamId::PartFeedbackMode(upper)), Ok(2.0));
+    // The first part keeps its own settings
+    assert_eq!(synth.get_param(ParamId::PartKeyLow(0)), Ok(0.0));
+    assert_eq!(synth.get_param(ParamId::PartFeedbackMode(0)), Ok(0.0));
+    assert!(synth
+        .set_param(ParamId::PartKeyLow(upper + 1), 60.0)
+        .is_err());
+
+    play(&mut synth, &[&[0x90, 48, 100]]);
+    let voices: Vec<usize> = synth.parts().iter().map(|p| p.active_voices()).collect();
+    assert_eq!(voices, vec![1, 0]);
+}
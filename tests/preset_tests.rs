@@ -0,0 +1,54 @@
+use rustfmsynth::synth::midi::MidiDecoder;
+use rustfmsynth::synth::note::NoteSource;
+use rustfmsynth::synth::params::ParamId;
+use rustfmsynth::synth::Synth;
+
+const SAMPLE_RATE: f32 = 44100.0;
+const BANK: &str = r#"[
+    { "name": "Low", "state": { "operators": [{ "ratio": 1 }], "masterVolume": 90 } },
+    { "name": "High", "state": { "operators": [{ "ratio": 2 }], "masterVolume": 90 } }
+]"#;
+
+fn synth_with_bank(messages: &[&[u8]]) -> Synth {
+    let mut synth = Synth::new();
+    synth.presets.import_json(130, BANK).unwrap();
+    let mut decoder = MidiDecoder::new(NoteSource::Midi);
+    for message in messages {
+        if let Some(event) = decoder.decode(message) {
+            synth.handle_event(&event);
+        }
+    }
+    synth
+}
+
+#[test]
+fn test_bank_select_and_program_change_switch_patches() {
+    // Bank 130 is MSB 1, LSB 2
+    let mut synth = synth_with_bank(&[&[0xB0, 0, 1], &[0xB0, 32, 2], &[0xC0, 1]]);
+    assert_eq!(synth.presets.current(), Some((130, 1)));
+    assert_eq!(synth.get_param(ParamId::OperatorRatio(0)), Ok(2.0));
+
+    // A missing program leaves the patch alone
+    let mut decoder = MidiDecoder::new(NoteSource::Midi);
+    synth.handle_event(&decoder.decode(&[0xC0, 5]).unwrap());
+    assert_eq!(synth.presets.current(), Some((130, 1)));
+    assert!(synth.program_change(130, 0).is_ok());
+    assert_eq!(synth.get_param(ParamId::OperatorRatio(0)), Ok(1.0));
+}
+
+#[test]
+fn test_sounding_notes_keep_the_old_patch() {
+    let select_low: &[&[u8]] = &[&[0xB0, 0, 1], &[0xB0, 32, 2], &[0xC0, 0], &[0x90, 57, 100]];
+    let mut switched = synth_with_bank(select_low);
+    let mut unswitched = synth_with_bank(select_low);
+    let mut switched_output = vec![0.0; 4096];
+    let mut unswitched_output = vec![0.0; 4096];
+    switched.process(&mut switched_output[..2048], SAMPLE_RATE);
+    unswitched.process(&mut unswitched_output[..2048], SAMPLE_RATE);
+
+    switched.program_change(130, 1).unwrap();
+    switched.process(&mut switched_output[2048..], SAMPLE_RATE);
+    unswitched.process(&mut unswitched_output[2048..], SAMPLE_RATE);
+    assert_eq!(switched_output, unswitched_output);
+    assert_eq!(switched.get_param(ParamId::OperatorRatio(0)), Ok(2.0));
+}
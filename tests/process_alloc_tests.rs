@@ -0,0 +1,251 @@
+// Asserts that rendering audio never touches the allocator, so `Synth::process`
+// is safe to call from a real-time audio callback.
+use rustfmsynth::synth::core::EffectSlot;
+use rustfmsynth::synth::event::TimedEvent;
+use rustfmsynth::synth::filter::Filter;
+use rustfmsynth::synth::handoff::{Retired, HANDOFF_CAPACITY};
+use rustfmsynth::synth::note::{NoteEvent, NoteSource};
+use rustfmsynth::synth::params::{ParamEvent, ParamId};
+use rustfmsynth::synth::preset::ProgramEvent;
+use rustfmsynth::synth::sequencer::{Pattern, Step};
+use rustfmsynth::synth::Synth;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Only count allocations made by the thread under test
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(|c| c.get()) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if COUNTING.with(|c| c.get()) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.with(|c| c.get()) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Runs `f` and returns how many allocator calls it made on this thread.
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    COUNTING.with(|c| c.set(true));
+    f();
+    COUNTING.with(|c| c.set(false));
+    ALLOCATIONS.load(Ordering::SeqCst) - before
+}
+
+#[test]
+fn test_synth_process_does_not_allocate() {
+    let sample_rate = 44100.0;
+    let buffer_size = 256;
+    let mut synth = Synth::new();
+    synth.set_buffer_size(buffer_size);
+    synth.set_effect_reverb(20.0, 1000.0, 0.3, rustfmsynth::synth::core::EffectSlot::One);
+    // Op 0 feeds back on itself, sample by sample
+    synth
+        .set_algorithm(&[vec![1, 1, 1], vec![0, 0, 0]])
+        .unwrap();
+    let layer = synth.add_part(8); // Layered over the first part, bypassing the reverb
+    synth.part_mut(layer).unwrap().set_effect_send(0.0);
+
+    let mut output = vec![0.0; buffer_size];
+    for note in [60, 64, 67] {
+        synth.note_on(&NoteEvent::new(note, 100, true, NoteSource::Midi).unwrap());
+    }
+
+    let allocations = count_allocations(|| {
+        for _ in 0..20 {
+            synth.process(&mut output, sample_rate);
+        }
+    });
+    assert_eq!(
+        allocations, 0,
+        "Synth::process allocated while notes were held"
+    );
+
+    // Larger host buffers are rendered in chunks rather than by growing buffers
+    let mut large_output = vec![0.0; buffer_size * 3 + 17];
+    let allocations = count_allocations(|| synth.process(&mut large_output, sample_rate));
+    assert_eq!(
+        allocations, 0,
+        "Synth::process allocated for an oversized buffer"
+    );
+
+    assert!(
+        output.iter().all(|s| s.is_finite()) && output.iter().any(|s| *s != 0.0),
+        "Expected audible, finite output"
+    );
+}
+
+#[test]
+fn test_notes_on_filtered_operators_do_not_allocate() {
+    let sample_rate = 44100.0;
+    let buffer_size = 256;
+    let mut synth = Synth::new();
+    synth.set_buffer_size(buffer_size);
+    synth
+        .set_algorithm(&[vec![0, 1, 0], vec![0, 0, 1]])
+        .unwrap();
+    synth
+        .set_operator_filter(0, Filter::new_pitched_comb(0.9))
+        .unwrap();
+    synth
+        .set_operator_filter(1, Filter::new_lowpass_biquad(2000.0, sample_rate))
+        .unwrap();
+
+    let mut output = vec![0.0; buffer_size];
+    for note in [48, 60, 72, 84, 48] {
+        let events = [TimedEvent::Note(
+            NoteEvent::new(note, 100, true, NoteSource::Midi).unwrap(),
+        )];
+        let allocations = count_allocations(|| {
+            synth.process_with_events(&mut output, sample_rate, &events);
+            synth.process(&mut output, sample_rate);
+        });
+        assert_eq!(
+            allocations, 0,
+            "Note {} on filtered operators allocated",
+            note
+        );
+    }
+    assert!(
+        output.iter().all(|s| s.is_finite()) && output.iter().any(|s| *s != 0.0),
+        "Expected audible, finite output"
+    );
+}
+
+#[test]
+fn test_program_changes_and_new_reverbs_do_not_allocate() {
+    let sample_rate = 44100.0;
+    let buffer_size = 256;
+    let mut synth = Synth::new();
+    synth.set_buffer_size(buffer_size);
+    synth.set_deferred_builds(true);
+    synth
+        .presets
+        .import_json(0, include_str!("../web/public/default-patches.json"))
+        .unwrap();
+    let presets = synth.presets.clone();
+    synth.set_effect_reverb(20.0, 1000.0, 0.3, EffectSlot::One);
+    for handoff in synth
+        .drain_build_requests()
+        .collect::<Vec<_>>()
+        .iter()
+        .filter_map(|request| request.build(&presets))
+    {
+        synth.install(handoff);
+    }
+    for note in [60, 64, 67] {
+        synth.note_on(&NoteEvent::new(note, 100, true, NoteSource::Midi).unwrap());
+    }
+
+    let mut output = vec![0.0; buffer_size];
+    // Collected as the host's queues would, without growing on the audio thread
+    let mut requests = Vec::with_capacity(HANDOFF_CAPACITY);
+    let mut retired: Vec<Retired> = Vec::with_capacity(HANDOFF_CAPACITY);
+    for program in 1..4 {
+        let events = [
+            TimedEvent::Program(ProgramEvent::new(0, 0, program)),
+            TimedEvent::Param(ParamEvent::new(
+                ParamId::ReverbPredelay(EffectSlot::One),
+                10.0 * program as f32,
+            )),
+        ];
+        let allocations = count_allocations(|| {
+            synth.process_with_events(&mut output, sample_rate, &events);
+            requests.extend(synth.drain_build_requests());
+        });
+        assert_eq!(allocations, 0, "Program and predelay changes allocated");
+        assert_eq!(requests.len(), 2, "Expected a part and a reverb to build");
+
+        // As a host would, off the audio thread
+        let mut handoffs: Vec<_> = requests
+            .drain(..)
+            .filter_map(|request| request.build(&presets))
+            .collect();
+        let allocations = count_allocations(|| {
+            for handoff in handoffs.drain(..) {
+                synth.install(handoff);
+            }
+            for _ in 0..20 {
+                synth.process(&mut output, sample_rate);
+            }
+            retired.extend(std::iter::from_fn(|| synth.pop_retired()));
+        });
+        assert_eq!(allocations, 0, "Installing what was built allocated");
+        retired.clear();
+    }
+    assert_eq!(synth.presets.current(), Some((0, 3)));
+}
+
+#[test]
+fn test_sequencer_playback_does_not_allocate() {
+    let sample_rate = 44100.0;
+    let buffer_size = 128;
+    let mut synth = Synth::new();
+    synth.set_buffer_size(buffer_size);
+    let steps = (0..8)
+        .map(|i| {
+            Step::note(60 + i, 100)
+                .with_gate(1.5)
+                .with_lock(ParamId::OperatorRatio(0), 1.0 + i as f32)
+        })
+        .collect();
+    synth.sequencer.set_pattern(0, Pattern::new(steps)).unwrap();
+    synth.transport.set_tempo(600.0); // Many steps per buffer
+    synth.start_transport();
+
+    let mut output = vec![0.0; buffer_size];
+    let allocations = count_allocations(|| {
+        for _ in 0..100 {
+            synth.process(&mut output, sample_rate);
+        }
+        synth.stop_transport();
+    });
+    assert_eq!(allocations, 0, "Sequencer playback allocated");
+}
+
+#[test]
+fn test_parallel_voice_rendering_does_not_allocate() {
+    let sample_rate = 44100.0;
+    let buffer_size = 256;
+    let mut synth = Synth::new();
+    synth.set_buffer_size(buffer_size);
+    synth.set_render_threads(3);
+    for note in 48..80 {
+        synth.note_on(&NoteEvent::new(note, 100, true, NoteSource::Midi).unwrap());
+    }
+
+    let mut output = vec![0.0; buffer_size];
+    synth.process(&mut output, sample_rate); // Let the workers start up
+    let allocations = count_allocations(|| {
+        for _ in 0..20 {
+            synth.process(&mut output, sample_rate);
+        }
+    });
+    assert_eq!(
+        allocations, 0,
+        "Synth::process allocated while rendering on worker threads"
+    );
+}
@@ -0,0 +1,68 @@
+use rustfmsynth::synth::note::{NoteEvent, NoteSource};
+use rustfmsynth::synth::Synth;
+
+const SAMPLE_RATE: f32 = 44100.0;
+const BUFFER_SIZE: usize = 256;
+
+/// Plays a chord that changes while rendering, on a two-operator stack.
+fn render(render_threads: usize) -> Vec<f32> {
+    let mut synth = Synth::new();
+    synth.set_buffer_size(BUFFER_SIZE);
+    synth.set_render_threads(render_threads);
+    let mut matrix = vec![vec![0; 13]; 12];
+    matrix[1][0] = 1; // Operator 1 modulates carrier 0
+    matrix[0][12] = 1;
+    synth.set_algorithm(&matrix).unwrap();
+
+    let note = |number, on| NoteEvent::new(number, 100, on, NoteSource::Midi).unwrap();
+    let mut output = vec![0.0; BUFFER_SIZE * 40];
+    for number in (36..96).step_by(3) {
+        synth.note_on(&note(number, true));
+    }
+    for (i, block) in output.chunks_mut(BUFFER_SIZE).enumerate() {
+        if i == 20 {
+            for number in (36..66).step_by(3) {
+                synth.note_off(&note(number, false));
+            }
+        }
+        synth.process(block, SAMPLE_RATE);
+    }
+    output
+}
+
+#[test]
+fn test_render_threads_match_single_threaded_output() {
+    let reference = render(0);
+    let peak = reference.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    assert!(peak > 0.0);
+    for render_threads in [1, 3] {
+        let output = render(render_threads);
+        // Only the order voices are summed in may differ
+        let difference = output
+            .iter()
+            .zip(&reference)
+            .fold(0.0f32, |max, (a, b)| max.max((a - b).abs()));
+        assert!(
+            difference < 1e-5 * peak,
+            "Output differs by {} with {} render threads",
+            difference,
+            render_threads
+        );
+    }
+}
+
+#[test]
+fn test_render_threads_can_be_changed() {
+    let mut synth = Synth::new();
+    synth.set_render_threads(2);
+    assert_eq!(synth.render_threads(), 2);
+    synth.set_buffer_size(64);
+    synth.note_on(&NoteEvent::new(60, 100, true, NoteSource::Midi).unwrap());
+    synth.note_on(&NoteEvent::new(64, 100, true, NoteSource::Midi).unwrap());
+    let mut output = vec![0.0; 200]; // Longer than the buffer size
+    synth.process(&mut output, SAMPLE_RATE);
+    assert!(output.iter().any(|s| *s != 0.0));
+    synth.set_render_threads(0);
+    assert_eq!(synth.render_threads(), 0);
+    synth.process(&mut output, SAMPLE_RATE);
+}
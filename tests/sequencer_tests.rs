@@ -0,0 +1,162 @@
+use rustfmsynth::synth::clock::{ClockEvent, ClockMessage};
+use rustfmsynth::synth::params::ParamId;
+use rustfmsynth::synth::render::{render_offline, ScheduledEvent};
+use rustfmsynth::synth::sequencer::{Pattern, Step};
+use rustfmsynth::synth::transport::ClockSource;
+use rustfmsynth::synth::Synth;
+
+const SAMPLE_RATE: f32 = 44100.0;
+// Sixteenth notes at 120 BPM
+const STEP_FRAMES: f32 = SAMPLE_RATE * 60.0 / (120.0 * 4.0);
+
+fn sequenced_synth(steps: Vec<Step>, swing: f32) -> Synth {
+    let mut synth = Synth::new();
+    synth.sequencer.set_pattern(0, Pattern::new(steps)).unwrap();
+    synth.sequencer.set_swing(swing);
+    synth
+}
+
+fn render(synth: &mut Synth, num_frames: usize, block_size: usize) -> Vec<f32> {
+    let start = [ScheduledEvent::new(0, ClockEvent::new(ClockMessage::Start))];
+    render_offline(synth, &start, num_frames, SAMPLE_RATE, block_size)
+}
+
+fn first_audible_frame(buffer: &[f32]) -> Option<usize> {
+    buffer.iter().position(|s| s.abs() > 0.0)
+}
+
+#[test]
+fn test_sequencer_output_is_independent_of_block_size() {
+    let steps = vec![
+        Step::note(60, 100),
+        Step::note(64, 90).with_gate(1.5),
+        Step::rest(),
+        Step::note(67, 110).with_lock(ParamId::OperatorRatio(0), 2.0),
+    ];
+    let num_frames = (STEP_FRAMES * 10.0) as usize;
+    let reference = render(&mut sequenced_synth(steps.clone(), 0.2), num_frames, 64);
+    for block_size in [100, 1024] {
+        let output = render(
+            &mut sequenced_synth(steps.clone(), 0.2),
+            num_frames,
+            block_size,
+        );
+        // Operators switch between scalar and vectorized rendering at block
+        // boundaries, which the vectorized path only approximates
+        let peak = reference.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let difference = output
+            .iter()
+            .zip(&reference)
+            .fold(0.0f32, |max, (a, b)| max.max((a - b).abs()));
+        assert!(
+            difference < 2e-3 * peak,
+            "Output changed by {} with block size {}",
+            difference,
+            block_size
+        );
+        assert_eq!(
+            first_audible_frame(&output),
+            first_audible_frame(&reference)
+        );
+    }
+}
+
+#[test]
+fn test_swing_delays_odd_steps() {
+    let steps = vec![Step::rest(), Step::note(69, 100)];
+    let output = render(
+        &mut sequenced_synth(steps, 0.5),
+        2 * STEP_FRAMES as usize,
+        256,
+    );
+    let expected = (STEP_FRAMES * 1.5).ceil() as usize;
+    let onset = first_audible_frame(&output).expect("Swung step should sound");
+    assert!(
+        (expected..expected + 2).contains(&onset),
+        "Expected onset at frame {}, got {}",
+        expected,
+        onset
+    );
+}
+
+#[test]
+fn test_zero_probability_step_is_silent() {
+    let steps = vec![Step::note(69, 100).with_probability(0.0)];
+    let output = render(
+        &mut sequenced_synth(steps, 0.0),
+        4 * STEP_FRAMES as usize,
+        256,
+    );
+    assert!(output.iter().all(|s| *s == 0.0));
+}
+
+#[test]
+fn test_parameter_locks_are_restored() {
+    let steps = vec![
+        Step::note(60, 100).with_lock(ParamId::OperatorRatio(0), 3.0),
+        Step::note(60, 100),
+    ];
+    let mut synth = sequenced_synth(steps, 0.0);
+    let ratio = ParamId::OperatorRatio(0);
+    render(&mut synth, STEP_FRAMES as usize / 2, 256);
+    assert_eq!(synth.get_param(ratio).unwrap(), 3.0);
+    // The unlocked second step puts the ratio back
+    render_offline(&mut synth, &[], STEP_FRAMES as usize, SAMPLE_RATE, 256);
+    assert_eq!(synth.get_param(ratio).unwrap(), 1.0);
+
+    // Stopping mid-lock also restores it
+    render_offline(&mut synth, &[], STEP_FRAMES as usize, SAMPLE_RATE, 256);
+    assert_eq!(synth.get_param(ratio).unwrap(), 3.0);
+    synth.stop_transport();
+    assert_eq!(synth.get_param(ratio).unwrap(), 1.0);
+    assert!(!synth.sequencer.is_playing());
+}
+
+#[test]
+fn test_song_position_and_continue_locate_the_pattern() {
+    let steps = vec![Step::rest(), Step::rest(), Step::note(69, 100)];
+    let mut synth = sequenced_synth(steps, 0.0);
+    let events = [
+        ScheduledEvent::new(0, ClockEvent::new(ClockMessage::SongPosition(2))),
+        ScheduledEvent::new(0, ClockEvent::new(ClockMessage::Continue)),
+    ];
+    let output = render_offline(&mut synth, &events, STEP_FRAMES as usize, SAMPLE_RATE, 256);
+    assert!(first_audible_frame(&output).is_some_and(|onset| onset < 2));
+}
+
+#[test]
+fn test_sequencer_follows_midi_clock() {
+    let mut synth = sequenced_synth(vec![Step::rest(), Step::note(69, 100)], 0.0);
+    synth.set_clock_source(ClockSource::Midi);
+    // 500 frames per pulse; start once the tempo has been measured
+    let pulse_frames = 500;
+    let mut events: Vec<ScheduledEvent> = (0..20)
+        .map(|i| ScheduledEvent::new(i * pulse_frames, ClockEvent::new(ClockMessage::Tick)))
+        .collect();
+    events.push(ScheduledEvent::new(
+        4 * pulse_frames,
+        ClockEvent::new(ClockMessage::Start),
+    ));
+    events.sort_by_key(|event| event.frame);
+    let output = render_offline(&mut synth, &events, 10_000, SAMPLE_RATE, 256);
+    // A sixteenth note is 6 pulses
+    let expected = 10 * pulse_frames as usize;
+    let onset = first_audible_frame(&output).expect("Clocked step should sound");
+    assert!(
+        (expected..expected + 2).contains(&onset),
+        "Expected onset at frame {}, got {}",
+        expected,
+        onset
+    );
+    assert!((synth.transport.tempo() - 220.5).abs() < 0.01);
+}
+
+#[test]
+fn test_sequencer_notes_and_locks_reach_midi_output() {
+    let steps = vec![Step::note(60, 100).with_lock(ParamId::OperatorModulationIndex(0), 5.0)];
+    let mut synth = sequenced_synth(steps, 0.0);
+    synth.midi_output.set_enabled(true);
+    render(&mut synth, STEP_FRAMES as usize / 2, 256);
+    let messages: Vec<[u8; 3]> = synth.midi_output.drain().collect();
+    assert_eq!(messages, vec![[0xB0, 20, 64], [0x90, 60, 100]]);
+}
@@ -0,0 +1,124 @@
+use rustfmsynth::synth::envelope::EnvelopeGenerator;
+use rustfmsynth::synth::error::SynthError;
+use rustfmsynth::synth::midi::MidiDecoder;
+use rustfmsynth::synth::note::NoteSource;
+use rustfmsynth::synth::voice_config::VoiceConfig;
+use rustfmsynth::synth::Synth;
+
+const SAMPLE_RATE: f32 = 44100.0;
+
+fn send(synth: &mut Synth, message: &[u8]) {
+    let mut decoder = MidiDecoder::new(NoteSource::Midi);
+    if let Some(event) = decoder.decode(message) {
+        synth.handle_event(&event);
+    }
+}
+
+fn render(synth: &mut Synth, seconds: f32) -> Vec<f32> {
+    let mut output = vec![0.0; (seconds * SAMPLE_RATE) as usize];
+    for chunk in output.chunks_mut(256) {
+        synth.process(chunk, SAMPLE_RATE);
+    }
+    output
+}
+
+fn peak(buffer: &[f32]) -> f32 {
+    buffer
+        .iter()
+        .fold(0.0, |peak, sample| peak.max(sample.abs()))
+}
+
+/// A synth whose operators sustain at full level and release slowly.
+fn sustaining_synth(config: VoiceConfig) -> Synth {
+    let mut synth = Synth::new();
+    for operator in 0..synth.parts()[0].operators().len() {
+        synth
+            .set_operator_envelope(operator, 0.0, 0.0, 1.0, 2.0)
+            .unwrap();
+    }
+    synth.set_voice_config(config);
+    synth
+}
+
+#[test]
+fn test_voice_attack_shapes_the_note() {
+    let config = VoiceConfig {
+        attack: 0.1,
+        ..VoiceConfig::default()
+    };
+    let mut slow = sustaining_synth(config);
+    let mut instant = sustaining_synth(VoiceConfig::default());
+    send(&mut slow, &[0x90, 69, 100]);
+    send(&mut instant, &[0x90, 69, 100]);
+    let slow = render(&mut slow, 0.2);
+    let instant = render(&mut instant, 0.2);
+
+    let early = 0..(0.01 * SAMPLE_RATE) as usize;
+    assert!(peak(&slow[early.clone()]) < 0.2 * peak(&instant[early]));
+    // Full level once the attack is over
+    let late = (0.15 * SAMPLE_RATE) as usize..;
+    assert!((peak(&slow[late.clone()]) - peak(&instant[late])).abs() < 1e-4);
+}
+
+#[test]
+fn test_voice_release_ends_the_voice() {
+    let config = VoiceConfig {
+        release: 0.05,
+        ..VoiceConfig::default()
+    };
+    let mut short = sustaining_synth(config);
+    let mut long = sustaining_synth(VoiceConfig::default());
+    for synth in [&mut short, &mut long] {
+        send(synth, &[0x90, 69, 100]);
+        render(synth, 0.1);
+        send(synth, &[0x80, 69, 0]);
+    }
+    let short_tail = render(&mut short, 0.2);
+    let long_tail = render(&mut long, 0.2);
+
+    assert_eq!(short.parts()[0].active_voices(), 0);
+    assert_eq!(peak(&short_tail[(0.06 * SAMPLE_RATE) as usize..]), 0.0);
+    // The operators' two-second release keeps the default voice going
+    assert_eq!(long.parts()[0].active_voices(), 1);
+    assert!(peak(&long_tail[(0.06 * SAMPLE_RATE) as usize..]) > 0.1);
+}
+
+#[test]
+fn test_default_voice_envelope_is_neutral() {
+    // Patches were designed without a voice envelope, so by default it must
+    // neither shape nor end a note
+    let config = VoiceConfig::default();
+    let mut envelope = EnvelopeGenerator::new();
+    envelope.set_params(config.attack, config.decay, config.sustain, config.release);
+    for time in [0.0, 0.001, 0.3, 5.0, 60.0] {
+        assert_eq!(envelope.evaluate(time, None), 1.0);
+        assert_eq!(envelope.evaluate(time + 1.0, Some(time)), 1.0);
+        assert!(!envelope.finished(Some(time)));
+    }
+}
+
+#[test]
+fn test_voice_envelope_rejects_invalid_times() {
+    let mut config = VoiceConfig::default();
+    assert!(config.set_envelope(0.01, 0.2, 0.5, f32::INFINITY).is_ok());
+    for (attack, decay, sustain, release) in [
+        (-0.1, 0.0, 1.0, 1.0),
+        (f32::NAN, 0.0, 1.0, 1.0),
+        (f32::INFINITY, 0.0, 1.0, 1.0),
+        (0.0, -1.0, 1.0, 1.0),
+        (0.0, 0.0, 1.5, 1.0),
+        (0.0, 0.0, f32::NAN, 1.0),
+        (0.0, 0.0, 1.0, -1.0),
+        (0.0, 0.0, 1.0, f32::NAN),
+    ] {
+        assert!(matches!(
+            config.set_envelope(attack, decay, sustain, release),
+            Err(SynthError::InvalidEnvelope(_))
+        ));
+    }
+    // Unchanged by the rejected calls
+    assert_eq!(
+        (config.attack, config.decay, config.sustain, config.release),
+        (0.01, 0.2, 0.5, f32::INFINITY)
+    );
+}
@@ -5,10 +5,22 @@ let synth = null;
 let ready = false;
 let sampleRate = 44100;
 const SCOPE_DATA_CHUNK_SIZE = 4096;
+
+// Frames from the start of the next render quantum until `time` (AudioContext
+// seconds), or 0 (as soon as possible) without a time.
+function frameOffsetFor(time) {
+  if (time === undefined) {
+    return 0;
+  }
+  return Math.max(0, Math.round((time - currentTime) * sampleRate));
+}
 class SynthProcessor extends AudioWorkletProcessor {
   constructor() {
     super();
     this._scope_accumulator = new Float32Array(0);
+    // The program before MIDI that may change it, checked once the MIDI has played
+    this._program_before_midi = undefined;
+    this._program_check_frame = 0;
     this.port.onmessage = async (event) => {
       const data = event.data;
 
@@ -26,7 +38,7 @@ class SynthProcessor extends AudioWorkletProcessor {
           ready = true;
           console.log("SynthProcessor: WasmSynth instance created.");
 
-          this.port.postMessage({ type: 'initialized' });
+          this.port.postMessage({ type: 'initialized', paramInfos: JSON.parse(synth.get_param_infos()) });
         } catch (e) {
           console.error("SynthProcessor: Error initializing Wasm or creating WasmSynth instance:", e);
           ready = false;
@@ -43,10 +55,18 @@ class SynthProcessor extends AudioWorkletProcessor {
         // console.log(data);
         switch (data.type) {
           case "note_on":
-            synth.note_on(data.note, data.velocity);
+            if (data.time !== undefined) {
+              synth.schedule_note_on(data.note, data.velocity, frameOffsetFor(data.time));
+            } else {
+              synth.note_on(data.note, data.velocity);
+            }
             break;
           case "note_off":
-            synth.note_off(data.note);
+            if (data.time !== undefined) {
+              synth.schedule_note_off(data.note, frameOffsetFor(data.time));
+            } else {
+              synth.note_off(data.note);
+            }
             break;
           case "set_master_volume":
             synth.set_master_volume(data.volume);
@@ -72,6 +92,15 @@ class SynthProcessor extends AudioWorkletProcessor {
           case "set_operator_modulation_index":
             synth.set_operator_modulation_index(data.operatorIndex, data.modIndex);
             break;
+          case "set_operator_feedback":
+            synth.set_operator_feedback(data.operatorIndex, data.feedback);
+            break;
+          case "set_feedback_mode":
+            synth.set_feedback_mode(data.mode);
+            break;
+          case "set_voice_envelope":
+            synth.set_voice_envelope(data.attack, data.decay, data.sustain, data.release);
+            break;
           case "set_operator_envelope":
             synth.set_operator_envelope(data.operatorIndex, data.attack, data.decay, data.sustain, data.release);
             break;
@@ -86,6 +115,96 @@ class SynthProcessor extends AudioWorkletProcessor {
           case "set_algorithm":
             synth.set_algorithm(data.matrix);
             break;
+          case "set_tuning":
+            synth.set_tuning(data.scl, data.kbm ?? "");
+            break;
+          case "reset_tuning":
+            synth.reset_tuning();
+            break;
+          case "set_reference_pitch":
+            synth.set_reference_pitch(data.frequency);
+            break;
+          case "midi_message": {
+            const learning = synth.midi_learning();
+            if (this._program_before_midi === undefined) {
+              this._program_before_midi = synth.current_program();
+            }
+            const offset = frameOffsetFor(data.time);
+            synth.schedule_midi_message(data.data, offset);
+            this._program_check_frame = Math.max(this._program_check_frame, currentFrame + offset);
+            if (learning && !synth.midi_learning()) {
+              this.port.postMessage({ type: 'midi_learned', mappings: JSON.parse(synth.get_midi_mappings()) });
+            }
+            break;
+          }
+          case "load_preset_bank":
+            synth.load_preset_bank(data.bank, JSON.stringify(data.patches));
+            break;
+          case "program_change":
+            synth.program_change(data.bank, data.program);
+            break;
+          case "add_part":
+            this.port.postMessage({ type: 'part_added', part: synth.add_part(data.maxVoices ?? 8) });
+            break;
+          case "remove_part":
+            synth.remove_part(data.part);
+            break;
+          case "select_part":
+            synth.select_part(data.part);
+            break;
+          case "set_part_routing":
+            if (data.channel !== undefined) synth.set_part_channel(data.part, data.channel);
+            if (data.keyRange) synth.set_part_key_range(data.part, data.keyRange[0], data.keyRange[1]);
+            if (data.velocityRange) synth.set_part_velocity_range(data.part, data.velocityRange[0], data.velocityRange[1]);
+            if (data.effectSend !== undefined) synth.set_part_effect_send(data.part, data.effectSend);
+            break;
+          case "start_midi_learn":
+            synth.start_midi_learn(data.paramId, data.min ?? 0, data.max ?? 1, data.curve ?? "linear");
+            break;
+          case "cancel_midi_learn":
+            synth.cancel_midi_learn();
+            break;
+          case "set_midi_mappings":
+            synth.set_midi_mappings(JSON.stringify(data.mappings));
+            break;
+          case "clear_midi_mapping":
+            synth.clear_midi_mapping(data.paramId);
+            break;
+          case "set_mpe_zone":
+            synth.set_mpe_zone(data.upper, data.memberChannels);
+            break;
+          case "set_sequencer_pattern":
+            synth.set_sequencer_pattern(data.index, JSON.stringify(data.pattern));
+            break;
+          case "set_sequencer_chain":
+            synth.set_sequencer_chain(new Uint32Array(data.chain));
+            break;
+          case "set_sequencer_swing":
+            synth.set_sequencer_swing(data.swing);
+            break;
+          case "set_arpeggiator":
+            if (data.mode !== undefined) synth.set_arpeggiator_mode(data.mode);
+            if (data.octaves !== undefined) synth.set_arpeggiator_octaves(data.octaves);
+            if (data.rate !== undefined) synth.set_arpeggiator_rate(data.rate);
+            if (data.gate !== undefined) synth.set_arpeggiator_gate(data.gate);
+            if (data.latch !== undefined) synth.set_arpeggiator_latch(data.latch);
+            if (data.enabled !== undefined) synth.set_arpeggiator_enabled(data.enabled);
+            break;
+          case "set_tempo":
+            synth.set_tempo(data.bpm);
+            break;
+          case "set_clock_source":
+            synth.set_clock_source(data.source === "midi");
+            break;
+          case "start_transport":
+            synth.start_transport(frameOffsetFor(data.time));
+            break;
+          case "stop_transport":
+            synth.stop_transport(frameOffsetFor(data.time));
+            break;
+          case "continue_transport":
+            synth.continue_transport(frameOffsetFor(data.time));
+            break;
           default:
             console.warn("SynthProcessor: Received unknown message type: ", data.type, data)
         }
@@ -109,12 +228,21 @@ class SynthProcessor extends AudioWorkletProcessor {
 
     outputChannel.set(rendered);
 
+    if (this._program_before_midi !== undefined && currentFrame + bufferLength > this._program_check_frame) {
+      const program = synth.current_program();
+      if (program !== this._program_before_midi) {
+        this.port.postMessage({ type: 'program_change', ...JSON.parse(program) });
+      }
+      this._program_before_midi = undefined;
+    }
+
     const newAccumulator = new Float32Array(this._scope_accumulator.length + rendered.length);
     newAccumulator.set(this._scope_accumulator, 0);
     newAccumulator.set(rendered, this._scope_accumulator.length);
     this._scope_accumulator = newAccumulator;
     if (this._scope_accumulator.length >= SCOPE_DATA_CHUNK_SIZE) {
       this.port.postMessage({ type: 'output', data: this._scope_accumulator });
+      this.port.postMessage({ type: 'transport', state: JSON.parse(synth.transport_state()) });
       this._scope_accumulator = new Float32Array(0);
     }
 
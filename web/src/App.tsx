@@ -1,7 +1,7 @@
 import { Component, onMount, onCleanup, createEffect, createSignal, For, on } from 'solid-js';
 import { createStore, unwrap, SetStoreFunction } from 'solid-js/store';
 
-import { Note, AppState, AlgorithmSetterArg, MASTER_VOLUME_MIN, MASTER_VOLUME_MAX } from './state';
+import { Note, AppState, AlgorithmSetterArg, EnvelopeState, MASTER_VOLUME_MIN, MASTER_VOLUME_MAX, RegistryParamInfo } from './state';
 import { NUM_OPERATORS } from './config';
 
 import {
@@ -21,10 +21,11 @@ import PatchManager from './components/PatchManager';
 import Oscilloscope from './components/Oscilloscope';
 
 import './style.css';
-import { createDefaultAppState } from './defaults';
+import { createDefaultAppState, fillMissingAppState } from './defaults';
 import { deserializeState } from './urlState';
 import Dial from './components/Dial';
 import EffectsManager from './components/EffectsManager';
+import EnvelopeControl from './components/EnvelopeControl';
 
 export const [appStore, setAppStore] = createStore<AppState>(createDefaultAppState());
 // export const setAppStore = createUrlStatePersistence(
@@ -33,6 +34,9 @@ export const [appStore, setAppStore] = createStore<AppState>(createDefaultAppSta
 //   { debounceMs: 500 }
 // );
 
+// The synth's parameter registry, sent by the worklet once it's initialized
+export const [paramInfos, setParamInfos] = createSignal<RegistryParamInfo[]>([]);
+
 const [isFineModeActive, setIsFineModeActive] = createSignal(false);
 // Main App Component
 const App: Component = () => {
@@ -79,6 +83,7 @@ const App: Component = () => {
       processorNode.port.onmessage = (event) => {
         if (event.data?.type === 'initialized') {
           console.log("App: Received 'initialized' confirmation from worklet.");
+          setParamInfos(event.data.paramInfos ?? []);
           setIsSynthReady(true);
           // Synth is ready in the worklet, now the handler is fully usable.
         } else if (event.data?.type === 'init_error') {
@@ -89,6 +94,14 @@ const App: Component = () => {
         } else if (event.data?.type === 'output') {
           // Send output to oscilloscope
           setAudioData(event.data.data);
+        } else if (event.data?.type === 'program_change') {
+          // The worklet already switched patch; bring the UI along without resending it
+          if (event.data.patch) {
+            setAppStore(fillMissingAppState(event.data.patch.state));
+          }
+        } else if (event.data?.type === 'midi_learned') {
+          setAppStore('midiMappings', event.data.mappings);
+          SynthInputHandler.saveControllerProfile(event.data.mappings);
         } else {
           console.log("App: Received message from worklet:", event.data);
         }
@@ -126,6 +139,10 @@ const App: Component = () => {
     setAppStore("masterVolume", newValue); // Update store directly
     SynthInputHandler.setMasterVolume(newValue); // Call synth handler
   }
+  const handleVoiceEnvelopeChange = (paramKey: keyof EnvelopeState, newValue: number) => {
+    setAppStore("voiceEnvelope", paramKey, newValue);
+    SynthInputHandler.setVoiceEnvelope(appStore.voiceEnvelope);
+  }
   // --- Fine Mode Global Listeners --- 
   const handleGlobalKeyDown = (e: KeyboardEvent) => {
     if (e.key === "Shift" && !e.repeat && !isFineModeActive()) {
@@ -222,13 +239,19 @@ const App: Component = () => {
                   valueDisplayFormatter={(value) => value.toFixed(0) + "%"}
                 />
               </div>
+              <EnvelopeControl
+                idPrefix="voice"
+                title="Voice Envelope"
+                value={() => appStore.voiceEnvelope}
+                onParamChange={handleVoiceEnvelopeChange}
+              />
               <AlgorithmMatrix
                 numOperators={NUM_OPERATORS}
                 algorithm={appStore.algorithm}
                 setAlgorithmState={(updater: AlgorithmSetterArg) => setAppStore('algorithm', updater)}
               />
             </div>
-            <PatchManager />
+            <PatchManager synthReady={isSynthReady()} />
           </div>
           <div id="operator-controls"> {/* Wrapper for layout */}
             <For each={operatorIndices()}>
@@ -1,5 +1,10 @@
 let audioCtx: AudioContext | null = null;
 
+// Input plays this long after it happened, so that messages reaching the
+// worklet at uneven times keep their spacing. Like the native engine's one
+// buffer of latency, it trades a constant delay for no jitter.
+const INPUT_SCHEDULE_DELAY = 0.01;
+
 /**
  * Gets the existing AudioContext or creates a new one if it doesn't exist.
  * Logs warnings if the context is initially suspended.
@@ -40,6 +45,19 @@ export function resumeAudioContext(): void {
   }
 }
 
+/**
+ * Converts an event's timestamp (`performance.now()` time, as on Web MIDI
+ * messages and key presses) to the AudioContext time to play it at.
+ * @param {DOMHighResTimeStamp} timestamp - When the event happened.
+ * @returns {number | undefined} AudioContext seconds, or undefined without an AudioContext.
+ */
+export function scheduleTimeFor(timestamp: DOMHighResTimeStamp): number | undefined {
+  if (!audioCtx || !timestamp) {
+    return undefined;
+  }
+  return audioCtx.currentTime + (timestamp - performance.now()) / 1000 + INPUT_SCHEDULE_DELAY;
+}
+
 /**
  * Closes the AudioContext if it exists, releasing system resources.
  * Sets the internal reference to null.
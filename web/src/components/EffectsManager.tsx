@@ -1,13 +1,14 @@
 // components/EffectsManager.tsx
 
 import { Component, createMemo } from 'solid-js';
-import { appStore, setAppStore } from '../App';
-import { EFFECTS, EffectState, ReverbParams, EffectSlot, createEmptyEffect } from '../state';
+import { appStore, setAppStore, paramInfos } from '../App';
+import { effectConfigs, EffectState, ReverbParams, EffectSlot, createEmptyEffect } from '../state';
 import * as SynthInputHandler from '../synthInputHandler';
 import GenericManager from './GenericManager';
 
 const EffectsManager: Component = () => {
   const activeEffects = createMemo(() => (appStore.effects as EffectState[]) ?? []);
+  const effects = createMemo(() => effectConfigs(paramInfos()));
 
   // Handler now correctly and safely typed with EffectState
   const handleAddEffect = async (newItem: EffectState) => {
@@ -68,7 +69,7 @@ const EffectsManager: Component = () => {
       title="Master Effects"
       itemNoun="Effect"
       itemNounPlural="Effects"
-      configArray={EFFECTS}
+      configArray={effects()}
       activeItemsAccessor={activeEffects}
       uniqueIdPrefix="global-effect"
       maxItems={3}
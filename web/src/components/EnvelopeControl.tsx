@@ -3,25 +3,24 @@ import { EnvelopeState, envelopeParamsInfo, EnvelopeParamInfo } from '../state';
 import NumericParameterInput from './NumericParameterInput';
 
 interface EnvelopeControlProps {
-  operatorIndex: number;
+  idPrefix: string; // Makes the input IDs unique, e.g. "op-0"
+  title?: string;
   value: Accessor<EnvelopeState | undefined>; // Accessor for the whole envelope object from parent state
   onParamChange: (paramKey: keyof EnvelopeState, value: number) => void; // Handler to commit numeric changes
   // isActive?: Accessor<boolean>; // Keep if needed for disabling
 }
 
 const EnvelopeControl: Component<EnvelopeControlProps> = (props) => {
-  const opIndex = props.operatorIndex;
-
   const handleEnvelopeCommit = (paramKey: keyof EnvelopeState, newValue: number) => {
     props.onParamChange(paramKey, newValue);
   };
 
   return (
     <div class="parameter-container">
-      <label class="parameter-title">Envelope</label>
+      <label class="parameter-title">{props.title ?? 'Envelope'}</label>
       <For each={envelopeParamsInfo}>
         {(paramInfo: EnvelopeParamInfo) => {
-          const inputId = `op-${opIndex}-adsr-${paramInfo.key}`;
+          const inputId = `${props.idPrefix}-adsr-${paramInfo.key}`;
 
           const numericValueAccessor = () => {
             const envelope = props.value(); // Get the parent state object
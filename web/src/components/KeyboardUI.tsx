@@ -1,6 +1,7 @@
 import { Component, createSignal, createMemo, Accessor, untrack, createEffect, For, onMount, onCleanup, JSX } from 'solid-js';
 import { KeyData, KeyboardLayout, getKeyboardLayoutData } from '../keyboardUtils';
 import * as SynthInputHandler from '../synthInputHandler';
+import { scheduleTimeFor } from '../audio';
 import { Note } from '../state';
 
 import '../style.css'; // Ensure path is correct
@@ -30,7 +31,7 @@ const KeyboardUI: Component<KeyboardUIProps> = (props) => {
     if (notesToStop.length > 0) {
       console.log("Keyboard Shift: Stopping UI notes:", notesToStop);
       notesToStop.forEach(note => {
-        SynthInputHandler.noteOff(note);
+        SynthInputHandler.noteOff(note, scheduleTimeFor(performance.now()));
       });
       setActiveNotes([]);
     }
@@ -70,7 +71,7 @@ const KeyboardUI: Component<KeyboardUIProps> = (props) => {
     if (notesToStop.length > 0) {
       console.log("Keyboard Cleanup: Stopping UI notes:", notesToStop);
       notesToStop.forEach(note => {
-        SynthInputHandler.noteOff(note);
+        SynthInputHandler.noteOff(note, scheduleTimeFor(performance.now()));
       });
     }
   });
@@ -88,8 +89,8 @@ const KeyboardUI: Component<KeyboardUIProps> = (props) => {
       const newNote: Note = { noteNumber: note, velocity: velocity, source: source };
       // Add to notes store
       setActiveNotes([...activeNotes(), newNote]);
-      // Send note on via handler
-      SynthInputHandler.noteOn(newNote);
+      // Send note on via handler, timed from the event
+      SynthInputHandler.noteOn(newNote, scheduleTimeFor(event.timeStamp));
     }
   };
   const handlePointerUpOrLeave = (keyData: KeyData, event: MouseEvent | TouchEvent): void => { /* ... as before ... */
@@ -98,7 +99,7 @@ const KeyboardUI: Component<KeyboardUIProps> = (props) => {
     if (isNoteActive(note, source)) {
       const newNote: Note = { noteNumber: note, velocity: 0, source: source };
       setActiveNotes((prev: Note[]) => prev.filter((n: Note) => !(n.noteNumber === note && n.source === source)));
-      SynthInputHandler.noteOff(newNote);
+      SynthInputHandler.noteOff(newNote, scheduleTimeFor(event.timeStamp));
     }
   };
   const handlePhysicalKeyDown = (event: KeyboardEvent): void => {
@@ -117,7 +118,7 @@ const KeyboardUI: Component<KeyboardUIProps> = (props) => {
       event.preventDefault();
       const newNote: Note = { noteNumber: keyData.note, velocity: velocity, source: source };
       setActiveNotes([...activeNotes(), newNote]);
-      SynthInputHandler.noteOn(newNote);
+      SynthInputHandler.noteOn(newNote, scheduleTimeFor(event.timeStamp));
     }
   };
 
@@ -129,7 +130,7 @@ const KeyboardUI: Component<KeyboardUIProps> = (props) => {
     if (keyData && isNoteActive(keyData.note, source)) {
       const newNote: Note = { noteNumber: keyData.note, velocity: 0, source: source };
       setActiveNotes(prev => prev.filter(n => !(n.noteNumber === keyData.note && n.source === source)));
-      SynthInputHandler.noteOff(newNote);
+      SynthInputHandler.noteOff(newNote, scheduleTimeFor(event.timeStamp));
     }
   };
 
@@ -139,7 +140,7 @@ const KeyboardUI: Component<KeyboardUIProps> = (props) => {
     if (notesToStop.length > 0) {
       console.log("Window lost focus. Stopping UI notes:", notesToStop);
       notesToStop.forEach(note => {
-        SynthInputHandler.noteOff(note);
+        SynthInputHandler.noteOff(note, scheduleTimeFor(performance.now()));
       });
       setActiveNotes([]); // Clear the active notes state in the UI
     }
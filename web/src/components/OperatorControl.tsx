@@ -28,6 +28,9 @@ const DEFAULT_FIXED_FREQ_ON_SWITCH = 440.0; // Default value when switching to f
 const MOD_INDEX_MIN = 0.0;
 const MOD_INDEX_MAX = 10.0; // Example
 const MOD_INDEX_STEP = 0.01;
+const FEEDBACK_MIN = 0.0;
+const FEEDBACK_MAX = 1.0;
+const FEEDBACK_STEP = 0.01;
 
 const OperatorControl: Component<OperatorControlProps> = (props) => {
   // TODO: I may want to push this down to the individual component
@@ -37,6 +40,7 @@ const OperatorControl: Component<OperatorControlProps> = (props) => {
   const fixedFreqValue = () => appStore.operators[props.operatorIndex]?.fixedFrequency; // Read imported store
   const detuneValue = () => appStore.operators[props.operatorIndex]?.detune; // Read imported store
   const modIndexValue = () => appStore.operators[props.operatorIndex]?.modulationIndex;
+  const feedbackValue = () => appStore.operators[props.operatorIndex]?.feedback;
   const waveformValue = () => appStore.operators[props.operatorIndex]?.waveform;
   const envelopeValue = () => appStore.operators[props.operatorIndex]?.envelope;
   const handleRatioChange = (newValue: number) => {
@@ -72,6 +76,10 @@ const OperatorControl: Component<OperatorControlProps> = (props) => {
     SynthInputHandler.setOperatorModIndex(props.operatorIndex, newValue); // Call synth handler
     setAppStore('operators', props.operatorIndex, 'modulationIndex', newValue);
   };
+  const handleFeedbackChange = (newValue: number) => {
+    SynthInputHandler.setOperatorFeedback(props.operatorIndex, newValue);
+    setAppStore('operators', props.operatorIndex, 'feedback', newValue);
+  };
   const handleWaveformChange = (waveformId: WaveformId) => {
     SynthInputHandler.setOperatorWaveform(props.operatorIndex, waveformId); // Call synth handler
     setAppStore('operators', props.operatorIndex, 'waveform', waveformId);
@@ -142,6 +150,16 @@ const OperatorControl: Component<OperatorControlProps> = (props) => {
         maxVal={MOD_INDEX_MAX}
         step={MOD_INDEX_STEP}
       />
+      <Crossfader
+        label={`Feedback`}
+        id={`feedback-` + props.operatorIndex}
+        value={feedbackValue}
+        onChange={handleFeedbackChange}
+        isFineModeActive={props.isFineModeActive}
+        minVal={FEEDBACK_MIN}
+        maxVal={FEEDBACK_MAX}
+        step={FEEDBACK_STEP}
+      />
       <hr />
       <WaveformSelect
         value={waveformValue}
@@ -151,7 +169,7 @@ const OperatorControl: Component<OperatorControlProps> = (props) => {
       />
       <hr />
       <EnvelopeControl
-        operatorIndex={props.operatorIndex} // Still needed for IDs maybe? Or just for context?
+        idPrefix={`op-${props.operatorIndex}`}
         //isActive={isActive}
         value={envelopeValue} // Pass the accessor for the whole envelope object
         onParamChange={handleEnvelopeParamChange} // Pass the single handler
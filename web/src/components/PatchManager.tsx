@@ -1,4 +1,4 @@
-import { Component, createSignal, createResource, createMemo, Show, For, onMount, batch } from 'solid-js';
+import { Component, createSignal, createResource, createMemo, createEffect, Show, For, onMount, batch } from 'solid-js';
 import { unwrap } from 'solid-js/store';
 import { AppState } from '../state'; // Adjust path as needed
 import { appStore, setAppStore } from '../App'; // Adjust path as needed
@@ -68,7 +68,7 @@ const exportPatch = () => {
 
 // --- Component ---
 
-const PatchManager: Component = () => {
+const PatchManager: Component<{ synthReady: boolean }> = (props) => {
 
   // --- State ---
   const [selectedPatchId, setSelectedPatchId] = createSignal<string | null>(null);
@@ -102,6 +102,13 @@ const PatchManager: Component = () => {
     setUserPatches(loadUserPatches());
   });
 
+  // Default patches are MIDI bank 0 in file order, user patches bank 1
+  createEffect(() => {
+    if (!props.synthReady) return;
+    SynthInputHandler.loadPresetBank(0, defaultPatchesResource() ?? []);
+    SynthInputHandler.loadPresetBank(1, userPatches());
+  });
+
   // --- Memos ---
 
   // Combine default and user patches, adding section headers
@@ -1,4 +1,4 @@
-import { AppState, EmptyEffectState, EffectState, EnvelopeState, OperatorState, createEmptyEffect } from './state';
+import { AppState, EmptyEffectState, EffectState, EnvelopeState, OperatorState, VOICE_RELEASE_HOLD, createEmptyEffect } from './state';
 import { NUM_OPERATORS } from './config';
 
 export const DEFAULT_ENVELOPE_STATE: EnvelopeState = {
@@ -8,6 +8,14 @@ export const DEFAULT_ENVELOPE_STATE: EnvelopeState = {
   release: 0.1,
 };
 
+// Neutral, so that patches sound as the operators shape them
+export const DEFAULT_VOICE_ENVELOPE_STATE: EnvelopeState = {
+  attack: 0,
+  decay: 0,
+  sustain: 1,
+  release: VOICE_RELEASE_HOLD,
+};
+
 
 function createDefaultOperatorState(): OperatorState {
   return {
@@ -15,6 +23,7 @@ function createDefaultOperatorState(): OperatorState {
     fixedFrequency: 0.0,
     detune: 0.0,
     modulationIndex: 10.0,
+    feedback: 0.1,
     waveform: 0,
     envelope: { ...DEFAULT_ENVELOPE_STATE },
     filters: [],
@@ -56,6 +65,7 @@ export function createDefaultAppState(): AppState {
     algorithm: createDefaultAlgorithmMatrixState(),
     operators: Array(NUM_OPERATORS).fill(null).map(() => createDefaultOperatorState()),
     masterVolume: 80.00,
+    voiceEnvelope: { ...DEFAULT_VOICE_ENVELOPE_STATE },
     effects: [createEmptyEffect(), createEmptyEffect(), createEmptyEffect()], // Start with an empty effect state
   };
 }
@@ -67,7 +77,9 @@ export function fillMissingAppState(partial: Partial<AppState>): AppState {
     algorithm: fillMatrix(partial.algorithm, defaultState.algorithm),
     operators: fillOperators(partial.operators, defaultState.operators),
     masterVolume: partial.masterVolume ?? defaultState.masterVolume,
+    voiceEnvelope: { ...defaultState.voiceEnvelope, ...partial.voiceEnvelope },
     effects: fillMissingEffects(partial.effects, defaultState.effects),
+    midiMappings: partial.midiMappings,
   };
 }
 // TODO: may want to extend this to fill partial effects based on their type and defaults as well
@@ -116,6 +128,7 @@ function fillOperators(
       fixedFrequency: partialOp.fixedFrequency ?? defaultOp.fixedFrequency,
       detune: partialOp.detune ?? defaultOp.detune,
       modulationIndex: partialOp.modulationIndex ?? defaultOp.modulationIndex,
+      feedback: partialOp.feedback ?? defaultOp.feedback,
       waveform: partialOp.waveform ?? defaultOp.waveform,
       filters: partialOp.filters ?? defaultOp.filters,
       envelope: {
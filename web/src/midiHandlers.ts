@@ -1,10 +1,15 @@
 import * as SynthInputHandler from './synthInputHandler';
-import { Note } from './state'; // Import Note type
+import { scheduleTimeFor } from './audio';
 
 // Standard MIDI Command Nibbles (Upper 4 bits)
 const NOTE_OFF_COMMAND = 0x80; // 128
 const NOTE_ON_COMMAND = 0x90;  // 144
-// const CC_COMMAND = 0xB0; // Example for later: Control Change
+const CC_COMMAND = 0xB0;
+const CHANNEL_PRESSURE_COMMAND = 0xD0;
+const PITCH_BEND_COMMAND = 0xE0;
+const CHANNEL_COMMANDS = [NOTE_OFF_COMMAND, NOTE_ON_COMMAND, CC_COMMAND, CHANNEL_PRESSURE_COMMAND, PITCH_BEND_COMMAND];
+const SYSEX_START = 0xF0;
+const TIMING_CLOCK = 0xF8;
 
 export class MidiInputHandler {
   private midiAccess: MIDIAccess | null = null;
@@ -19,7 +24,9 @@ export class MidiInputHandler {
       return;
     }
     try {
-      this.midiAccess = await navigator.requestMIDIAccess();
+      // SysEx is needed for MTS retuning; fall back to plain MIDI if it's refused
+      this.midiAccess = await navigator.requestMIDIAccess({ sysex: true })
+        .catch(() => navigator.requestMIDIAccess());
       console.log("MIDI access granted.");
       this.setupMidiInput();
     } catch (error) {
@@ -40,30 +47,16 @@ export class MidiInputHandler {
     });
   }
 
+  // Notes and expression are decoded by the synth itself, which tracks
+  // per-channel state such as MPE zones and pitch bend ranges. They play at
+  // the time they arrived rather than whenever the worklet gets them.
   private handleMidiMessage(event: MIDIMessageEvent): void {
-    if (!event.data || event.data.length < 3) {
+    if (!event.data || event.data.length === 0) {
       return;
     }
-
-    const [statusByte, noteNumber, velocity] = event.data;
-    // Extract the command (upper 4 bits)
-    const command = statusByte & 0xF0;
-    // Optional: Extract channel (lower 4 bits)
-    // const channel = statusByte & 0x0F;
-
-    if (command === NOTE_OFF_COMMAND || (command === NOTE_ON_COMMAND && velocity === 0)) {
-      const noteOffEvent: Note = { noteNumber: noteNumber, velocity: 0, source: 'midi' };
-      SynthInputHandler.noteOff(noteOffEvent);
-
-    } else if (command === NOTE_ON_COMMAND) {
-      const noteOnEvent: Note = { noteNumber: noteNumber, velocity: velocity, source: 'midi' };
-      SynthInputHandler.noteOn(noteOnEvent);
+    const command = event.data[0] & 0xF0;
+    if (event.data[0] === SYSEX_START || event.data[0] === TIMING_CLOCK || CHANNEL_COMMANDS.includes(command)) {
+      SynthInputHandler.midiMessage(event.data, scheduleTimeFor(event.timeStamp));
     }
-    // else if (command === CC_COMMAND) {
-    //    // Handle Control Change later if needed
-    // }
-    // else {
-    //    console.log(`Ignoring MIDI command: ${command.toString(16)}`);
-    // }
   }
 }
@@ -44,35 +44,47 @@ export type ReverbParamInfo = {
   step: number;
   minDecimals: number;
 }
-export const reverbParamsInfo: ReadonlyArray<ReverbParamInfo> = [
-  {
-    key: 'predelayMs',
-    label: 'Predelay (ms)',
-    min: 0,
-    max: 250,     // Max predelay of 250ms is quite long, good for special effects. 100ms is often plenty.
-    default: 10,
-    step: 1,
-    minDecimals: 0, // Display as whole milliseconds
-  },
-  {
-    key: 'decayMs',
-    label: 'Decay (ms)',
-    min: 100,     // Very short decay
-    max: 10000,   // 10 seconds is a very long reverb
-    default: 2000, // Default to 2 seconds, a common reverb length
-    step: 50,
-    minDecimals: 0,
-  },
-  {
-    key: 'wetMix',
-    label: 'Mix',
-    min: 0.0,
-    max: 1.0,
-    default: 0.5, // Default to 50% wet mix
-    step: 0.01,
-    minDecimals: 2, // For displaying 0.00 to 1.00
-  },
-]
+// One entry of the synth's parameter registry (see params.rs and WasmSynth::get_param_infos)
+export interface RegistryParamInfo {
+  id: string; // Path ID, e.g. "fx/1/reverb/decay"
+  name: string;
+  unit: string;
+  min: number;
+  max: number;
+  default: number;
+  scale: { type: 'linear' | 'exponential' | 'power' | 'stepped'; exponent?: number };
+}
+
+// Registry names for the reverb parameters, by their key in ReverbParams
+const REVERB_PARAM_IDS: ReadonlyArray<[keyof ReverbParams, string]> = [
+  ['predelayMs', 'predelay'],
+  ['decayMs', 'decay'],
+  ['wetMix', 'wet_mix'],
+];
+
+/**
+ * Reverb controls built from the parameter registry. Every slot shares the same
+ * ranges, so slot 1 describes them all.
+ */
+export function reverbParamsInfo(registry: ReadonlyArray<RegistryParamInfo>): ReverbParamInfo[] {
+  return REVERB_PARAM_IDS.flatMap(([key, name]) => {
+    const info = registry.find(p => p.id === `fx/1/reverb/${name}`);
+    if (!info) return [];
+    // Roughly a thousand steps across the range, on a round number
+    const step = info.scale.type === 'stepped'
+      ? 1
+      : Math.pow(10, Math.floor(Math.log10(info.max - info.min)) - 2);
+    return [{
+      key,
+      label: info.name.replace(/^Effect 1 Reverb /, '') + (info.unit ? ` (${info.unit})` : ''),
+      min: info.min,
+      max: info.max,
+      default: info.default,
+      step,
+      minDecimals: Math.max(0, -Math.log10(step)),
+    }];
+  });
+}
 
 export type EffectParamInfo =
   | ReverbParamInfo;
@@ -86,20 +98,22 @@ export type EffectConfig = {
   value: number | string; // A simple value potentially used for select dropdowns or simple backend messages (optional).
   params: ReadonlyArray<EffectParamInfo>; // Array defining the parameters for this filter type
 };
-export const EFFECTS: ReadonlyArray<EffectConfig> = [
-  {
-    name: "Reverb",
-    type: "Reverb", // Matches LowPassFilterState['typeTag']
-    value: 0,
-    params: reverbParamsInfo
-  },
-  {
-    name: "Empty",
-    type: "Empty", // Matches EmptyEffectState['typeTag']
-    value: 1,
-    params: []
-  },
-]
+export function effectConfigs(registry: ReadonlyArray<RegistryParamInfo>): ReadonlyArray<EffectConfig> {
+  return [
+    {
+      name: "Reverb",
+      type: "Reverb", // Matches ReverbState['type']
+      value: 0,
+      params: reverbParamsInfo(registry)
+    },
+    {
+      name: "Empty",
+      type: "Empty", // Matches EmptyEffectState['typeTag']
+      value: 1,
+      params: []
+    },
+  ];
+}
 export enum EffectSlot {
   One = 1,
   Two,
@@ -153,6 +167,7 @@ export interface OperatorState {
   fixedFrequency: number;
   detune: number;
   modulationIndex: number;
+  feedback: number; // Only heard through a cycle in the algorithm
   waveform: WaveformId;
   envelope: EnvelopeState;
   filters: FilterState[];
@@ -163,11 +178,24 @@ export interface Note {
   source: 'keyboard' | 'pointer' | 'midi' | string;
 }
 
+// A controller bound to a synth parameter by MIDI learn (see midi_learn.rs)
+export interface MidiMapping {
+  param: string; // Parameter id, e.g. "op/0/ratio"
+  type: 'cc' | 'cc14' | 'nrpn';
+  channel: number; // 0-15
+  number: number; // Controller or NRPN number
+  min: number; // Normalized parameter range the controller sweeps
+  max: number;
+  curve: 'linear' | 'exponential' | 'logarithmic';
+}
+
 export interface AppState {
   algorithm: number[][];
   operators: OperatorState[];
   masterVolume: number;
+  voiceEnvelope: EnvelopeState; // Applied on top of every operator envelope
   effects: EffectState[];
+  midiMappings?: MidiMapping[]; // Saved with the patch; the controller profile applies without
 }
 export const MASTER_VOLUME_MAX = 100;
 export const MASTER_VOLUME_MIN = 0;
@@ -216,6 +244,10 @@ export const envelopeParamsInfo: ReadonlyArray<EnvelopeParamInfo> = [
   { key: 'release', label: 'Release:', min: 0, max: 10, step: .01, minDecimals: 2, },
 ] as const;
 
+// A voice release this long (the top of the range) holds, leaving the release
+// to the operators, as for the voice/release parameter
+export const VOICE_RELEASE_HOLD = 10;
+
 export type FilterParamInfo = {
   name: string;      // UI Label: "Cutoff", "Alpha"
   id: string;        // Key in the corresponding FilterState['params'] object: "cutoff", "alpha"
@@ -1,5 +1,5 @@
 import { resumeAudioContext } from './audio'; // We'll put resumeAudioContext in App.tsx initially
-import { Note, WaveformId, AppState, FILTERS, ReverbParams, EffectSlot } from './state';
+import { Note, WaveformId, AppState, EnvelopeState, FILTERS, ReverbParams, EffectSlot, MidiMapping, VOICE_RELEASE_HOLD } from './state';
 import { objToJsonBytes, stringToBytes } from './utils';
 import { fillMissingAppState } from './defaults';
 
@@ -15,28 +15,42 @@ export function initializeSynthInputHandler(port: MessagePort | null): void {
   processorPort = port;
 }
 
-// Simple fire-and-forget note on
-export function noteOn(note: Note): void {
+// Post `{ type, ...payload }` to the synth worklet, logging rather than throwing on failure.
+function postWorkletMessage(type: string, payload: Record<string, unknown> = {}): void {
+  if (!processorPort) {
+    console.warn(`SynthInputHandler: Port not connected, cannot send ${type}.`);
+    return;
+  }
+  try {
+    processorPort.postMessage({ type, ...payload });
+  } catch (e) {
+    console.error(`SynthInputHandler: Error sending ${type}:`, e);
+  }
+}
+
+// Simple fire-and-forget note on.
+// `time` (AudioContext seconds) schedules the note sample-accurately instead of at the next render.
+export function noteOn(note: Note, time?: number): void {
   if (!processorPort) {
     console.warn("SynthInputHandler: Port not connected, cannot send note_on.");
     return;
   }
   resumeAudioContext();
   try {
-    processorPort.postMessage({ type: 'note_on', note: note.noteNumber, velocity: note.velocity });
+    processorPort.postMessage({ type: 'note_on', note: note.noteNumber, velocity: note.velocity, time });
   } catch (e) {
     console.error("SynthInputHandler: Error sending note_on:", e);
   }
 }
 
 // Simple fire-and-forget note off
-export function noteOff(note: Note): void {
+export function noteOff(note: Note, time?: number): void {
   if (!processorPort) {
     console.warn("SynthInputHandler: Port not connected, cannot send note_off.");
     return;
   }
   try {
-    processorPort.postMessage({ type: 'note_off', note: note.noteNumber });
+    processorPort.postMessage({ type: 'note_off', note: note.noteNumber, time });
   } catch (e) {
     console.error("SynthInputHandler: Error sending note_off:", e);
   }
@@ -124,6 +138,13 @@ export function setOperatorModIndex(operatorIndex: number, modIndex: number): vo
     console.error("SynthInputHandler: Error setting modulation index:", e);
   }
 }
+// How much of a feedback path (a cycle in the algorithm) reaches the operator, 0-1.
+export function setOperatorFeedback(operatorIndex: number, feedback: number): void {
+  postWorkletMessage('set_operator_feedback', { operatorIndex, feedback });
+}
+export function setFeedbackMode(mode: 'one-sample' | 'averaged' | 'unrolled'): void {
+  postWorkletMessage('set_feedback_mode', { mode });
+}
 export function setOperatorWaveform(operatorIndex: number, waveformId: WaveformId): void {
   if (!processorPort) {
     console.warn("SynthInputHandler: Port not connected, cannot set waveform.");
@@ -169,6 +190,167 @@ export function removeOperatorFilter(operatorIndex: number, filterType: Uint8Arr
     console.error("SynthInputHandler: Error removing filter:", e);
   }
 }
+// Switch to a Scala tuning; `kbm` is the optional keyboard mapping file contents.
+export function setTuning(scl: string, kbm?: string): void {
+  postWorkletMessage('set_tuning', { scl, kbm });
+}
+export function resetTuning(): void {
+  postWorkletMessage('reset_tuning');
+}
+// Set the amplitude envelope applied on top of every operator envelope
+export function setVoiceEnvelope(envelope: EnvelopeState): void {
+  const release = envelope.release >= VOICE_RELEASE_HOLD ? Infinity : envelope.release;
+  postWorkletMessage('set_voice_envelope', { ...envelope, release });
+}
+export function setReferencePitch(frequency: number): void {
+  postWorkletMessage('set_reference_pitch', { frequency });
+}
+// Forward a raw MIDI message (notes, MPE expression, SysEx) to the synth.
+// `time` (AudioContext seconds) schedules its notes and expression like `noteOn`'s.
+export function midiMessage(data: Uint8Array, time?: number): void {
+  if (!processorPort) {
+    return;
+  }
+  resumeAudioContext();
+  try {
+    processorPort.postMessage({ type: 'midi_message', data, time });
+  } catch (e) {
+    console.error("SynthInputHandler: Error sending MIDI message:", e);
+  }
+}
+// Configure an MPE zone; zero member channels disables it
+export function setMpeZone(upper: boolean, memberChannels: number): void {
+  postWorkletMessage('set_mpe_zone', { upper, memberChannels });
+}
+export interface SequencerStep {
+  note?: number; // Omit for a rest
+  velocity?: number;
+  gate?: number; // Fraction of a step; above 1 ties into later steps
+  probability?: number; // 0-1
+  locks?: { id: string; value: number }[]; // Operator params, e.g. "op/1/ratio"
+}
+export interface SequencerPattern {
+  stepsPerBeat?: number;
+  steps: SequencerStep[];
+}
+export function setSequencerPattern(index: number, pattern: SequencerPattern): void {
+  postWorkletMessage('set_sequencer_pattern', { index, pattern });
+}
+// Order patterns play in; an empty chain loops pattern 0
+export function setSequencerChain(chain: number[]): void {
+  postWorkletMessage('set_sequencer_chain', { chain });
+}
+// Delay of every second step as a fraction of a step (0-0.75)
+export function setSequencerSwing(swing: number): void {
+  postWorkletMessage('set_sequencer_swing', { swing });
+}
+// Transport state posted by the worklet as { type: 'transport', state }
+export interface TransportState {
+  playing: boolean;
+  source: 'internal' | 'midi';
+  bpm: number;
+  beats: number; // Quarter notes since the start of the song
+  songPosition: number; // Sixteenth notes
+}
+// Tempo for the sequencer and arpeggiator when not following MIDI clock
+export function setTempo(bpm: number): void {
+  postWorkletMessage('set_tempo', { bpm });
+}
+export function setClockSource(source: 'internal' | 'midi'): void {
+  postWorkletMessage('set_clock_source', { source });
+}
+// `time` (AudioContext seconds) starts, stops or continues sample-accurately
+export function startTransport(time?: number): void {
+  resumeAudioContext();
+  postWorkletMessage('start_transport', { time });
+}
+export function stopTransport(time?: number): void {
+  postWorkletMessage('stop_transport', { time });
+}
+// Carry on from the current song position
+export function continueTransport(time?: number): void {
+  resumeAudioContext();
+  postWorkletMessage('continue_transport', { time });
+}
+export interface ArpeggiatorSettings {
+  enabled?: boolean;
+  mode?: 'up' | 'down' | 'up-down' | 'random' | 'as-played' | 'chord';
+  octaves?: number; // 1-4
+  rate?: string; // Note division, e.g. "1/16" or "1/8t"
+  gate?: number; // Fraction of a step
+  latch?: boolean;
+}
+// Only the given settings change
+export function setArpeggiator(settings: ArpeggiatorSettings): void {
+  postWorkletMessage('set_arpeggiator', { ...settings });
+}
+// A patch as stored in default-patches.json
+export interface PresetPatch {
+  name: string;
+  section?: string;
+  state: Partial<AppState>;
+}
+// Patches become programs 0 up of `bank`, for MIDI Bank Select and Program Change.
+// The worklet posts { type: 'program_change', bank, program, patch } when MIDI switches patch.
+export function loadPresetBank(bank: number, patches: PresetPatch[]): void {
+  postWorkletMessage('load_preset_bank', { bank, patches });
+}
+export function programChange(bank: number, program: number): void {
+  postWorkletMessage('program_change', { bank, program });
+}
+export interface PartRouting {
+  channel?: number; // 0-15, or -1 for all channels
+  keyRange?: [number, number];
+  velocityRange?: [number, number];
+  effectSend?: number; // 0 (dry) to 1
+}
+// The worklet posts { type: 'part_added', part } with the new part's index.
+export function addPart(maxVoices = 8): void {
+  postWorkletMessage('add_part', { maxVoices });
+}
+export function removePart(part: number): void {
+  postWorkletMessage('remove_part', { part });
+}
+// Parameter changes and patches go to the selected part.
+export function selectPart(part: number): void {
+  postWorkletMessage('select_part', { part });
+}
+export function setPartRouting(part: number, routing: PartRouting): void {
+  postWorkletMessage('set_part_routing', { part, ...routing });
+}
+// Bind `paramId` to the next controller that moves; the worklet posts
+// { type: 'midi_learned', mappings } once it has one.
+export function startMidiLearn(paramId: string, min = 0, max = 1, curve: MidiMapping['curve'] = 'linear'): void {
+  postWorkletMessage('start_midi_learn', { paramId, min, max, curve });
+}
+export function cancelMidiLearn(): void {
+  postWorkletMessage('cancel_midi_learn');
+}
+export function setMidiMappings(mappings: MidiMapping[]): void {
+  postWorkletMessage('set_midi_mappings', { mappings });
+}
+export function clearMidiMapping(paramId: string): void {
+  postWorkletMessage('clear_midi_mapping', { paramId });
+}
+// The controller profile holds mappings for patches that don't bring their own
+const CONTROLLER_PROFILE_STORAGE_KEY = 'midiControllerProfile_v1';
+export function saveControllerProfile(mappings: MidiMapping[]): void {
+  try {
+    localStorage.setItem(CONTROLLER_PROFILE_STORAGE_KEY, JSON.stringify(mappings));
+  } catch (error) {
+    console.error("Error saving controller profile to localStorage:", error);
+  }
+}
+export function loadControllerProfile(): MidiMapping[] {
+  try {
+    const stored = localStorage.getItem(CONTROLLER_PROFILE_STORAGE_KEY);
+    return stored ? JSON.parse(stored) : [];
+  } catch (error) {
+    console.error("Error loading controller profile from localStorage:", error);
+    localStorage.removeItem(CONTROLLER_PROFILE_STORAGE_KEY);
+    return [];
+  }
+}
 export function setMasterVolume(volume: number): void {
   if (!processorPort) {
     console.warn("SynthInputHandler: Port not connected, cannot set volume.");
@@ -197,8 +379,9 @@ export function setSynthState(appState: AppState): void {
   }
   appState = fillMissingAppState(appState);
 
-  // 0. Set Master Volume
+  // 0. Set Master Volume and the voice envelope
   setMasterVolume(appState.masterVolume);
+  setVoiceEnvelope(appState.voiceEnvelope);
   // 1. Set Algorithm
   setAlgorithm(appState.algorithm);
 
@@ -223,6 +406,7 @@ export function setSynthState(appState: AppState): void {
       setOperatorFixedFrequency(index, opState.fixedFrequency);
     }
     setOperatorModIndex(index, opState.modulationIndex);
+    setOperatorFeedback(index, opState.feedback);
     setOperatorWaveform(index, opState.waveform);
 
     // Set envelope
@@ -252,5 +436,7 @@ export function setSynthState(appState: AppState): void {
     });
   });
 
+  setMidiMappings(appState.midiMappings ?? loadControllerProfile());
+
   console.log("SynthInputHandler: Full synth state update complete.");
 }